@@ -1,6 +1,7 @@
 use std::error::Error;
 
 use epoch_db::DB;
+use epoch_db::db::errors::TransactionError;
 use tempfile::tempdir;
 
 /// This example demonstrates how to use the high-level transaction API
@@ -27,8 +28,13 @@ fn main() -> Result<(), Box<dyn Error>> {
         let alice_balance_str = tx.get("user:alice")?.unwrap();
         let bob_balance_str = tx.get("user:bob")?.unwrap();
 
-        let mut alice_balance: u64 = alice_balance_str.parse()?;
-        let mut bob_balance: u64 = bob_balance_str.parse()?;
+        // Our own errors abort the transaction too.
+        let mut alice_balance: u64 = alice_balance_str
+            .parse()
+            .map_err(|_| TransactionError::Abort("Alice's balance is corrupted!"))?;
+        let mut bob_balance: u64 = bob_balance_str
+            .parse()
+            .map_err(|_| TransactionError::Abort("Bob's balance is corrupted!"))?;
 
         let transfer_amount = 20;
 
@@ -36,7 +42,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         if alice_balance < transfer_amount {
             // By returning an error, we abort the entire transaction.
             // No changes will be saved.
-            return Err(Box::from("Alice has insufficient funds!"));
+            return Err(TransactionError::Abort("Alice has insufficient funds!"));
         }
 
         // Perform the transfer.
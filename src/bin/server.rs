@@ -3,11 +3,10 @@
 use std::error::Error;
 use std::io;
 use std::path::PathBuf;
-use std::sync::Arc;
 use std::time::Duration;
 
 use clap::Parser;
-use epoch_db::DB;
+use epoch_db::db::async_db::AsyncDB;
 use epoch_db::server::response_handler;
 use epoch_db::server::utils::init_logger;
 use tokio::net::TcpListener;
@@ -67,7 +66,7 @@ async fn async_main(args: (Cli)) -> Result<(), Box<dyn Error>> {
     info!("Listening to {}", addr);
 
     // TODO: LAZY STATIC DB
-    let store = Arc::new(DB::new(&PathBuf::from(cli.path))?);
+    let store = AsyncDB::open(&PathBuf::from(cli.path)).await?;
 
     loop {
         let stream_set = listener.accept().await;
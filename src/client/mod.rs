@@ -39,11 +39,9 @@ pub async fn handle_response(res: Result<Response, TransientError>) -> Result<()
                 },
                 // MAKE THIS RECURSIVE YAYYYY
                 Response::Array(a) => {
-                    let mut c = 1;
-                    for i in a {
+                    for (c, i) in (1..).zip(a) {
                         print!("{c}) ");
                         handle_response(Ok(i)).await?;
-                        c += 1
                     }
                 },
                 Response::BulkString(bs) => {
@@ -0,0 +1,985 @@
+//! This module defines the `AsyncDB`, a wrapper of the `DB` for async code.
+//!
+//! Every call of the `DB` reads or writes the storage backend synchronously,
+//! which would block the worker thread of the runtime it is made on. The
+//! `AsyncDB` runs each call on the blocking thread pool of tokio instead, and
+//! awaits its result.
+//!
+//! The arguments are copied before the call is moved to the pool, since it may
+//! outlive the future awaiting it if that future is dropped.
+
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task;
+
+use crate::db::config::DBConfig;
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+use crate::db::hash::HashFields;
+use crate::db::lease::Lease;
+use crate::db::rate_limit::{
+    RateLimit,
+    RateLimitDecision
+};
+use crate::db::scan::ScanPage;
+use crate::db::sorted_set::ScoredMember;
+use crate::db::stats::{
+    DBStats,
+    KeyInfo,
+    PrefixReport,
+    StorageStats
+};
+use crate::db::storage::StorageBackend;
+use crate::db::stream::{
+    PendingEntry,
+    StreamEntry,
+    StreamId,
+    StreamTrim
+};
+use crate::db::transaction::TransactionalGuard;
+use crate::{
+    DB,
+    Metadata
+};
+
+/// A `DB` whose calls run on the blocking thread pool of tokio, see the
+/// module documentation.
+///
+/// The `AsyncDB` is cheap to clone, like the `DB` it wraps. The calls have to
+/// be awaited inside a tokio runtime.
+#[derive(Debug, Clone)]
+pub struct AsyncDB {
+    db: DB
+}
+
+impl From<DB> for AsyncDB {
+    fn from(db: DB) -> Self {
+        AsyncDB::new(db)
+    }
+}
+
+impl From<Arc<DB>> for AsyncDB {
+    fn from(db: Arc<DB>) -> Self {
+        AsyncDB::new(DB::clone(&db))
+    }
+}
+
+impl AsyncDB {
+    /// Wraps the database.
+    pub fn new(db: DB) -> AsyncDB {
+        AsyncDB {
+            db
+        }
+    }
+
+    /// Opens the database at the path, see `DB::new`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened.
+    pub async fn open(path: &Path) -> Result<AsyncDB, TransientError> {
+        let path = path.to_path_buf();
+        let db = spawn(move || DB::new(&path)).await??;
+
+        Ok(AsyncDB::new(db))
+    }
+
+    /// Restores a backup into a new database at `db_path`, see
+    /// `DB::load_from`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backup cannot be read or the database cannot be
+    /// written.
+    pub async fn load_from(path: &Path, db_path: &Path) -> Result<AsyncDB, TransientError> {
+        let (path, db_path) = (path.to_path_buf(), db_path.to_path_buf());
+        let db = spawn(move || DB::load_from(&path, &db_path)).await??;
+
+        Ok(AsyncDB::new(db))
+    }
+
+    /// Restores a backup into a new database on top of the storage backend,
+    /// see `DB::load_from_with_config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backup cannot be read or decrypted, or the
+    /// database cannot be written.
+    pub async fn load_from_with_config<S: StorageBackend + 'static>(
+        path: &Path,
+        storage: S,
+        config: DBConfig
+    ) -> Result<AsyncDB, TransientError> {
+        let path = path.to_path_buf();
+        let db = spawn(move || DB::load_from_with_config(&path, storage, config)).await??;
+
+        Ok(AsyncDB::new(db))
+    }
+
+    /// Returns the wrapped database, for the calls which do not touch the
+    /// storage, e.g. `DB::subscribe_expirations`.
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+
+    /// Runs the closure with the database on the blocking thread pool, for
+    /// the calls this wrapper has no async version of.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the closure, or `TaskCancelled` if the runtime
+    /// shut down before the closure ran.
+    pub async fn run<T, F>(&self, f: F) -> Result<T, TransientError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&DB) -> Result<T, TransientError> + Send + 'static
+    {
+        let db = self.db.clone();
+        spawn(move || f(&db)).await?
+    }
+
+    /// Async version of `DB::transaction`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::transaction`, or `TaskCancelled` if the
+    /// runtime shut down before the transaction ran.
+    pub async fn transaction<F, R, E>(&self, f: F) -> Result<R, TransactionError<E>>
+    where
+        F: FnMut(&mut TransactionalGuard) -> Result<R, TransactionError<E>> + Send + 'static,
+        R: Send + 'static,
+        E: Send + 'static
+    {
+        let db = self.db.clone();
+        spawn(move || db.transaction(f)).await?
+    }
+
+    /// Async version of `DB::set`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::set`.
+    pub async fn set(
+        &self,
+        key: &str,
+        val: &str,
+        ttl: Option<Duration>
+    ) -> Result<(), TransientError> {
+        let (key, val) = (key.to_string(), val.to_string());
+        self.run(move |db| db.set(&key, &val, ttl)).await
+    }
+
+    /// Async version of `DB::get`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::get`.
+    pub async fn get(&self, key: &str) -> Result<Option<String>, TransientError> {
+        let key = key.to_string();
+        self.run(move |db| db.get(&key)).await
+    }
+
+    /// Async version of `DB::increment_frequency`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::increment_frequency`.
+    pub async fn increment_frequency(&self, key: &str) -> Result<Option<()>, TransientError> {
+        let key = key.to_string();
+        self.run(move |db| db.increment_frequency(&key)).await
+    }
+
+    /// Async version of `DB::remove`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::remove`.
+    pub async fn remove(&self, key: &str) -> Result<(), TransientError> {
+        let key = key.to_string();
+        self.run(move |db| db.remove(&key)).await
+    }
+
+    /// Async version of `DB::get_metadata`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::get_metadata`.
+    pub async fn get_metadata(&self, key: &str) -> Result<Option<Metadata>, TransientError> {
+        let key = key.to_string();
+        self.run(move |db| db.get_metadata(&key)).await
+    }
+
+    /// Async version of `DB::set_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::set_raw`.
+    pub async fn set_raw<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        val: &V,
+        ttl: Option<Duration>
+    ) -> Result<(), TransientError> {
+        let (key, val) = (bytes(key), bytes(val));
+        self.run(move |db| db.set_raw(&key, &val, ttl)).await
+    }
+
+    /// Async version of `DB::get_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::get_raw`.
+    pub async fn get_raw<K: AsRef<[u8]>>(
+        &self,
+        key: &K
+    ) -> Result<Option<Vec<u8>>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.get_raw(&key)).await
+    }
+
+    /// Async version of `DB::get_metadata_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::get_metadata_raw`.
+    pub async fn get_metadata_raw<K: AsRef<[u8]>>(
+        &self,
+        key: &K
+    ) -> Result<Option<Metadata>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.get_metadata_raw(&key)).await
+    }
+
+    /// Async version of `DB::remove_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::remove_raw`.
+    pub async fn remove_raw<K: AsRef<[u8]>>(&self, key: K) -> Result<(), TransientError> {
+        let key = bytes(&key);
+        self.run(move |db| db.remove_raw(key)).await
+    }
+
+    /// Async version of `DB::increment_frequency_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::increment_frequency_raw`.
+    pub async fn increment_frequency_raw(&self, key: &[u8]) -> Result<Option<()>, TransientError> {
+        let key = key.to_vec();
+        self.run(move |db| db.increment_frequency_raw(&key)).await
+    }
+
+    /// Async version of `DB::set_durable`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::set_durable`.
+    pub async fn set_durable(
+        &self,
+        key: &str,
+        val: &str,
+        ttl: Option<Duration>
+    ) -> Result<(), TransientError> {
+        let (key, val) = (key.to_string(), val.to_string());
+        self.run(move |db| db.set_durable(&key, &val, ttl)).await
+    }
+
+    /// Async version of `DB::set_raw_durable`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::set_raw_durable`.
+    pub async fn set_raw_durable<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        val: &V,
+        ttl: Option<Duration>
+    ) -> Result<(), TransientError> {
+        let (key, val) = (bytes(key), bytes(val));
+        self.run(move |db| db.set_raw_durable(&key, &val, ttl))
+            .await
+    }
+
+    /// Async version of `DB::exists`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::exists`.
+    pub async fn exists<K: AsRef<[u8]>>(&self, key: &K) -> Result<bool, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.exists(&key)).await
+    }
+
+    /// Async version of `DB::ttl`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::ttl`.
+    pub async fn ttl<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Duration>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.ttl(&key)).await
+    }
+
+    /// Async version of `DB::expire`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::expire`.
+    pub async fn expire<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        ttl: Duration
+    ) -> Result<bool, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.expire(&key, ttl)).await
+    }
+
+    /// Async version of `DB::persist`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::persist`.
+    pub async fn persist<K: AsRef<[u8]>>(&self, key: &K) -> Result<bool, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.persist(&key)).await
+    }
+
+    /// Async version of `DB::remove_expired`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::remove_expired`.
+    pub async fn remove_expired(&self) -> Result<u64, TransientError> {
+        self.run(|db| db.remove_expired()).await
+    }
+
+    /// Async version of `DB::flush`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::flush`.
+    pub async fn flush(&self) -> Result<(), TransientError> {
+        self.run(|db| db.flush()).await
+    }
+
+    /// Async version of `DB::backup_to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::backup_to`.
+    pub async fn backup_to(&self, path: &Path) -> Result<(), TransientError> {
+        let path: PathBuf = path.to_path_buf();
+        self.run(move |db| db.backup_to(&path)).await
+    }
+
+    /// Async version of `DB::get_db_size`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TaskCancelled` if the runtime shut down before the keys were
+    /// counted.
+    pub async fn get_db_size(&self) -> Result<usize, TransientError> {
+        self.run(|db| Ok(db.get_db_size())).await
+    }
+
+    /// Async version of `DB::rebuild_bloom_filter`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::rebuild_bloom_filter`.
+    pub async fn rebuild_bloom_filter(&self) -> Result<(), TransientError> {
+        self.run(|db| db.rebuild_bloom_filter()).await
+    }
+
+    /// Async version of `RateLimiter::check`, with a limiter of the policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `RateLimiter::check`.
+    pub async fn check_rate_limit<K: AsRef<[u8]>>(
+        &self,
+        policy: RateLimit,
+        key: &K,
+        cost: u64
+    ) -> Result<RateLimitDecision, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.rate_limiter(policy).check(&key, cost))
+            .await
+    }
+
+    /// Async version of `DB::acquire_lease`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::acquire_lease`.
+    pub async fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration
+    ) -> Result<Option<Lease>, TransientError> {
+        let (name, owner) = (name.to_string(), owner.to_string());
+        self.run(move |db| db.acquire_lease(&name, &owner, ttl))
+            .await
+    }
+
+    /// Async version of `DB::renew_lease`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::renew_lease`.
+    pub async fn renew_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration
+    ) -> Result<Option<Lease>, TransientError> {
+        let (name, owner) = (name.to_string(), owner.to_string());
+        self.run(move |db| db.renew_lease(&name, &owner, ttl)).await
+    }
+
+    /// Async version of `DB::release_lease`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::release_lease`.
+    pub async fn release_lease(&self, name: &str, owner: &str) -> Result<bool, TransientError> {
+        let (name, owner) = (name.to_string(), owner.to_string());
+        self.run(move |db| db.release_lease(&name, &owner)).await
+    }
+
+    /// Async version of `DB::get_lease`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::get_lease`.
+    pub async fn get_lease(&self, name: &str) -> Result<Option<Lease>, TransientError> {
+        let name = name.to_string();
+        self.run(move |db| db.get_lease(&name)).await
+    }
+
+    /// Async version of `DB::lpush`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::lpush`.
+    pub async fn lpush<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        values: &[V]
+    ) -> Result<u64, TransientError> {
+        let (key, values) = (bytes(key), all_bytes(values));
+        self.run(move |db| db.lpush(&key, &values)).await
+    }
+
+    /// Async version of `DB::rpush`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::rpush`.
+    pub async fn rpush<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        values: &[V]
+    ) -> Result<u64, TransientError> {
+        let (key, values) = (bytes(key), all_bytes(values));
+        self.run(move |db| db.rpush(&key, &values)).await
+    }
+
+    /// Async version of `DB::lpop`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::lpop`.
+    pub async fn lpop<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Vec<u8>>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.lpop(&key)).await
+    }
+
+    /// Async version of `DB::rpop`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::rpop`.
+    pub async fn rpop<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Vec<u8>>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.rpop(&key)).await
+    }
+
+    /// Async version of `DB::llen`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::llen`.
+    pub async fn llen<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.llen(&key)).await
+    }
+
+    /// Async version of `DB::lrange`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::lrange`.
+    pub async fn lrange<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: i64,
+        stop: i64
+    ) -> Result<Vec<Vec<u8>>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.lrange(&key, start, stop)).await
+    }
+
+    /// Async version of `DB::ltrim`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::ltrim`.
+    pub async fn ltrim<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: i64,
+        stop: i64
+    ) -> Result<(), TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.ltrim(&key, start, stop)).await
+    }
+
+    /// Async version of `DB::hset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::hset`.
+    pub async fn hset<K: AsRef<[u8]>, F: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        fields: &[(F, V)]
+    ) -> Result<u64, TransientError> {
+        let (key, fields) = (bytes(key), all_pairs(fields));
+        self.run(move |db| db.hset(&key, &fields)).await
+    }
+
+    /// Async version of `DB::hget`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::hget`.
+    pub async fn hget<K: AsRef<[u8]>, F: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        field: &F
+    ) -> Result<Option<Vec<u8>>, TransientError> {
+        let (key, field) = (bytes(key), bytes(field));
+        self.run(move |db| db.hget(&key, &field)).await
+    }
+
+    /// Async version of `DB::hdel`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::hdel`.
+    pub async fn hdel<K: AsRef<[u8]>, F: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        fields: &[F]
+    ) -> Result<u64, TransientError> {
+        let (key, fields) = (bytes(key), all_bytes(fields));
+        self.run(move |db| db.hdel(&key, &fields)).await
+    }
+
+    /// Async version of `DB::hgetall`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::hgetall`.
+    pub async fn hgetall<K: AsRef<[u8]>>(&self, key: &K) -> Result<HashFields, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.hgetall(&key)).await
+    }
+
+    /// Async version of `DB::hlen`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::hlen`.
+    pub async fn hlen<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.hlen(&key)).await
+    }
+
+    /// Async version of `DB::hincrby`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::hincrby`.
+    pub async fn hincrby<K: AsRef<[u8]>, F: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        field: &F,
+        delta: i64
+    ) -> Result<i64, TransientError> {
+        let (key, field) = (bytes(key), bytes(field));
+        self.run(move |db| db.hincrby(&key, &field, delta)).await
+    }
+
+    /// Async version of `DB::zadd`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::zadd`.
+    pub async fn zadd<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        members: &[(M, f64)]
+    ) -> Result<u64, TransientError> {
+        let key = bytes(key);
+        let members: Vec<(Vec<u8>, f64)> = members.iter().map(|(m, s)| (bytes(m), *s)).collect();
+        self.run(move |db| db.zadd(&key, &members)).await
+    }
+
+    /// Async version of `DB::zrem`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::zrem`.
+    pub async fn zrem<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        members: &[M]
+    ) -> Result<u64, TransientError> {
+        let (key, members) = (bytes(key), all_bytes(members));
+        self.run(move |db| db.zrem(&key, &members)).await
+    }
+
+    /// Async version of `DB::zscore`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::zscore`.
+    pub async fn zscore<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        member: &M
+    ) -> Result<Option<f64>, TransientError> {
+        let (key, member) = (bytes(key), bytes(member));
+        self.run(move |db| db.zscore(&key, &member)).await
+    }
+
+    /// Async version of `DB::zcard`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::zcard`.
+    pub async fn zcard<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.zcard(&key)).await
+    }
+
+    /// Async version of `DB::zrank`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::zrank`.
+    pub async fn zrank<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        member: &M
+    ) -> Result<Option<u64>, TransientError> {
+        let (key, member) = (bytes(key), bytes(member));
+        self.run(move |db| db.zrank(&key, &member)).await
+    }
+
+    /// Async version of `DB::zrange`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::zrange`.
+    pub async fn zrange<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: i64,
+        stop: i64
+    ) -> Result<Vec<ScoredMember>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.zrange(&key, start, stop)).await
+    }
+
+    /// Async version of `DB::zrange_by_score`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::zrange_by_score`.
+    pub async fn zrange_by_score<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        min: f64,
+        max: f64
+    ) -> Result<Vec<ScoredMember>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.zrange_by_score(&key, min, max)).await
+    }
+
+    /// Async version of `DB::xadd`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xadd`.
+    pub async fn xadd<K: AsRef<[u8]>, F: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        fields: &[(F, V)]
+    ) -> Result<StreamId, TransientError> {
+        let (key, fields) = (bytes(key), all_pairs(fields));
+        self.run(move |db| db.xadd(&key, &fields)).await
+    }
+
+    /// Async version of `DB::xlen`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xlen`.
+    pub async fn xlen<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.xlen(&key)).await
+    }
+
+    /// Async version of `DB::xrange`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xrange`.
+    pub async fn xrange<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>
+    ) -> Result<Vec<StreamEntry>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.xrange(&key, start, end, count)).await
+    }
+
+    /// Async version of `DB::xtrim`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xtrim`.
+    pub async fn xtrim<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        trim: StreamTrim
+    ) -> Result<u64, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.xtrim(&key, trim)).await
+    }
+
+    /// Async version of `DB::xgroup_create`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xgroup_create`.
+    pub async fn xgroup_create<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str,
+        start: Option<StreamId>
+    ) -> Result<bool, TransientError> {
+        let (key, group) = (bytes(key), group.to_string());
+        self.run(move |db| db.xgroup_create(&key, &group, start))
+            .await
+    }
+
+    /// Async version of `DB::xreadgroup`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xreadgroup`.
+    pub async fn xreadgroup<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str,
+        consumer: &str,
+        count: usize
+    ) -> Result<Vec<StreamEntry>, TransientError> {
+        let (key, group, consumer) = (bytes(key), group.to_string(), consumer.to_string());
+        self.run(move |db| db.xreadgroup(&key, &group, &consumer, count))
+            .await
+    }
+
+    /// Async version of `DB::xack`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xack`.
+    pub async fn xack<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str,
+        ids: &[StreamId]
+    ) -> Result<u64, TransientError> {
+        let (key, group, ids) = (bytes(key), group.to_string(), ids.to_vec());
+        self.run(move |db| db.xack(&key, &group, &ids)).await
+    }
+
+    /// Async version of `DB::xpending`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::xpending`.
+    pub async fn xpending<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str
+    ) -> Result<Vec<PendingEntry>, TransientError> {
+        let (key, group) = (bytes(key), group.to_string());
+        self.run(move |db| db.xpending(&key, &group)).await
+    }
+
+    /// Async version of `DB::create_index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::create_index`.
+    pub async fn create_index<F>(&self, name: &str, extractor: F) -> Result<(), TransientError>
+    where
+        F: Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static
+    {
+        let name = name.to_string();
+        self.run(move |db| db.create_index(&name, extractor)).await
+    }
+
+    /// Async version of `DB::drop_index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::drop_index`.
+    pub async fn drop_index(&self, name: &str) -> Result<bool, TransientError> {
+        let name = name.to_string();
+        self.run(move |db| db.drop_index(&name)).await
+    }
+
+    /// Async version of `DB::query_index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::query_index`.
+    pub async fn query_index<T: AsRef<[u8]>>(
+        &self,
+        name: &str,
+        term: &T
+    ) -> Result<Vec<Vec<u8>>, TransientError> {
+        let (name, term) = (name.to_string(), bytes(term));
+        self.run(move |db| db.query_index(&name, &term)).await
+    }
+
+    /// Async version of `DB::rebuild_index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::rebuild_index`.
+    pub async fn rebuild_index(&self, name: &str) -> Result<(), TransientError> {
+        let name = name.to_string();
+        self.run(move |db| db.rebuild_index(&name)).await
+    }
+
+    /// Async version of `DB::keys`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::keys`.
+    pub async fn keys<P: AsRef<[u8]>>(&self, pattern: &P) -> Result<Vec<Vec<u8>>, TransientError> {
+        let pattern = bytes(pattern);
+        self.run(move |db| db.keys(&pattern)).await
+    }
+
+    /// Async version of `DB::scan`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::scan`.
+    pub async fn scan(
+        &self,
+        cursor: &[u8],
+        pattern: Option<&[u8]>,
+        count: usize
+    ) -> Result<ScanPage, TransientError> {
+        let (cursor, pattern) = (cursor.to_vec(), pattern.map(<[u8]>::to_vec));
+        self.run(move |db| db.scan(&cursor, pattern.as_deref(), count))
+            .await
+    }
+
+    /// Async version of `DB::stats`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::stats`.
+    pub async fn stats(&self) -> Result<DBStats, TransientError> {
+        self.run(|db| db.stats()).await
+    }
+
+    /// Async version of `DB::storage_stats`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::storage_stats`.
+    pub async fn storage_stats(&self) -> Result<StorageStats, TransientError> {
+        self.run(|db| db.storage_stats()).await
+    }
+
+    /// Async version of `DB::key_info`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::key_info`.
+    pub async fn key_info<K: AsRef<[u8]>>(
+        &self,
+        key: &K
+    ) -> Result<Option<KeyInfo>, TransientError> {
+        let key = bytes(key);
+        self.run(move |db| db.key_info(&key)).await
+    }
+
+    /// Async version of `DB::big_keys`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `DB::big_keys`.
+    pub async fn big_keys(&self, n: usize) -> Result<Vec<PrefixReport>, TransientError> {
+        self.run(move |db| db.big_keys(n)).await
+    }
+}
+
+/// Runs the closure on the blocking thread pool, a panic in the closure is
+/// resumed in the caller.
+async fn spawn<T, F>(f: F) -> Result<T, TransientError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static
+{
+    match task::spawn_blocking(f).await {
+        Ok(t) => Ok(t),
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(TransientError::TaskCancelled)
+    }
+}
+
+fn bytes<B: AsRef<[u8]> + ?Sized>(b: &B) -> Vec<u8> {
+    b.as_ref().to_vec()
+}
+
+fn all_bytes<B: AsRef<[u8]>>(all: &[B]) -> Vec<Vec<u8>> {
+    all.iter().map(bytes).collect()
+}
+
+fn all_pairs<F: AsRef<[u8]>, V: AsRef<[u8]>>(pairs: &[(F, V)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    pairs.iter().map(|(f, v)| (bytes(f), bytes(v))).collect()
+}
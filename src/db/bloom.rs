@@ -0,0 +1,223 @@
+//! This module defines the `BloomFilter`, an optional in-memory filter which
+//! lets `get` and `get_metadata` answer that a key is absent without reading
+//! the storage.
+//!
+//! It is a counting Bloom filter, every slot is a small counter instead of a
+//! bit, so keys can also be removed from it when they are removed or expire.
+
+use std::hash::{
+    DefaultHasher,
+    Hash,
+    Hasher
+};
+use std::sync::RwLock;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering
+};
+
+use crate::db::errors::TransientError;
+use crate::metrics::Metrics;
+
+/// The sizing of the `BloomFilter`, passed to `DBConfig::bloom_filter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomConfig {
+    /// How many keys the filter is sized for
+    pub expected_keys: usize,
+    /// The targeted false positive rate once `expected_keys` keys are stored
+    pub false_positive_rate: f64
+}
+
+impl BloomConfig {
+    /// Creates a config sized for `expected_keys` keys, with a targeted false
+    /// positive rate of 1%.
+    pub fn new(expected_keys: usize) -> BloomConfig {
+        BloomConfig {
+            expected_keys,
+            false_positive_rate: 0.01
+        }
+    }
+
+    /// Sets the targeted false positive rate, between 0 and 1 exclusive.
+    pub fn false_positive_rate(mut self, rate: f64) -> BloomConfig {
+        self.false_positive_rate = rate;
+        self
+    }
+}
+
+/// A counting Bloom filter over the keys of the database.
+///
+/// A negative answer is always right, a positive one may be a false positive,
+/// which `record_miss` tracks to report the observed false positive rate.
+///
+/// To never answer absent for a present key, a key has to be inserted before
+/// the write adding it commits, and removed after the write removing it
+/// commits. An insertion which turns out useless only costs false positives.
+#[derive(Debug)]
+pub struct BloomFilter {
+    counters: RwLock<Vec<u8>>,
+    /// Incremented by every rebuild
+    epoch: AtomicU64,
+    /// Amount of slots set per key
+    hashes: u32,
+    /// Lookups of absent keys the filter answered
+    negatives: AtomicU64,
+    /// Lookups of absent keys the filter let through
+    false_positives: AtomicU64
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized according to the config.
+    pub fn new(config: BloomConfig) -> BloomFilter {
+        let n = config.expected_keys.max(1) as f64;
+        let p = config.false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+
+        let slots = (-(n * p.ln()) / (ln2 * ln2)).ceil().max(8.0);
+        let hashes = ((slots / n) * ln2).round().max(1.0);
+
+        BloomFilter {
+            counters: RwLock::new(vec![0; slots as usize]),
+            hashes: hashes as u32,
+            epoch: AtomicU64::new(0),
+            negatives: AtomicU64::new(0),
+            false_positives: AtomicU64::new(0)
+        }
+    }
+
+    /// Adds a key to the filter.
+    pub fn insert(&self, key: &[u8]) {
+        if let Ok(mut counters) = self.counters.write() {
+            for i in self.slots(key, counters.len()) {
+                counters[i] = counters[i].saturating_add(1);
+            }
+        }
+    }
+
+    /// Adds the key again if the filter was rebuilt since `epoch` was taken,
+    /// this has to be called once the write adding a key commits, since the
+    /// rebuild may have dropped the insertion done before the commit.
+    pub fn confirm_insert(&self, key: &[u8], epoch: u64) {
+        if let Ok(mut counters) = self.counters.write()
+            && self.epoch() != epoch
+        {
+            for i in self.slots(key, counters.len()) {
+                counters[i] = counters[i].saturating_add(1);
+            }
+        }
+    }
+
+    /// Returns the current epoch, to be taken before a write adding or removing
+    /// a key and passed to `confirm_insert` or `remove`.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Removes a key from the filter, the key must have been inserted before.
+    ///
+    /// Nothing is removed if the filter was rebuilt since `epoch` was taken,
+    /// since the rebuild may not have seen the key.
+    pub fn remove(&self, key: &[u8], epoch: u64) {
+        if let Ok(mut counters) = self.counters.write() {
+            // Checked under the lock, rebuilds also take it
+            if self.epoch() != epoch {
+                return;
+            }
+
+            for i in self.slots(key, counters.len()) {
+                // NOTE: A saturated counter could be shared with other keys, so it is left
+                // as is
+                if counters[i] != u8::MAX {
+                    counters[i] = counters[i].saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Returns false if the key is definitely absent, true if it may be
+    /// present.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        match self.counters.read() {
+            Ok(counters) => self.slots(key, counters.len()).all(|i| counters[i] > 0),
+            Err(_) => true
+        }
+    }
+
+    /// Records the outcome of a lookup of an absent key, `filtered` being true
+    /// if the filter answered it, and reports the false positive rate.
+    pub fn record_miss(&self, filtered: bool) {
+        if filtered {
+            self.negatives.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.false_positives.fetch_add(1, Ordering::Relaxed);
+            Metrics::increment_bloom_false_positives();
+        }
+        Metrics::set_bloom_false_positive_rate(self.false_positive_rate());
+    }
+
+    /// Returns the ratio of lookups of absent keys which the filter let
+    /// through.
+    pub fn false_positive_rate(&self) -> f64 {
+        let fp = self.false_positives.load(Ordering::Relaxed);
+        let total = fp + self.negatives.load(Ordering::Relaxed);
+
+        if total == 0 {
+            return 0.0;
+        }
+
+        fp as f64 / total as f64
+    }
+
+    /// Empties the filter and inserts every key of the iterator.
+    ///
+    /// The filter is locked for the whole rebuild, and bumps the epoch so the
+    /// writes racing with it are applied again or skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of the iterator, the filter is then left
+    /// answering true for every key until the next successful rebuild.
+    pub fn rebuild<I>(&self, keys: I) -> Result<(), TransientError>
+    where
+        I: Iterator<Item = Result<Vec<u8>, TransientError>>
+    {
+        let mut counters = self
+            .counters
+            .write()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        counters.iter_mut().for_each(|c| *c = 0);
+
+        for key in keys {
+            let key = match key {
+                Ok(k) => k,
+                Err(e) => {
+                    counters.iter_mut().for_each(|c| *c = u8::MAX);
+                    return Err(e);
+                }
+            };
+
+            for i in self.slots(&key, counters.len()) {
+                counters[i] = counters[i].saturating_add(1);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the slots of a key, with double hashing.
+    fn slots(&self, key: &[u8], len: usize) -> impl Iterator<Item = usize> {
+        let h1 = hash_with_seed(key, 0);
+        let h2 = hash_with_seed(key, 1) | 1;
+
+        (0..self.hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len as u64) as usize)
+    }
+}
+
+fn hash_with_seed(key: &[u8], seed: u8) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,163 @@
+//! This module defines the `HotCache`, an optional in-process cache of values
+//! placed in front of the `data_tree`.
+//!
+//! The cache is frequency aware, in the spirit of TinyLFU: when it is full, a
+//! key is only admitted if its access frequency (the `Metadata.freq` of the
+//! key) is higher than the frequency of the coldest cached key, which is then
+//! evicted. So a burst of one-off reads never flushes out the hot keys.
+
+use std::collections::{
+    BTreeSet,
+    HashMap
+};
+use std::sync::Mutex;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering
+};
+
+use crate::metrics::Metrics;
+
+/// A frequency aware cache of values.
+#[derive(Debug)]
+pub struct HotCache {
+    /// Maximum amount of cached keys
+    capacity: usize,
+    inner: Mutex<CacheInner>,
+    /// Incremented on every invalidation, so a value read before a write is
+    /// never cached after it
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64
+}
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    /// key -> (value, frequency)
+    entries: HashMap<Vec<u8>, (Vec<u8>, u64)>,
+    /// (frequency, key), ordered so the coldest key comes first
+    by_freq: BTreeSet<(u64, Vec<u8>)>
+}
+
+impl HotCache {
+    /// Creates an empty cache holding at most `capacity` keys.
+    pub fn new(capacity: usize) -> HotCache {
+        HotCache {
+            capacity,
+            inner: Mutex::new(CacheInner::default()),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0)
+        }
+    }
+
+    /// Returns the cached value of the key, and records the hit or miss.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let res = match self.inner.lock() {
+            Ok(mut inner) => inner.hit(key),
+            Err(_) => None
+        };
+
+        if res.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            Metrics::increment_cache_hits();
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            Metrics::increment_cache_misses();
+        }
+        Metrics::set_cache_hit_ratio(self.hit_ratio());
+
+        res
+    }
+
+    /// Returns the current generation, to be passed to `admit` after reading
+    /// the value from the storage.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Offers a value read from the storage to the cache.
+    ///
+    /// The value is dropped if any key was invalidated since `generation` was
+    /// taken, or if the cache is full and the key is colder than every cached
+    /// key.
+    pub fn admit(&self, key: &[u8], value: &[u8], freq: u64, generation: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = match self.inner.lock() {
+            Ok(i) => i,
+            Err(_) => return
+        };
+
+        // Checked under the lock, invalidations also take it
+        if self.generation() != generation || inner.entries.contains_key(key) {
+            return;
+        }
+
+        if inner.entries.len() >= self.capacity {
+            let coldest = match inner.by_freq.first() {
+                Some(c) => c.clone(),
+                None => return
+            };
+
+            if coldest.0 >= freq {
+                return;
+            }
+
+            inner.by_freq.remove(&coldest);
+            inner.entries.remove(&coldest.1);
+        }
+
+        inner.by_freq.insert((freq, key.to_vec()));
+        inner.entries.insert(key.to_vec(), (value.to_vec(), freq));
+    }
+
+    /// Removes the key from the cache, this has to be called whenever the
+    /// value of the key changes or the key is removed.
+    pub fn invalidate(&self, key: &[u8]) {
+        if let Ok(mut inner) = self.inner.lock() {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+
+            if let Some((_, freq)) = inner.entries.remove(key) {
+                inner.by_freq.remove(&(freq, key.to_vec()));
+            }
+        }
+    }
+
+    /// Returns the amount of cached keys.
+    pub fn len(&self) -> usize {
+        self.inner.lock().map(|i| i.entries.len()).unwrap_or(0)
+    }
+
+    /// Returns true if no key is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the ratio of lookups which were served by the cache.
+    pub fn hit_ratio(&self) -> f64 {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let total = hits + self.misses.load(Ordering::Relaxed);
+
+        if total == 0 {
+            return 0.0;
+        }
+
+        hits as f64 / total as f64
+    }
+}
+
+impl CacheInner {
+    /// Returns the value of the key and bumps its frequency.
+    fn hit(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let (value, freq) = self.entries.get_mut(key)?;
+
+        self.by_freq.remove(&(*freq, key.to_vec()));
+        *freq += 1;
+        self.by_freq.insert((*freq, key.to_vec()));
+
+        Some(value.clone())
+    }
+}
@@ -0,0 +1,358 @@
+//! This module defines the cache-aside helpers, `DB::get_or_insert_with` and
+//! `DB::get_or_load`, which return a cached value or load it, making sure
+//! concurrent callers missing the same key only load it once.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{
+    Debug,
+    Display
+};
+use std::sync::{
+    Arc,
+    Condvar,
+    Mutex
+};
+use std::time::Duration;
+
+use bincode::serde::{
+    decode_from_slice,
+    encode_to_vec
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::DB;
+use crate::db::clock::millis_after;
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+
+/// The prefix of the keys holding the freshness of the cached values,
+/// followed by the key.
+pub const CACHE_ASIDE_PREFIX: &[u8] = b"cache_aside:";
+
+/// How `DB::get_or_load` caches the loaded values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheAsideOptions {
+    /// How long a loaded value is fresh, `None` keeps it forever
+    pub ttl: Option<Duration>,
+    /// How long a value is still served after it went stale, while a single
+    /// caller reloads it
+    pub stale_while_revalidate: Option<Duration>,
+    /// How long a "not found" from the loader is cached, `None` doesn't cache
+    /// it
+    pub negative_ttl: Option<Duration>
+}
+
+impl CacheAsideOptions {
+    /// Creates options caching values for `ttl`, without stale values nor
+    /// negative caching.
+    pub fn new(ttl: Option<Duration>) -> CacheAsideOptions {
+        CacheAsideOptions {
+            ttl,
+            ..CacheAsideOptions::default()
+        }
+    }
+
+    /// Sets how long stale values are served while being reloaded.
+    pub fn stale_while_revalidate(mut self, window: Duration) -> CacheAsideOptions {
+        self.stale_while_revalidate = Some(window);
+        self
+    }
+
+    /// Sets how long a "not found" is cached.
+    pub fn negative_ttl(mut self, ttl: Duration) -> CacheAsideOptions {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+}
+
+/// The error returned by the cache-aside helpers, which keeps the error of the
+/// loader apart from the errors of the database itself.
+#[derive(Debug)]
+pub enum LoadError<E> {
+    /// The loader failed with its own error.
+    Loader(E),
+    /// The database failed.
+    Transient(TransientError)
+}
+
+impl<E> From<TransientError> for LoadError<E> {
+    fn from(value: TransientError) -> Self {
+        LoadError::Transient(value)
+    }
+}
+
+impl<E: Display> Display for LoadError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Loader(e) => writeln!(f, "Loader failed {e}"),
+            LoadError::Transient(e) => write!(f, "{e}")
+        }
+    }
+}
+
+impl<E: Display + Debug> Error for LoadError<E> {}
+
+/// The freshness of a cached value, stored under `CACHE_ASIDE_PREFIX`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Freshness {
+    /// In milliseconds since the UNIX epoch
+    fresh_until: u64,
+    /// True if the loader found nothing
+    negative: bool
+}
+
+/// What the cache holds for a key.
+enum Lookup {
+    Fresh(Option<Vec<u8>>),
+    Stale(Vec<u8>),
+    Missing
+}
+
+/// Tracks the keys being loaded, so concurrent callers wait for the first
+/// one instead of loading the key again.
+#[derive(Debug, Default)]
+pub(crate) struct SingleFlight {
+    flights: Mutex<HashMap<Vec<u8>, Arc<Flight>>>
+}
+
+#[derive(Debug, Default)]
+struct Flight {
+    done: Mutex<bool>,
+    finished: Condvar
+}
+
+/// Held by the caller loading a key, ends the flight when dropped, even if
+/// the loader panicked.
+struct FlightGuard<'a> {
+    single_flight: &'a SingleFlight,
+    key: Vec<u8>,
+    flight: Arc<Flight>
+}
+
+impl SingleFlight {
+    /// Starts a flight for the key, or returns the flight already running.
+    fn join(&self, key: &[u8]) -> Result<Result<FlightGuard<'_>, Arc<Flight>>, TransientError> {
+        let mut flights = self
+            .flights
+            .lock()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        if let Some(f) = flights.get(key) {
+            return Ok(Err(Arc::clone(f)));
+        }
+
+        let flight = Arc::new(Flight::default());
+        flights.insert(key.to_vec(), Arc::clone(&flight));
+
+        Ok(Ok(FlightGuard {
+            single_flight: self,
+            key: key.to_vec(),
+            flight
+        }))
+    }
+}
+
+impl Flight {
+    fn wait(&self) -> Result<(), TransientError> {
+        let done = self
+            .done
+            .lock()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+        let _done = self
+            .finished
+            .wait_while(done, |d| !*d)
+            .map_err(|_| TransientError::PoisonedMutex)?;
+        Ok(())
+    }
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut flights) = self.single_flight.flights.lock() {
+            flights.remove(&self.key);
+        }
+        if let Ok(mut done) = self.flight.done.lock() {
+            *done = true;
+        }
+        self.flight.finished.notify_all();
+    }
+}
+
+impl DB {
+    /// Returns the value of the key, or calls the loader, stores its value
+    /// with the TTL and returns it.
+    ///
+    /// Concurrent callers missing the same key wait for the first one, so the
+    /// loader is called once. If that call fails, the waiting callers call
+    /// their own loader.
+    ///
+    /// # Errors
+    ///
+    /// Returns `LoadError::Loader` if the loader fails, or
+    /// `LoadError::Transient` if the database fails.
+    pub fn get_or_insert_with<K, V, E, F>(
+        &self,
+        key: &K,
+        ttl: Option<Duration>,
+        loader: F
+    ) -> Result<Vec<u8>, LoadError<E>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+        F: FnOnce() -> Result<V, E>
+    {
+        let value = self.get_or_load(key, &CacheAsideOptions::new(ttl), || {
+            loader().map(|v| Some(v.as_ref().to_vec()))
+        })?;
+
+        // The loader never returns "not found", so a value is always there
+        value.ok_or(LoadError::Transient(TransientError::ValueNotFound))
+    }
+
+    /// Returns the value of the key, or calls the loader and caches what it
+    /// found according to the options, `None` meaning the loader found
+    /// nothing.
+    ///
+    /// Like `get_or_insert_with`, concurrent callers missing the same key
+    /// wait for the first one. If the value is stale but within the
+    /// `stale_while_revalidate` window, only the first caller reloads it and
+    /// the other ones get the stale value right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns `LoadError::Loader` if the loader fails, or
+    /// `LoadError::Transient` if the database fails.
+    pub fn get_or_load<K, E, F>(
+        &self,
+        key: &K,
+        options: &CacheAsideOptions,
+        loader: F
+    ) -> Result<Option<Vec<u8>>, LoadError<E>>
+    where
+        K: AsRef<[u8]>,
+        F: FnOnce() -> Result<Option<Vec<u8>>, E>
+    {
+        let key = key.as_ref();
+
+        loop {
+            let stale = match self.lookup(key)? {
+                Lookup::Fresh(v) => return Ok(v),
+                Lookup::Stale(v) => Some(v),
+                Lookup::Missing => None
+            };
+
+            match self.single_flight.join(key)? {
+                Ok(_guard) => {
+                    // Another caller may have loaded it before the flight started
+                    if let Lookup::Fresh(v) = self.lookup(key)? {
+                        return Ok(v);
+                    }
+
+                    let value = loader().map_err(LoadError::Loader)?;
+                    self.store_loaded(key, &value, options)?;
+                    return Ok(value);
+                },
+                Err(flight) => {
+                    if let Some(v) = stale {
+                        return Ok(Some(v));
+                    }
+                    flight.wait()?;
+                }
+            }
+        }
+    }
+
+    /// Returns what the cache holds for the key.
+    fn lookup(&self, key: &[u8]) -> Result<Lookup, TransientError> {
+        let now = self.clock.now_millis();
+        let freshness = match self.get_raw(&freshness_key(key))? {
+            Some(f) => {
+                Some(
+                    decode_from_slice::<Freshness, _>(&f, bincode::config::standard())
+                        .map_err(|_| TransientError::ParsingFromByteError)?
+                        .0
+                )
+            },
+            None => None
+        };
+
+        match (self.get_raw(&key)?, freshness) {
+            // Written by a plain `set`, or the freshness already expired
+            (Some(v), None) => Ok(Lookup::Fresh(Some(v))),
+            (Some(v), Some(f)) if f.negative || now < f.fresh_until => Ok(Lookup::Fresh(Some(v))),
+            (Some(v), Some(_)) => Ok(Lookup::Stale(v)),
+            (None, Some(f)) if f.negative && now < f.fresh_until => Ok(Lookup::Fresh(None)),
+            (None, _) => Ok(Lookup::Missing)
+        }
+    }
+
+    /// Stores what the loader returned along with its freshness.
+    fn store_loaded(
+        &self,
+        key: &[u8],
+        value: &Option<Vec<u8>>,
+        options: &CacheAsideOptions
+    ) -> Result<(), TransientError> {
+        let now = self.clock.now_millis();
+        let fresh_key = freshness_key(key);
+
+        self.transaction(|tx| {
+            match value {
+                Some(v) => {
+                    let ttl = options.ttl.map(|t| {
+                        t.saturating_add(options.stale_while_revalidate.unwrap_or_default())
+                    });
+                    let freshness = Freshness {
+                        fresh_until: options.ttl.map_or(u64::MAX, |t| millis_after(now, t)),
+                        negative: false
+                    };
+
+                    tx.set_raw(&key, v, ttl)?;
+                    match options.ttl {
+                        Some(_) => tx.set_raw(&fresh_key, &encode(&freshness)?, ttl)?,
+                        None if tx.exists(&fresh_key)? => tx.remove_raw(&fresh_key)?,
+                        None => ()
+                    }
+                },
+                None => {
+                    if tx.exists(&key)? {
+                        tx.remove_raw(&key)?;
+                    }
+
+                    match options.negative_ttl {
+                        Some(t) => {
+                            let freshness = Freshness {
+                                fresh_until: millis_after(now, t),
+                                negative: true
+                            };
+                            // Rounded up, and one more second since the TTL thread rounds down
+                            let ttl = Duration::from_secs(
+                                (freshness.fresh_until - now).div_ceil(1000) + 1
+                            );
+                            tx.set_raw(&fresh_key, &encode(&freshness)?, Some(ttl))?;
+                        },
+                        None if tx.exists(&fresh_key)? => tx.remove_raw(&fresh_key)?,
+                        None => ()
+                    }
+                }
+            }
+            Ok(())
+        })
+        .map_err(TransactionError::into_transient)
+    }
+}
+
+fn freshness_key(key: &[u8]) -> Vec<u8> {
+    [CACHE_ASIDE_PREFIX, key].concat()
+}
+
+fn encode(freshness: &Freshness) -> Result<Vec<u8>, TransientError> {
+    encode_to_vec(freshness, bincode::config::standard())
+        .map_err(|_| TransientError::ParsingToByteError)
+}
@@ -0,0 +1,88 @@
+//! This module defines the `Clock` trait, which every TTL, lease, session and
+//! rate limit reads the time from.
+//!
+//! The database uses the `SystemClock` by default, tests can pass a
+//! `MockClock` to `DBConfig::clock` and advance it by hand, so expirations
+//! happen instantly and deterministically instead of after a real sleep.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering
+};
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH
+};
+
+/// A source of the current time.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the time elapsed since the UNIX epoch.
+    fn now(&self) -> Duration;
+
+    /// Returns the current time, in seconds since the UNIX epoch.
+    fn now_secs(&self) -> u64 {
+        self.now().as_secs()
+    }
+
+    /// Returns the current time, in milliseconds since the UNIX epoch.
+    fn now_millis(&self) -> u64 {
+        self.now().as_millis() as u64
+    }
+}
+
+/// Returns the timestamp, in milliseconds, `after` the timestamp `from`,
+/// clamped to `u64::MAX` instead of overflowing on the longest durations.
+pub(crate) fn millis_after(from: u64, after: Duration) -> u64 {
+    from.saturating_add(u64::try_from(after.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// The clock of the system, the default one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Cant get SystemTime")
+    }
+}
+
+/// A clock which only moves when it is told to, for tests.
+///
+/// Clones share the same time, so a test can keep a clone to advance the
+/// clock it gave to the database.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    /// The current time, in milliseconds since the UNIX epoch
+    millis: Arc<AtomicU64>
+}
+
+impl MockClock {
+    /// Creates a clock set to `now`, the time elapsed since the UNIX epoch.
+    pub fn new(now: Duration) -> MockClock {
+        MockClock {
+            millis: Arc::new(AtomicU64::new(now.as_millis() as u64))
+        }
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, by: Duration) {
+        self.millis
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Sets the clock to `now`, the time elapsed since the UNIX epoch.
+    pub fn set(&self, now: Duration) {
+        self.millis.store(now.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.millis.load(Ordering::SeqCst))
+    }
+}
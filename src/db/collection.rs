@@ -0,0 +1,234 @@
+//! This module defines the collections, values made of members which are
+//! stored one by one in the `Members` tree, so changing a member never
+//! rewrites the whole value.
+//!
+//! The key of a collection is a regular key, with its own `Metadata` and TTL,
+//! whose value is the header of the collection. The id of the collection is
+//! kept in the `ValueFormat` of the metadata, never in the value, so a plain
+//! value can never be read as a collection nor the other way around. The
+//! members are stored under
+//! the random id of the collection instead of its key, so the members left by
+//! a removed or expired collection are never seen by a new collection of the
+//! same key, even before they are purged.
+
+use std::ops::Bound;
+
+use bincode::serde::{
+    decode_from_slice,
+    encode_to_vec
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::db::errors::TransientError;
+use crate::db::storage::{
+    StorageBackend,
+    TreeKind
+};
+use crate::db::stream::StreamId;
+use crate::db::transaction::TransactionalGuard;
+use crate::{
+    DB,
+    ValueFormat
+};
+
+/// A collection, whose kind is stored as the value of its key and id in the
+/// metadata of its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Collection {
+    /// The random id the members are stored under
+    pub(crate) id: u64,
+    pub(crate) kind: CollectionKind
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum CollectionKind {
+    /// The members are stored under their position, from `head` included to
+    /// `tail` excluded
+    List { head: u64, tail: u64 },
+    /// The members are stored under their field
+    Hash { len: u64 },
+    /// The members are stored with their score and in a score index, see
+    /// the `sorted_set` module
+    SortedSet { len: u64 },
+    /// The members are the entries, the consumer groups and their pending
+    /// entries, see the `stream` module
+    Stream { len: u64, last_id: StreamId }
+}
+
+impl Collection {
+    /// Creates an empty collection with a new random id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RandomSourceFailed` if the OS fails to provide random bytes.
+    pub(crate) fn new(kind: CollectionKind) -> Result<Collection, TransientError> {
+        let mut bytes = [0u8; 8];
+        getrandom::fill(&mut bytes).map_err(|_| TransientError::RandomSourceFailed)?;
+
+        Ok(Collection {
+            id: u64::from_be_bytes(bytes),
+            kind
+        })
+    }
+
+    /// Returns the header of the collection, stored as the value of its key.
+    pub(crate) fn to_u8(&self) -> Result<Vec<u8>, TransientError> {
+        encode_to_vec(&self.kind, bincode::config::standard())
+            .map_err(|_| TransientError::ParsingToByteError)
+    }
+
+    /// Reads the header of the collection with the id.
+    pub(crate) fn from_u8(id: u64, header: &[u8]) -> Result<Collection, TransientError> {
+        let (kind, _) = decode_from_slice(header, bincode::config::standard())
+            .map_err(|_| TransientError::ParsingFromByteError)?;
+
+        Ok(Collection {
+            id,
+            kind
+        })
+    }
+
+    /// Returns the format the header is stored in.
+    pub(crate) fn format(&self) -> ValueFormat {
+        ValueFormat {
+            collection: Some(self.id),
+            ..ValueFormat::default()
+        }
+    }
+
+    /// Returns the key of a member in the `Members` tree.
+    pub(crate) fn member_key(&self, member: &[u8]) -> Vec<u8> {
+        [&self.id.to_be_bytes()[..], member].concat()
+    }
+}
+
+/// Returns the id of the collection whose members have to be purged after the
+/// format of a key changed from `old` to `new`, `None` meaning the key does
+/// not exist.
+pub(crate) fn dropped_collection(
+    old: Option<ValueFormat>,
+    new: Option<ValueFormat>
+) -> Option<u64> {
+    let old = old?.collection?;
+
+    (new.and_then(|f| f.collection) != Some(old)).then_some(old)
+}
+
+/// Removes every member of the collection.
+///
+/// The members are not removed atomically, which is fine since no header
+/// points to the id anymore.
+///
+/// # Errors
+///
+/// Returns an error if the members cannot be read or removed.
+pub(crate) fn purge_members(storage: &dyn StorageBackend, id: u64) -> Result<(), TransientError> {
+    let start = id.to_be_bytes();
+    let end = id.checked_add(1).map(u64::to_be_bytes);
+    let end = match &end {
+        Some(e) => Bound::Excluded(&e[..]),
+        None => Bound::Unbounded
+    };
+
+    for i in storage.range(TreeKind::Members, Bound::Included(&start[..]), end) {
+        storage.remove(TreeKind::Members, &i?.0)?;
+    }
+
+    Ok(())
+}
+
+impl TransactionalGuard<'_> {
+    /// Returns the collection stored at the key, `None` if the key does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds a plain value.
+    pub(crate) fn get_collection<K: AsRef<[u8]>>(
+        &mut self,
+        key: &K
+    ) -> Result<Option<Collection>, TransientError> {
+        match self.get_value(key.as_ref())? {
+            Some((header, format)) => {
+                let id = format.collection.ok_or(TransientError::WrongType)?;
+                Ok(Some(Collection::from_u8(id, &header)?))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Writes the header of the collection, a new key is created without a
+    /// TTL, an existing one keeps its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header cannot be serialized or written.
+    pub(crate) fn set_collection<K: AsRef<[u8]>>(
+        &mut self,
+        key: &K,
+        collection: &Collection
+    ) -> Result<(), TransientError> {
+        let header = collection.to_u8()?;
+
+        if self.exists(key)? {
+            self.replace_value(key.as_ref(), &header, collection.format())
+        } else {
+            self.write_value(key.as_ref(), &header, collection.format(), None)
+        }
+    }
+}
+
+impl DB {
+    /// Returns the collection stored at the key, `None` if the key does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds a plain value.
+    pub(crate) fn get_collection<K: AsRef<[u8]>>(
+        &self,
+        key: &K
+    ) -> Result<Option<Collection>, TransientError> {
+        match self.get_value(key.as_ref())? {
+            Some((header, format)) => {
+                let id = format.collection.ok_or(TransientError::WrongType)?;
+                Ok(Some(Collection::from_u8(id, &header)?))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Returns the member stored under the key, decoded.
+    pub(crate) fn get_member(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        self.storage
+            .get(TreeKind::Members, key)?
+            .map(|v| self.values.decode_member(key, &v))
+            .transpose()
+    }
+
+    /// Returns an ordered iterator over the members of the collection, whose
+    /// keys are stripped of the id.
+    pub(crate) fn members(
+        &self,
+        collection: &Collection
+    ) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), TransientError>> {
+        self.prefix_range(TreeKind::Members, &collection.id.to_be_bytes())
+            .map(|i| {
+                let (k, v) = i?;
+                let value = self.values.decode_member(&k, &v)?;
+                Ok((k[8..].to_vec(), value))
+            })
+    }
+
+    /// Removes the members of the collections which were dropped.
+    pub(crate) fn purge_collections(&self, ids: &[u64]) -> Result<(), TransientError> {
+        for id in ids {
+            purge_members(self.storage.as_ref(), *id)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+//! This module defines the transparent compression of the values, configured
+//! for the whole database or for the keys starting with a prefix, see
+//! `DBConfig::compression` and `DBConfig::namespace_compression`.
+//!
+//! The id of the codec a value was compressed with is kept in the
+//! `ValueFormat` of its metadata, never in the value, so a value is only ever
+//! decompressed if it was compressed. Compressed and uncompressed values
+//! coexist: enabling, disabling or changing the compression only applies to
+//! the values written from then on, and the values written before stay
+//! readable.
+//!
+//! The built-in codecs are behind cargo features, `zstd` for [`Zstd`] and
+//! `deflate` for [`Deflate`]. Any other algorithm can be plugged in by
+//! implementing [`Codec`].
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering
+};
+
+use crate::db::errors::TransientError;
+use crate::metrics::Metrics;
+
+/// The values shorter than this are stored as they are by default.
+pub const DEFAULT_MIN_SIZE: usize = 512;
+
+/// A compression algorithm.
+///
+/// The id is stored with every value the codec compressed, so it has to stay
+/// the same for as long as such values exist, and be unique among the codecs
+/// of a database. The ids below 16 are reserved for the built-in codecs.
+pub trait Codec: Debug + Send + Sync {
+    /// The id stored in the metadata of the values the codec compressed.
+    fn id(&self) -> u8;
+
+    /// The name of the codec, used as the label of its metrics.
+    fn name(&self) -> &str;
+
+    /// Compresses the value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CompressionFailed` if the value cannot be compressed.
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError>;
+
+    /// Decompresses a value the codec compressed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DecompressionFailed` if the value is corrupted.
+    fn decompress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError>;
+}
+
+/// The zstd codec, with id 1.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zstd {
+    /// The compression level, from 1 to 22, 0 picks zstd's default
+    pub level: i32
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Zstd {
+        Zstd {
+            level: 3
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn name(&self) -> &str {
+        "zstd"
+    }
+
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError> {
+        zstd::encode_all(value, self.level).map_err(|_| TransientError::CompressionFailed)
+    }
+
+    fn decompress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError> {
+        zstd::decode_all(value).map_err(|_| TransientError::DecompressionFailed)
+    }
+}
+
+/// The deflate codec, with id 2.
+#[cfg(feature = "deflate")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deflate {
+    /// The compression level, from 0 to 9
+    pub level: u32
+}
+
+#[cfg(feature = "deflate")]
+impl Default for Deflate {
+    fn default() -> Deflate {
+        Deflate {
+            level: 6
+        }
+    }
+}
+
+#[cfg(feature = "deflate")]
+impl Codec for Deflate {
+    fn id(&self) -> u8 {
+        2
+    }
+
+    fn name(&self) -> &str {
+        "deflate"
+    }
+
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError> {
+        use std::io::Write;
+
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::new(self.level));
+        encoder
+            .write_all(value)
+            .and_then(|_| encoder.finish())
+            .map_err(|_| TransientError::CompressionFailed)
+    }
+
+    fn decompress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError> {
+        use std::io::Read;
+
+        let mut decompressed = Vec::new();
+        flate2::read::DeflateDecoder::new(value)
+            .read_to_end(&mut decompressed)
+            .map_err(|_| TransientError::DecompressionFailed)?;
+
+        Ok(decompressed)
+    }
+}
+
+/// The compression of the values, passed to `DBConfig::compression`.
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// The codec the values are compressed with
+    pub codec: Arc<dyn Codec>,
+    /// The values shorter than this are stored as they are
+    pub min_size: usize
+}
+
+impl CompressionConfig {
+    /// Compresses the values with the codec, from `DEFAULT_MIN_SIZE` bytes.
+    pub fn new<C: Codec + 'static>(codec: C) -> CompressionConfig {
+        CompressionConfig {
+            codec: Arc::new(codec),
+            min_size: DEFAULT_MIN_SIZE
+        }
+    }
+
+    /// Sets the size from which the values are compressed, the smaller ones
+    /// rarely get smaller.
+    pub fn min_size(mut self, min_size: usize) -> CompressionConfig {
+        self.min_size = min_size;
+        self
+    }
+}
+
+/// How much the values were compressed, returned by
+/// `DB::compression_stats`.
+///
+/// Only the values written since the database was opened are counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// The values stored compressed
+    pub compressed: u64,
+    /// The values stored as they are while compression applied to their key,
+    /// because they were below the minimum size or did not get smaller
+    pub skipped: u64,
+    /// The length of the compressed values before compression
+    pub raw_bytes: u64,
+    /// The length of the compressed values once stored
+    pub stored_bytes: u64
+}
+
+impl CompressionStats {
+    /// Returns how many times smaller the compressed values got, 1 if no
+    /// value was compressed.
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            return 1.0;
+        }
+
+        self.raw_bytes as f64 / self.stored_bytes as f64
+    }
+}
+
+/// Compresses the values on their way to the storage and decompresses them on
+/// their way back.
+#[derive(Debug, Default)]
+pub(crate) struct Compressor {
+    /// The compression of each prefix, the longest prefix first
+    rules: Vec<(Vec<u8>, CompressionConfig)>,
+    /// The codecs by id, which the values can be decompressed with
+    codecs: HashMap<u8, Arc<dyn Codec>>,
+    compressed: AtomicU64,
+    skipped: AtomicU64,
+    raw_bytes: AtomicU64,
+    stored_bytes: AtomicU64
+}
+
+impl Compressor {
+    /// Creates a compressor applying the compression of the longest matching
+    /// prefix, the empty prefix matching every key.
+    ///
+    /// Besides the configured codecs, the values can always be decompressed
+    /// with the built-in codecs which are enabled, so a database opened
+    /// without the compression configured, e.g. by `DB::load_from`, still
+    /// reads them.
+    pub(crate) fn new(mut rules: Vec<(Vec<u8>, CompressionConfig)>) -> Compressor {
+        rules.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+
+        let mut codecs: HashMap<u8, Arc<dyn Codec>> = HashMap::new();
+        #[cfg(feature = "zstd")]
+        codecs.insert(1, Arc::new(Zstd::default()));
+        #[cfg(feature = "deflate")]
+        codecs.insert(2, Arc::new(Deflate::default()));
+        for (_, config) in &rules {
+            codecs.insert(config.codec.id(), Arc::clone(&config.codec));
+        }
+
+        Compressor {
+            rules,
+            codecs,
+            ..Compressor::default()
+        }
+    }
+
+    /// Returns the value as it has to be stored under the key, compressed if
+    /// the compression applies to the key and the value got smaller, with the
+    /// id of the codec it was compressed with.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CompressionFailed` if the codec failed.
+    pub(crate) fn encode<'a>(
+        &self,
+        key: &[u8],
+        value: &'a [u8]
+    ) -> Result<(Cow<'a, [u8]>, Option<u8>), TransientError> {
+        let config = match self.rules.iter().find(|(p, _)| key.starts_with(p)) {
+            Some((_, c)) => c,
+            None => return Ok((Cow::Borrowed(value), None))
+        };
+
+        let codec = config.codec.as_ref();
+        if value.len() >= config.min_size {
+            let compressed = codec.compress(value)?;
+
+            if compressed.len() < value.len() {
+                self.compressed.fetch_add(1, Ordering::Relaxed);
+                let raw_bytes = self
+                    .raw_bytes
+                    .fetch_add(value.len() as u64, Ordering::Relaxed)
+                    + value.len() as u64;
+                let stored_bytes = self
+                    .stored_bytes
+                    .fetch_add(compressed.len() as u64, Ordering::Relaxed)
+                    + compressed.len() as u64;
+                Metrics::add_compression_bytes(codec.name(), value.len(), compressed.len());
+                Metrics::set_compression_ratio(raw_bytes as f64 / stored_bytes as f64);
+
+                return Ok((Cow::Owned(compressed), Some(codec.id())));
+            }
+        }
+
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        Metrics::increment_compression_skipped(codec.name());
+        Ok((Cow::Borrowed(value), None))
+    }
+
+    /// Returns the value as it was written, decompressed with the codec if it
+    /// was stored compressed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `UnknownCodec` if the codec the value was compressed with is
+    /// not configured, or `DecompressionFailed` if the value is corrupted.
+    pub(crate) fn decode<'a>(
+        &self,
+        value: &'a [u8],
+        codec: Option<u8>
+    ) -> Result<Cow<'a, [u8]>, TransientError> {
+        let id = match codec {
+            Some(id) => id,
+            None => return Ok(Cow::Borrowed(value))
+        };
+
+        let codec = self.codecs.get(&id).ok_or(TransientError::UnknownCodec {
+            id
+        })?;
+        Ok(Cow::Owned(codec.decompress(value)?))
+    }
+
+    /// Same as `decode`, without copying the uncompressed values.
+    pub(crate) fn decode_owned(
+        &self,
+        value: Vec<u8>,
+        codec: Option<u8>
+    ) -> Result<Vec<u8>, TransientError> {
+        if codec.is_none() {
+            return Ok(value);
+        }
+
+        Ok(self.decode(&value, codec)?.into_owned())
+    }
+
+    pub(crate) fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            compressed: self.compressed.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            raw_bytes: self.raw_bytes.load(Ordering::Relaxed),
+            stored_bytes: self.stored_bytes.load(Ordering::Relaxed)
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! This module defines the `DBConfig` struct, which holds every option of a
+//! `DB`.
+
+use std::sync::Arc;
+
+use crate::db::bloom::BloomConfig;
+use crate::db::clock::Clock;
+use crate::db::compression::CompressionConfig;
+use crate::db::durability::Durability;
+use crate::db::encryption::KeyProvider;
+
+/// The configuration of a `DB`, passed to `DB::with_config`.
+///
+/// Every option has a default, so only the needed ones have to be set:
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use epoch_db::db::config::DBConfig;
+/// use epoch_db::db::durability::Durability;
+///
+/// let config = DBConfig::new().durability(Durability::GroupCommit(Duration::from_millis(5)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DBConfig {
+    /// How writes are made durable
+    pub durability: Durability,
+    /// How many times a conflicting transaction is retried, `None` leaves it
+    /// to the backend, the built-in backends giving up after
+    /// `MAX_CONFLICT_RETRIES` retries
+    pub max_transaction_retries: Option<u32>,
+    /// How many keys the hot-key cache holds, `None` disables the cache
+    pub cache_capacity: Option<usize>,
+    /// The sizing of the bloom filter, `None` disables the filter
+    pub bloom_filter: Option<BloomConfig>,
+    /// Where the time is read from, `None` uses the `SystemClock`
+    pub clock: Option<Arc<dyn Clock>>,
+    /// The compression of the values by key prefix, the empty prefix for the
+    /// whole database, empty disables the compression
+    pub compression: Vec<(Vec<u8>, CompressionConfig)>,
+    /// Supplies the keys the values and the backups are encrypted with,
+    /// `None` disables the encryption
+    pub encryption: Option<Arc<dyn KeyProvider>>
+}
+
+impl DBConfig {
+    /// Creates a config with every option set to its default.
+    pub fn new() -> DBConfig {
+        DBConfig::default()
+    }
+
+    /// Sets the durability policy.
+    pub fn durability(mut self, durability: Durability) -> DBConfig {
+        self.durability = durability;
+        self
+    }
+
+    /// Sets how many times a conflicting transaction is retried before
+    /// `DB::transaction` gives up with `TransactionRetryLimitReached`.
+    ///
+    /// The backend bounds the retries as well, the built-in backends fail
+    /// with `TransactionConflict` after `MAX_CONFLICT_RETRIES` (100) retries
+    /// whatever the limit.
+    pub fn max_transaction_retries(mut self, retries: u32) -> DBConfig {
+        self.max_transaction_retries = Some(retries);
+        self
+    }
+
+    /// Enables the in-memory cache of hot keys, holding at most `capacity`
+    /// keys, see [`HotCache`](crate::db::cache::HotCache).
+    pub fn cache_capacity(mut self, capacity: usize) -> DBConfig {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Enables the bloom filter answering lookups of absent keys, see
+    /// [`BloomFilter`](crate::db::bloom::BloomFilter).
+    pub fn bloom_filter(mut self, bloom: BloomConfig) -> DBConfig {
+        self.bloom_filter = Some(bloom);
+        self
+    }
+
+    /// Sets the clock the database reads the time from, e.g. a
+    /// [`MockClock`](crate::db::clock::MockClock) in tests.
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> DBConfig {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Compresses the values of the whole database, see
+    /// [`compression`](crate::db::compression).
+    pub fn compression(self, compression: CompressionConfig) -> DBConfig {
+        self.namespace_compression(b"", compression)
+    }
+
+    /// Compresses the values of the keys starting with the prefix, e.g.
+    /// `fragment:`, which takes precedence over the compression of a shorter
+    /// prefix or of the whole database.
+    pub fn namespace_compression<P: AsRef<[u8]>>(
+        mut self,
+        prefix: P,
+        compression: CompressionConfig
+    ) -> DBConfig {
+        let prefix = prefix.as_ref().to_vec();
+        self.compression.retain(|(p, _)| *p != prefix);
+        self.compression.push((prefix, compression));
+        self
+    }
+
+    /// Encrypts the values and the backups with the keys of the provider, see
+    /// [`encryption`](crate::db::encryption).
+    pub fn encryption<P: KeyProvider + 'static>(mut self, provider: P) -> DBConfig {
+        self.encryption = Some(Arc::new(provider));
+        self
+    }
+}
@@ -0,0 +1,230 @@
+//! This module defines how writes are made durable, see [`Durability`].
+
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering
+};
+use std::sync::{
+    Arc,
+    Condvar,
+    Mutex
+};
+use std::thread::{
+    self,
+    JoinHandle
+};
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::DB;
+use crate::db::errors::TransientError;
+use crate::db::storage::StorageBackend;
+
+/// The policy used to make writes durable.
+///
+/// It only applies to the writes which changes the data (`set`, `remove` and
+/// `transaction`), frequency updates are always left to the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Rely on the backend to flush in the background, writes return as soon
+    /// as they are applied. This is the fastest and the default policy.
+    #[default]
+    Background,
+    /// Flush after every single write, writes return once they are on disk.
+    SyncEveryWrite,
+    /// Writes wait until the next flush, which happens at most every given
+    /// duration, so every write done within that window shares a single
+    /// flush.
+    GroupCommit(Duration),
+    /// Flush every given duration, writes return without waiting for it.
+    Periodic(Duration)
+}
+
+/// Tracks the writes which are waiting for a group commit.
+#[derive(Debug, Default)]
+pub struct GroupCommit {
+    state: Mutex<GroupCommitState>,
+    flushed: Condvar
+}
+
+#[derive(Debug, Default)]
+struct GroupCommitState {
+    /// Sequence number of the last write
+    written: u64,
+    /// Sequence number of the last write which is on disk
+    flushed: u64,
+    /// Sequence number of the last write whose flush failed
+    failed: u64,
+    /// Set once the flush thread is stopped, the writes then flush themselves
+    closed: bool
+}
+
+impl GroupCommit {
+    /// Registers a write and blocks until a flush covers it, or flushes the
+    /// storage itself if the group commits are closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FlushFailed` if the flush covering the write failed, and
+    /// `PoisonedMutex` if the lock is poisoned.
+    pub fn wait(&self, storage: &dyn StorageBackend) -> Result<(), TransientError> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+        state.written += 1;
+        let seq = state.written;
+
+        let state = self
+            .flushed
+            .wait_while(state, |s| s.flushed < seq && !s.closed)
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        // Closed before a flush covered the write
+        if state.flushed < seq {
+            drop(state);
+            return storage.flush();
+        }
+
+        if state.failed >= seq {
+            return Err(TransientError::FlushFailed);
+        }
+
+        Ok(())
+    }
+
+    /// Wakes the waiting writes up to flush themselves, once the flush thread
+    /// is stopped.
+    pub(crate) fn close(&self) -> Result<(), TransientError> {
+        self.state
+            .lock()
+            .map_err(|_| TransientError::PoisonedMutex)?
+            .closed = true;
+        self.flushed.notify_all();
+
+        Ok(())
+    }
+
+    /// Flushes the storage if any write is waiting, and wakes the waiting
+    /// writes up.
+    fn commit(&self, storage: &dyn StorageBackend) -> Result<(), TransientError> {
+        let target = {
+            let state = self
+                .state
+                .lock()
+                .map_err(|_| TransientError::PoisonedMutex)?;
+            if state.written == state.flushed {
+                return Ok(());
+            }
+            state.written
+        };
+
+        // NOTE: Writes registered while flushing are not guaranteed to be covered,
+        // so they are left for the next commit
+        let res = storage.flush();
+
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+        state.flushed = target;
+        if res.is_err() {
+            state.failed = target;
+        }
+        self.flushed.notify_all();
+
+        Ok(())
+    }
+}
+
+/// Spawns the background thread needed by the durability policy, if it needs
+/// one.
+pub(crate) fn spawn_flush_thread(
+    durability: Durability,
+    storage: Arc<dyn StorageBackend>,
+    group_commit: Arc<GroupCommit>,
+    shutdown: Arc<AtomicBool>
+) -> Option<JoinHandle<Result<(), TransientError>>> {
+    let interval = match durability {
+        Durability::GroupCommit(d) | Durability::Periodic(d) => d,
+        Durability::Background | Durability::SyncEveryWrite => return None
+    };
+
+    Some(thread::spawn(move || {
+        loop {
+            // Sleep in small steps, so dropping the DB doesnt wait for a whole interval
+            let mut waited = Duration::ZERO;
+            while waited < interval && !shutdown.load(Ordering::SeqCst) {
+                let step = (interval - waited).min(Duration::new(0, 100000000));
+                thread::sleep(step);
+                waited += step;
+            }
+
+            let stop = shutdown.load(Ordering::SeqCst);
+
+            let res = match durability {
+                Durability::GroupCommit(_) => group_commit.commit(&*storage),
+                _ => storage.flush()
+            };
+
+            // The last flush is reported by `DB::close`, a failed one before it
+            // is retried on the next tick
+            if stop {
+                return res;
+            }
+            if let Err(e) = res {
+                warn!("Flushing the database failed: {e}");
+            }
+        }
+    }))
+}
+
+impl DB {
+    /// Makes the last write durable according to the durability policy of the
+    /// database.
+    pub(crate) fn commit_write(&self) -> Result<(), TransientError> {
+        match self.durability {
+            Durability::SyncEveryWrite => self.flush(),
+            Durability::GroupCommit(_) => self.group_commit.wait(&*self.storage),
+            Durability::Background | Durability::Periodic(_) => Ok(())
+        }
+    }
+
+    /// Sets a key-value pair like `set`, but only returns once the write is on
+    /// disk, whatever the durability policy of the database is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the write or the flush fails.
+    pub fn set_durable(
+        &self,
+        key: &str,
+        val: &str,
+        ttl: Option<Duration>
+    ) -> Result<(), TransientError> {
+        self.set_raw_durable(&key, &val, ttl)
+    }
+
+    /// Sets a raw key-value pair like `set_raw`, but only returns once the
+    /// write is on disk, whatever the durability policy of the database is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the write or the flush fails.
+    pub fn set_raw_durable<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        val: &V,
+        ttl: Option<Duration>
+    ) -> Result<(), TransientError> {
+        self.set_raw(key, val, ttl)?;
+
+        // The other policies already waited for the write to be on disk
+        if let Durability::Background | Durability::Periodic(_) = self.durability {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+}
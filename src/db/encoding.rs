@@ -0,0 +1,188 @@
+//! This module defines the `ValueEncoding`, the way the values are stored in
+//! the data tree: compressed, then encrypted, each if enabled.
+//!
+//! How a value was encoded is returned as its `ValueFormat`, which is kept in
+//! the metadata of its key and given back to decode it, so nothing about the
+//! encoding is ever read from the value itself.
+//!
+//! The members of the collections have no metadata, they are encrypted the
+//! same way and stored behind a flag byte which every member has, so it never
+//! depends on what the member holds.
+
+use std::borrow::Cow;
+
+use crate::ValueFormat;
+use crate::db::compression::{
+    CompressionStats,
+    Compressor
+};
+use crate::db::encryption::{
+    Encryptor,
+    KeyId
+};
+use crate::db::errors::TransientError;
+
+/// The flag of the members stored as they are.
+const PLAIN_MEMBER: u8 = 0;
+
+/// The flag of the encrypted members, followed by the id of their key.
+const ENCRYPTED_MEMBER: u8 = 1;
+
+/// Turns the values into their stored form and back.
+#[derive(Debug, Default)]
+pub(crate) struct ValueEncoding {
+    pub(crate) compression: Compressor,
+    pub(crate) encryption: Encryptor
+}
+
+impl ValueEncoding {
+    /// Returns the value of the key as it has to be stored, and the format
+    /// with how it was encoded filled in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value cannot be compressed or encrypted.
+    pub(crate) fn encode<'a>(
+        &self,
+        key: &[u8],
+        value: &'a [u8],
+        format: ValueFormat
+    ) -> Result<(Cow<'a, [u8]>, ValueFormat), TransientError> {
+        let (compressed, codec) = self.compression.encode(key, value)?;
+        let (encrypted, key_id) = self.encryption.encrypt(key, compressed)?;
+
+        Ok((
+            encrypted,
+            ValueFormat {
+                codec,
+                key_id,
+                ..format
+            }
+        ))
+    }
+
+    /// Returns the value of the key stored in the format as it was written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value cannot be decrypted or decompressed, or
+    /// `NotEncrypted` if it is not encrypted while the encryption is enabled.
+    pub(crate) fn decode<'a>(
+        &self,
+        key: &[u8],
+        value: &'a [u8],
+        format: ValueFormat
+    ) -> Result<Cow<'a, [u8]>, TransientError> {
+        match self.encryption.decrypt(key, value, format.key_id)? {
+            Cow::Borrowed(v) => self.compression.decode(v, format.codec),
+            Cow::Owned(v) => Ok(Cow::Owned(self.compression.decode_owned(v, format.codec)?))
+        }
+    }
+
+    /// Same as `decode`, without copying the values stored as they are.
+    pub(crate) fn decode_owned(
+        &self,
+        key: &[u8],
+        value: Vec<u8>,
+        format: ValueFormat
+    ) -> Result<Vec<u8>, TransientError> {
+        let decoded = match self.decode(key, &value, format)? {
+            Cow::Owned(v) => Some(v),
+            Cow::Borrowed(_) => None
+        };
+
+        Ok(decoded.unwrap_or(value))
+    }
+
+    /// Decodes a value stored in the format, even if it is not encrypted while
+    /// the encryption is enabled, and encodes it again with the current
+    /// configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value cannot be decoded or encoded.
+    pub(crate) fn reencode(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        format: ValueFormat
+    ) -> Result<(Vec<u8>, ValueFormat), TransientError> {
+        let decrypted = self.encryption.decrypt_any(key, value, format.key_id)?;
+        let decoded = self.compression.decode(&decrypted, format.codec)?;
+        let base = ValueFormat {
+            collection: format.collection,
+            ..ValueFormat::default()
+        };
+
+        let (encoded, format) = self.encode(key, &decoded, base)?;
+        Ok((encoded.into_owned(), format))
+    }
+
+    /// Returns the member of a collection as it has to be stored, under its
+    /// key in the `Members` tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member cannot be encrypted.
+    pub(crate) fn encode_member(
+        &self,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Vec<u8>, TransientError> {
+        match self.encryption.encrypt(key, Cow::Borrowed(value))? {
+            (sealed, Some(id)) => {
+                Ok([&[ENCRYPTED_MEMBER][..], &id.to_be_bytes(), &sealed].concat())
+            },
+            (_, None) => Ok([&[PLAIN_MEMBER][..], value].concat())
+        }
+    }
+
+    /// Returns the member stored under the key as it was written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the member cannot be decrypted, or `NotEncrypted`
+    /// if it is not encrypted while the encryption is enabled.
+    pub(crate) fn decode_member(
+        &self,
+        key: &[u8],
+        stored: &[u8]
+    ) -> Result<Vec<u8>, TransientError> {
+        let (key_id, value) = split_member(stored)?;
+
+        Ok(self.encryption.decrypt(key, value, key_id)?.into_owned())
+    }
+
+    /// Same as `reencode`, for a member.
+    pub(crate) fn reencode_member(
+        &self,
+        key: &[u8],
+        stored: &[u8]
+    ) -> Result<Vec<u8>, TransientError> {
+        let (key_id, value) = split_member(stored)?;
+        let decrypted = self.encryption.decrypt_any(key, value, key_id)?;
+
+        self.encode_member(key, &decrypted)
+    }
+
+    pub(crate) fn compression_stats(&self) -> CompressionStats {
+        self.compression.stats()
+    }
+}
+
+/// Splits a stored member into the id of the key it was encrypted with and
+/// its value.
+fn split_member(stored: &[u8]) -> Result<(Option<KeyId>, &[u8]), TransientError> {
+    match stored.split_first() {
+        Some((&PLAIN_MEMBER, value)) => Ok((None, value)),
+        Some((&ENCRYPTED_MEMBER, rest)) if rest.len() >= 4 => {
+            let (id, sealed) = rest.split_at(4);
+            let id = KeyId::from_be_bytes(
+                id.try_into()
+                    .map_err(|_| TransientError::DecryptionFailed)?
+            );
+            Ok((Some(id), sealed))
+        },
+        _ => Err(TransientError::ParsingFromByteError)
+    }
+}
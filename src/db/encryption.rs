@@ -0,0 +1,487 @@
+//! This module defines the encryption at rest of the values and the backups,
+//! enabled by `DBConfig::encryption`.
+//!
+//! The values and the members of the collections are encrypted with
+//! AES-256-GCM, which authenticates them: a value which was tampered with, or
+//! moved to another key, fails to decrypt instead of being read. The keys are
+//! supplied by a [`KeyProvider`], and the id of the key a value was encrypted
+//! with is kept in its metadata, so the keys can be rotated: the new values
+//! are encrypted with the current key, while the values encrypted with a
+//! previous key stay readable as long as the provider still has it.
+//!
+//! Once the encryption is enabled, a value stored unencrypted fails to read
+//! with `NotEncrypted` instead of being trusted, so a database is encrypted by
+//! loading a backup of it with the encryption configured. The terms of the
+//! secondary indexes are stored hashed with a key derived from the current
+//! key. The keys, their metadata and the keys of the members, e.g. the fields
+//! of a hash, are not encrypted.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{
+    ErrorKind,
+    Read,
+    Write
+};
+use std::sync::Arc;
+
+use ring::aead::{
+    AES_256_GCM,
+    Aad,
+    LessSafeKey,
+    NONCE_LEN,
+    Nonce,
+    UnboundKey
+};
+use ring::hmac;
+
+use crate::db::errors::TransientError;
+
+/// What the key of the indexes is derived from, so it differs from the key
+/// of the values.
+const INDEX_KEY_LABEL: &[u8] = b"epoch:index";
+
+/// The bytes every encrypted backup starts with, followed by the id of its
+/// key and the prefix of its nonces.
+const BACKUP_MAGIC: &[u8] = b"EPOCHENC";
+
+/// The length of the chunks of plaintext a backup is encrypted in.
+const BACKUP_CHUNK: usize = 64 * 1024;
+
+/// The length of the authentication tag appended to every ciphertext.
+const TAG_LEN: usize = 16;
+
+/// The id of an encryption key, stored with every value it encrypted.
+pub type KeyId = u32;
+
+/// A 256 bit AES key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Wraps the bytes of a key, e.g. fetched from a secret manager.
+    pub fn new(bytes: [u8; 32]) -> EncryptionKey {
+        EncryptionKey(bytes)
+    }
+
+    /// Generates a random key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RandomSourceFailed` if the OS fails to provide random bytes.
+    pub fn generate() -> Result<EncryptionKey, TransientError> {
+        let mut bytes = [0u8; 32];
+        getrandom::fill(&mut bytes).map_err(|_| TransientError::RandomSourceFailed)?;
+
+        Ok(EncryptionKey(bytes))
+    }
+
+    /// Returns the bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Debug for EncryptionKey {
+    /// Never prints the key itself.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// Supplies the keys the values are encrypted with.
+///
+/// The provider is asked for a key on every read and write, so one fetching
+/// its keys from a remote service should cache them.
+pub trait KeyProvider: Debug + Send + Sync {
+    /// The id of the key the new values are encrypted with.
+    fn current_key_id(&self) -> KeyId;
+
+    /// Returns the key with the id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `KeyNotFound` if the provider doesn't have the key.
+    fn key(&self, id: KeyId) -> Result<EncryptionKey, TransientError>;
+}
+
+/// A provider holding its keys in memory.
+///
+/// ```
+/// use epoch_db::db::encryption::{
+///     EncryptionKey,
+///     StaticKeys
+/// };
+///
+/// let old = EncryptionKey::generate().unwrap();
+/// let new = EncryptionKey::generate().unwrap();
+///
+/// // The new values are encrypted with the key 2, the ones encrypted with
+/// // the key 1 stay readable
+/// let keys = StaticKeys::new(1, old).rotate(2, new);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticKeys {
+    current: KeyId,
+    keys: HashMap<KeyId, EncryptionKey>
+}
+
+impl StaticKeys {
+    /// Creates a provider encrypting with the key.
+    pub fn new(id: KeyId, key: EncryptionKey) -> StaticKeys {
+        StaticKeys {
+            current: id,
+            keys: HashMap::from([(id, key)])
+        }
+    }
+
+    /// Adds a key and encrypts the new values with it, the previous keys are
+    /// kept to decrypt the values they encrypted.
+    pub fn rotate(mut self, id: KeyId, key: EncryptionKey) -> StaticKeys {
+        self.keys.insert(id, key);
+        self.current = id;
+        self
+    }
+
+    /// Adds a key which only decrypts, e.g. a retired key whose values were
+    /// not all rewritten yet.
+    pub fn with_key(mut self, id: KeyId, key: EncryptionKey) -> StaticKeys {
+        self.keys.insert(id, key);
+        self
+    }
+}
+
+impl KeyProvider for StaticKeys {
+    fn current_key_id(&self) -> KeyId {
+        self.current
+    }
+
+    fn key(&self, id: KeyId) -> Result<EncryptionKey, TransientError> {
+        self.keys
+            .get(&id)
+            .cloned()
+            .ok_or(TransientError::KeyNotFound {
+                id
+            })
+    }
+}
+
+/// Encrypts the values on their way to the storage and decrypts them on their
+/// way back, leaves them as they are if the encryption is disabled.
+#[derive(Debug, Default)]
+pub(crate) struct Encryptor {
+    provider: Option<Arc<dyn KeyProvider>>
+}
+
+impl Encryptor {
+    pub(crate) fn new(provider: Option<Arc<dyn KeyProvider>>) -> Encryptor {
+        Encryptor {
+            provider
+        }
+    }
+
+    /// Returns true if the values and the backups are encrypted.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// Encrypts the value of the key with the current key, the key of the
+    /// value is authenticated along with it. Returns the value as it has to
+    /// be stored and the id of the key, `None` if the encryption is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider has no current key, or if the value
+    /// cannot be encrypted.
+    pub(crate) fn encrypt<'a>(
+        &self,
+        key: &[u8],
+        value: Cow<'a, [u8]>
+    ) -> Result<(Cow<'a, [u8]>, Option<KeyId>), TransientError> {
+        let provider = match &self.provider {
+            Some(p) => p,
+            None => return Ok((value, None))
+        };
+
+        let id = provider.current_key_id();
+        let nonce = random_nonce()?;
+        let mut sealed = value.into_owned();
+        cipher(provider.as_ref(), id)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key),
+                &mut sealed
+            )
+            .map_err(|_| TransientError::EncryptionFailed)?;
+
+        Ok((Cow::Owned([&nonce[..], &sealed].concat()), Some(id)))
+    }
+
+    /// Decrypts the value of the key if it was encrypted with the key of the
+    /// id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotEncrypted` if the value is not encrypted while the
+    /// encryption is enabled, `KeyNotFound` if the key the value was
+    /// encrypted with is not available, or `DecryptionFailed` if the value
+    /// was tampered with, corrupted or belongs to another key.
+    pub(crate) fn decrypt<'a>(
+        &self,
+        key: &[u8],
+        value: &'a [u8],
+        key_id: Option<KeyId>
+    ) -> Result<Cow<'a, [u8]>, TransientError> {
+        if key_id.is_none() && self.is_enabled() {
+            return Err(TransientError::NotEncrypted);
+        }
+
+        self.decrypt_any(key, value, key_id)
+    }
+
+    /// Same as `decrypt`, except that the values which are not encrypted are
+    /// returned as they are, only to be encrypted.
+    pub(crate) fn decrypt_any<'a>(
+        &self,
+        key: &[u8],
+        value: &'a [u8],
+        key_id: Option<KeyId>
+    ) -> Result<Cow<'a, [u8]>, TransientError> {
+        let id = match key_id {
+            Some(id) => id,
+            None => return Ok(Cow::Borrowed(value))
+        };
+        if value.len() < NONCE_LEN + TAG_LEN {
+            return Err(TransientError::DecryptionFailed);
+        }
+        let (nonce, sealed) = value.split_at(NONCE_LEN);
+
+        let provider = self.provider.as_ref().ok_or(TransientError::KeyNotFound {
+            id
+        })?;
+        let mut opened = sealed.to_vec();
+        let len = cipher(provider.as_ref(), id)?
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce)
+                    .map_err(|_| TransientError::DecryptionFailed)?,
+                Aad::from(key),
+                &mut opened
+            )
+            .map_err(|_| TransientError::DecryptionFailed)?
+            .len();
+        opened.truncate(len);
+
+        Ok(Cow::Owned(opened))
+    }
+
+    /// Returns the key the terms of the indexes are hashed with, derived from
+    /// the current key, `None` if the encryption is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider has no current key.
+    pub(crate) fn index_key(&self) -> Result<Option<hmac::Key>, TransientError> {
+        let provider = match &self.provider {
+            Some(p) => p,
+            None => return Ok(None)
+        };
+
+        let key = provider.key(provider.current_key_id())?;
+        let derived = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes()),
+            INDEX_KEY_LABEL
+        );
+        Ok(Some(hmac::Key::new(hmac::HMAC_SHA256, derived.as_ref())))
+    }
+
+    /// Encrypts a backup archive with the current key, in chunks which are
+    /// authenticated along with their position, so a truncated or reordered
+    /// backup fails to decrypt.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the encryption is disabled, the archive cannot be
+    /// read or the backup cannot be written.
+    pub(crate) fn encrypt_backup<R: Read, W: Write>(
+        &self,
+        archive: &mut R,
+        backup: &mut W
+    ) -> Result<(), TransientError> {
+        let provider = self
+            .provider
+            .as_ref()
+            .ok_or(TransientError::EncryptionFailed)?;
+        let id = provider.current_key_id();
+        let cipher = cipher(provider.as_ref(), id)?;
+
+        let mut prefix = [0u8; NONCE_LEN - 4];
+        getrandom::fill(&mut prefix).map_err(|_| TransientError::RandomSourceFailed)?;
+        write_all(backup, &[BACKUP_MAGIC, &id.to_be_bytes(), &prefix].concat())?;
+
+        let mut chunk = vec![0u8; BACKUP_CHUNK];
+        let mut counter: u32 = 0;
+        loop {
+            let len = read_chunk(archive, &mut chunk)?;
+            let last = len < BACKUP_CHUNK;
+
+            let mut sealed = chunk[..len].to_vec();
+            cipher
+                .seal_in_place_append_tag(
+                    chunk_nonce(&prefix, counter),
+                    Aad::from(chunk_aad(counter, last)),
+                    &mut sealed
+                )
+                .map_err(|_| TransientError::EncryptionFailed)?;
+            write_all(backup, &(sealed.len() as u32).to_be_bytes())?;
+            write_all(backup, &sealed)?;
+
+            if last {
+                return Ok(());
+            }
+            counter = counter
+                .checked_add(1)
+                .ok_or(TransientError::EncryptionFailed)?;
+        }
+    }
+
+    /// Decrypts a backup written by `encrypt_backup` into the archive, returns
+    /// false if the backup is not encrypted, in which case its first bytes
+    /// were read and it has to be rewound.
+    ///
+    /// # Errors
+    ///
+    /// Returns `KeyNotFound` if the key the backup was encrypted with is not
+    /// available, `DecryptionFailed` if the backup was tampered with or
+    /// truncated, or an error if it cannot be read or the archive written.
+    pub(crate) fn decrypt_backup<R: Read, W: Write>(
+        &self,
+        backup: &mut R,
+        archive: &mut W
+    ) -> Result<bool, TransientError> {
+        let mut magic = [0u8; BACKUP_MAGIC.len()];
+        if read_chunk(backup, &mut magic)? < magic.len() || magic != BACKUP_MAGIC {
+            return Ok(false);
+        }
+
+        let mut header = [0u8; 4 + NONCE_LEN - 4];
+        read_exact(backup, &mut header)?;
+        let (id, prefix) = header.split_at(4);
+        let id = KeyId::from_be_bytes(
+            id.try_into()
+                .map_err(|_| TransientError::DecryptionFailed)?
+        );
+        let provider = self.provider.as_ref().ok_or(TransientError::KeyNotFound {
+            id
+        })?;
+        let cipher = cipher(provider.as_ref(), id)?;
+
+        let mut counter: u32 = 0;
+        loop {
+            let mut len = [0u8; 4];
+            read_exact(backup, &mut len)?;
+            let len = u32::from_be_bytes(len) as usize;
+            if !(TAG_LEN..=BACKUP_CHUNK + TAG_LEN).contains(&len) {
+                return Err(TransientError::DecryptionFailed);
+            }
+
+            let mut sealed = vec![0u8; len];
+            read_exact(backup, &mut sealed)?;
+            let last = len < BACKUP_CHUNK + TAG_LEN;
+            let opened = cipher
+                .open_in_place(
+                    chunk_nonce(prefix, counter),
+                    Aad::from(chunk_aad(counter, last)),
+                    &mut sealed
+                )
+                .map_err(|_| TransientError::DecryptionFailed)?;
+            write_all(archive, opened)?;
+
+            if last {
+                return Ok(true);
+            }
+            counter = counter
+                .checked_add(1)
+                .ok_or(TransientError::DecryptionFailed)?;
+        }
+    }
+}
+
+/// Returns the cipher of the key with the id.
+fn cipher(provider: &dyn KeyProvider, id: KeyId) -> Result<LessSafeKey, TransientError> {
+    let key = provider.key(id)?;
+    let unbound = UnboundKey::new(&AES_256_GCM, key.as_bytes())
+        .map_err(|_| TransientError::EncryptionFailed)?;
+
+    Ok(LessSafeKey::new(unbound))
+}
+
+fn random_nonce() -> Result<[u8; NONCE_LEN], TransientError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::fill(&mut nonce).map_err(|_| TransientError::RandomSourceFailed)?;
+
+    Ok(nonce)
+}
+
+/// The nonce of a chunk of a backup, the random prefix of the backup followed
+/// by the position of the chunk.
+fn chunk_nonce(prefix: &[u8], counter: u32) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_LEN - 4].copy_from_slice(prefix);
+    nonce[NONCE_LEN - 4..].copy_from_slice(&counter.to_be_bytes());
+
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// The data authenticated along with a chunk of a backup: its position and
+/// whether it is the last one.
+fn chunk_aad(counter: u32, last: bool) -> [u8; 5] {
+    let mut aad = [0u8; 5];
+    aad[..4].copy_from_slice(&counter.to_be_bytes());
+    aad[4] = last as u8;
+
+    aad
+}
+
+/// Fills the buffer, returns how much was read, less than its length only at
+/// the end of the reader.
+fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, TransientError> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => {
+                return Err(TransientError::IOError {
+                    error: e
+                });
+            }
+        }
+    }
+
+    Ok(len)
+}
+
+/// Reads exactly the length of the buffer, a backup ending early is
+/// truncated.
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), TransientError> {
+    reader.read_exact(buf).map_err(|e| {
+        match e.kind() {
+            ErrorKind::UnexpectedEof => TransientError::DecryptionFailed,
+            _ => {
+                TransientError::IOError {
+                    error: e
+                }
+            },
+        }
+    })
+}
+
+fn write_all<W: Write>(writer: &mut W, buf: &[u8]) -> Result<(), TransientError> {
+    writer.write_all(buf).map_err(|e| {
+        TransientError::IOError {
+            error: e
+        }
+    })
+}
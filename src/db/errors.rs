@@ -1,5 +1,6 @@
 //! This module defines the custom error types used throughout the EpochDB
 //! library.
+use std::convert::Infallible;
 use std::error::Error;
 use std::fmt::Display;
 use std::path::PathBuf;
@@ -22,6 +23,12 @@ pub enum TransientError {
     },
     /// Error that occurs during a `sled` transaction.
     SledTransactionError,
+    /// Error that occurs when a transaction conflicts with another one, the
+    /// transaction closure will be retried.
+    TransactionConflict,
+    /// Error that occurs when a transaction conflicted more times than the
+    /// configured retry limit.
+    TransactionRetryLimitReached,
     /// Error that occurs when parsing a byte slice to a u64 fails.
     ParsingToU64ByteFailed,
     /// Error that occurs when any folder in the path doesnt exist.
@@ -41,8 +48,60 @@ pub enum TransientError {
     DBMetadataNotFound,
     /// Error that occurs when a Mutex is poisoned.
     PoisonedMutex,
+    /// Error that occurs when a background thread of the database panicked.
+    WorkerPanicked,
+    /// Error that occurs when the flush which should make a write durable
+    /// fails.
+    FlushFailed,
     /// Error that occurs when parsing from a byte slice to any type.
     ParsingFromByteError,
+    /// Error that occurs when a rate limit has a zero limit or window.
+    InvalidRateLimit,
+    /// Error that occurs when the OS fails to provide random bytes.
+    RandomSourceFailed,
+    /// Error that occurs when a key is used as a type of value it doesn't
+    /// hold, e.g. a plain value as a list.
+    WrongType,
+    /// Error that occurs when a value incremented as an integer is not one, or
+    /// the increment overflows.
+    NotAnInteger,
+    /// Error that occurs when the score of a sorted set member is NaN.
+    InvalidScore,
+    /// Error that occurs when a stream entry id is not `ms` or `ms-seq`.
+    InvalidStreamId,
+    /// Error that occurs when reading from a consumer group which does not
+    /// exist.
+    GroupNotFound,
+    /// Error that occurs when using a secondary index which was not created.
+    IndexNotFound,
+    /// Error that occurs when the codec fails to compress a value.
+    CompressionFailed,
+    /// Error that occurs when a compressed value is corrupted.
+    DecompressionFailed,
+    /// Error that occurs when a value was compressed with a codec which is
+    /// not configured.
+    UnknownCodec {
+        /// The id of the codec, from the flag byte of the value
+        id: u8
+    },
+    /// Error that occurs when a value or a backup cannot be encrypted.
+    EncryptionFailed,
+    /// Error that occurs when an encrypted value or backup fails to
+    /// authenticate: it was tampered with, corrupted, truncated or decrypted
+    /// with the wrong key.
+    DecryptionFailed,
+    /// Error that occurs when the key a value or a backup was encrypted with
+    /// is not available, or the encryption is not configured.
+    KeyNotFound {
+        /// The id of the key
+        id: u32
+    },
+    /// Error that occurs when a value stored unencrypted is read while the
+    /// encryption is enabled, it has to be written again to be encrypted.
+    NotEncrypted,
+    /// Error that occurs when the runtime shuts down before a call of the
+    /// `AsyncDB` completed.
+    TaskCancelled,
     /// Wrapper for `std::io::Error`.
     IOError {
         /// The underlying `std::io::Error`
@@ -70,6 +129,10 @@ impl Display for TransientError {
                 error
             } => writeln!(f, "Sled failed {error}"),
             TransientError::SledTransactionError => writeln!(f, "Sled Transaction failed"),
+            TransientError::TransactionConflict => writeln!(f, "Transaction conflicted"),
+            TransientError::TransactionRetryLimitReached => {
+                writeln!(f, "Transaction conflicted more times than the retry limit")
+            },
             TransientError::ParsingToU64ByteFailed => {
                 writeln!(f, "Failed to parse a variable to a U64 byte [u8; 8]")
             },
@@ -85,7 +148,44 @@ impl Display for TransientError {
             TransientError::MetadataNotFound => writeln!(f, "Metadata is not found"),
             TransientError::DBMetadataNotFound => writeln!(f, "DB metadata is not found"),
             TransientError::PoisonedMutex => writeln!(f, "Mutex is poisoned"),
+            TransientError::WorkerPanicked => writeln!(f, "Background thread panicked"),
+            TransientError::FlushFailed => writeln!(f, "Flushing the write to disk failed"),
             TransientError::ParsingFromByteError => writeln!(f, "Parsing from byte failed"),
+            TransientError::InvalidRateLimit => {
+                writeln!(f, "Rate limit needs a non zero limit and window")
+            },
+            TransientError::RandomSourceFailed => writeln!(f, "Failed to get random bytes"),
+            TransientError::WrongType => {
+                writeln!(f, "Key holds the wrong kind of value for this operation")
+            },
+            TransientError::NotAnInteger => {
+                writeln!(f, "Value is not an integer or out of range")
+            },
+            TransientError::InvalidScore => writeln!(f, "Score is not a valid number"),
+            TransientError::InvalidStreamId => writeln!(f, "Stream id is not valid"),
+            TransientError::GroupNotFound => writeln!(f, "Consumer group is not found"),
+            TransientError::IndexNotFound => writeln!(f, "Index is not found"),
+            TransientError::CompressionFailed => writeln!(f, "Compressing the value failed"),
+            TransientError::DecompressionFailed => {
+                writeln!(f, "Decompressing the value failed, it is corrupted")
+            },
+            TransientError::UnknownCodec {
+                id
+            } => writeln!(f, "Value was compressed with the unknown codec {id}"),
+            TransientError::EncryptionFailed => writeln!(f, "Encrypting failed"),
+            TransientError::DecryptionFailed => {
+                writeln!(
+                    f,
+                    "Decrypting failed, the data was tampered with, corrupted or the key is wrong"
+                )
+            },
+            TransientError::KeyNotFound {
+                id
+            } => writeln!(f, "Encryption key {id} is not available"),
+            TransientError::NotEncrypted => {
+                writeln!(f, "Value is not encrypted while the encryption is enabled")
+            },
+            TransientError::TaskCancelled => writeln!(f, "Blocking task was cancelled"),
             TransientError::IOError {
                 error
             } => writeln!(f, "std IO failed {error}"),
@@ -116,3 +216,42 @@ impl Display for TransientError {
 }
 
 impl Error for TransientError {}
+
+/// The error returned by `DB::transaction`, which keeps the error the closure
+/// aborted with apart from the errors of the database itself.
+#[derive(Debug)]
+pub enum TransactionError<E> {
+    /// The closure aborted the transaction with its own error.
+    Abort(E),
+    /// The database failed, e.g. a key is missing or the backend had an IO
+    /// error.
+    Transient(TransientError)
+}
+
+impl TransactionError<Infallible> {
+    /// Unwraps the error of a transaction which can never be aborted by its
+    /// closure.
+    pub fn into_transient(self) -> TransientError {
+        match self {
+            TransactionError::Abort(never) => match never {},
+            TransactionError::Transient(e) => e
+        }
+    }
+}
+
+impl<E> From<TransientError> for TransactionError<E> {
+    fn from(value: TransientError) -> Self {
+        TransactionError::Transient(value)
+    }
+}
+
+impl<E: Display> Display for TransactionError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionError::Abort(e) => writeln!(f, "Transaction was aborted: {e}"),
+            TransactionError::Transient(e) => writeln!(f, "Transaction failed: {e}")
+        }
+    }
+}
+
+impl<E: Display + std::fmt::Debug> Error for TransactionError<E> {}
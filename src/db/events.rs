@@ -0,0 +1,65 @@
+//! This module defines the expiration events, which are sent to subscribers
+//! whenever a key expires.
+
+use std::sync::Mutex;
+use std::sync::mpsc::{
+    Receiver,
+    Sender,
+    channel
+};
+
+use crate::DB;
+
+/// A key which expired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expiration {
+    /// The key which expired
+    pub key: Vec<u8>,
+    /// The value the key held, `None` if it was already removed
+    pub value: Option<Vec<u8>>
+}
+
+/// Sends the expirations to every subscriber.
+#[derive(Debug, Default)]
+pub(crate) struct ExpiryNotifier {
+    subscribers: Mutex<Vec<Sender<Expiration>>>
+}
+
+impl ExpiryNotifier {
+    /// Returns a receiver of every expiration from now on.
+    pub(crate) fn subscribe(&self) -> Receiver<Expiration> {
+        let (tx, rx) = channel();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(tx);
+        }
+        rx
+    }
+
+    /// Sends the expiration to the subscribers, dropping the ones whose
+    /// receiver is gone.
+    pub(crate) fn notify(&self, key: &[u8], value: Option<Vec<u8>>) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            if subscribers.is_empty() {
+                return;
+            }
+
+            let expiration = Expiration {
+                key: key.to_vec(),
+                value
+            };
+            subscribers.retain(|s| s.send(expiration.clone()).is_ok());
+        }
+    }
+}
+
+impl DB {
+    /// Returns a receiver of every key which expires from now on, whether the
+    /// TTL thread removed it or a lifecycle API (e.g. the `SessionStore`)
+    /// found it expired.
+    ///
+    /// Expirations are buffered until received, so a receiver which is never
+    /// read from should be dropped.
+    pub fn subscribe_expirations(&self) -> Receiver<Expiration> {
+        self.expirations.subscribe()
+    }
+}
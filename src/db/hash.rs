@@ -0,0 +1,227 @@
+//! This module defines the hashes, maps of fields stored one by one, so
+//! changing a field never rewrites the other ones.
+//!
+//! Every field of a hash shares the `Metadata` of the key of the hash, so the
+//! frequency, the creation time and the TTL apply to the whole hash. Like in
+//! Redis, a hash is created by its first field and removed with its last one.
+
+use std::str::from_utf8;
+
+use crate::DB;
+use crate::db::collection::{
+    Collection,
+    CollectionKind
+};
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+
+/// The fields of a hash with their values, ordered by field.
+pub type HashFields = Vec<(Vec<u8>, Vec<u8>)>;
+
+impl DB {
+    /// Sets the fields of the hash, creating it if needed, and returns how
+    /// many fields were added.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a hash, or an
+    /// error if the transaction fails.
+    pub fn hset<K: AsRef<[u8]>, F: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        fields: &[(F, V)]
+    ) -> Result<u64, TransientError> {
+        let key = key.as_ref();
+        if fields.is_empty() {
+            return Ok(0);
+        }
+
+        self.transaction(|tx| {
+            let mut hash = match tx.get_collection(&key)? {
+                Some(h) => h,
+                None => {
+                    Collection::new(CollectionKind::Hash {
+                        len: 0
+                    })?
+                },
+            };
+            let len = hash_len(&hash)?;
+
+            let mut added = 0;
+            for (field, value) in fields {
+                let member = hash.member_key(field.as_ref());
+                if tx.insert_member(&member, value.as_ref())?.is_none() {
+                    added += 1;
+                }
+            }
+
+            hash.kind = CollectionKind::Hash {
+                len: len + added
+            };
+            tx.set_collection(&key, &hash)?;
+
+            Ok(added)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Returns the value of the field, `None` if the hash or the field does
+    /// not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a hash.
+    pub fn hget<K: AsRef<[u8]>, F: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        field: &F
+    ) -> Result<Option<Vec<u8>>, TransientError> {
+        match self.get_collection(key)? {
+            Some(hash) => {
+                hash_len(&hash)?;
+                self.get_member(&hash.member_key(field.as_ref()))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Removes the fields of the hash and returns how many existed, the hash
+    /// is removed with its last field.
+    ///
+    /// # Errors
+    ///
+    /// Same as `hset`.
+    pub fn hdel<K: AsRef<[u8]>, F: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        fields: &[F]
+    ) -> Result<u64, TransientError> {
+        let key = key.as_ref();
+
+        self.transaction(|tx| {
+            let mut hash = match tx.get_collection(&key)? {
+                Some(h) => h,
+                None => return Ok(0)
+            };
+            let len = hash_len(&hash)?;
+
+            let mut removed = 0;
+            for field in fields {
+                if tx
+                    .remove_member(&hash.member_key(field.as_ref()))?
+                    .is_some()
+                {
+                    removed += 1;
+                }
+            }
+
+            if removed == len {
+                tx.remove_raw(&key)?;
+            } else if removed > 0 {
+                hash.kind = CollectionKind::Hash {
+                    len: len - removed
+                };
+                tx.set_collection(&key, &hash)?;
+            }
+
+            Ok(removed)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Returns every field of the hash with its value, ordered by field.
+    ///
+    /// The fields are scanned outside of a transaction, so a concurrent write
+    /// to the hash may or may not be seen.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a hash, or an
+    /// error if the fields cannot be read.
+    pub fn hgetall<K: AsRef<[u8]>>(&self, key: &K) -> Result<HashFields, TransientError> {
+        match self.get_collection(key)? {
+            Some(hash) => {
+                hash_len(&hash)?;
+                self.members(&hash).collect()
+            },
+            None => Ok(Vec::new())
+        }
+    }
+
+    /// Returns the number of fields of the hash, 0 if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a hash.
+    pub fn hlen<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        match self.get_collection(key)? {
+            Some(hash) => hash_len(&hash),
+            None => Ok(0)
+        }
+    }
+
+    /// Increments the integer stored in the field by `delta` and returns the
+    /// new value, a missing field or hash starts from 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotAnInteger` if the field does not hold an integer or the
+    /// increment overflows, otherwise same as `hset`.
+    pub fn hincrby<K: AsRef<[u8]>, F: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        field: &F,
+        delta: i64
+    ) -> Result<i64, TransientError> {
+        let key = key.as_ref();
+        let field = field.as_ref();
+
+        self.transaction(|tx| {
+            let mut hash = match tx.get_collection(&key)? {
+                Some(h) => h,
+                None => {
+                    Collection::new(CollectionKind::Hash {
+                        len: 0
+                    })?
+                },
+            };
+            let len = hash_len(&hash)?;
+            let member = hash.member_key(field);
+
+            let (current, added) = match tx.get_member(&member)? {
+                Some(v) => {
+                    let current = from_utf8(&v)
+                        .ok()
+                        .and_then(|v| v.parse::<i64>().ok())
+                        .ok_or(TransientError::NotAnInteger)?;
+                    (current, 0)
+                },
+                None => (0, 1)
+            };
+            let value = current
+                .checked_add(delta)
+                .ok_or(TransientError::NotAnInteger)?;
+
+            tx.insert_member(&member, value.to_string().as_bytes())?;
+            hash.kind = CollectionKind::Hash {
+                len: len + added
+            };
+            tx.set_collection(&key, &hash)?;
+
+            Ok(value)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+}
+
+/// Returns the number of fields of the hash.
+fn hash_len(hash: &Collection) -> Result<u64, TransientError> {
+    match hash.kind {
+        CollectionKind::Hash {
+            len
+        } => Ok(len),
+        _ => Err(TransientError::WrongType)
+    }
+}
@@ -0,0 +1,399 @@
+//! This module defines the secondary indexes, which find the keys by a term
+//! extracted from their value, e.g. the `tenant_id` field of JSON documents.
+//!
+//! An index is a name and an extractor, a function which returns the terms of
+//! a value. The entries of an index are stored in the `Index` tree under the
+//! name of the index, the term and the key, and are written in the same
+//! transaction as the value, whether it is set, removed or expires, so a query
+//! never sees a key under a term its value doesn't have.
+//!
+//! The extractors are code, so they are not persisted: the indexes have to be
+//! created again every time the database is opened, which rebuilds them from
+//! the stored values.
+//!
+//! If the encryption is enabled, the terms are stored as their HMAC under a
+//! key derived from the current key when the index is created, so the entries
+//! don't reveal the values they were extracted from.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::ops::Bound;
+use std::sync::{
+    Arc,
+    RwLock,
+    RwLockReadGuard
+};
+
+use ring::hmac;
+use serde_json::Value;
+
+use crate::db::encoding::ValueEncoding;
+use crate::db::errors::TransientError;
+use crate::db::iter::prefix_end;
+use crate::db::storage::{
+    StorageTransaction,
+    StorageTxError,
+    TreeKind
+};
+use crate::{
+    DB,
+    Metadata,
+    ValueFormat
+};
+
+/// Returns the terms a value is indexed under.
+pub(crate) type Extractor = Arc<dyn Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync>;
+
+/// An index created on the database.
+#[derive(Clone)]
+pub(crate) struct Index {
+    extractor: Extractor,
+    /// The key the terms are hashed with, `None` if the encryption is
+    /// disabled
+    term_key: Option<hmac::Key>
+}
+
+impl Index {
+    /// Returns the terms of the value as they are stored, none if there is
+    /// no value.
+    fn terms(&self, name: &str, value: Option<&[u8]>) -> Vec<Vec<u8>> {
+        value
+            .map(|v| (self.extractor)(v))
+            .unwrap_or_default()
+            .iter()
+            .map(|t| self.stored_term(name, t))
+            .collect()
+    }
+
+    /// Returns the term as it is stored in the entries of the index.
+    fn stored_term(&self, name: &str, term: &[u8]) -> Vec<u8> {
+        match &self.term_key {
+            Some(k) => {
+                hmac::sign(k, &[&name_prefix(name)[..], term].concat())
+                    .as_ref()
+                    .to_vec()
+            },
+            None => term.to_vec()
+        }
+    }
+}
+
+/// The indexes by name.
+pub(crate) type Indexes = HashMap<String, Index>;
+
+/// The indexes created on the database.
+#[derive(Default)]
+pub(crate) struct IndexRegistry {
+    indexes: RwLock<Indexes>
+}
+
+impl Debug for IndexRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<String> = match self.indexes.read() {
+            Ok(indexes) => indexes.keys().cloned().collect(),
+            Err(_) => Vec::new()
+        };
+
+        f.debug_struct("IndexRegistry")
+            .field("indexes", &names)
+            .finish()
+    }
+}
+
+impl IndexRegistry {
+    /// Locks the indexes for reading.
+    ///
+    /// Every write holds this lock for its whole transaction, so once an index
+    /// is registered, every write which did not see it has already committed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PoisonedMutex` if a thread panicked while holding the lock.
+    pub(crate) fn read(&self) -> Result<RwLockReadGuard<'_, Indexes>, TransientError> {
+        self.indexes
+            .read()
+            .map_err(|_| TransientError::PoisonedMutex)
+    }
+
+    fn get(&self, name: &str) -> Result<Index, TransientError> {
+        self.read()?
+            .get(name)
+            .cloned()
+            .ok_or(TransientError::IndexNotFound)
+    }
+}
+
+/// Updates the entries of every index after the value of the key changed from
+/// `old` to `new`, `None` meaning the key does not exist.
+///
+/// The values are given as stored along with their format, they are decoded
+/// before reaching the extractors. A value which cannot be decoded has no
+/// terms, so a corrupted value never prevents the key from being written,
+/// removed or expired; the entries it leaves behind are removed by
+/// `DB::rebuild_index`.
+///
+/// # Errors
+///
+/// Returns an error if the entries cannot be written.
+pub(crate) fn update_entries(
+    tx: &dyn StorageTransaction,
+    indexes: &Indexes,
+    values: &ValueEncoding,
+    key: &[u8],
+    old: Option<(&[u8], ValueFormat)>,
+    new: Option<(&[u8], ValueFormat)>
+) -> Result<(), StorageTxError> {
+    if indexes.is_empty() {
+        return Ok(());
+    }
+    let old = old.and_then(|(v, f)| indexed_value(values, key, v, f));
+    let new = new.and_then(|(v, f)| indexed_value(values, key, v, f));
+    let (old, new) = (old.as_deref(), new.as_deref());
+
+    for (name, index) in indexes {
+        let old_terms = index.terms(name, old);
+        let new_terms = index.terms(name, new);
+
+        for term in old_terms.iter().filter(|t| !new_terms.contains(t)) {
+            tx.remove(TreeKind::Index, &entry_key(name, term, key))?;
+        }
+        for term in &new_terms {
+            tx.insert(TreeKind::Index, &entry_key(name, term, key), &[])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns an extractor which indexes the JSON documents by a field, given as
+/// a path of names separated by dots, e.g. `owner.tenant_id`.
+///
+/// Strings are indexed by their content, numbers and booleans by their JSON
+/// text, and arrays by each of their elements. Values which are not JSON
+/// objects or lack the field are not indexed.
+pub fn json_field(path: &str) -> impl Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static {
+    let path: Vec<String> = path.split('.').map(str::to_string).collect();
+
+    move |value: &[u8]| {
+        let document: Value = match serde_json::from_slice(value) {
+            Ok(d) => d,
+            Err(_) => return Vec::new()
+        };
+
+        let field = path
+            .iter()
+            .try_fold(&document, |v, name| v.as_object()?.get(name));
+        match field {
+            Some(Value::Array(elements)) => elements.iter().filter_map(json_term).collect(),
+            Some(v) => json_term(v).into_iter().collect(),
+            None => Vec::new()
+        }
+    }
+}
+
+fn json_term(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::String(s) => Some(s.as_bytes().to_vec()),
+        Value::Number(_) | Value::Bool(_) => Some(value.to_string().into_bytes()),
+        _ => None
+    }
+}
+
+impl DB {
+    /// Creates an index which finds the keys by the terms the extractor
+    /// returns for their value, and builds it from the stored values. An
+    /// index of the same name is replaced.
+    ///
+    /// Indexes are not persisted, so they have to be created again every time
+    /// the database is opened. Collections (lists, hashes, ...) are never
+    /// indexed. If the encryption is enabled, the terms are hashed with a key
+    /// derived from the current key, rebuilding the index also rehashes them
+    /// after the key was rotated.
+    ///
+    /// This waits for the running transactions to commit, and reads every key
+    /// of the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be built.
+    pub fn create_index<F>(&self, name: &str, extractor: F) -> Result<(), TransientError>
+    where
+        F: Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static
+    {
+        let index = Index {
+            extractor: Arc::new(extractor),
+            term_key: self.values.encryption.index_key()?
+        };
+        self.indexes
+            .indexes
+            .write()
+            .map_err(|_| TransientError::PoisonedMutex)?
+            .insert(name.to_string(), index);
+
+        self.rebuild_index(name)
+    }
+
+    /// Removes the index and its entries, returns false if it did not exist.
+    ///
+    /// The entries are removed in a single transaction, while the index is
+    /// still locked for writing, so no write can add an entry back and an
+    /// index created again with the same name starts empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entries cannot be removed, the index is then
+    /// left in place.
+    pub fn drop_index(&self, name: &str) -> Result<bool, TransientError> {
+        let mut indexes = self
+            .indexes
+            .indexes
+            .write()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        let prefix = name_prefix(name);
+        let end = prefix_end(&prefix);
+        let end = match &end {
+            Some(e) => Bound::Excluded(&e[..]),
+            None => Bound::Unbounded
+        };
+        self.storage.transaction(&mut |tx| {
+            for (entry, _) in tx.range(TreeKind::Index, Bound::Included(&prefix), end)? {
+                tx.remove(TreeKind::Index, &entry)?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(indexes.remove(name).is_some())
+    }
+
+    /// Returns the keys indexed under the term, ordered by key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `IndexNotFound` if the index was not created, or an error if
+    /// the entries cannot be read.
+    pub fn query_index<T: AsRef<[u8]>>(
+        &self,
+        name: &str,
+        term: &T
+    ) -> Result<Vec<Vec<u8>>, TransientError> {
+        let index = self.indexes.get(name)?;
+
+        let prefix = term_prefix(name, &index.stored_term(name, term.as_ref()));
+        self.prefix_range(TreeKind::Index, &prefix)
+            .map(|i| Ok(i?.0[prefix.len()..].to_vec()))
+            .collect()
+    }
+
+    /// Rebuilds the index from the stored values, removing the entries whose
+    /// key no longer has the term and adding the missing ones.
+    ///
+    /// Every entry is checked in its own transaction, so the database stays
+    /// writable while the index is rebuilt. Values which cannot be decoded are
+    /// not indexed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `IndexNotFound` if the index was not created, or an error if
+    /// the entries cannot be read or written.
+    pub fn rebuild_index(&self, name: &str) -> Result<(), TransientError> {
+        let index = self.indexes.get(name)?;
+
+        let prefix = name_prefix(name);
+        for i in self.prefix_range(TreeKind::Index, &prefix) {
+            let (entry, _) = i?;
+            let (term, key) =
+                split_entry(&entry[prefix.len()..]).ok_or(TransientError::ParsingFromByteError)?;
+
+            self.storage.transaction(&mut |tx| {
+                let value = read_indexed_value(tx, &self.values, key)?;
+                if !index
+                    .terms(name, value.as_deref())
+                    .iter()
+                    .any(|t| t == term)
+                {
+                    tx.remove(TreeKind::Index, &entry)?;
+                }
+
+                Ok(())
+            })?;
+        }
+
+        for i in self.storage.iter(TreeKind::Data) {
+            let (key, _) = i?;
+
+            self.storage.transaction(&mut |tx| {
+                let value = read_indexed_value(tx, &self.values, &key)?;
+                for term in index.terms(name, value.as_deref()) {
+                    tx.insert(TreeKind::Index, &entry_key(name, &term, &key), &[])?;
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the stored value decoded, `None` if it is a collection or cannot
+/// be decoded, which are not indexed.
+fn indexed_value<'a>(
+    values: &ValueEncoding,
+    key: &[u8],
+    value: &'a [u8],
+    format: ValueFormat
+) -> Option<Cow<'a, [u8]>> {
+    if format.collection.is_some() {
+        return None;
+    }
+
+    values.decode(key, value, format).ok()
+}
+
+/// Reads the value of the key in the transaction, see `indexed_value`.
+fn read_indexed_value(
+    tx: &dyn StorageTransaction,
+    values: &ValueEncoding,
+    key: &[u8]
+) -> Result<Option<Vec<u8>>, StorageTxError> {
+    let (value, meta) = match (tx.get(TreeKind::Data, key)?, tx.get(TreeKind::Meta, key)?) {
+        (Some(v), Some(m)) => (v, m),
+        _ => return Ok(None)
+    };
+    let format = match Metadata::from_u8(&meta) {
+        Ok(m) => m.format,
+        Err(_) => return Ok(None)
+    };
+
+    Ok(indexed_value(values, key, &value, format).map(Cow::into_owned))
+}
+
+/// The name and the term are prefixed by their length, so an index or a term
+/// never shares a prefix with another one.
+fn name_prefix(name: &str) -> Vec<u8> {
+    [&(name.len() as u32).to_be_bytes()[..], name.as_bytes()].concat()
+}
+
+fn term_prefix(name: &str, term: &[u8]) -> Vec<u8> {
+    [
+        &name_prefix(name)[..],
+        &(term.len() as u32).to_be_bytes(),
+        term
+    ]
+    .concat()
+}
+
+fn entry_key(name: &str, term: &[u8], key: &[u8]) -> Vec<u8> {
+    [term_prefix(name, term), key.to_vec()].concat()
+}
+
+/// Splits an entry stripped of its name prefix into its term and its key.
+fn split_entry(entry: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u32::from_be_bytes(entry.get(..4)?.try_into().ok()?) as usize;
+    let rest = &entry[4..];
+
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
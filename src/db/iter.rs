@@ -1,7 +1,14 @@
 use std::error::Error;
+use std::ops::Bound;
 use std::str::from_utf8;
 use std::sync::Arc;
 
+use crate::db::encoding::ValueEncoding;
+use crate::db::storage::{
+    StorageBackend,
+    StorageIter,
+    TreeKind
+};
 use crate::{
     DB,
     Metadata
@@ -10,66 +17,114 @@ use crate::{
 /// This is an iterator struct that represents the Database main iterator
 /// struct.
 pub struct DataIter {
-    pub data: (sled::Iter, Arc<sled::Tree>)
+    pub data: (StorageIter, Arc<dyn StorageBackend>),
+    /// Decodes the values
+    values: Arc<ValueEncoding>
 }
 
 impl Iterator for DataIter {
     type Item = Result<(String, String, Metadata), Box<dyn Error>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let data_iter = &mut self.data.0;
+        loop {
+            let data = match self.data.0.next()? {
+                Ok(a) => a,
+                Err(e) => {
+                    return Some(Err(Box::new(e)));
+                }
+            };
 
-        let data = match data_iter.next()? {
-            Ok(a) => a,
-            Err(e) => {
-                return Some(Err(Box::new(e)));
-            }
-        };
+            let kb = data.0;
 
-        let (kb, vb) = data;
+            // Read again along with the metadata, which says how the value is stored
+            let stored = match self.data.1.get_many(&[TreeKind::Data, TreeKind::Meta], &kb) {
+                Ok(a) => a,
+                Err(e) => {
+                    return Some(Err(Box::new(e)));
+                }
+            };
+            let (vb, mb) = match <[_; 2]>::try_from(stored) {
+                Ok([Some(v), Some(m)]) => (v, m),
+                // Removed since it was iterated over
+                _ => continue
+            };
 
-        let meta_tree = &mut self.data.1;
+            let meta = match Metadata::from_u8(&mb) {
+                Ok(a) => a,
+                Err(e) => {
+                    return Some(Err(Box::new(e)));
+                }
+            };
 
-        let mb = match meta_tree.get(&kb) {
-            Ok(a) => a,
-            Err(e) => {
-                return Some(Err(Box::new(e)));
+            // The collections have no value of their own
+            if meta.format.collection.is_some() {
+                continue;
             }
-        }?;
 
-        let key = match from_utf8(&kb) {
-            Ok(a) => a,
-            Err(e) => {
-                return Some(Err(Box::new(e)));
+            let key = match from_utf8(&kb) {
+                Ok(a) => a,
+                Err(e) => {
+                    return Some(Err(Box::new(e)));
+                }
             }
-        }
-        .to_string();
+            .to_string();
 
-        let value = match from_utf8(&vb) {
-            Ok(a) => a,
-            Err(e) => {
-                return Some(Err(Box::new(e)));
-            }
-        }
-        .to_string();
+            let vb = match self.values.decode_owned(&kb, vb, meta.format) {
+                Ok(a) => a,
+                Err(e) => {
+                    return Some(Err(Box::new(e)));
+                }
+            };
 
-        let meta = match Metadata::from_u8(&mb) {
-            Ok(a) => a,
-            Err(e) => {
-                return Some(Err(Box::new(e)));
+            let value = match from_utf8(&vb) {
+                Ok(a) => a,
+                Err(e) => {
+                    return Some(Err(Box::new(e)));
+                }
             }
-        };
+            .to_string();
 
-        Some(Ok((key, value, meta)))
+            return Some(Ok((key, value, meta)));
+        }
     }
 }
 
 impl DB {
+    /// Returns an ordered iterator over the keys of the tree starting with the
+    /// prefix.
+    pub(crate) fn prefix_range(&self, tree: TreeKind, prefix: &[u8]) -> StorageIter {
+        match prefix_end(prefix) {
+            Some(end) => {
+                self.storage
+                    .range(tree, Bound::Included(prefix), Bound::Excluded(&end))
+            },
+            None => {
+                self.storage
+                    .range(tree, Bound::Included(prefix), Bound::Unbounded)
+            },
+        }
+    }
+
     /// This function returns the iterator of the database, which will contain a
-    /// key and its corresponding value in each iteration, (key, value).
+    /// key and its corresponding value in each iteration, (key, value). The
+    /// collections are skipped.
     pub fn iter(&mut self) -> DataIter {
         DataIter {
-            data: (self.data_tree.iter(), self.meta_tree.clone())
+            data: (self.storage.iter(TreeKind::Data), self.storage.clone()),
+            values: Arc::clone(&self.values)
+        }
+    }
+}
+
+/// Returns the smallest key greater than every key starting with the prefix,
+/// `None` if there is none, i.e. the prefix is only made of 0xFF.
+pub(crate) fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
         }
     }
+    None
 }
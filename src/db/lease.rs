@@ -0,0 +1,208 @@
+//! This module defines the leases, locks which expire on their own through the
+//! TTL thread if their holder stops renewing them, e.g. because it crashed.
+//!
+//! Every acquisition returns a fencing token, which is greater than the token
+//! of every previous holder of the same lease. Resources guarded by the lease
+//! should reject writes carrying a token lower than the last one they saw, so
+//! a holder which lost its lease without noticing cannot corrupt them.
+
+use std::time::Duration;
+
+use bincode::serde::{
+    decode_from_slice,
+    encode_to_vec
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::DB;
+use crate::db::clock::millis_after;
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+use crate::db::transaction::TransactionalGuard;
+
+/// The prefix of the keys holding the leases, followed by the lease name.
+pub const LEASE_PREFIX: &[u8] = b"lease:";
+
+/// The prefix of the keys holding the last fencing token of each lease,
+/// followed by the lease name. These keys never expire, so tokens keep
+/// increasing after a lease expired.
+pub const LEASE_TOKEN_PREFIX: &[u8] = b"lease_token:";
+
+/// A lease held by an owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    /// The name of the lease
+    pub name: String,
+    /// The owner holding the lease
+    pub owner: String,
+    /// The fencing token of this acquisition
+    pub token: u64,
+    /// When the lease expires if it is not renewed, in milliseconds since the
+    /// UNIX epoch
+    pub expires_at: u64
+}
+
+impl Lease {
+    fn to_u8(&self) -> Result<Vec<u8>, TransientError> {
+        encode_to_vec(self, bincode::config::standard())
+            .map_err(|_| TransientError::ParsingToByteError)
+    }
+
+    fn from_u8(slice: &[u8]) -> Result<Lease, TransientError> {
+        Ok(decode_from_slice(slice, bincode::config::standard())
+            .map_err(|_| TransientError::ParsingFromByteError)?
+            .0)
+    }
+}
+
+impl DB {
+    /// Acquires the lease for `ttl`, returns `None` if another owner holds it.
+    ///
+    /// Acquiring a lease the owner already holds renews it and keeps its
+    /// token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction fails.
+    pub fn acquire_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration
+    ) -> Result<Option<Lease>, TransientError> {
+        self.transaction(|tx| {
+            let now = self.clock.now_millis();
+
+            let token = match current_lease(tx, name, now)? {
+                Some(l) if l.owner != owner => return Ok(None),
+                Some(l) => l.token,
+                None => {
+                    let token_key = [LEASE_TOKEN_PREFIX, name.as_bytes()].concat();
+                    let token = match tx.get_raw(&token_key)? {
+                        Some(t) => {
+                            u64::from_be_bytes(
+                                t.try_into()
+                                    .map_err(|_| TransientError::ParsingToU64ByteFailed)?
+                            ) + 1
+                        },
+                        None => 1
+                    };
+                    tx.set_raw(&token_key, &token.to_be_bytes(), None)?;
+                    token
+                }
+            };
+
+            Ok(Some(write_lease(tx, name, owner, token, ttl, now)?))
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Extends the lease by `ttl` from now, returns `None` if the owner
+    /// doesn't hold it anymore.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction fails.
+    pub fn renew_lease(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration
+    ) -> Result<Option<Lease>, TransientError> {
+        self.transaction(|tx| {
+            let now = self.clock.now_millis();
+
+            match current_lease(tx, name, now)? {
+                Some(l) if l.owner == owner => {
+                    Ok(Some(write_lease(tx, name, owner, l.token, ttl, now)?))
+                },
+                _ => Ok(None)
+            }
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Releases the lease, returns false if the owner doesn't hold it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction fails.
+    pub fn release_lease(&self, name: &str, owner: &str) -> Result<bool, TransientError> {
+        self.transaction(|tx| {
+            match current_lease(tx, name, self.clock.now_millis())? {
+                Some(l) if l.owner == owner => {
+                    tx.remove_raw(&lease_key(name))?;
+                    Ok(true)
+                },
+                _ => Ok(false)
+            }
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Returns the current holder of the lease, `None` if it is free.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lease cannot be read or deserialized.
+    pub fn get_lease(&self, name: &str) -> Result<Option<Lease>, TransientError> {
+        let lease = match self.get_raw(&lease_key(name))? {
+            Some(l) => Lease::from_u8(&l)?,
+            None => return Ok(None)
+        };
+
+        // The TTL thread works with seconds, so it may not have removed it yet
+        if self.clock.now_millis() >= lease.expires_at {
+            return Ok(None);
+        }
+
+        Ok(Some(lease))
+    }
+}
+
+fn lease_key(name: &str) -> Vec<u8> {
+    [LEASE_PREFIX, name.as_bytes()].concat()
+}
+
+/// Returns the lease if it is held and not expired.
+fn current_lease(
+    tx: &mut TransactionalGuard,
+    name: &str,
+    now: u64
+) -> Result<Option<Lease>, TransientError> {
+    match tx.get_raw(&lease_key(name))? {
+        Some(l) => {
+            let lease = Lease::from_u8(&l)?;
+            Ok((now < lease.expires_at).then_some(lease))
+        },
+        None => Ok(None)
+    }
+}
+
+fn write_lease(
+    tx: &mut TransactionalGuard,
+    name: &str,
+    owner: &str,
+    token: u64,
+    ttl: Duration,
+    now: u64
+) -> Result<Lease, TransientError> {
+    let lease = Lease {
+        name: name.to_string(),
+        owner: owner.to_string(),
+        token,
+        expires_at: millis_after(now, ttl)
+    };
+
+    // Rounded up, and one more second since the TTL thread rounds down, the
+    // expiry itself is checked against `expires_at`
+    let key_ttl = Duration::from_secs((lease.expires_at - now).div_ceil(1000) + 1);
+    tx.set_raw(&lease_key(name), &lease.to_u8()?, Some(key_ttl))?;
+
+    Ok(lease)
+}
@@ -0,0 +1,303 @@
+//! This module defines the lists, whose elements are stored one by one under
+//! their position, so pushing and popping only writes the elements which
+//! changed and the header of the list.
+//!
+//! Like in Redis, a list is created by its first push and removed once its
+//! last element is popped or trimmed away. The key of a list is a regular
+//! key, so `DB::expire`, `DB::ttl` and `DB::remove` work on the whole list.
+
+use std::ops::Bound;
+
+use crate::DB;
+use crate::db::collection::{
+    Collection,
+    CollectionKind
+};
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+use crate::db::storage::TreeKind;
+use crate::db::transaction::TransactionalGuard;
+
+/// The position of the first element of a new list, so it can grow both ways.
+const LIST_START: u64 = u64::MAX / 2;
+
+/// The end of a list an element is pushed to or popped from.
+#[derive(Debug, Clone, Copy)]
+enum End {
+    Front,
+    Back
+}
+
+impl DB {
+    /// Pushes the values to the front of the list, creating it if needed, and
+    /// returns the length of the list.
+    ///
+    /// The values are pushed one after the other, so the last one ends up
+    /// first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a list, or an
+    /// error if the transaction fails.
+    pub fn lpush<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        values: &[V]
+    ) -> Result<u64, TransientError> {
+        self.push(key.as_ref(), values, End::Front)
+    }
+
+    /// Pushes the values to the back of the list, creating it if needed, and
+    /// returns the length of the list.
+    ///
+    /// # Errors
+    ///
+    /// Same as `lpush`.
+    pub fn rpush<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        values: &[V]
+    ) -> Result<u64, TransientError> {
+        self.push(key.as_ref(), values, End::Back)
+    }
+
+    /// Removes and returns the first element of the list, `None` if the list
+    /// does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Same as `lpush`.
+    pub fn lpop<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Vec<u8>>, TransientError> {
+        self.pop(key.as_ref(), End::Front)
+    }
+
+    /// Removes and returns the last element of the list, `None` if the list
+    /// does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Same as `lpush`.
+    pub fn rpop<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Vec<u8>>, TransientError> {
+        self.pop(key.as_ref(), End::Back)
+    }
+
+    /// Returns the length of the list, 0 if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a list.
+    pub fn llen<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        match self.get_collection(key)? {
+            Some(list) => {
+                let (head, tail) = list_bounds(&list)?;
+                Ok(tail - head)
+            },
+            None => Ok(0)
+        }
+    }
+
+    /// Returns the elements from `start` to `stop` included.
+    ///
+    /// Negative positions count from the end of the list, -1 being the last
+    /// element. Positions out of the list are clamped, so `lrange(key, 0, -1)`
+    /// returns the whole list.
+    ///
+    /// # Errors
+    ///
+    /// Same as `lpush`.
+    pub fn lrange<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: i64,
+        stop: i64
+    ) -> Result<Vec<Vec<u8>>, TransientError> {
+        let list = match self.get_collection(key)? {
+            Some(l) => l,
+            None => return Ok(Vec::new())
+        };
+        let (head, tail) = list_bounds(&list)?;
+
+        let (first, last) = match resolve_range(start, stop, tail - head) {
+            Some(r) => r,
+            None => return Ok(Vec::new())
+        };
+
+        // Read without a transaction like the other collections, the elements
+        // popped in the meantime are left out
+        let start = list.member_key(&(head + first).to_be_bytes());
+        let end = list.member_key(&(head + last).to_be_bytes());
+        self.storage
+            .range(
+                TreeKind::Members,
+                Bound::Included(&start),
+                Bound::Included(&end)
+            )
+            .map(|i| {
+                let (k, v) = i?;
+                self.values.decode_member(&k, &v)
+            })
+            .collect()
+    }
+
+    /// Trims the list so it only holds the elements from `start` to `stop`
+    /// included, with the same positions as `lrange`. The list is removed if
+    /// nothing is left.
+    ///
+    /// # Errors
+    ///
+    /// Same as `lpush`.
+    pub fn ltrim<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: i64,
+        stop: i64
+    ) -> Result<(), TransientError> {
+        let key = key.as_ref();
+
+        self.transaction(|tx| {
+            let mut list = match tx.get_collection(&key)? {
+                Some(l) => l,
+                None => return Ok(())
+            };
+            let (head, tail) = list_bounds(&list)?;
+
+            let (new_head, new_tail) = match resolve_range(start, stop, tail - head) {
+                Some((first, last)) => (head + first, head + last + 1),
+                None => (tail, tail)
+            };
+
+            for pos in (head..new_head).chain(new_tail..tail) {
+                tx.remove_member(&list.member_key(&pos.to_be_bytes()))?;
+            }
+
+            update_or_remove(tx, key, &mut list, new_head, new_tail)?;
+
+            Ok(())
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    fn push<V: AsRef<[u8]>>(
+        &self,
+        key: &[u8],
+        values: &[V],
+        end: End
+    ) -> Result<u64, TransientError> {
+        if values.is_empty() {
+            return self.llen(&key);
+        }
+
+        self.transaction(|tx| {
+            let mut list = match tx.get_collection(&key)? {
+                Some(l) => l,
+                None => {
+                    Collection::new(CollectionKind::List {
+                        head: LIST_START,
+                        tail: LIST_START
+                    })?
+                },
+            };
+            let (mut head, mut tail) = list_bounds(&list)?;
+
+            for v in values {
+                let pos = match end {
+                    End::Front => {
+                        head -= 1;
+                        head
+                    },
+                    End::Back => {
+                        tail += 1;
+                        tail - 1
+                    }
+                };
+                tx.insert_member(&list.member_key(&pos.to_be_bytes()), v.as_ref())?;
+            }
+
+            list.kind = CollectionKind::List {
+                head,
+                tail
+            };
+            tx.set_collection(&key, &list)?;
+
+            Ok(tail - head)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    fn pop(&self, key: &[u8], end: End) -> Result<Option<Vec<u8>>, TransientError> {
+        self.transaction(|tx| {
+            let mut list = match tx.get_collection(&key)? {
+                Some(l) => l,
+                None => return Ok(None)
+            };
+            let (mut head, mut tail) = list_bounds(&list)?;
+
+            let pos = match end {
+                End::Front => {
+                    head += 1;
+                    head - 1
+                },
+                End::Back => {
+                    tail -= 1;
+                    tail
+                }
+            };
+            let value = tx.remove_member(&list.member_key(&pos.to_be_bytes()))?;
+
+            update_or_remove(tx, key, &mut list, head, tail)?;
+
+            Ok(value)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+}
+
+/// Writes the new bounds of the list, or removes it if it is empty.
+fn update_or_remove(
+    tx: &mut TransactionalGuard,
+    key: &[u8],
+    list: &mut Collection,
+    head: u64,
+    tail: u64
+) -> Result<(), TransientError> {
+    if head == tail {
+        return tx.remove_raw(&key);
+    }
+
+    list.kind = CollectionKind::List {
+        head,
+        tail
+    };
+    tx.set_collection(&key, list)
+}
+
+/// Returns the bounds of the list.
+fn list_bounds(list: &Collection) -> Result<(u64, u64), TransientError> {
+    match list.kind {
+        CollectionKind::List {
+            head,
+            tail
+        } => Ok((head, tail)),
+        _ => Err(TransientError::WrongType)
+    }
+}
+
+/// Resolves the positions given to `lrange` and `ltrim` into offsets from the
+/// head of the list, both included, `None` if the range is empty.
+fn resolve_range(start: i64, stop: i64, len: u64) -> Option<(u64, u64)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+
+    (start <= stop && start < len).then_some((start as u64, stop as u64))
+}
@@ -2,38 +2,92 @@
 //! It includes the `DB` struct and its implementation, which provides the
 //! primary API for interacting with the database.
 
+pub mod async_db;
+pub mod bloom;
+pub mod cache;
+pub mod cache_aside;
+pub mod clock;
+pub(crate) mod collection;
+pub mod compression;
+pub mod config;
+pub mod durability;
+pub(crate) mod encoding;
+pub mod encryption;
 pub mod errors;
+pub mod events;
+pub mod hash;
+pub mod index;
 pub mod iter;
+pub mod lease;
+pub mod list;
+pub mod rate_limit;
+pub mod scan;
+pub mod session;
+pub mod sorted_set;
+pub mod stats;
+pub mod storage;
+pub mod stream;
 pub mod transaction;
+pub(crate) mod workers;
 
 use std::fs::File;
 use std::io::{
+    BufReader,
+    BufWriter,
     ErrorKind,
     Read,
+    Seek,
     Write
 };
 use std::path::Path;
-use std::str::from_utf8;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use std::thread::{
     self,
     JoinHandle
 };
-use std::time::{
-    Duration,
-    SystemTime,
-    UNIX_EPOCH
-};
+use std::time::Duration;
 
+use bloom::BloomFilter;
+use cache::HotCache;
+use cache_aside::SingleFlight;
 use chrono::Local;
-use errors::TransientError;
-use sled::Config;
-use sled::transaction::{
-    ConflictableTransactionError,
+use clock::{
+    Clock,
+    SystemClock
+};
+use collection::{
+    dropped_collection,
+    purge_members
+};
+use compression::{
+    CompressionStats,
+    Compressor
+};
+use config::DBConfig;
+use durability::{
+    GroupCommit,
+    spawn_flush_thread
+};
+use encoding::ValueEncoding;
+use encryption::Encryptor;
+use errors::{
     TransactionError,
-    Transactional
+    TransientError
+};
+use events::ExpiryNotifier;
+use index::{
+    IndexRegistry,
+    update_entries
 };
+use storage::{
+    SledBackend,
+    StorageBackend,
+    StorageTxError,
+    TreeKind
+};
+use tracing::warn;
+use workers::Workers;
 use zip::write::SimpleFileOptions;
 use zip::{
     ZipArchive,
@@ -43,7 +97,8 @@ use zip::{
 use crate::metrics::Metrics;
 use crate::{
     DB,
-    Metadata
+    Metadata,
+    ValueFormat
 };
 
 impl DB {
@@ -59,42 +114,69 @@ impl DB {
     /// Returns a `sled::Error` if the database cannot be opened at the given
     /// path.
     pub fn new(path: &Path) -> Result<DB, TransientError> {
-        let db = Config::new()
-            .path(path)
-            .cache_capacity(512 * 1024 * 1024)
-            .open()
-            .map_err(|e| {
-                TransientError::SledError {
-                    error: e
-                }
-            })?;
+        DB::with_storage(SledBackend::open(path)?)
+    }
 
-        let data_tree = Arc::new(db.open_tree("data_tree").map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?);
-        let meta_tree = Arc::new(db.open_tree("freq_tree").map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?);
-        let ttl_tree = Arc::new(db.open_tree("ttl_tree").map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?);
+    /// Creates a new `DB` instance on top of the given storage backend, with
+    /// the default config.
+    ///
+    /// # Errors
+    ///
+    /// This function currently never fails, the Result is kept to mirror
+    /// `DB::new`.
+    pub fn with_storage<S: StorageBackend + 'static>(storage: S) -> Result<DB, TransientError> {
+        DB::with_config(storage, DBConfig::default())
+    }
 
-        let ttl_tree_clone = Arc::clone(&ttl_tree);
-        let meta_tree_clone = Arc::clone(&meta_tree);
-        let data_tree_clone = Arc::clone(&data_tree);
+    /// Creates a new `DB` instance on top of the given storage backend, with
+    /// the given config.
+    ///
+    /// This spawns the same background threads as `DB::new`, the size thread
+    /// is only spawned if the backend is persisted to a path, and the flush
+    /// thread only if the durability policy needs one.
+    ///
+    /// # Errors
+    ///
+    /// This function currently never fails, the Result is kept to mirror
+    /// `DB::new`.
+    pub fn with_config<S: StorageBackend + 'static>(
+        storage: S,
+        config: DBConfig
+    ) -> Result<DB, TransientError> {
+        let storage: Arc<dyn StorageBackend> = Arc::new(storage);
+        let storage_clone = Arc::clone(&storage);
+
+        let cache = config.cache_capacity.map(|c| Arc::new(HotCache::new(c)));
+        let cache_clone = cache.clone();
+
+        let bloom = config.bloom_filter.map(|b| Arc::new(BloomFilter::new(b)));
+        let bloom_clone = bloom.clone();
+
+        let expirations = Arc::new(ExpiryNotifier::default());
+        let expirations_clone = Arc::clone(&expirations);
+
+        let indexes = Arc::new(IndexRegistry::default());
+        let indexes_clone = Arc::clone(&indexes);
+
+        let values = Arc::new(ValueEncoding {
+            compression: Compressor::new(config.compression),
+            encryption: Encryptor::new(config.encryption)
+        });
+        let values_clone = Arc::clone(&values);
+
+        let clock: Arc<dyn Clock> = config.clock.unwrap_or_else(|| Arc::new(SystemClock));
+        let clock_clone = Arc::clone(&clock);
+
+        if let Some(bloom) = &bloom {
+            bloom.rebuild(storage.iter(TreeKind::Meta).map(|i| i.map(|(k, _)| k)))?;
+        }
 
         let shutdown: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
         let shutdown_clone_ttl_thread = Arc::clone(&shutdown);
         let shutdown_clone_size_thread = Arc::clone(&shutdown);
 
         // Convert to pathbuf to gain ownership
-        let path_buf = path.to_path_buf();
+        let path = storage.path().map(|p| p.to_path_buf());
 
         // TODO: Later have a clean up thread that checks if the following thread is
         // fine and spawn it back and join the thread lol
@@ -107,86 +189,71 @@ impl DB {
                     break;
                 }
 
-                let keys = ttl_tree_clone.iter();
-
-                for i in keys {
-                    let full_key = i.map_err(|e| {
-                        TransientError::SledError {
-                            error: e
-                        }
-                    })?;
+                // A failed pass is retried on the next tick, the thread only
+                // stops on shutdown
+                if let Err(e) = remove_expired_keys(
+                    storage_clone.as_ref(),
+                    clock_clone.as_ref(),
+                    cache_clone.as_deref(),
+                    bloom_clone.as_deref(),
+                    &indexes_clone,
+                    &values_clone,
+                    &expirations_clone
+                ) {
+                    warn!("Removing the expired keys failed: {e}");
+                }
+            }
+            Ok(())
+        });
 
-                    // NOTE: The reason time is 14 u8s long is because it is being stored like
-                    // this ([time,key], key) not ((time,key), key)
-                    let key = full_key.0;
-                    let key_byte = full_key.1;
+        let size_thread = path.as_ref().map(|_| {
+            let storage = Arc::clone(&storage);
+            thread::spawn(move || -> Result<(), TransientError> {
+                loop {
+                    thread::sleep(Duration::new(0, 100000000));
 
-                    if key.len() < 8 {
-                        Err(TransientError::ParsingToU64ByteFailed)?
+                    if shutdown_clone_size_thread.load(std::sync::atomic::Ordering::SeqCst) {
+                        break;
                     }
 
-                    let time_byte: [u8; 8] = (&key[..8])
-                        .try_into()
-                        .map_err(|_| TransientError::ParsingToByteError)?;
-
-                    let time = u64::from_be_bytes(time_byte);
-                    let curr_time = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Cant get SystemTime")
-                        .as_secs();
-
-                    if curr_time >= time {
-                        let l: Result<(), TransactionError<()>> =
-                            (&*data_tree_clone, &*meta_tree_clone, &*ttl_tree_clone).transaction(
-                                |(data, freq, ttl_tree_clone)| {
-                                    let byte = &key_byte;
-                                    data.remove(byte)?;
-                                    freq.remove(byte)?;
-
-                                    let _ = ttl_tree_clone.remove([&time_byte, &byte[..]].concat());
-
-                                    // Prometheus Metrics
-                                    Metrics::dec_keys_total("data");
-                                    Metrics::dec_keys_total("meta");
-                                    Metrics::dec_keys_total("ttl");
-                                    Metrics::increment_ttl_expired_keys();
-
-                                    Ok(())
-                                }
-                            );
-                        l.map_err(|_| TransientError::SledTransactionError)?;
-                    } else {
-                        break;
+                    // A file removed during a compaction may fail the walk, the
+                    // size is read again on the next tick
+                    match storage.disk_size() {
+                        Ok(size) => Metrics::set_disk_size(size as f64),
+                        Err(e) => warn!("Reading the size of the database failed: {e}")
                     }
                 }
-            }
-            Ok(())
+                Ok(())
+            })
         });
 
-        let size_thread: JoinHandle<Result<(), TransientError>> = thread::spawn(move || {
-            loop {
-                thread::sleep(Duration::new(0, 100000000));
-
-                if shutdown_clone_size_thread.load(std::sync::atomic::Ordering::SeqCst) {
-                    break;
-                }
+        let group_commit = Arc::new(GroupCommit::default());
+        let flush_thread = spawn_flush_thread(
+            config.durability,
+            Arc::clone(&storage),
+            Arc::clone(&group_commit),
+            Arc::clone(&shutdown)
+        );
 
-                let metadata = path_buf
-                    .metadata()
-                    .map_err(|_| TransientError::DBMetadataNotFound)?;
-                Metrics::set_disk_size((metadata.len() as f64) / 1024.0 / 1024.0);
-            }
-            Ok(())
-        });
+        let threads = [Some(ttl_thread), size_thread, flush_thread]
+            .into_iter()
+            .flatten()
+            .collect();
 
         Ok(DB {
-            data_tree,
-            meta_tree,
-            ttl_tree,
-            ttl_thread: Some(ttl_thread),
-            size_thread: Some(size_thread),
-            shutdown,
-            path: path.to_path_buf()
+            storage,
+            workers: Arc::new(Workers::new(shutdown, threads)),
+            durability: config.durability,
+            group_commit,
+            max_transaction_retries: config.max_transaction_retries,
+            cache,
+            bloom,
+            single_flight: Arc::new(SingleFlight::default()),
+            expirations,
+            indexes,
+            values,
+            clock,
+            path: path.unwrap_or_default()
         })
     }
 
@@ -200,63 +267,7 @@ impl DB {
     /// This function can return an error if there's an issue with the
     /// underlying
     pub fn set(&self, key: &str, val: &str, ttl: Option<Duration>) -> Result<(), TransientError> {
-        let data_tree = &self.data_tree;
-        let freq_tree = &self.meta_tree;
-        let ttl_tree = &self.ttl_tree;
-        let byte = key.as_bytes();
-        let ttl_sec = match ttl {
-            Some(t) => {
-                let systime = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Cant get SystemTime");
-                Some((t + systime).as_secs())
-            },
-            None => None
-        };
-
-        let l: Result<(), TransactionError<()>> = (&**data_tree, &**freq_tree, &**ttl_tree)
-            .transaction(|(data, freq, ttl_tree)| {
-                match freq.get(byte)? {
-                    Some(m) => {
-                        let mut meta = Metadata::from_u8(&m)
-                            .map_err(|_| ConflictableTransactionError::Abort(()))?;
-                        if let Some(t) = meta.ttl {
-                            let _ = ttl_tree.remove([&t.to_be_bytes()[..], byte].concat());
-                        }
-                        meta.ttl = ttl_sec;
-                        freq.insert(
-                            byte,
-                            meta.to_u8()
-                                .map_err(|_| ConflictableTransactionError::Abort(()))?
-                        )?;
-                    },
-                    None => {
-                        freq.insert(
-                            byte,
-                            Metadata::new(ttl_sec)
-                                .to_u8()
-                                .map_err(|_| ConflictableTransactionError::Abort(()))?
-                        )?;
-                    }
-                }
-
-                data.insert(byte, val.as_bytes())?;
-
-                if let Some(d) = ttl_sec {
-                    ttl_tree.insert([&d.to_be_bytes()[..], byte].concat(), byte)?;
-                    Metrics::inc_keys_total("ttl");
-                };
-
-                Ok(())
-            });
-        l.map_err(|_| TransientError::SledTransactionError)?;
-
-        // Prometheus metrics
-        Metrics::increment_operations("set");
-        Metrics::inc_keys_total("data");
-        Metrics::inc_keys_total("meta");
-
-        Ok(())
+        self.set_raw(&key, &val, ttl)
     }
 
     /// Retrieves the value for a given key.
@@ -266,22 +277,10 @@ impl DB {
     /// Returns an error if the value cannot be retrieved from the database or
     /// if the value is not valid UTF-8.
     pub fn get(&self, key: &str) -> Result<Option<String>, TransientError> {
-        let data_tree = &self.data_tree;
-        let byte = key.as_bytes();
-        let val = data_tree.get(byte).map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?;
-
-        Metrics::increment_operations("get");
-
-        match val {
+        match self.get_raw(&key)? {
             Some(val) => {
                 Ok(Some(
-                    from_utf8(&val)
-                        .map_err(|_| TransientError::ParsingToUTF8Error)?
-                        .to_string()
+                    String::from_utf8(val).map_err(|_| TransientError::ParsingToUTF8Error)?
                 ))
             },
             None => Ok(None)
@@ -295,39 +294,7 @@ impl DB {
     /// This function can return an error if the key does not exist or if there
     /// is an issue with the compare-and-swap operation.
     pub fn increment_frequency(&self, key: &str) -> Result<Option<()>, TransientError> {
-        let freq_tree = &self.meta_tree;
-        let byte = &key.as_bytes();
-
-        loop {
-            let metadata_opt = freq_tree.get(byte).map_err(|e| {
-                TransientError::SledError {
-                    error: e
-                }
-            })?;
-            let metadata = match metadata_opt {
-                Some(t) => t,
-                None => return Ok(None)
-            };
-            let meta =
-                Metadata::from_u8(&metadata).map_err(|_| TransientError::ParsingFromByteError)?;
-            let s = freq_tree.compare_and_swap(
-                byte,
-                Some(metadata),
-                Some(
-                    meta.freq_incretement()
-                        .to_u8()
-                        .map_err(|_| TransientError::ParsingToByteError)?
-                )
-            );
-            if let Ok(ss) = s
-                && ss.is_ok()
-            {
-                break;
-            }
-        }
-        Metrics::increment_operations("increment_frequency");
-
-        Ok(Some(()))
+        self.increment_frequency_raw(key.as_bytes())
     }
 
     /// Removes a key-value pair and its associated metadata from the database.
@@ -336,37 +303,7 @@ impl DB {
     ///
     /// Can return an error if the transaction to remove the data fails.
     pub fn remove(&self, key: &str) -> Result<(), TransientError> {
-        let data_tree = &self.data_tree;
-        let freq_tree = &self.meta_tree;
-        let ttl_tree = &self.ttl_tree;
-        let byte = &key.as_bytes();
-        let l: Result<(), TransactionError<()>> = (&**data_tree, &**freq_tree, &**ttl_tree)
-            .transaction(|(data, freq, ttl_tree)| {
-                data.remove(*byte)?;
-                let meta = freq
-                    .get(byte)?
-                    .ok_or(ConflictableTransactionError::Abort(()))?;
-                let time = Metadata::from_u8(&meta)
-                    .map_err(|_| ConflictableTransactionError::Abort(()))?
-                    .ttl;
-                freq.remove(*byte)?;
-
-                Metrics::dec_keys_total("data");
-                Metrics::dec_keys_total("meta");
-
-                if let Some(t) = time {
-                    Metrics::dec_keys_total("ttl");
-
-                    let _ = ttl_tree.remove([&t.to_be_bytes()[..], &byte[..]].concat());
-                }
-
-                Ok(())
-            });
-        l.map_err(|_| TransientError::SledTransactionError)?;
-
-        Metrics::increment_operations("rm");
-
-        Ok(())
+        self.remove_raw(key)
     }
 
     /// Retrieves the metadata for a given key.
@@ -375,46 +312,114 @@ impl DB {
     ///
     /// Returns an error if the metadata cannot be retrieved or deserialized.
     pub fn get_metadata(&self, key: &str) -> Result<Option<Metadata>, TransientError> {
-        let freq_tree = &self.meta_tree;
-        let byte = key.as_bytes();
-        let meta = freq_tree.get(byte).map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?;
-        match meta {
-            Some(val) => {
-                Ok(Some(
-                    Metadata::from_u8(&val).map_err(|_| TransientError::ParsingFromByteError)?
-                ))
-            },
-            None => Ok(None)
+        self.get_metadata_raw(&key)
+    }
+
+    /// Returns true if the key exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be retrieved from the database.
+    pub fn exists<K: AsRef<[u8]>>(&self, key: &K) -> Result<bool, TransientError> {
+        if !self.may_contain(key.as_ref()) {
+            return Ok(false);
         }
+
+        Ok(self.checked_get(TreeKind::Meta, key.as_ref())?.is_some())
+    }
+
+    /// Returns the time left before the key expires.
+    ///
+    /// Returns `None` if the key does not exist or is persistent, an expired
+    /// key which the TTL thread didn't remove yet has `Duration::ZERO` left.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be retrieved or deserialized.
+    pub fn ttl<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Duration>, TransientError> {
+        let ttl = match self.get_metadata_raw(key)?.and_then(|m| m.ttl) {
+            Some(t) => t,
+            None => return Ok(None)
+        };
+
+        Ok(Some(Duration::from_secs(
+            ttl.saturating_sub(self.clock.now_secs())
+        )))
+    }
+
+    /// Sets the Time-To-Live of an existing key, replacing its previous one.
+    ///
+    /// Returns false if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction updating the metadata fails.
+    pub fn expire<K: AsRef<[u8]>>(&self, key: &K, ttl: Duration) -> Result<bool, TransientError> {
+        self.transaction(|tx| Ok(tx.expire(key, ttl)?))
+            .map_err(TransactionError::into_transient)
+    }
+
+    /// Removes the Time-To-Live of a key, making it persistent.
+    ///
+    /// Returns false if the key does not exist or was already persistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction updating the metadata fails.
+    pub fn persist<K: AsRef<[u8]>>(&self, key: &K) -> Result<bool, TransientError> {
+        self.transaction(|tx| Ok(tx.persist(key)?))
+            .map_err(TransactionError::into_transient)
+    }
+
+    /// Removes every key whose TTL has passed right away, instead of waiting
+    /// for the TTL thread, and returns how many were removed.
+    ///
+    /// Along with a `MockClock`, this makes expirations happen at a known
+    /// point of a test.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the expired keys cannot be read or removed.
+    pub fn remove_expired(&self) -> Result<u64, TransientError> {
+        remove_expired_keys(
+            self.storage.as_ref(),
+            self.clock.as_ref(),
+            self.cache.as_deref(),
+            self.bloom.as_deref(),
+            &self.indexes,
+            &self.values,
+            &self.expirations
+        )
     }
 
     /// Flushes all the trees in the database.
     ///
     /// # Errors
     ///
-    /// Returns an error if sled fails to flush the trees.
+    /// Returns an error if the backend fails to flush the trees.
     pub fn flush(&self) -> Result<(), TransientError> {
-        self.data_tree.flush().map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?;
-        self.meta_tree.flush().map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?;
-        self.ttl_tree.flush().map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?;
+        self.storage.flush()
+    }
 
-        Ok(())
+    /// Stops the background threads of the database and waits for them to
+    /// finish, for every handle of the database.
+    ///
+    /// The threads are stopped anyway when the last handle is dropped, this
+    /// is for stopping them at a known point, e.g. before the process exits
+    /// while handles are still held by other tasks. The handles stay usable,
+    /// but the expired keys are only removed by `remove_expired` from then on,
+    /// and the group commits flush on every write.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WorkerPanicked` if a background thread panicked, the error a
+    /// background thread stopped with, e.g. the last periodic flush failing,
+    /// or `PoisonedMutex` if a thread panicked while waiting for a group
+    /// commit.
+    pub fn close(&self) -> Result<(), TransientError> {
+        let stopped = self.workers.stop();
+        self.group_commit.close()?;
+        stopped
     }
 
     /// Backup the database to the corresponding path.
@@ -440,7 +445,26 @@ impl DB {
 
         let backup_name = format!("backup-{}.zip", Local::now().format("%Y-%m-%d_%H-%M-%S"));
 
-        let zip_file = File::create(path.join(&backup_name)).map_err(|_| {
+        let backup_path = path.join(&backup_name);
+        // An encrypted backup is first written in the clear, since the archive
+        // has to be complete before it is encrypted. The file is anonymous, so
+        // it cannot be opened by anyone else and is gone once closed
+        let clear = match self.values.encryption.is_enabled() {
+            true => {
+                Some(tempfile::tempfile().map_err(|e| {
+                    TransientError::IOError {
+                        error: e
+                    }
+                })?)
+            },
+            false => None
+        };
+
+        let zip_file = match &clear {
+            Some(c) => c.try_clone(),
+            None => File::create(&backup_path)
+        }
+        .map_err(|_| {
             TransientError::FolderNotFound {
                 path: path.to_path_buf()
             }
@@ -454,24 +478,18 @@ impl DB {
             }
         })?;
 
-        for i in self.data_tree.iter() {
-            let iu = i.map_err(|e| {
-                TransientError::SledError {
-                    error: e
-                }
-            })?;
-
-            let key = &iu.0;
-            let value = &iu.1;
-            let meta = self
-                .meta_tree
-                .get(key)
-                .map_err(|e| {
-                    TransientError::SledError {
-                        error: e
-                    }
-                })?
-                .ok_or(TransientError::MetadataNotFound)?;
+        for i in self.storage.iter(TreeKind::Data) {
+            let (key, _) = i?;
+
+            // Read again along with the metadata, which says how the value is stored
+            let stored = self
+                .storage
+                .get_many(&[TreeKind::Data, TreeKind::Meta], &key)?;
+            let (value, meta) = match <[_; 2]>::try_from(stored) {
+                Ok([Some(v), Some(m)]) => (v, m),
+                // Removed since it was iterated over
+                _ => continue
+            };
 
             // NOTE: A usize is diffrent on diffrent machines
             // and a usize will never exceed a u64 in length on paper lol
@@ -493,7 +511,7 @@ impl DB {
                     error: e
                 }
             })?;
-            zipw.write_all(key).map_err(|e| {
+            zipw.write_all(&key).map_err(|e| {
                 TransientError::IOError {
                     error: e
                 }
@@ -503,7 +521,7 @@ impl DB {
                     error: e
                 }
             })?;
-            zipw.write_all(value).map_err(|e| {
+            zipw.write_all(&value).map_err(|e| {
                 TransientError::IOError {
                     error: e
                 }
@@ -520,13 +538,42 @@ impl DB {
             })?;
         }
 
+        // The members of the collections have no metadata, they get their own file
+        zipw.start_file("members.epoch", options).map_err(|e| {
+            TransientError::ZipError {
+                error: e
+            }
+        })?;
+
+        for i in self.storage.iter(TreeKind::Members) {
+            let (key, value) = i?;
+
+            write_field(&mut zipw, &key)?;
+            write_field(&mut zipw, &value)?;
+        }
+
         zipw.finish().map_err(|e| {
             TransientError::ZipError {
                 error: e
             }
         })?;
 
-        let zip_file = File::open(path.join(backup_name)).map_err(|_| {
+        if let Some(mut clear) = clear {
+            let io_error = |e| {
+                TransientError::IOError {
+                    error: e
+                }
+            };
+            clear.rewind().map_err(io_error)?;
+            let mut archive = BufReader::new(clear);
+            let mut backup = BufWriter::new(File::create(&backup_path).map_err(io_error)?);
+            self.values
+                .encryption
+                .encrypt_backup(&mut archive, &mut backup)?;
+            backup.flush().map_err(io_error)?;
+        }
+
+        let zip_file = File::open(&backup_path).map_err(|_| {
             TransientError::FolderNotFound {
                 path: path.to_path_buf()
             }
@@ -559,20 +606,61 @@ impl DB {
     /// - It fails to parse the .epoch file which may occur due to data
     ///   corruption or wrong formatting.
     pub fn load_from(path: &Path, db_path: &Path) -> Result<DB, TransientError> {
+        DB::load_from_with_config(path, SledBackend::open(db_path)?, DBConfig::default())
+    }
+
+    /// Same as `load_from`, on top of the given storage backend and with the
+    /// given config.
+    ///
+    /// An encrypted backup needs the config to have the encryption enabled
+    /// with a provider which has the key of the backup, and of the values.
+    ///
+    /// The values and the members are stored again with the compression and
+    /// the encryption of the config, so loading a backup is how a database
+    /// is encrypted, or moved to another key or codec.
+    ///
+    /// # Errors
+    ///
+    /// Fails for the same reasons as `load_from`, or with `KeyNotFound` or
+    /// `DecryptionFailed` if an encrypted backup or value cannot be
+    /// decrypted.
+    pub fn load_from_with_config<S: StorageBackend + 'static>(
+        path: &Path,
+        storage: S,
+        config: DBConfig
+    ) -> Result<DB, TransientError> {
         if !path.is_file() {
             Err(TransientError::FolderNotFound {
                 path: path.to_path_buf()
             })?;
         }
 
-        let db = DB::new(db_path)?;
+        let db = DB::with_config(storage, config)?;
 
-        let file = File::open(path).map_err(|_| {
+        let mut file = File::open(path).map_err(|_| {
             TransientError::FolderNotFound {
                 path: path.to_path_buf()
             }
         })?;
 
+        let io_error = |e| {
+            TransientError::IOError {
+                error: e
+            }
+        };
+        let mut clear = tempfile::tempfile().map_err(io_error)?;
+        // The archive is read from the decrypted copy if the backup is encrypted
+        let mut file = if db
+            .values
+            .encryption
+            .decrypt_backup(&mut BufReader::new(&mut file), &mut clear)?
+        {
+            clear
+        } else {
+            file
+        };
+        file.rewind().map_err(io_error)?;
+
         let mut archive = ZipArchive::new(file).map_err(|e| {
             TransientError::ZipError {
                 error: e
@@ -646,63 +734,183 @@ impl DB {
                 }
             })?;
 
-            let meta =
+            let mut meta =
                 Metadata::from_u8(&meta_byte).map_err(|_| TransientError::ParsingFromByteError)?;
+            let (val, format) = db.values.reencode(&key, &val, meta.format)?;
+            meta.format = format;
 
-            db.meta_tree
-                .insert(
-                    &key,
-                    meta.to_u8()
-                        .map_err(|_| TransientError::ParsingToByteError)?
-                )
-                .map_err(|e| {
-                    TransientError::SledError {
-                        error: e
-                    }
-                })?;
+            db.storage.insert(
+                TreeKind::Meta,
+                &key,
+                &meta
+                    .to_u8()
+                    .map_err(|_| TransientError::ParsingToByteError)?
+            )?;
 
-            db.data_tree.insert(&key, val).map_err(|e| {
-                TransientError::SledError {
-                    error: e
-                }
-            })?;
+            db.storage.insert(TreeKind::Data, &key, &val)?;
 
             if let Some(d) = meta.ttl {
-                db.ttl_tree
-                    .insert([&d.to_be_bytes()[..], &key].concat(), key)
-                    .map_err(|e| {
-                        TransientError::SledError {
-                            error: e
-                        }
-                    })?;
+                db.storage
+                    .insert(TreeKind::Ttl, &[&d.to_be_bytes()[..], &key].concat(), &key)?;
             };
+            if let Some(cache) = &db.cache {
+                cache.invalidate(&key);
+            }
+        }
+        drop(data);
+
+        // Backups made before the collections have no members file
+        if let Ok(mut members) = archive.by_name("members.epoch") {
+            while let Some(key) = read_field(&mut members)? {
+                let value = read_field(&mut members)?.ok_or(TransientError::IOError {
+                    error: ErrorKind::UnexpectedEof.into()
+                })?;
+                let value = db.values.reencode_member(&key, &value)?;
+                db.storage.insert(TreeKind::Members, &key, &value)?;
+            }
         }
 
+        // The filter was built before the keys were written
+        db.rebuild_bloom_filter()?;
+
         Ok(db)
     }
 
     pub fn get_db_size(&self) -> usize {
-        self.data_tree.len()
+        self.storage.len(TreeKind::Data)
     }
 
     /// Retrieves the raw value for a given raw key.
     ///
+    /// If the hot-key cache is enabled the value is served from it when
+    /// possible, otherwise the value read is offered to the cache.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the value cannot be retrieved from the database or
-    /// if the value is not valid UTF-8.
+    /// Returns `WrongType` if the key holds a collection, or an error if the
+    /// value cannot be retrieved from the database.
     pub fn get_raw<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Vec<u8>>, TransientError> {
-        let data_tree = &self.data_tree;
         let byte = key.as_ref();
-        let val = data_tree.get(byte).map_err(|e| {
-            TransientError::SledError {
-                error: e
+        Metrics::increment_operations("get");
+
+        if !self.may_contain(byte) {
+            return Ok(None);
+        }
+
+        let cache = match &self.cache {
+            Some(c) => c,
+            None => return self.get_plain_value(byte)
+        };
+
+        if let Some(val) = cache.get(byte) {
+            return Ok(Some(val));
+        }
+
+        // Taken before the read, so a write racing with it prevents the admission
+        let generation = cache.generation();
+        let val = self.get_plain_value(byte)?;
+
+        if let Some(v) = &val {
+            let freq = self.get_metadata_raw(&byte)?.map_or(0, |m| m.freq);
+            cache.admit(byte, v, freq, generation);
+        }
+
+        Ok(val)
+    }
+
+    /// Returns the hot-key cache, `None` if it is disabled.
+    pub fn cache(&self) -> Option<&HotCache> {
+        self.cache.as_deref()
+    }
+
+    /// Returns the bloom filter, `None` if it is disabled.
+    pub fn bloom_filter(&self) -> Option<&BloomFilter> {
+        self.bloom.as_deref()
+    }
+
+    /// Rebuilds the bloom filter from the keys in the database, which resets
+    /// the false positives left by removed keys.
+    ///
+    /// Lookups wait for the rebuild to finish. Does nothing if the filter is
+    /// disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keys cannot be read from the database.
+    pub fn rebuild_bloom_filter(&self) -> Result<(), TransientError> {
+        match &self.bloom {
+            Some(bloom) => {
+                bloom.rebuild(self.storage.iter(TreeKind::Meta).map(|i| i.map(|(k, _)| k)))
+            },
+            None => Ok(())
+        }
+    }
+
+    /// Returns false if the bloom filter knows the key is absent, and records
+    /// the negative lookup.
+    fn may_contain(&self, key: &[u8]) -> bool {
+        match &self.bloom {
+            Some(bloom) if !bloom.may_contain(key) => {
+                bloom.record_miss(true);
+                false
+            },
+            _ => true
+        }
+    }
+
+    /// Reads a key which the bloom filter let through, and records it if it
+    /// was a false positive.
+    fn checked_get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        let val = self.storage.get(tree, key)?;
+
+        if val.is_none()
+            && let Some(bloom) = &self.bloom
+        {
+            bloom.record_miss(false);
+        }
+
+        Ok(val)
+    }
+
+    /// Reads the value of a key which is not a collection, decoded.
+    fn get_plain_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        match self.get_value(key)? {
+            Some((_, format)) if format.collection.is_some() => Err(TransientError::WrongType),
+            Some((val, _)) => Ok(Some(val)),
+            None => Ok(None)
+        }
+    }
+
+    /// Reads the value of a key, decoded, and the format it is stored in,
+    /// both from the same snapshot.
+    pub(crate) fn get_value(
+        &self,
+        key: &[u8]
+    ) -> Result<Option<(Vec<u8>, ValueFormat)>, TransientError> {
+        let mut stored = self
+            .storage
+            .get_many(&[TreeKind::Data, TreeKind::Meta], key)?
+            .into_iter();
+        let (val, meta) = match (stored.next().flatten(), stored.next().flatten()) {
+            (Some(v), Some(m)) => (v, m),
+            _ => {
+                if let Some(bloom) = &self.bloom {
+                    bloom.record_miss(false);
+                }
+                return Ok(None);
             }
-        });
+        };
+        let format = Metadata::from_u8(&meta)
+            .map_err(|_| TransientError::ParsingFromByteError)?
+            .format;
 
-        Metrics::increment_operations("get");
+        Ok(Some((self.values.decode_owned(key, val, format)?, format)))
+    }
 
-        val.map(|a| a.map(|b| b.to_vec()))
+    /// Returns how much the values written since the database was opened were
+    /// compressed.
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.values.compression_stats()
     }
 
     /// Sets a raw key-value pair with an optional Time-To-Live (TTL).
@@ -720,63 +928,98 @@ impl DB {
         val: &V,
         ttl: Option<Duration>
     ) -> Result<(), TransientError> {
-        let data_tree = &self.data_tree;
-        let freq_tree = &self.meta_tree;
-        let ttl_tree = &self.ttl_tree;
         let byte: &[u8] = key.as_ref();
-        let ttl_sec = match ttl {
-            Some(t) => {
-                let systime = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Cant get SystemTime");
-                Some((t + systime).as_secs())
-            },
-            None => None
-        };
-
-        let l: Result<(), TransactionError<()>> = (&**data_tree, &**freq_tree, &**ttl_tree)
-            .transaction(|(data, freq, ttl_tree)| {
-                match freq.get(byte)? {
-                    Some(m) => {
-                        let mut meta = Metadata::from_u8(&m)
-                            .map_err(|_| ConflictableTransactionError::Abort(()))?;
-                        if let Some(t) = meta.ttl {
-                            let _ = ttl_tree.remove([&t.to_be_bytes()[..], byte].concat());
-                        }
-                        meta.ttl = ttl_sec;
-                        freq.insert(
-                            byte,
-                            meta.to_u8()
-                                .map_err(|_| ConflictableTransactionError::Abort(()))?
-                        )?;
-                    },
-                    None => {
-                        freq.insert(
-                            byte,
-                            Metadata::new(ttl_sec)
-                                .to_u8()
-                                .map_err(|_| ConflictableTransactionError::Abort(()))?
-                        )?;
+        let (val, format) = self
+            .values
+            .encode(byte, val.as_ref(), ValueFormat::default())?;
+        let ttl_sec = ttl.map(|t| expiry_from_now(self.clock.as_ref(), t));
+        let bloom_epoch = self.bloom.as_ref().map_or(0, |b| b.epoch());
+        let mut added = false;
+        let mut dropped = None;
+        let indexes = self.indexes.read()?;
+
+        let l = self.storage.transaction(&mut |tx| {
+            added = false;
+            let old_format = match tx.get(TreeKind::Meta, byte)? {
+                Some(m) => {
+                    let mut meta = Metadata::from_u8(&m).map_err(|_| {
+                        StorageTxError::Storage(TransientError::ParsingFromByteError)
+                    })?;
+                    if let Some(t) = meta.ttl {
+                        let _ = tx.remove(TreeKind::Ttl, &[&t.to_be_bytes()[..], byte].concat());
                     }
+                    let old_format = meta.format;
+                    meta.ttl = ttl_sec;
+                    meta.format = format;
+                    tx.insert(
+                        TreeKind::Meta,
+                        byte,
+                        &meta.to_u8().map_err(|_| {
+                            StorageTxError::Storage(TransientError::ParsingToByteError)
+                        })?
+                    )?;
+                    Some(old_format)
+                },
+                None => {
+                    let mut meta = Metadata::with_clock(self.clock.as_ref(), ttl_sec);
+                    meta.format = format;
+                    tx.insert(
+                        TreeKind::Meta,
+                        byte,
+                        &meta.to_u8().map_err(|_| {
+                            StorageTxError::Storage(TransientError::ParsingToByteError)
+                        })?
+                    )?;
+
+                    // Inserted before the commit, so the key is never filtered out once visible
+                    if let Some(bloom) = &self.bloom {
+                        bloom.insert(byte);
+                    }
+                    added = true;
+                    None
                 }
+            };
 
-                data.insert(byte, val.as_ref())?;
+            let old = tx.insert(TreeKind::Data, byte, &val)?;
+            update_entries(
+                tx,
+                &indexes,
+                &self.values,
+                byte,
+                old.as_deref().zip(old_format),
+                Some((&val, format))
+            )?;
+            dropped = dropped_collection(old_format, Some(format));
+
+            if let Some(d) = ttl_sec {
+                tx.insert(TreeKind::Ttl, &[&d.to_be_bytes()[..], byte].concat(), byte)?;
+                Metrics::inc_keys_total("ttl");
+            };
 
-                if let Some(d) = ttl_sec {
-                    ttl_tree.insert([&d.to_be_bytes()[..], byte].concat(), byte)?;
-                    Metrics::inc_keys_total("ttl");
-                };
+            Ok(())
+        });
+        l?;
+        drop(indexes);
 
-                Ok(())
-            });
-        l.map_err(|_| TransientError::SledTransactionError)?;
+        if let Some(id) = dropped {
+            purge_members(self.storage.as_ref(), id)?;
+        }
+
+        if let Some(cache) = &self.cache {
+            cache.invalidate(byte);
+        }
+        if let Some(bloom) = &self.bloom
+            && added
+        {
+            bloom.confirm_insert(byte, bloom_epoch);
+        }
 
         // Prometheus metrics
         Metrics::increment_operations("set");
         Metrics::inc_keys_total("data");
         Metrics::inc_keys_total("meta");
 
-        Ok(())
+        self.commit_write()
     }
 
     /// Retrieves the metadata for a given raw key.
@@ -788,13 +1031,12 @@ impl DB {
         &self,
         key: &K
     ) -> Result<Option<Metadata>, TransientError> {
-        let freq_tree = &self.meta_tree;
         let byte = key.as_ref();
-        let meta = freq_tree.get(byte).map_err(|e| {
-            TransientError::SledError {
-                error: e
-            }
-        })?;
+        if !self.may_contain(byte) {
+            return Ok(None);
+        }
+
+        let meta = self.checked_get(TreeKind::Meta, byte)?;
         match meta {
             Some(val) => {
                 Ok(Some(
@@ -810,39 +1052,59 @@ impl DB {
     ///
     /// # Errors
     ///
-    /// Can return an error if the transaction to remove the data fails.
+    /// Returns `MetadataNotFound` if the key does not exist, or the error the
+    /// transaction to remove the data failed with.
     pub fn remove_raw<K: AsRef<[u8]>>(&self, key: K) -> Result<(), TransientError> {
-        let data_tree = &self.data_tree;
-        let freq_tree = &self.meta_tree;
-        let ttl_tree = &self.ttl_tree;
         let byte = key.as_ref();
-        let l: Result<(), TransactionError<()>> = (&**data_tree, &**freq_tree, &**ttl_tree)
-            .transaction(|(data, freq, ttl_tree)| {
-                data.remove(byte)?;
-                let meta = freq
-                    .get(byte)?
-                    .ok_or(ConflictableTransactionError::Abort(()))?;
-                let time = Metadata::from_u8(&meta)
-                    .map_err(|_| ConflictableTransactionError::Abort(()))?
-                    .ttl;
-                freq.remove(byte)?;
-
-                Metrics::dec_keys_total("data");
-                Metrics::dec_keys_total("meta");
-
-                if let Some(t) = time {
-                    Metrics::dec_keys_total("ttl");
-
-                    let _ = ttl_tree.remove([&t.to_be_bytes()[..], byte].concat());
-                }
+        let bloom_epoch = self.bloom.as_ref().map_or(0, |b| b.epoch());
+        let mut dropped = None;
+        let indexes = self.indexes.read()?;
+        let l = self.storage.transaction(&mut |tx| {
+            let meta = tx
+                .get(TreeKind::Meta, byte)?
+                .ok_or(StorageTxError::Storage(TransientError::MetadataNotFound))?;
+            let meta = Metadata::from_u8(&meta)
+                .map_err(|_| StorageTxError::Storage(TransientError::ParsingFromByteError))?;
+            let old = tx.remove(TreeKind::Data, byte)?;
+            update_entries(
+                tx,
+                &indexes,
+                &self.values,
+                byte,
+                old.as_deref().map(|v| (v, meta.format)),
+                None
+            )?;
+            dropped = dropped_collection(Some(meta.format), None);
+            let time = meta.ttl;
+            tx.remove(TreeKind::Meta, byte)?;
 
-                Ok(())
-            });
-        l.map_err(|_| TransientError::SledTransactionError)?;
+            Metrics::dec_keys_total("data");
+            Metrics::dec_keys_total("meta");
+
+            if let Some(t) = time {
+                Metrics::dec_keys_total("ttl");
+
+                let _ = tx.remove(TreeKind::Ttl, &[&t.to_be_bytes()[..], byte].concat());
+            }
+
+            Ok(())
+        });
+        l?;
+        drop(indexes);
+
+        if let Some(id) = dropped {
+            purge_members(self.storage.as_ref(), id)?;
+        }
+        if let Some(cache) = &self.cache {
+            cache.invalidate(byte);
+        }
+        if let Some(bloom) = &self.bloom {
+            bloom.remove(byte, bloom_epoch);
+        }
 
         Metrics::increment_operations("rm");
 
-        Ok(())
+        self.commit_write()
     }
 
     /// Atomically increments the frequency counter for a given raw key.
@@ -852,33 +1114,26 @@ impl DB {
     /// This function can return an error if the key does not exist or if there
     /// is an issue with the compare-and-swap operation.
     pub fn increment_frequency_raw(&self, key: &[u8]) -> Result<Option<()>, TransientError> {
-        let freq_tree = &self.meta_tree;
-        let byte = &key;
-
         loop {
-            let metadata_opt = freq_tree.get(byte).map_err(|e| {
-                TransientError::SledError {
-                    error: e
-                }
-            })?;
-            let metadata = match metadata_opt {
+            let metadata = match self.storage.get(TreeKind::Meta, key)? {
                 Some(t) => t,
                 None => return Ok(None)
             };
             let meta =
                 Metadata::from_u8(&metadata).map_err(|_| TransientError::ParsingFromByteError)?;
-            let s = freq_tree.compare_and_swap(
-                byte,
-                Some(metadata),
+            let s = self.storage.compare_and_swap(
+                TreeKind::Meta,
+                key,
+                Some(&metadata),
                 Some(
-                    meta.freq_incretement()
+                    &meta
+                        .freq_incretement()
+                        .accessed(self.clock.now_secs())
                         .to_u8()
                         .map_err(|_| TransientError::ParsingToByteError)?
                 )
             );
-            if let Ok(ss) = s
-                && ss.is_ok()
-            {
+            if let Ok(true) = s {
                 break;
             }
         }
@@ -888,25 +1143,150 @@ impl DB {
     }
 }
 
-impl Drop for DB {
-    /// Gracefully shuts down the TTL background thread when the `DB` instance
-    /// goes out of scope.
-    fn drop(&mut self) {
-        self.shutdown
-            .store(true, std::sync::atomic::Ordering::SeqCst);
-
-        let _ = self
-            .ttl_thread
-            .take()
-            .expect("Fail to take ownership of ttl_thread")
-            .join()
-            .expect("Joining failed");
-
-        let _ = self
-            .size_thread
-            .take()
-            .expect("Fail to take ownership of ttl_thread")
-            .join()
-            .expect("Joining failed");
+/// Writes a field of a backup file, prefixed by its length.
+fn write_field<W: Write>(writer: &mut W, field: &[u8]) -> Result<(), TransientError> {
+    let len: u64 = field
+        .len()
+        .try_into()
+        .map_err(|_| TransientError::ParsingToU64ByteFailed)?;
+
+    writer
+        .write_all(&len.to_be_bytes())
+        .and_then(|_| writer.write_all(field))
+        .map_err(|e| {
+            TransientError::IOError {
+                error: e
+            }
+        })
+}
+
+/// Reads a field written by `write_field`, `None` at the end of the file.
+fn read_field<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, TransientError> {
+    let mut len = [0u8; 8];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => {
+            return Err(TransientError::IOError {
+                error: e
+            });
+        }
+    }
+
+    let mut field = vec![
+        0;
+        u64::from_be_bytes(len)
+            .try_into()
+            .map_err(|_| TransientError::ParsingToU64ByteFailed)?
+    ];
+    reader.read_exact(&mut field).map_err(|e| {
+        TransientError::IOError {
+            error: e
+        }
+    })?;
+
+    Ok(Some(field))
+}
+
+/// Converts a Time-To-Live into the timestamp at which the key expires, in
+/// seconds since the UNIX epoch, saturating on the longest TTLs.
+pub(crate) fn expiry_from_now(clock: &dyn Clock, ttl: Duration) -> u64 {
+    clock.now().saturating_add(ttl).as_secs()
+}
+
+/// Removes every key whose TTL has passed, and returns how many were removed.
+///
+/// The TTL entries are ordered by expiration time, so this stops at the first
+/// one which is not due yet.
+fn remove_expired_keys(
+    storage: &dyn StorageBackend,
+    clock: &dyn Clock,
+    cache: Option<&HotCache>,
+    bloom: Option<&BloomFilter>,
+    indexes: &IndexRegistry,
+    values: &ValueEncoding,
+    expirations: &ExpiryNotifier
+) -> Result<u64, TransientError> {
+    let mut removed = 0;
+
+    for i in storage.iter(TreeKind::Ttl) {
+        // NOTE: The TTL entries are stored as ([time, key], key), not ((time, key),
+        // key)
+        let (ttl_key, key) = i?;
+
+        if ttl_key.len() < 8 {
+            Err(TransientError::ParsingToU64ByteFailed)?
+        }
+
+        let time_byte: [u8; 8] = (&ttl_key[..8])
+            .try_into()
+            .map_err(|_| TransientError::ParsingToByteError)?;
+
+        if clock.now_secs() < u64::from_be_bytes(time_byte) {
+            break;
+        }
+
+        let bloom_epoch = bloom.map_or(0, |b| b.epoch());
+        let mut expired = false;
+        let mut value = None;
+        let mut format = None;
+        let indexes = indexes.read()?;
+        let l = storage.transaction(&mut |tx| {
+            // The key was removed or given another TTL since it was read
+            expired = tx.remove(TreeKind::Ttl, &ttl_key)?.is_some();
+            if !expired {
+                return Ok(());
+            }
+
+            // A corrupted metadata is still removed, the key is then expired as a plain
+            // value
+            format = tx
+                .remove(TreeKind::Meta, &key)?
+                .and_then(|m| Metadata::from_u8(&m).ok())
+                .map(|m| m.format);
+            value = tx.remove(TreeKind::Data, &key)?;
+            update_entries(
+                tx,
+                &indexes,
+                values,
+                &key,
+                value.as_deref().zip(format),
+                None
+            )?;
+
+            Ok(())
+        });
+        l?;
+        drop(indexes);
+
+        if !expired {
+            continue;
+        }
+
+        // Prometheus Metrics
+        Metrics::dec_keys_total("data");
+        Metrics::dec_keys_total("meta");
+        Metrics::dec_keys_total("ttl");
+        Metrics::increment_ttl_expired_keys();
+
+        if let Some(id) = dropped_collection(format, None) {
+            purge_members(storage, id)?;
+        }
+        if let Some(cache) = cache {
+            cache.invalidate(&key);
+        }
+        if let Some(bloom) = bloom {
+            bloom.remove(&key, bloom_epoch);
+        }
+        // A corrupted value or a collection is still removed, the subscribers only miss
+        // it
+        let value = value
+            .filter(|_| format.is_some_and(|f| f.collection.is_none()))
+            .zip(format)
+            .and_then(|(v, f)| values.decode_owned(&key, v, f).ok());
+        expirations.notify(&key, value);
+        removed += 1;
     }
+
+    Ok(removed)
 }
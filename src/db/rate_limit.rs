@@ -0,0 +1,389 @@
+//! This module defines the `RateLimiter`, which checks and consumes quotas
+//! stored as regular keys with a TTL, so the state of idle clients expires on
+//! its own.
+
+use std::time::Duration;
+
+use bincode::error::{
+    DecodeError,
+    EncodeError
+};
+use bincode::serde::{
+    decode_from_slice,
+    encode_to_vec
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::DB;
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+
+/// The prefix of the keys holding the state of the rate limiters.
+pub const RATE_LIMIT_PREFIX: &[u8] = b"ratelimit:";
+
+/// The algorithm used to limit the rate, every one of them allows `limit`
+/// units per `window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimit {
+    /// Counts the units consumed in fixed windows, which start every `window`
+    /// since the UNIX epoch. Cheap, but allows bursts of twice the limit around
+    /// the window boundaries.
+    FixedWindow { limit: u64, window: Duration },
+    /// Weights the count of the previous window by how much of it still
+    /// overlaps the last `window`, smoothing the boundary bursts out.
+    SlidingWindow { limit: u64, window: Duration },
+    /// A bucket of `limit` tokens, refilled continuously at `limit` tokens per
+    /// `window`.
+    TokenBucket { limit: u64, window: Duration }
+}
+
+/// The outcome of `RateLimiter::check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    /// Whether the units were consumed
+    pub allowed: bool,
+    /// The units which can still be consumed right now
+    pub remaining: u64,
+    /// The time until the whole quota is available again
+    pub reset_after: Duration,
+    /// The time until the same request would be allowed, zero if it was
+    pub retry_after: Duration
+}
+
+/// The state of a rate limiter, stored as the value of its key.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum RateLimitState {
+    Window {
+        /// Start of the current window, in milliseconds since the UNIX epoch
+        start: u64,
+        count: u64,
+        /// Count of the previous window, only used by the sliding window
+        previous: u64
+    },
+    Bucket {
+        tokens: f64,
+        /// Last refill, in milliseconds since the UNIX epoch
+        last: u64
+    }
+}
+
+impl RateLimitState {
+    fn to_u8(&self) -> Result<Vec<u8>, EncodeError> {
+        encode_to_vec(self, bincode::config::standard())
+    }
+
+    fn from_u8(slice: &[u8]) -> Result<RateLimitState, DecodeError> {
+        Ok(decode_from_slice(slice, bincode::config::standard())?.0)
+    }
+}
+
+/// Checks and consumes quotas of a `RateLimit`, see `DB::rate_limiter`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimiter<'a> {
+    db: &'a DB,
+    policy: RateLimit
+}
+
+impl DB {
+    /// Returns a rate limiter applying the policy to the keys it is given.
+    pub fn rate_limiter(&self, policy: RateLimit) -> RateLimiter<'_> {
+        RateLimiter {
+            db: self,
+            policy
+        }
+    }
+}
+
+impl RateLimiter<'_> {
+    /// Tries to consume `cost` units of the quota of the key.
+    ///
+    /// The check and the consumption happen in a single transaction, so
+    /// concurrent checks never consume more than the quota. The state is
+    /// stored under `RATE_LIMIT_PREFIX` followed by the key.
+    ///
+    /// A `cost` above the limit can never be allowed, it is denied without
+    /// touching the state, with a zero `retry_after`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidRateLimit` if the limit or the window of the policy is
+    /// zero, or an error if the transaction fails.
+    pub fn check<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        cost: u64
+    ) -> Result<RateLimitDecision, TransientError> {
+        let (limit, _) = self.bounds()?;
+        if cost > limit {
+            let decision = self.peek(key)?;
+            return Ok(RateLimitDecision {
+                allowed: false,
+                retry_after: Duration::ZERO,
+                ..decision
+            });
+        }
+
+        self.decide(key, cost, true)
+    }
+
+    /// Returns the quota of the key without consuming any of it, the state of
+    /// the key is only read.
+    ///
+    /// # Errors
+    ///
+    /// Same as `check`.
+    pub fn peek<K: AsRef<[u8]>>(&self, key: &K) -> Result<RateLimitDecision, TransientError> {
+        self.decide(key, 0, false)
+    }
+
+    /// Returns the limit and the window in milliseconds of the policy.
+    fn bounds(&self) -> Result<(u64, u64), TransientError> {
+        let (limit, window) = match self.policy {
+            RateLimit::FixedWindow {
+                limit,
+                window
+            }
+            | RateLimit::SlidingWindow {
+                limit,
+                window
+            }
+            | RateLimit::TokenBucket {
+                limit,
+                window
+            } => (limit, u64::try_from(window.as_millis()).unwrap_or(u64::MAX))
+        };
+        if limit == 0 || window == 0 {
+            return Err(TransientError::InvalidRateLimit);
+        }
+
+        Ok((limit, window))
+    }
+
+    /// Computes the decision for `cost` units from the state of the key, and
+    /// stores the new state if `consume` is set.
+    fn decide<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        cost: u64,
+        consume: bool
+    ) -> Result<RateLimitDecision, TransientError> {
+        let (limit, window) = self.bounds()?;
+        let state_key = [RATE_LIMIT_PREFIX, key.as_ref()].concat();
+
+        self.db
+            .transaction(|tx| {
+                let now = self.db.clock.now_millis();
+                let state = match tx.get_raw(&state_key)? {
+                    Some(v) => RateLimitState::from_u8(&v).ok(),
+                    None => None
+                };
+
+                let (state, decision, ttl) = match self.policy {
+                    RateLimit::FixedWindow {
+                        ..
+                    } => fixed_window(state, limit, window, cost, now),
+                    RateLimit::SlidingWindow {
+                        ..
+                    } => sliding_window(state, limit, window, cost, now),
+                    RateLimit::TokenBucket {
+                        ..
+                    } => token_bucket(state, limit, window, cost, now)
+                };
+
+                if consume {
+                    tx.set_raw(
+                        &state_key,
+                        &state
+                            .to_u8()
+                            .map_err(|_| TransientError::ParsingToByteError)?,
+                        Some(ttl)
+                    )?;
+                }
+
+                Ok(decision)
+            })
+            .map_err(TransactionError::into_transient)
+    }
+}
+
+/// Returns the time until the timestamp, in milliseconds.
+fn millis_until(deadline: u64, now: u64) -> Duration {
+    Duration::from_millis(deadline.saturating_sub(now))
+}
+
+/// Returns the TTL of the state key, rounded up to the second the TTL thread
+/// works with.
+fn state_ttl(millis: u64) -> Duration {
+    Duration::from_secs(millis.div_ceil(1000) + 1)
+}
+
+fn fixed_window(
+    state: Option<RateLimitState>,
+    limit: u64,
+    window: u64,
+    cost: u64,
+    now: u64
+) -> (RateLimitState, RateLimitDecision, Duration) {
+    let start = now - now % window;
+    let mut count = match state {
+        Some(RateLimitState::Window {
+            start: s,
+            count,
+            ..
+        }) if s == start => count,
+        _ => 0
+    };
+
+    let end = start.saturating_add(window);
+    let allowed = count.checked_add(cost).is_some_and(|c| c <= limit);
+    if allowed {
+        count += cost;
+    }
+
+    let decision = RateLimitDecision {
+        allowed,
+        remaining: limit.saturating_sub(count),
+        reset_after: millis_until(end, now),
+        retry_after: if allowed || cost > limit {
+            Duration::ZERO
+        } else {
+            millis_until(end, now)
+        }
+    };
+
+    (
+        RateLimitState::Window {
+            start,
+            count,
+            previous: 0
+        },
+        decision,
+        state_ttl(end.saturating_sub(now))
+    )
+}
+
+fn sliding_window(
+    state: Option<RateLimitState>,
+    limit: u64,
+    window: u64,
+    cost: u64,
+    now: u64
+) -> (RateLimitState, RateLimitDecision, Duration) {
+    let start = now - now % window;
+    let (mut count, previous) = match state {
+        Some(RateLimitState::Window {
+            start: s,
+            count,
+            previous
+        }) => {
+            if s == start {
+                (count, previous)
+            } else if s.checked_add(window) == Some(start) {
+                (0, count)
+            } else {
+                (0, 0)
+            }
+        },
+        _ => (0, 0)
+    };
+
+    // The part of the previous window still within the last `window`
+    let elapsed = now - start;
+    let weighted = |count: u64| {
+        let overlap = previous as u128 * (window - elapsed) as u128 / window as u128;
+        (overlap as u64).saturating_add(count)
+    };
+
+    let allowed = weighted(count)
+        .checked_add(cost)
+        .is_some_and(|c| c <= limit);
+    if allowed {
+        count += cost;
+    }
+    let used = weighted(count);
+
+    // The whole quota is back once both windows slid out
+    let slid_out = start.saturating_add(window.saturating_mul(2));
+    let reset = if count > 0 {
+        slid_out
+    } else if previous > 0 {
+        start.saturating_add(window)
+    } else {
+        now
+    };
+
+    let retry_after = if allowed || cost > limit {
+        Duration::ZERO
+    } else if count.checked_add(cost).is_some_and(|c| c <= limit) && previous > 0 {
+        // Waiting for the previous window to slide out enough
+        let excess = used.saturating_add(cost) - limit;
+        let wait = (excess as u128 * window as u128).div_ceil(previous as u128);
+        Duration::from_millis(u64::try_from(wait).unwrap_or(u64::MAX))
+    } else {
+        millis_until(start.saturating_add(window), now)
+    };
+
+    (
+        RateLimitState::Window {
+            start,
+            count,
+            previous
+        },
+        RateLimitDecision {
+            allowed,
+            remaining: limit.saturating_sub(used),
+            reset_after: millis_until(reset, now),
+            retry_after
+        },
+        state_ttl(slid_out.saturating_sub(now))
+    )
+}
+
+fn token_bucket(
+    state: Option<RateLimitState>,
+    limit: u64,
+    window: u64,
+    cost: u64,
+    now: u64
+) -> (RateLimitState, RateLimitDecision, Duration) {
+    let rate = limit as f64 / window as f64;
+    let mut tokens = match state {
+        Some(RateLimitState::Bucket {
+            tokens,
+            last
+        }) => (tokens + now.saturating_sub(last) as f64 * rate).min(limit as f64),
+        _ => limit as f64
+    };
+
+    let cost_f = cost as f64;
+    let allowed = tokens >= cost_f;
+    if allowed {
+        tokens -= cost_f;
+    }
+
+    let until_full = ((limit as f64 - tokens) / rate).ceil() as u64;
+    let retry_after = if allowed || cost > limit {
+        Duration::ZERO
+    } else {
+        Duration::from_millis(((cost_f - tokens) / rate).ceil() as u64)
+    };
+
+    (
+        RateLimitState::Bucket {
+            tokens,
+            last: now
+        },
+        RateLimitDecision {
+            allowed,
+            remaining: tokens.floor() as u64,
+            reset_after: Duration::from_millis(until_full),
+            retry_after
+        },
+        state_ttl(until_full)
+    )
+}
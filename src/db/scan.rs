@@ -0,0 +1,201 @@
+//! This module defines the key listing, by glob pattern or page by page with a
+//! cursor.
+//!
+//! The keys are read in order, and the cursor of a page is the last key it
+//! examined, so resuming a scan starts right after it whatever was written in
+//! between: every key which exists for the whole scan is returned exactly
+//! once, the keys added or removed during the scan may or may not be.
+
+use std::ops::Bound;
+
+use crate::DB;
+use crate::db::errors::TransientError;
+use crate::db::iter::prefix_end;
+use crate::db::storage::{
+    StorageIter,
+    TreeKind
+};
+
+/// A page of keys returned by `DB::scan`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanPage {
+    /// The keys of the page which match the pattern, ordered
+    pub keys: Vec<Vec<u8>>,
+    /// The cursor to pass to get the next page, `None` once the scan is done
+    pub cursor: Option<Vec<u8>>
+}
+
+/// Returns true if the key matches the glob pattern.
+///
+/// Like in Redis, `*` matches any bytes, `?` any single byte, `[abc]` one of
+/// the bytes of the set, `[a-z]` one of the range and `[^a]` anything but the
+/// set. A `\` matches the byte after it literally.
+pub fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // Where to resume after the last `*`, if the rest fails to match
+    let mut star: Option<(usize, usize)> = None;
+
+    while k < key.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, k));
+                p += 1;
+                continue;
+            },
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, key[k]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == key[k]).then_some(p + 2),
+            Some(&b) => (b == key[k]).then_some(p + 1),
+            None => None
+        };
+
+        match (step, star) {
+            (Some(next), _) => {
+                p = next;
+                k += 1;
+            },
+            // Let the last `*` swallow one more byte
+            (None, Some((after, swallowed))) => {
+                p = after;
+                k = swallowed + 1;
+                star = Some((after, swallowed + 1));
+            },
+            (None, None) => return false
+        }
+    }
+
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Matches the byte against the class starting at `start`, returns the
+/// position after the class if it matches.
+fn match_class(pattern: &[u8], start: usize, byte: u8) -> Option<usize> {
+    let mut p = start + 1;
+    let negated = matches!(pattern.get(p), Some(b'^' | b'!'));
+    if negated {
+        p += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let b = match pattern.get(p) {
+            // An unclosed class is a literal `[`
+            None => return (byte == b'[').then_some(start + 1),
+            Some(b']') if !first => break,
+            Some(b'\\') if p + 1 < pattern.len() => {
+                p += 1;
+                pattern[p]
+            },
+            Some(&b) => b
+        };
+        first = false;
+
+        if pattern.get(p + 1) == Some(&b'-') && pattern.get(p + 2).is_some_and(|&e| e != b']') {
+            let end = pattern[p + 2];
+            matched |= (b.min(end)..=b.max(end)).contains(&byte);
+            p += 3;
+        } else {
+            matched |= b == byte;
+            p += 1;
+        }
+    }
+
+    (matched != negated).then_some(p + 1)
+}
+
+/// Returns the bytes every key matching the pattern starts with.
+fn literal_prefix(pattern: &[u8]) -> &[u8] {
+    let end = pattern
+        .iter()
+        .position(|b| matches!(b, b'*' | b'?' | b'[' | b'\\'))
+        .unwrap_or(pattern.len());
+
+    &pattern[..end]
+}
+
+impl DB {
+    /// Returns every key matching the glob pattern, ordered, see `glob_match`
+    /// for the syntax.
+    ///
+    /// This reads every key starting with the literal prefix of the pattern,
+    /// use `DB::scan` to list a large database page by page.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keys cannot be read.
+    pub fn keys<P: AsRef<[u8]>>(&self, pattern: &P) -> Result<Vec<Vec<u8>>, TransientError> {
+        let pattern = pattern.as_ref();
+
+        let mut keys = Vec::new();
+        for i in self.prefix_range(TreeKind::Data, literal_prefix(pattern)) {
+            let (key, _) = i?;
+            if glob_match(pattern, &key) {
+                keys.push(key);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Examines at most `count` keys after the cursor, and returns the ones
+    /// matching the pattern with the cursor of the next page.
+    ///
+    /// Start with an empty cursor, and pass the returned cursor until it is
+    /// `None`. Since `count` bounds the keys examined rather than the keys
+    /// returned, a page may be empty before the scan is done.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keys cannot be read.
+    pub fn scan(
+        &self,
+        cursor: &[u8],
+        pattern: Option<&[u8]>,
+        count: usize
+    ) -> Result<ScanPage, TransientError> {
+        let prefix = pattern.map(literal_prefix).unwrap_or_default();
+        let end = prefix_end(prefix);
+        // The cursor of another pattern may be past the keys of this one
+        if end.as_deref().is_some_and(|e| cursor >= e) {
+            return Ok(ScanPage {
+                keys: Vec::new(),
+                cursor: None
+            });
+        }
+        let end = match &end {
+            Some(e) => Bound::Excluded(&e[..]),
+            None => Bound::Unbounded
+        };
+
+        // A key equal to the prefix may be the cursor, which must not be
+        // returned again
+        let iter: StorageIter = if !cursor.is_empty() && cursor >= prefix {
+            self.storage
+                .range(TreeKind::Data, Bound::Excluded(cursor), end)
+        } else {
+            self.storage
+                .range(TreeKind::Data, Bound::Included(prefix), end)
+        };
+        let mut iter = iter.peekable();
+
+        let mut keys = Vec::new();
+        let mut last = None;
+        for _ in 0..count.max(1) {
+            let key = match iter.next() {
+                Some(i) => i?.0,
+                None => break
+            };
+
+            if pattern.is_none_or(|p| glob_match(p, &key)) {
+                keys.push(key.clone());
+            }
+            last = Some(key);
+        }
+
+        Ok(ScanPage {
+            keys,
+            cursor: last.filter(|_| iter.peek().is_some())
+        })
+    }
+}
@@ -0,0 +1,461 @@
+//! This module defines the `SessionStore`, which manages user sessions on top
+//! of the `DB`: random session IDs, serializable payloads, idle and absolute
+//! timeouts, revocation of every session of a user and expiry events.
+
+use std::sync::mpsc::{
+    Receiver,
+    RecvTimeoutError
+};
+use std::time::{
+    Duration,
+    Instant
+};
+
+use bincode::serde::{
+    decode_from_slice,
+    encode_to_vec
+};
+use serde::de::DeserializeOwned;
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::DB;
+use crate::db::clock::millis_after;
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+use crate::db::events::Expiration;
+use crate::db::storage::TreeKind;
+use crate::db::transaction::TransactionalGuard;
+
+/// The prefix of the keys holding the sessions, followed by the session ID.
+pub const SESSION_PREFIX: &[u8] = b"session:";
+
+/// The prefix of the secondary index from users to their sessions, followed by
+/// the length of the user as a big endian u32, the user and the session ID.
+pub const SESSION_USER_PREFIX: &[u8] = b"session_user:";
+
+/// The timeouts of the sessions of a `SessionStore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionConfig {
+    /// How long a session lives without being accessed
+    pub idle_timeout: Duration,
+    /// How long a session lives at most, whatever its accesses are
+    pub absolute_timeout: Option<Duration>
+}
+
+impl SessionConfig {
+    /// Creates a config with the idle timeout and no absolute timeout.
+    pub fn new(idle_timeout: Duration) -> SessionConfig {
+        SessionConfig {
+            idle_timeout,
+            absolute_timeout: None
+        }
+    }
+
+    /// Sets the absolute timeout.
+    pub fn absolute_timeout(mut self, timeout: Duration) -> SessionConfig {
+        self.absolute_timeout = Some(timeout);
+        self
+    }
+}
+
+/// A session returned by the `SessionStore`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session<T> {
+    /// The session ID
+    pub id: String,
+    /// The user owning the session
+    pub user: String,
+    /// The data stored in the session
+    pub payload: T,
+    /// Creation of the session, in milliseconds since the UNIX epoch
+    pub created_at: u64,
+    /// When the session expires if it is not accessed again, in milliseconds
+    /// since the UNIX epoch
+    pub expires_at: u64
+}
+
+/// A session which expired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionExpired {
+    /// The session ID
+    pub id: String,
+    /// The user owning the session, `None` if the session couldn't be decoded
+    pub user: Option<String>
+}
+
+/// The session as it is stored.
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionRecord {
+    user: String,
+    payload: Vec<u8>,
+    /// In milliseconds since the UNIX epoch
+    created_at: u64,
+    /// In milliseconds since the UNIX epoch
+    last_access: u64,
+    /// In milliseconds since the UNIX epoch
+    absolute_deadline: Option<u64>
+}
+
+impl SessionRecord {
+    fn to_u8(&self) -> Result<Vec<u8>, TransientError> {
+        encode_to_vec(self, bincode::config::standard())
+            .map_err(|_| TransientError::ParsingToByteError)
+    }
+
+    fn from_u8(slice: &[u8]) -> Result<SessionRecord, TransientError> {
+        Ok(decode_from_slice(slice, bincode::config::standard())
+            .map_err(|_| TransientError::ParsingFromByteError)?
+            .0)
+    }
+
+    /// Returns when the session expires, in milliseconds since the UNIX epoch.
+    fn expires_at(&self, idle_timeout: Duration) -> u64 {
+        let idle = millis_after(self.last_access, idle_timeout);
+        match self.absolute_deadline {
+            Some(d) => idle.min(d),
+            None => idle
+        }
+    }
+}
+
+/// Manages sessions stored in a `DB`, see `DB::session_store`.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionStore<'a> {
+    db: &'a DB,
+    config: SessionConfig
+}
+
+impl DB {
+    /// Returns a session store whose sessions follow the config.
+    pub fn session_store(&self, config: SessionConfig) -> SessionStore<'_> {
+        SessionStore {
+            db: self,
+            config
+        }
+    }
+}
+
+impl SessionStore<'_> {
+    /// Creates a session for the user and returns its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload cannot be serialized, no random ID can
+    /// be generated or the transaction fails.
+    pub fn create<T: Serialize>(&self, user: &str, payload: &T) -> Result<String, TransientError> {
+        let id = new_session_id()?;
+        let now = self.db.clock.now_millis();
+        let record = SessionRecord {
+            user: user.to_string(),
+            payload: encode_payload(payload)?,
+            created_at: now,
+            last_access: now,
+            absolute_deadline: self.config.absolute_timeout.map(|t| millis_after(now, t))
+        };
+
+        self.db
+            .transaction(|tx| Ok(self.write(tx, &id, &record, now)?))
+            .map_err(TransactionError::into_transient)?;
+
+        Ok(id)
+    }
+
+    /// Retrieves a session and renews it, `None` if it doesn't exist or
+    /// expired.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session cannot be deserialized or the
+    /// transaction fails.
+    pub fn get<T: DeserializeOwned>(&self, id: &str) -> Result<Option<Session<T>>, TransientError> {
+        let record = match self.access(id, None)? {
+            Some(r) => r,
+            None => return Ok(None)
+        };
+
+        Ok(Some(Session {
+            id: id.to_string(),
+            expires_at: record.expires_at(self.config.idle_timeout),
+            payload: decode_payload(&record.payload)?,
+            user: record.user,
+            created_at: record.created_at
+        }))
+    }
+
+    /// Renews a session without reading it, returns false if it doesn't exist
+    /// or expired.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction fails.
+    pub fn renew(&self, id: &str) -> Result<bool, TransientError> {
+        Ok(self.access(id, None)?.is_some())
+    }
+
+    /// Replaces the payload of a session and renews it, returns false if it
+    /// doesn't exist or expired.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload cannot be serialized or the transaction
+    /// fails.
+    pub fn update<T: Serialize>(&self, id: &str, payload: &T) -> Result<bool, TransientError> {
+        Ok(self.access(id, Some(encode_payload(payload)?))?.is_some())
+    }
+
+    /// Revokes a session, returns false if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction fails.
+    pub fn revoke(&self, id: &str) -> Result<bool, TransientError> {
+        self.db
+            .transaction(|tx| {
+                let record = match tx.get_raw(&session_key(id))? {
+                    Some(r) => SessionRecord::from_u8(&r)?,
+                    None => return Ok(false)
+                };
+                remove_session(tx, id, &record.user)?;
+                Ok(true)
+            })
+            .map_err(TransactionError::into_transient)
+    }
+
+    /// Revokes every session of the user, returns how many were revoked.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read or the transaction fails.
+    pub fn revoke_user(&self, user: &str) -> Result<usize, TransientError> {
+        let prefix = user_prefix(user);
+
+        // The index is read in the transaction, so a session created meanwhile
+        // is either revoked too or created after the revocation
+        self.db
+            .transaction(|tx| {
+                let mut revoked = 0;
+                for (_, id) in tx.scan_prefix(&prefix)? {
+                    let id =
+                        String::from_utf8(id).map_err(|_| TransientError::ParsingToUTF8Error)?;
+                    if tx.exists(&session_key(&id))? {
+                        remove_session(tx, &id, user)?;
+                        revoked += 1;
+                    }
+                }
+                Ok(revoked)
+            })
+            .map_err(TransactionError::into_transient)
+    }
+
+    /// Returns the IDs of the sessions of the user.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read.
+    pub fn user_sessions(&self, user: &str) -> Result<Vec<String>, TransientError> {
+        let prefix = user_prefix(user);
+
+        let mut ids = Vec::new();
+        for i in self.db.prefix_range(TreeKind::Data, &prefix) {
+            let (key, _) = i?;
+            // Read again along with its format, unless it was removed since
+            if let Some((id, _)) = self.db.get_value(&key)? {
+                ids.push(String::from_utf8(id).map_err(|_| TransientError::ParsingToUTF8Error)?);
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Returns a receiver of the sessions which expire from now on, see
+    /// `DB::subscribe_expirations`.
+    pub fn expirations(&self) -> SessionExpirations {
+        SessionExpirations {
+            rx: self.db.subscribe_expirations()
+        }
+    }
+
+    /// Reads a session, expires it if it is past its timeouts, otherwise
+    /// renews it and replaces its payload if one is given.
+    fn access(
+        &self,
+        id: &str,
+        payload: Option<Vec<u8>>
+    ) -> Result<Option<SessionRecord>, TransientError> {
+        let key = session_key(id);
+        let mut expired = None;
+
+        let record = self
+            .db
+            .transaction(|tx| {
+                expired = None;
+                let now = self.db.clock.now_millis();
+
+                let raw = match tx.get_raw(&key)? {
+                    Some(r) => r,
+                    None => return Ok(None)
+                };
+                let mut record = SessionRecord::from_u8(&raw)?;
+
+                // The TTL thread works with seconds, so it may not have removed it yet
+                if now >= record.expires_at(self.config.idle_timeout) {
+                    remove_session(tx, id, &record.user)?;
+                    expired = Some(raw);
+                    return Ok(None);
+                }
+
+                record.last_access = now;
+                if let Some(p) = &payload {
+                    record.payload = p.clone();
+                }
+                self.write(tx, id, &record, now)?;
+
+                Ok(Some(record))
+            })
+            .map_err(TransactionError::into_transient)?;
+
+        if let Some(raw) = expired {
+            self.db.expirations.notify(&key, Some(raw));
+        }
+
+        Ok(record)
+    }
+
+    /// Writes the session and its index entry, both expiring with it.
+    fn write(
+        &self,
+        tx: &mut TransactionalGuard,
+        id: &str,
+        record: &SessionRecord,
+        now: u64
+    ) -> Result<(), TransientError> {
+        // Rounded up, and one more second since the TTL thread rounds down
+        let left = record.expires_at(self.config.idle_timeout) - now;
+        let ttl = Duration::from_secs(left.div_ceil(1000) + 1);
+
+        tx.set_raw(&session_key(id), &record.to_u8()?, Some(ttl))?;
+        tx.set_raw(&index_key(&record.user, id), &id, Some(ttl))
+    }
+}
+
+/// Receives the sessions which expired, see `SessionStore::expirations`.
+#[derive(Debug)]
+pub struct SessionExpirations {
+    rx: Receiver<Expiration>
+}
+
+impl SessionExpirations {
+    /// Returns the next expired session, if one expired already.
+    pub fn try_recv(&self) -> Option<SessionExpired> {
+        while let Ok(e) = self.rx.try_recv() {
+            if let Some(s) = to_session_expired(e) {
+                return Some(s);
+            }
+        }
+        None
+    }
+
+    /// Waits at most `timeout` for the next expired session.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<SessionExpired> {
+        // A deadline on the monotonic clock, which the wall clock jumping
+        // cannot move
+        let deadline = Instant::now().checked_add(timeout);
+
+        loop {
+            let left = match deadline {
+                Some(d) => d.saturating_duration_since(Instant::now()),
+                None => timeout
+            };
+
+            match self.rx.recv_timeout(left) {
+                Ok(e) => {
+                    if let Some(s) = to_session_expired(e) {
+                        return Some(s);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None
+            }
+        }
+    }
+}
+
+impl Iterator for SessionExpirations {
+    type Item = SessionExpired;
+
+    /// Blocks until the next session expires, `None` once the `DB` is
+    /// dropped.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(s) = to_session_expired(self.rx.recv().ok()?) {
+                return Some(s);
+            }
+        }
+    }
+}
+
+/// Keeps the expirations of the session keys, ignoring the other keys and
+/// the index entries.
+fn to_session_expired(expiration: Expiration) -> Option<SessionExpired> {
+    let id = expiration.key.strip_prefix(SESSION_PREFIX)?;
+
+    Some(SessionExpired {
+        id: String::from_utf8(id.to_vec()).ok()?,
+        user: expiration
+            .value
+            .and_then(|v| SessionRecord::from_u8(&v).ok())
+            .map(|r| r.user)
+    })
+}
+
+/// Removes the session and its index entry.
+fn remove_session(tx: &mut TransactionalGuard, id: &str, user: &str) -> Result<(), TransientError> {
+    tx.remove_raw(&session_key(id))?;
+
+    let index = index_key(user, id);
+    if tx.exists(&index)? {
+        tx.remove_raw(&index)?;
+    }
+
+    Ok(())
+}
+
+fn session_key(id: &str) -> Vec<u8> {
+    [SESSION_PREFIX, id.as_bytes()].concat()
+}
+
+fn user_prefix(user: &str) -> Vec<u8> {
+    [
+        SESSION_USER_PREFIX,
+        &(user.len() as u32).to_be_bytes(),
+        user.as_bytes()
+    ]
+    .concat()
+}
+
+fn index_key(user: &str, id: &str) -> Vec<u8> {
+    [user_prefix(user), id.as_bytes().to_vec()].concat()
+}
+
+fn encode_payload<T: Serialize>(payload: &T) -> Result<Vec<u8>, TransientError> {
+    encode_to_vec(payload, bincode::config::standard())
+        .map_err(|_| TransientError::ParsingToByteError)
+}
+
+fn decode_payload<T: DeserializeOwned>(payload: &[u8]) -> Result<T, TransientError> {
+    Ok(decode_from_slice(payload, bincode::config::standard())
+        .map_err(|_| TransientError::ParsingFromByteError)?
+        .0)
+}
+
+/// Generates a random session ID of 128 bits, hex encoded.
+fn new_session_id() -> Result<String, TransientError> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|_| TransientError::RandomSourceFailed)?;
+
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
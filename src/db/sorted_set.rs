@@ -0,0 +1,386 @@
+//! This module defines the sorted sets, sets of members ordered by a score,
+//! e.g. a leaderboard or a queue of jobs ordered by their due time.
+//!
+//! Every member is stored twice in the `Members` tree under the id of the
+//! set: once under the member itself, which holds its score, and once in the
+//! score index, under its encoded score followed by the member, so ranges of
+//! scores and ranks are read in order without loading the whole set. Members
+//! with the same score are ordered by their bytes.
+
+use std::ops::Bound;
+
+use crate::DB;
+use crate::db::collection::{
+    Collection,
+    CollectionKind
+};
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+use crate::db::iter::prefix_end;
+use crate::db::storage::{
+    StorageIter,
+    TreeKind
+};
+use crate::db::transaction::TransactionalGuard;
+
+/// Prefixes the members holding the score of each member.
+const SCORE_TAG: u8 = 0;
+
+/// Prefixes the score index.
+const INDEX_TAG: u8 = 1;
+
+/// A member of a sorted set with its score.
+pub type ScoredMember = (Vec<u8>, f64);
+
+impl DB {
+    /// Adds the members with their score to the sorted set, creating it if
+    /// needed, and returns how many members were added. The score of the
+    /// members already in the set is updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidScore` if a score is NaN, `WrongType` if the key holds
+    /// something else than a sorted set, or an error if the transaction fails.
+    pub fn zadd<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        members: &[(M, f64)]
+    ) -> Result<u64, TransientError> {
+        let key = key.as_ref();
+        if members.iter().any(|(_, s)| s.is_nan()) {
+            return Err(TransientError::InvalidScore);
+        }
+        if members.is_empty() {
+            return Ok(0);
+        }
+
+        self.transaction(|tx| {
+            let mut set = match tx.get_collection(&key)? {
+                Some(s) => s,
+                None => {
+                    Collection::new(CollectionKind::SortedSet {
+                        len: 0
+                    })?
+                },
+            };
+            let len = set_len(&set)?;
+
+            let mut added = 0;
+            for (member, score) in members {
+                let member = member.as_ref();
+
+                match remove_member(tx, &set, member)? {
+                    Some(_) => (),
+                    None => added += 1
+                }
+                tx.insert_member(&score_key(&set, member), &score.to_be_bytes())?;
+                tx.insert_member(&index_key(&set, *score, member), &[])?;
+            }
+
+            set.kind = CollectionKind::SortedSet {
+                len: len + added
+            };
+            tx.set_collection(&key, &set)?;
+
+            Ok(added)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Removes the members from the sorted set and returns how many were in
+    /// it, the set is removed with its last member.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a sorted set,
+    /// or an error if the transaction fails.
+    pub fn zrem<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        members: &[M]
+    ) -> Result<u64, TransientError> {
+        let key = key.as_ref();
+
+        self.transaction(|tx| {
+            let mut set = match tx.get_collection(&key)? {
+                Some(s) => s,
+                None => return Ok(0)
+            };
+            let len = set_len(&set)?;
+
+            let mut removed = 0;
+            for member in members {
+                if remove_member(tx, &set, member.as_ref())?.is_some() {
+                    removed += 1;
+                }
+            }
+
+            if removed == len {
+                tx.remove_raw(&key)?;
+            } else if removed > 0 {
+                set.kind = CollectionKind::SortedSet {
+                    len: len - removed
+                };
+                tx.set_collection(&key, &set)?;
+            }
+
+            Ok(removed)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Returns the score of the member, `None` if it is not in the set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a sorted set.
+    pub fn zscore<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        member: &M
+    ) -> Result<Option<f64>, TransientError> {
+        let set = match self.get_sorted_set(key)? {
+            Some(s) => s,
+            None => return Ok(None)
+        };
+
+        match self.get_member(&score_key(&set, member.as_ref()))? {
+            Some(s) => Ok(Some(decode_score(&s)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Returns the number of members of the sorted set, 0 if it does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a sorted set.
+    pub fn zcard<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        match self.get_sorted_set(key)? {
+            Some(set) => set_len(&set),
+            None => Ok(0)
+        }
+    }
+
+    /// Returns the rank of the member, its position in the set ordered by
+    /// ascending score starting from 0, `None` if it is not in the set.
+    ///
+    /// This walks the members ranked before it, so it is linear in the rank.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a sorted set,
+    /// or an error if the members cannot be read.
+    pub fn zrank<K: AsRef<[u8]>, M: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        member: &M
+    ) -> Result<Option<u64>, TransientError> {
+        let member = member.as_ref();
+        let set = match self.get_sorted_set(key)? {
+            Some(s) => s,
+            None => return Ok(None)
+        };
+
+        let score = match self.get_member(&score_key(&set, member))? {
+            Some(s) => decode_score(&s)?,
+            None => return Ok(None)
+        };
+
+        let start = index_prefix(&set);
+        let end = index_key(&set, score, member);
+        let mut rank = 0;
+        for i in self.storage.range(
+            TreeKind::Members,
+            Bound::Included(&start),
+            Bound::Excluded(&end)
+        ) {
+            i?;
+            rank += 1;
+        }
+
+        Ok(Some(rank))
+    }
+
+    /// Returns the members from rank `start` to `stop` included, ordered by
+    /// ascending score.
+    ///
+    /// Negative ranks count from the end of the set, -1 being the member with
+    /// the highest score, and ranks out of the set are clamped, like
+    /// `DB::lrange`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a sorted set,
+    /// or an error if the members cannot be read.
+    pub fn zrange<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: i64,
+        stop: i64
+    ) -> Result<Vec<ScoredMember>, TransientError> {
+        let set = match self.get_sorted_set(key)? {
+            Some(s) => s,
+            None => return Ok(Vec::new())
+        };
+
+        let len = set_len(&set)? as i64;
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            len + stop
+        } else {
+            stop.min(len - 1)
+        };
+        if start > stop || start >= len {
+            return Ok(Vec::new());
+        }
+
+        self.prefix_range(TreeKind::Members, &index_prefix(&set))
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .map(|i| decode_index(&i?.0))
+            .collect()
+    }
+
+    /// Returns the members whose score is between `min` and `max` included,
+    /// ordered by ascending score.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a sorted set,
+    /// or an error if the members cannot be read.
+    pub fn zrange_by_score<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        min: f64,
+        max: f64
+    ) -> Result<Vec<ScoredMember>, TransientError> {
+        let set = match self.get_sorted_set(key)? {
+            Some(s) => s,
+            None => return Ok(Vec::new())
+        };
+        if min.is_nan() || max.is_nan() || min > max {
+            return Ok(Vec::new());
+        }
+
+        let start = [&index_prefix(&set)[..], &encode_score(min)].concat();
+        let end = [&index_prefix(&set)[..], &encode_score(max)].concat();
+        let iter: StorageIter = match prefix_end(&end) {
+            Some(end) => {
+                self.storage.range(
+                    TreeKind::Members,
+                    Bound::Included(&start),
+                    Bound::Excluded(&end)
+                )
+            },
+            None => {
+                self.storage
+                    .range(TreeKind::Members, Bound::Included(&start), Bound::Unbounded)
+            },
+        };
+
+        iter.map(|i| decode_index(&i?.0)).collect()
+    }
+
+    /// Returns the sorted set stored at the key.
+    fn get_sorted_set<K: AsRef<[u8]>>(
+        &self,
+        key: &K
+    ) -> Result<Option<Collection>, TransientError> {
+        match self.get_collection(key)? {
+            Some(set) => {
+                set_len(&set)?;
+                Ok(Some(set))
+            },
+            None => Ok(None)
+        }
+    }
+}
+
+/// Removes the member and its entry in the score index, returns its score.
+fn remove_member(
+    tx: &mut TransactionalGuard,
+    set: &Collection,
+    member: &[u8]
+) -> Result<Option<f64>, TransientError> {
+    let score = match tx.remove_member(&score_key(set, member))? {
+        Some(s) => decode_score(&s)?,
+        None => return Ok(None)
+    };
+    tx.remove_member(&index_key(set, score, member))?;
+
+    Ok(Some(score))
+}
+
+/// Returns the number of members of the sorted set.
+fn set_len(set: &Collection) -> Result<u64, TransientError> {
+    match set.kind {
+        CollectionKind::SortedSet {
+            len
+        } => Ok(len),
+        _ => Err(TransientError::WrongType)
+    }
+}
+
+fn score_key(set: &Collection, member: &[u8]) -> Vec<u8> {
+    set.member_key(&[&[SCORE_TAG][..], member].concat())
+}
+
+fn index_prefix(set: &Collection) -> Vec<u8> {
+    set.member_key(&[INDEX_TAG])
+}
+
+fn index_key(set: &Collection, score: f64, member: &[u8]) -> Vec<u8> {
+    [&index_prefix(set)[..], &encode_score(score), member].concat()
+}
+
+/// Encodes the score so its bytes sort in the same order as the scores.
+///
+/// Flipping the sign bit puts the positive scores after the negative ones,
+/// and flipping every bit of the negative ones reverses their order.
+fn encode_score(score: f64) -> [u8; 8] {
+    // -0.0 and 0.0 are the same score
+    let bits = (score + 0.0).to_bits();
+    let bits = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    };
+    bits.to_be_bytes()
+}
+
+fn decode_score(bytes: &[u8]) -> Result<f64, TransientError> {
+    Ok(f64::from_be_bytes(
+        bytes
+            .try_into()
+            .map_err(|_| TransientError::ParsingFromByteError)?
+    ))
+}
+
+/// Decodes a key of the score index, made of the id, the tag, the encoded
+/// score and the member.
+fn decode_index(key: &[u8]) -> Result<ScoredMember, TransientError> {
+    if key.len() < 17 {
+        return Err(TransientError::ParsingFromByteError);
+    }
+
+    let bits = u64::from_be_bytes(
+        key[9..17]
+            .try_into()
+            .map_err(|_| TransientError::ParsingFromByteError)?
+    );
+    let bits = if bits >> 63 == 1 {
+        bits & !(1 << 63)
+    } else {
+        !bits
+    };
+
+    Ok((key[17..].to_vec(), f64::from_bits(bits)))
+}
@@ -0,0 +1,372 @@
+//! This module defines the statistics of the database: how many keys and bytes
+//! it stores and how much disk it takes, and how its keys are used and when
+//! they expire.
+
+use std::cmp::Reverse;
+use std::collections::{
+    BinaryHeap,
+    HashMap
+};
+use std::time::Duration;
+
+use crate::db::errors::TransientError;
+use crate::db::storage::TreeKind;
+use crate::{
+    DB,
+    Metadata,
+    ValueFormat
+};
+
+/// The number of keys of a tree and the bytes they take, before any storage
+/// overhead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// The number of keys
+    pub keys: u64,
+    /// The length of the keys, added up
+    pub key_bytes: u64,
+    /// The length of the values, added up
+    pub value_bytes: u64
+}
+
+impl TreeStats {
+    /// Returns the bytes of the keys and the values.
+    pub fn bytes(&self) -> u64 {
+        self.key_bytes + self.value_bytes
+    }
+}
+
+/// The size of the database, returned by `DB::storage_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// The bytes the backend takes on disk, 0 if it is not persistent
+    pub disk_bytes: u64,
+    /// The keys and values
+    pub data: TreeStats,
+    /// The metadata of the keys
+    pub meta: TreeStats,
+    /// The expiration timestamps
+    pub ttl: TreeStats,
+    /// The members of the collections
+    pub members: TreeStats,
+    /// The entries of the secondary indexes
+    pub index: TreeStats
+}
+
+impl StorageStats {
+    /// Returns the bytes of every tree, the size of the database before any
+    /// storage overhead.
+    pub fn logical_bytes(&self) -> u64 {
+        [self.data, self.meta, self.ttl, self.members, self.index]
+            .iter()
+            .map(TreeStats::bytes)
+            .sum()
+    }
+}
+
+/// The distribution of a value over the keys, e.g. their frequency.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Distribution {
+    /// The number of keys
+    pub count: u64,
+    /// The smallest value, 0 if there are no keys
+    pub min: u64,
+    /// The largest value, 0 if there are no keys
+    pub max: u64,
+    /// The average value, 0 if there are no keys
+    pub mean: f64,
+    /// The median value
+    pub p50: u64,
+    /// The value 90% of the keys are below or equal to
+    pub p90: u64,
+    /// The value 99% of the keys are below or equal to
+    pub p99: u64
+}
+
+impl Distribution {
+    /// Computes the distribution of the values, in any order.
+    pub fn from_values(mut values: Vec<u64>) -> Distribution {
+        if values.is_empty() {
+            return Distribution::default();
+        }
+        values.sort_unstable();
+
+        // The nearest-rank percentile
+        let percentile = |p: usize| values[(values.len() * p).div_ceil(100).max(1) - 1];
+        Distribution {
+            count: values.len() as u64,
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<u64>() as f64 / values.len() as f64,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99)
+        }
+    }
+}
+
+/// The statistics of the keys, returned by `DB::stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DBStats {
+    /// The keys which are not expired
+    pub live_keys: u64,
+    /// The live keys which have a TTL
+    pub ttl_keys: u64,
+    /// The keys whose TTL has passed but which the TTL thread didn't remove
+    /// yet, they are not counted as live
+    pub expired_keys: u64,
+    /// The frequency of the live keys
+    pub freq: Distribution,
+    /// The length of the values of the live keys as stored, after
+    /// compression, collections only count their header
+    pub value_size: Distribution,
+    /// The time since the oldest live key was created
+    pub oldest_key_age: Option<Duration>,
+    /// The time left before the next live key expires
+    pub next_expiry: Option<Duration>
+}
+
+/// The largest keys and their size in a min heap, so the smallest one is the
+/// one evicted.
+type Largest = BinaryHeap<Reverse<(u64, Vec<u8>)>>;
+
+/// The size and the usage of a key, returned by `DB::key_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    /// The length of the stored value, after compression, the header of a
+    /// collection
+    pub value_len: u64,
+    /// The bytes of the members of a collection, 0 for a plain value
+    pub members_len: u64,
+    /// The length of the encoded metadata
+    pub metadata_len: u64,
+    /// The number of times the key has been accessed
+    pub freq: u64,
+    /// The time left before the key expires, `None` if it is persistent
+    pub ttl: Option<Duration>,
+    /// The time since the key was last accessed, or created if it never was
+    pub idle: Duration
+}
+
+impl KeyInfo {
+    /// Returns the bytes of the value, members included.
+    pub fn size(&self) -> u64 {
+        self.value_len + self.members_len
+    }
+}
+
+/// The largest keys sharing a prefix, returned by `DB::big_keys`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixReport {
+    /// The key up to its first `:` included, empty for the keys without one
+    pub prefix: Vec<u8>,
+    /// The number of keys with the prefix
+    pub keys: u64,
+    /// The size of the values of every key with the prefix, added up
+    pub bytes: u64,
+    /// The largest keys with the prefix and their size, largest first
+    pub largest: Vec<(Vec<u8>, u64)>
+}
+
+impl DB {
+    /// Returns the size and the usage of the key, `None` if it does not
+    /// exist.
+    ///
+    /// A key is accessed when its frequency is incremented, reading it does
+    /// not count as an access. A key which expired but is not removed yet has
+    /// `Duration::ZERO` left.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key cannot be read or its metadata cannot be
+    /// deserialized.
+    pub fn key_info<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<KeyInfo>, TransientError> {
+        let key = key.as_ref();
+
+        let mut stored = self
+            .storage
+            .get_many(&[TreeKind::Data, TreeKind::Meta], key)?
+            .into_iter();
+        let (value, meta_bytes) = match (stored.next().flatten(), stored.next().flatten()) {
+            (Some(v), Some(m)) => (v, m),
+            _ => return Ok(None)
+        };
+        let meta =
+            Metadata::from_u8(&meta_bytes).map_err(|_| TransientError::ParsingFromByteError)?;
+
+        let now = self.clock.now_secs();
+        Ok(Some(KeyInfo {
+            value_len: value.len() as u64,
+            members_len: self.members_len(meta.format)?,
+            metadata_len: meta_bytes.len() as u64,
+            freq: meta.freq,
+            ttl: meta.ttl.map(|t| Duration::from_secs(t.saturating_sub(now))),
+            idle: Duration::from_secs(now.saturating_sub(meta.last_accessed))
+        }))
+    }
+
+    /// Walks every key and returns, for each prefix, the `n` largest keys and
+    /// the size of all the keys, the largest prefixes first.
+    ///
+    /// The prefix of a key is everything up to its first `:`, e.g. `session:`
+    /// for `session:42`. The size of a key is the size of its value, the
+    /// members of a collection included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keys cannot be read.
+    pub fn big_keys(&self, n: usize) -> Result<Vec<PrefixReport>, TransientError> {
+        let mut prefixes: HashMap<Vec<u8>, (u64, u64, Largest)> = HashMap::new();
+
+        for i in self.storage.iter(TreeKind::Data) {
+            let (key, value) = i?;
+            let format = match self.storage.get(TreeKind::Meta, &key)? {
+                Some(m) => {
+                    Metadata::from_u8(&m)
+                        .map_err(|_| TransientError::ParsingFromByteError)?
+                        .format
+                },
+                // Removed since it was read
+                None => continue
+            };
+            let size = value.len() as u64 + self.members_len(format)?;
+            let prefix = match key.iter().position(|&b| b == b':') {
+                Some(p) => key[..=p].to_vec(),
+                None => Vec::new()
+            };
+
+            let (keys, bytes, largest) = prefixes.entry(prefix).or_default();
+            *keys += 1;
+            *bytes += size;
+            largest.push(Reverse((size, key)));
+            if largest.len() > n {
+                largest.pop();
+            }
+        }
+
+        let mut reports: Vec<PrefixReport> = prefixes
+            .into_iter()
+            .map(|(prefix, (keys, bytes, largest))| {
+                PrefixReport {
+                    prefix,
+                    keys,
+                    bytes,
+                    largest: largest
+                        .into_sorted_vec()
+                        .into_iter()
+                        .map(|Reverse((size, key))| (key, size))
+                        .collect()
+                }
+            })
+            .collect();
+        reports.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.prefix.cmp(&b.prefix)));
+
+        Ok(reports)
+    }
+
+    /// Returns the bytes of the members of the collection stored in the
+    /// format, 0 if the value is not a collection.
+    fn members_len(&self, format: ValueFormat) -> Result<u64, TransientError> {
+        let id = match format.collection {
+            Some(id) => id,
+            None => return Ok(0)
+        };
+
+        let mut len = 0;
+        for i in self.prefix_range(TreeKind::Members, &id.to_be_bytes()) {
+            let (member, value) = i?;
+            len += (member.len() + value.len()) as u64;
+        }
+
+        Ok(len)
+    }
+
+    /// Returns the statistics of the keys: how many are live, have a TTL or
+    /// are waiting for the TTL thread, and how their frequency and value size
+    /// are distributed.
+    ///
+    /// Unlike `get_db_size`, the keys which expired but are not removed yet
+    /// are not counted as live. This reads the metadata and the value of every
+    /// key, so it is not meant to be called in a hot path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keys cannot be read or their metadata cannot be
+    /// deserialized.
+    pub fn stats(&self) -> Result<DBStats, TransientError> {
+        let now = self.clock.now_secs();
+
+        let mut live_keys = 0;
+        let mut ttl_keys = 0;
+        let mut expired_keys = 0;
+        let mut freqs = Vec::new();
+        let mut value_sizes = Vec::new();
+        let mut oldest: Option<u64> = None;
+        let mut next_expiry: Option<u64> = None;
+
+        for i in self.storage.iter(TreeKind::Meta) {
+            let (key, meta) = i?;
+            let meta =
+                Metadata::from_u8(&meta).map_err(|_| TransientError::ParsingFromByteError)?;
+
+            if let Some(ttl) = meta.ttl {
+                if ttl <= now {
+                    expired_keys += 1;
+                    continue;
+                }
+                ttl_keys += 1;
+                next_expiry = Some(next_expiry.map_or(ttl, |t| t.min(ttl)));
+            }
+
+            live_keys += 1;
+            freqs.push(meta.freq);
+            oldest = Some(oldest.map_or(meta.created_at, |c| c.min(meta.created_at)));
+            if let Some(value) = self.storage.get(TreeKind::Data, &key)? {
+                value_sizes.push(value.len() as u64);
+            }
+        }
+
+        Ok(DBStats {
+            live_keys,
+            ttl_keys,
+            expired_keys,
+            freq: Distribution::from_values(freqs),
+            value_size: Distribution::from_values(value_sizes),
+            oldest_key_age: oldest.map(|c| Duration::from_secs(now.saturating_sub(c))),
+            next_expiry: next_expiry.map(|t| Duration::from_secs(t - now))
+        })
+    }
+
+    /// Returns the size of the database on disk and the bytes stored in each
+    /// of its trees.
+    ///
+    /// The tree sizes are computed by reading every key of the database, so
+    /// this is not meant to be called in a hot path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the disk size cannot be computed or the trees
+    /// cannot be read.
+    pub fn storage_stats(&self) -> Result<StorageStats, TransientError> {
+        Ok(StorageStats {
+            disk_bytes: self.storage.disk_size()?,
+            data: self.tree_stats(TreeKind::Data)?,
+            meta: self.tree_stats(TreeKind::Meta)?,
+            ttl: self.tree_stats(TreeKind::Ttl)?,
+            members: self.tree_stats(TreeKind::Members)?,
+            index: self.tree_stats(TreeKind::Index)?
+        })
+    }
+
+    fn tree_stats(&self, tree: TreeKind) -> Result<TreeStats, TransientError> {
+        let mut stats = TreeStats::default();
+        for i in self.storage.iter(tree) {
+            let (key, value) = i?;
+            stats.keys += 1;
+            stats.key_bytes += key.len() as u64;
+            stats.value_bytes += value.len() as u64;
+        }
+
+        Ok(stats)
+    }
+}
@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::db::errors::TransientError;
+use crate::db::storage::{
+    KeyValues,
+    MAX_CONFLICT_RETRIES,
+    StorageBackend,
+    StorageIter,
+    StorageTransaction,
+    StorageTxError,
+    TreeKind,
+    TxReads,
+    TxWrites,
+    is_inverted,
+    overlay,
+    tree_index
+};
+
+type Trees = [BTreeMap<Vec<u8>, Vec<u8>>; 5];
+
+/// A non persistent backend which keeps the trees in `BTreeMap`s.
+///
+/// This backend is meant for tests and benchmarks, everything is lost when it
+/// is dropped. Iterators work on a snapshot of the tree taken when they are
+/// created.
+///
+/// Transactions run optimistically like the ones of the `SledBackend`: the
+/// closure runs without holding the lock, and its reads are checked again
+/// under the write lock before its writes are applied. A conflicting closure
+/// is retried at most `MAX_CONFLICT_RETRIES` times.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    trees: RwLock<Trees>
+}
+
+impl MemoryBackend {
+    /// Creates an empty in-memory backend.
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        let trees = self
+            .trees
+            .read()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        Ok(trees[tree_index(tree)].get(key).cloned())
+    }
+
+    fn get_many(
+        &self,
+        trees: &[TreeKind],
+        key: &[u8]
+    ) -> Result<Vec<Option<Vec<u8>>>, TransientError> {
+        let current = self
+            .trees
+            .read()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        Ok(trees
+            .iter()
+            .map(|t| current[tree_index(*t)].get(key).cloned())
+            .collect())
+    }
+
+    fn insert(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Option<Vec<u8>>, TransientError> {
+        let mut trees = self
+            .trees
+            .write()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        Ok(trees[tree_index(tree)].insert(key.to_vec(), value.to_vec()))
+    }
+
+    fn remove(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        let mut trees = self
+            .trees
+            .write()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        Ok(trees[tree_index(tree)].remove(key))
+    }
+
+    fn compare_and_swap(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>
+    ) -> Result<bool, TransientError> {
+        let mut trees = self
+            .trees
+            .write()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+        let tree = &mut trees[tree_index(tree)];
+
+        if tree.get(key).map(|v| &v[..]) != old {
+            return Ok(false);
+        }
+
+        match new {
+            Some(v) => tree.insert(key.to_vec(), v.to_vec()),
+            None => tree.remove(key)
+        };
+
+        Ok(true)
+    }
+
+    fn range(&self, tree: TreeKind, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StorageIter {
+        let trees = match self.trees.read() {
+            Ok(t) => t,
+            Err(_) => return Box::new(std::iter::once(Err(TransientError::PoisonedMutex)))
+        };
+
+        Box::new(read_range(&trees, tree, start, end).into_iter().map(Ok))
+    }
+
+    fn len(&self, tree: TreeKind) -> usize {
+        self.trees
+            .read()
+            .map(|t| t[tree_index(tree)].len())
+            .unwrap_or(0)
+    }
+
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn StorageTransaction) -> Result<(), StorageTxError>
+    ) -> Result<(), StorageTxError> {
+        let mut retries = 0;
+        loop {
+            let tx = MemoryTransaction {
+                backend: self,
+                writes: RefCell::new(TxWrites::default()),
+                reads: RefCell::new(TxReads::default())
+            };
+            let result = f(&tx);
+
+            let mut trees = self
+                .trees
+                .write()
+                .map_err(|_| StorageTxError::Storage(TransientError::PoisonedMutex))?;
+            let current = tx
+                .reads
+                .borrow()
+                .are_current(
+                    |tree, key| Ok(trees[tree_index(tree)].get(key).cloned()),
+                    |tree, start, end| Ok(read_range(&trees, tree, start, end))
+                )
+                .map_err(StorageTxError::Storage)?;
+
+            match result {
+                Ok(()) if current => {
+                    let writes = tx.writes.into_inner();
+                    for (tree, writes) in trees.iter_mut().zip(writes) {
+                        for (key, value) in writes {
+                            match value {
+                                Some(v) => tree.insert(key, v),
+                                None => tree.remove(&key)
+                            };
+                        }
+                    }
+                    return Ok(());
+                },
+                Ok(()) | Err(StorageTxError::Conflict) => (),
+                // A failed run is retried as well if its reads changed, the failure may come
+                // from reading half of another commit
+                Err(_) if !current => (),
+                Err(e) => return Err(e)
+            }
+
+            if retries == MAX_CONFLICT_RETRIES {
+                return Err(StorageTxError::Conflict);
+            }
+            retries += 1;
+        }
+    }
+
+    fn flush(&self) -> Result<(), TransientError> {
+        Ok(())
+    }
+
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Returns a copy of the pairs of the range of the tree.
+fn read_range(trees: &Trees, tree: TreeKind, start: Bound<&[u8]>, end: Bound<&[u8]>) -> KeyValues {
+    if is_inverted(start, end) {
+        return Vec::new();
+    }
+
+    trees[tree_index(tree)]
+        .range::<[u8], _>((start, end))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+/// The in-memory implementation of the `StorageTransaction`, the writes are
+/// buffered and only applied to the trees when the transaction commits.
+struct MemoryTransaction<'a> {
+    backend: &'a MemoryBackend,
+    writes: RefCell<TxWrites>,
+    reads: RefCell<TxReads>
+}
+
+impl MemoryTransaction<'_> {
+    fn current(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError> {
+        if let Some(v) = self.writes.borrow()[tree_index(tree)].get(key) {
+            return Ok(v.clone());
+        }
+
+        let value = self
+            .backend
+            .get(tree, key)
+            .map_err(StorageTxError::Storage)?;
+        self.reads.borrow_mut().read_key(tree, key, &value);
+        Ok(value)
+    }
+}
+
+impl StorageTransaction for MemoryTransaction<'_> {
+    fn get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError> {
+        self.current(tree, key)
+    }
+
+    fn insert(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Option<Vec<u8>>, StorageTxError> {
+        let prev = self.current(tree, key)?;
+        self.writes.borrow_mut()[tree_index(tree)].insert(key.to_vec(), Some(value.to_vec()));
+        Ok(prev)
+    }
+
+    fn remove(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError> {
+        let prev = self.current(tree, key)?;
+        self.writes.borrow_mut()[tree_index(tree)].insert(key.to_vec(), None);
+        Ok(prev)
+    }
+
+    fn range(
+        &self,
+        tree: TreeKind,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>
+    ) -> Result<KeyValues, StorageTxError> {
+        let pairs = {
+            let trees = self
+                .backend
+                .trees
+                .read()
+                .map_err(|_| StorageTxError::Storage(TransientError::PoisonedMutex))?;
+            read_range(&trees, tree, start, end)
+        };
+        self.reads.borrow_mut().read_range(tree, start, end, &pairs);
+
+        overlay(
+            pairs.into_iter().map(Ok),
+            &self.writes.borrow()[tree_index(tree)],
+            start,
+            end
+        )
+        .map_err(StorageTxError::Storage)
+    }
+}
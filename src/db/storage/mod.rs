@@ -0,0 +1,374 @@
+//! The `storage` module defines the `StorageBackend` trait which abstracts the
+//! underlying key-value engine away from EpochDB's lifecycle logic.
+//!
+//! EpochDB stores everything in five logical trees (see [`TreeKind`]), a
+//! backend only has to provide ordered byte maps for those five trees, plus a
+//! way to run a closure atomically over all of them.
+
+pub mod memory_backend;
+pub mod sled_backend;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{
+    Debug,
+    Display
+};
+use std::fs;
+use std::ops::Bound;
+use std::path::Path;
+
+pub use memory_backend::MemoryBackend;
+pub use sled_backend::SledBackend;
+
+use crate::db::errors::TransientError;
+
+/// The five logical trees every EpochDB backend has to provide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TreeKind {
+    /// Stores the key and value
+    Data,
+    /// Stores the key and the metadata
+    Meta,
+    /// Stores the ttl timestamp and the key
+    Ttl,
+    /// Stores the members of the collections (lists, ...), under the id of
+    /// their collection
+    Members,
+    /// Stores the entries of the secondary indexes, under the name of their
+    /// index and their term
+    Index
+}
+
+/// An ordered iterator over the (key, value) pairs of a tree.
+pub type StorageIter = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), TransientError>> + Send>;
+
+/// The ordered (key, value) pairs of a range, read by a transaction.
+pub type KeyValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// How many times a conflicting transaction closure is run again by the
+/// built-in backends, before the transaction fails with the conflict.
+pub const MAX_CONFLICT_RETRIES: u32 = 100;
+
+/// The writes buffered by a transaction, by tree and key, `None` being a
+/// removal.
+pub(crate) type TxWrites = [BTreeMap<Vec<u8>, Option<Vec<u8>>>; 5];
+
+/// A range read by a transaction: its tree, its bounds and the pairs it held.
+type RangeRead = (TreeKind, Bound<Vec<u8>>, Bound<Vec<u8>>, KeyValues);
+
+/// The reads of a transaction, checked again right before it commits: if any
+/// of them would now return something else, another write committed since it
+/// was read and the transaction conflicts.
+#[derive(Default)]
+pub(crate) struct TxReads {
+    /// The value of each key when it was first read, by tree
+    keys: [BTreeMap<Vec<u8>, Option<Vec<u8>>>; 5],
+    /// The ranges read, with the pairs the tree held before the writes of the
+    /// transaction were applied
+    ranges: Vec<RangeRead>
+}
+
+impl TxReads {
+    /// Records the value read from the tree, unless the key was read already.
+    pub(crate) fn read_key(&mut self, tree: TreeKind, key: &[u8], value: &Option<Vec<u8>>) {
+        self.keys[tree_index(tree)]
+            .entry(key.to_vec())
+            .or_insert_with(|| value.clone());
+    }
+
+    /// Records the pairs read from the range of the tree.
+    pub(crate) fn read_range(
+        &mut self,
+        tree: TreeKind,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        pairs: &KeyValues
+    ) {
+        self.ranges.push((
+            tree,
+            start.map(<[u8]>::to_vec),
+            end.map(<[u8]>::to_vec),
+            pairs.clone()
+        ));
+    }
+
+    /// Returns true if every read still returns the same thing, with `get`
+    /// and `range` reading the trees as they are now. The caller has to keep
+    /// anything else from writing until the transaction is committed.
+    pub(crate) fn are_current(
+        &self,
+        get: impl Fn(TreeKind, &[u8]) -> Result<Option<Vec<u8>>, TransientError>,
+        range: impl Fn(TreeKind, Bound<&[u8]>, Bound<&[u8]>) -> Result<KeyValues, TransientError>
+    ) -> Result<bool, TransientError> {
+        for tree in [
+            TreeKind::Data,
+            TreeKind::Meta,
+            TreeKind::Ttl,
+            TreeKind::Members,
+            TreeKind::Index
+        ] {
+            for (key, value) in &self.keys[tree_index(tree)] {
+                if get(tree, key)? != *value {
+                    return Ok(false);
+                }
+            }
+        }
+
+        for (tree, start, end, pairs) in &self.ranges {
+            let start = start.as_ref().map(Vec::as_slice);
+            let end = end.as_ref().map(Vec::as_slice);
+            if range(*tree, start, end)? != *pairs {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Returns the position of the tree in the arrays of trees.
+pub(crate) fn tree_index(tree: TreeKind) -> usize {
+    match tree {
+        TreeKind::Data => 0,
+        TreeKind::Meta => 1,
+        TreeKind::Ttl => 2,
+        TreeKind::Members => 3,
+        TreeKind::Index => 4
+    }
+}
+
+/// Returns true if the start bound is past the end bound, a `BTreeMap`
+/// panics on such a range where sled yields nothing.
+pub(crate) fn is_inverted(start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    match (start, end) {
+        (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e)) => s > e,
+        (Bound::Included(s), Bound::Excluded(e)) => s >= e,
+        (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+        _ => false
+    }
+}
+
+/// Returns the pairs of a range read from a tree, with the writes the
+/// transaction made to the tree applied over them.
+pub(crate) fn overlay(
+    pairs: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), TransientError>>,
+    writes: &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    start: Bound<&[u8]>,
+    end: Bound<&[u8]>
+) -> Result<KeyValues, TransientError> {
+    if is_inverted(start, end) {
+        return Ok(Vec::new());
+    }
+
+    let mut range = pairs.collect::<Result<BTreeMap<_, _>, _>>()?;
+    for (key, value) in writes.range::<[u8], _>((start, end)) {
+        match value {
+            Some(v) => range.insert(key.clone(), v.clone()),
+            None => range.remove(key)
+        };
+    }
+
+    Ok(range.into_iter().collect())
+}
+
+/// Error raised by an operation inside a storage transaction.
+#[derive(Debug)]
+pub enum StorageTxError {
+    /// The transaction conflicted with another one, the backend will retry the
+    /// transaction closure.
+    Conflict,
+    /// The backend failed while executing the transaction.
+    Storage(TransientError),
+    /// The transaction was aborted by the caller, every change will be rolled
+    /// back.
+    Abort
+}
+
+impl Display for StorageTxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageTxError::Conflict => writeln!(f, "Transaction conflicted"),
+            StorageTxError::Storage(e) => writeln!(f, "Storage failed during transaction {e}"),
+            StorageTxError::Abort => writeln!(f, "Transaction was aborted")
+        }
+    }
+}
+
+impl Error for StorageTxError {}
+
+impl From<StorageTxError> for TransientError {
+    fn from(value: StorageTxError) -> Self {
+        match value {
+            StorageTxError::Conflict => TransientError::TransactionConflict,
+            StorageTxError::Storage(e) => e,
+            StorageTxError::Abort => TransientError::SledTransactionError
+        }
+    }
+}
+
+/// The view of the five trees given to a transaction closure.
+///
+/// Every read sees the writes done previously in the same transaction, and
+/// nothing is visible to other readers until the transaction commits. The
+/// transaction only commits if nothing it read was written since, otherwise
+/// it conflicts and the closure is run again, so the reads of a committed
+/// transaction all come from the same snapshot.
+pub trait StorageTransaction {
+    /// Retrieves the value of a key.
+    fn get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError>;
+
+    /// Inserts a key-value pair, returning the previous value.
+    fn insert(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Option<Vec<u8>>, StorageTxError>;
+
+    /// Removes a key, returning the previous value.
+    fn remove(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError>;
+
+    /// Returns the ordered (key, value) pairs within the given bounds.
+    ///
+    /// The range is read with the writes of the transaction applied. Like
+    /// any other read, if a key is added to, changed in or removed from the
+    /// range before the transaction commits, the transaction conflicts.
+    fn range(
+        &self,
+        tree: TreeKind,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>
+    ) -> Result<KeyValues, StorageTxError>;
+}
+
+/// The storage engine which EpochDB runs on top of.
+///
+/// Implementations have to be thread safe, since the `DB` shares the backend
+/// with its background threads.
+pub trait StorageBackend: Debug + Send + Sync {
+    /// Retrieves the value of a key.
+    fn get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError>;
+
+    /// Inserts a key-value pair, returning the previous value.
+    fn insert(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Option<Vec<u8>>, TransientError>;
+
+    /// Removes a key, returning the previous value.
+    fn remove(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError>;
+
+    /// Retrieves the values of a key in each of the trees, in their order, all
+    /// read from the same snapshot.
+    ///
+    /// By default this runs a transaction, a backend which can read a
+    /// snapshot without waiting for the other transactions should override
+    /// it.
+    fn get_many(
+        &self,
+        trees: &[TreeKind],
+        key: &[u8]
+    ) -> Result<Vec<Option<Vec<u8>>>, TransientError> {
+        let mut values = Vec::new();
+        self.transaction(&mut |tx| {
+            values = trees
+                .iter()
+                .map(|t| tx.get(*t, key))
+                .collect::<Result<_, _>>()?;
+            Ok(())
+        })?;
+
+        Ok(values)
+    }
+
+    /// Atomically replaces the value of a key if the current value is `old`.
+    ///
+    /// A `None` as `old` means the key must not exist, a `None` as `new`
+    /// removes the key. Returns `true` if the swap happened.
+    fn compare_and_swap(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>
+    ) -> Result<bool, TransientError>;
+
+    /// Returns an ordered iterator over the keys within the given bounds.
+    fn range(&self, tree: TreeKind, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StorageIter;
+
+    /// Returns the number of keys in a tree.
+    fn len(&self, tree: TreeKind) -> usize;
+
+    /// Runs the closure atomically over the five trees.
+    ///
+    /// If something the closure read was written before the transaction
+    /// commits, or if the closure returns `StorageTxError::Conflict`, the
+    /// backend retries it, a backend which bounds the retries returns the
+    /// conflict once it gives up. Any other error rolls back the transaction
+    /// and is returned.
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn StorageTransaction) -> Result<(), StorageTxError>
+    ) -> Result<(), StorageTxError>;
+
+    /// Makes sure every write done so far is durable.
+    fn flush(&self) -> Result<(), TransientError>;
+
+    /// The path the backend persists its data to, `None` if the backend is not
+    /// persistent.
+    fn path(&self) -> Option<&Path>;
+
+    /// Returns the number of bytes the backend takes on disk, 0 if it is not
+    /// persistent.
+    ///
+    /// By default this adds up the size of every file under `path`.
+    fn disk_size(&self) -> Result<u64, TransientError> {
+        match self.path() {
+            Some(p) => dir_size(p),
+            None => Ok(0)
+        }
+    }
+
+    /// Returns an ordered iterator over the whole tree.
+    fn iter(&self, tree: TreeKind) -> StorageIter {
+        self.range(tree, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Returns true if the tree contains no keys.
+    fn is_empty(&self, tree: TreeKind) -> bool {
+        self.len(tree) == 0
+    }
+}
+
+/// Adds up the size of every file under the path, recursively.
+///
+/// # Errors
+///
+/// Returns an `IOError` if a directory cannot be read, files removed during
+/// the walk are skipped.
+pub(crate) fn dir_size(path: &Path) -> Result<u64, TransientError> {
+    let entries = fs::read_dir(path).map_err(|e| {
+        TransientError::IOError {
+            error: e
+        }
+    })?;
+
+    let mut size = 0;
+    for entry in entries.flatten() {
+        let metadata = match entry.metadata() {
+            Ok(m) => m,
+            Err(_) => continue
+        };
+
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+
+    Ok(size)
+}
@@ -0,0 +1,433 @@
+use std::cell::RefCell;
+use std::fs;
+use std::ops::Bound;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::sync::RwLock;
+
+use sled::transaction::{
+    TransactionError,
+    Transactional
+};
+use sled::{
+    Config,
+    Tree
+};
+
+use crate::db::errors::TransientError;
+use crate::db::storage::{
+    KeyValues,
+    MAX_CONFLICT_RETRIES,
+    StorageBackend,
+    StorageIter,
+    StorageTransaction,
+    StorageTxError,
+    TreeKind,
+    TxReads,
+    TxWrites,
+    dir_size,
+    is_inverted,
+    overlay,
+    tree_index
+};
+
+/// The default backend of EpochDB, which persists the trees with `sled`.
+///
+/// This struct holds the 5 sled::Tree directly instead of only the sled::Db,
+/// since almost all of the functions uses the tree directly which requires the
+/// sled::Db to constantly open each trees.
+///
+/// A transaction runs optimistically: it reads the trees directly, records
+/// what it read and buffers its writes. To commit, its reads are checked
+/// again, ranges included, which sled's transactional trees cannot iterate
+/// over, and if none changed the writes are applied at once with a sled
+/// transaction over the 5 trees. Otherwise the transaction conflicts and is
+/// retried. Nothing is locked while the closure runs, so it can call the
+/// database itself, only the check and the commit exclude the other writes.
+#[derive(Debug)]
+pub struct SledBackend {
+    /// The sled database itself
+    db: sled::Db,
+    /// Stores the key and value
+    data_tree: Tree,
+    /// Stores the key and the metadata
+    meta_tree: Tree,
+    /// Stores the ttl timestamp and the key
+    ttl_tree: Tree,
+    /// Stores the members of the collections
+    members_tree: Tree,
+    /// Stores the entries of the secondary indexes
+    index_tree: Tree,
+    /// Held for writing by the transactions while they commit, and for
+    /// reading by the other writes
+    writers: RwLock<()>,
+    /// Path to the database
+    path: PathBuf
+}
+
+impl SledBackend {
+    /// Opens the sled database at the path and its five trees, the database is
+    /// created if it doesnt exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SledError` if the database or any of the trees cannot be
+    /// opened.
+    pub fn open(path: &Path) -> Result<SledBackend, TransientError> {
+        let db = Config::new()
+            .path(path)
+            .cache_capacity(512 * 1024 * 1024)
+            .open()
+            .map_err(|e| {
+                TransientError::SledError {
+                    error: e
+                }
+            })?;
+
+        let data_tree = db.open_tree("data_tree").map_err(|e| {
+            TransientError::SledError {
+                error: e
+            }
+        })?;
+        let meta_tree = db.open_tree("freq_tree").map_err(|e| {
+            TransientError::SledError {
+                error: e
+            }
+        })?;
+        let ttl_tree = db.open_tree("ttl_tree").map_err(|e| {
+            TransientError::SledError {
+                error: e
+            }
+        })?;
+        let members_tree = db.open_tree("members_tree").map_err(|e| {
+            TransientError::SledError {
+                error: e
+            }
+        })?;
+        let index_tree = db.open_tree("index_tree").map_err(|e| {
+            TransientError::SledError {
+                error: e
+            }
+        })?;
+
+        Ok(SledBackend {
+            db,
+            data_tree,
+            meta_tree,
+            ttl_tree,
+            members_tree,
+            index_tree,
+            writers: RwLock::new(()),
+            path: path.to_path_buf()
+        })
+    }
+
+    fn tree(&self, tree: TreeKind) -> &Tree {
+        match tree {
+            TreeKind::Data => &self.data_tree,
+            TreeKind::Meta => &self.meta_tree,
+            TreeKind::Ttl => &self.ttl_tree,
+            TreeKind::Members => &self.members_tree,
+            TreeKind::Index => &self.index_tree
+        }
+    }
+
+    /// Applies the writes of a transaction atomically.
+    fn commit(&self, writes: &TxWrites) -> Result<(), StorageTxError> {
+        let l: Result<(), TransactionError<()>> = (
+            &self.data_tree,
+            &self.meta_tree,
+            &self.ttl_tree,
+            &self.members_tree,
+            &self.index_tree
+        )
+            .transaction(
+                |(data_tree, meta_tree, ttl_tree, members_tree, index_tree)| {
+                    let trees = [data_tree, meta_tree, ttl_tree, members_tree, index_tree];
+                    for (tree, writes) in trees.into_iter().zip(writes) {
+                        for (key, value) in writes {
+                            match value {
+                                Some(v) => tree.insert(&key[..], &v[..])?,
+                                None => tree.remove(&key[..])?
+                            };
+                        }
+                    }
+                    Ok(())
+                }
+            );
+
+        l.map_err(|e| {
+            match e {
+                TransactionError::Abort(()) => StorageTxError::Abort,
+                TransactionError::Storage(e) => {
+                    StorageTxError::Storage(TransientError::SledError {
+                        error: e
+                    })
+                },
+            }
+        })
+    }
+}
+
+impl StorageBackend for SledBackend {
+    fn get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        self.tree(tree)
+            .get(key)
+            .map(|v| v.map(|v| v.to_vec()))
+            .map_err(|e| {
+                TransientError::SledError {
+                    error: e
+                }
+            })
+    }
+
+    fn insert(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Option<Vec<u8>>, TransientError> {
+        let _writers = self
+            .writers
+            .read()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        self.tree(tree)
+            .insert(key, value)
+            .map(|v| v.map(|v| v.to_vec()))
+            .map_err(|e| {
+                TransientError::SledError {
+                    error: e
+                }
+            })
+    }
+
+    /// The transactions wait for the reads to commit, so none commits in
+    /// between.
+    fn get_many(
+        &self,
+        trees: &[TreeKind],
+        key: &[u8]
+    ) -> Result<Vec<Option<Vec<u8>>>, TransientError> {
+        let _writers = self
+            .writers
+            .read()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        trees.iter().map(|t| self.get(*t, key)).collect()
+    }
+
+    fn remove(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        let _writers = self
+            .writers
+            .read()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        self.tree(tree)
+            .remove(key)
+            .map(|v| v.map(|v| v.to_vec()))
+            .map_err(|e| {
+                TransientError::SledError {
+                    error: e
+                }
+            })
+    }
+
+    fn compare_and_swap(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>
+    ) -> Result<bool, TransientError> {
+        let _writers = self
+            .writers
+            .read()
+            .map_err(|_| TransientError::PoisonedMutex)?;
+
+        let res = self
+            .tree(tree)
+            .compare_and_swap(key, old, new)
+            .map_err(|e| {
+                TransientError::SledError {
+                    error: e
+                }
+            })?;
+
+        Ok(res.is_ok())
+    }
+
+    fn range(&self, tree: TreeKind, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StorageIter {
+        Box::new(self.tree(tree).range::<&[u8], _>((start, end)).map(|i| {
+            i.map(|(k, v)| (k.to_vec(), v.to_vec())).map_err(|e| {
+                TransientError::SledError {
+                    error: e
+                }
+            })
+        }))
+    }
+
+    fn len(&self, tree: TreeKind) -> usize {
+        self.tree(tree).len()
+    }
+
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn StorageTransaction) -> Result<(), StorageTxError>
+    ) -> Result<(), StorageTxError> {
+        let mut retries = 0;
+        loop {
+            let tx = SledTransaction {
+                backend: self,
+                writes: RefCell::new(TxWrites::default()),
+                reads: RefCell::new(TxReads::default())
+            };
+            let result = f(&tx);
+
+            let _writers = self
+                .writers
+                .write()
+                .map_err(|_| StorageTxError::Storage(TransientError::PoisonedMutex))?;
+            let current = tx
+                .reads
+                .borrow()
+                .are_current(
+                    |tree, key| self.get(tree, key),
+                    |tree, start, end| self.range(tree, start, end).collect()
+                )
+                .map_err(StorageTxError::Storage)?;
+
+            match result {
+                Ok(()) if current => return self.commit(&tx.writes.into_inner()),
+                Ok(()) | Err(StorageTxError::Conflict) => (),
+                // A failed run is retried as well if its reads changed, the failure may come
+                // from reading half of another commit
+                Err(_) if !current => (),
+                Err(e) => return Err(e)
+            }
+
+            if retries == MAX_CONFLICT_RETRIES {
+                return Err(StorageTxError::Conflict);
+            }
+            retries += 1;
+        }
+    }
+
+    fn flush(&self) -> Result<(), TransientError> {
+        self.db.flush().map_err(|e| {
+            TransientError::SledError {
+                error: e
+            }
+        })?;
+
+        Ok(())
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    /// Sled reports the size of its log and blobs, the files it doesnt count
+    /// (the config and the snapshots) are added up by walking the directory.
+    fn disk_size(&self) -> Result<u64, TransientError> {
+        let mut size = self.db.size_on_disk().map_err(|e| {
+            TransientError::SledError {
+                error: e
+            }
+        })?;
+
+        let entries = fs::read_dir(&self.path).map_err(|e| {
+            TransientError::IOError {
+                error: e
+            }
+        })?;
+        for entry in entries.flatten() {
+            if matches!(entry.file_name().to_str(), Some("db" | "blobs")) {
+                continue;
+            }
+
+            let path = entry.path();
+            size += match entry.metadata() {
+                Ok(m) if m.is_dir() => dir_size(&path)?,
+                Ok(m) => m.len(),
+                Err(_) => 0
+            };
+        }
+
+        Ok(size)
+    }
+}
+
+/// The sled implementation of the `StorageTransaction`, the writes are
+/// buffered and only applied to the trees when the transaction commits.
+struct SledTransaction<'a> {
+    backend: &'a SledBackend,
+    writes: RefCell<TxWrites>,
+    reads: RefCell<TxReads>
+}
+
+impl SledTransaction<'_> {
+    fn current(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError> {
+        if let Some(v) = self.writes.borrow()[tree_index(tree)].get(key) {
+            return Ok(v.clone());
+        }
+
+        let value = self
+            .backend
+            .get(tree, key)
+            .map_err(StorageTxError::Storage)?;
+        self.reads.borrow_mut().read_key(tree, key, &value);
+        Ok(value)
+    }
+}
+
+impl StorageTransaction for SledTransaction<'_> {
+    fn get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError> {
+        self.current(tree, key)
+    }
+
+    fn insert(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Option<Vec<u8>>, StorageTxError> {
+        let prev = self.current(tree, key)?;
+        self.writes.borrow_mut()[tree_index(tree)].insert(key.to_vec(), Some(value.to_vec()));
+        Ok(prev)
+    }
+
+    fn remove(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, StorageTxError> {
+        let prev = self.current(tree, key)?;
+        self.writes.borrow_mut()[tree_index(tree)].insert(key.to_vec(), None);
+        Ok(prev)
+    }
+
+    fn range(
+        &self,
+        tree: TreeKind,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>
+    ) -> Result<KeyValues, StorageTxError> {
+        if is_inverted(start, end) {
+            return Ok(Vec::new());
+        }
+
+        let pairs = self
+            .backend
+            .range(tree, start, end)
+            .collect::<Result<KeyValues, _>>()
+            .map_err(StorageTxError::Storage)?;
+        self.reads.borrow_mut().read_range(tree, start, end, &pairs);
+
+        overlay(
+            pairs.into_iter().map(Ok),
+            &self.writes.borrow()[tree_index(tree)],
+            start,
+            end
+        )
+        .map_err(StorageTxError::Storage)
+    }
+}
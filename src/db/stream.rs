@@ -0,0 +1,600 @@
+//! This module defines the streams, append-only logs of entries made of
+//! field-value pairs, read in order by consumer groups.
+//!
+//! Every entry gets an id made of the time it was added at, in milliseconds
+//! since the UNIX epoch, and a sequence number, so the ids always increase and
+//! the entries are stored in the order they were added. Trimming a stream by
+//! age is then a removal of the entries whose id is older than the cutoff.
+//!
+//! A consumer group remembers the last entry it delivered, so every entry is
+//! delivered to a single consumer of the group, and keeps the delivered
+//! entries pending until a consumer acknowledges them.
+
+use std::fmt::Display;
+use std::ops::Bound;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bincode::serde::{
+    decode_from_slice,
+    encode_to_vec
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+use crate::DB;
+use crate::db::collection::{
+    Collection,
+    CollectionKind
+};
+use crate::db::errors::{
+    TransactionError,
+    TransientError
+};
+use crate::db::iter::prefix_end;
+use crate::db::storage::TreeKind;
+
+/// Prefixes the entries.
+const ENTRY_TAG: u8 = 0;
+
+/// Prefixes the consumer groups.
+const GROUP_TAG: u8 = 1;
+
+/// Prefixes the pending entries of the consumer groups.
+const PENDING_TAG: u8 = 2;
+
+/// The id of a stream entry, written `ms-seq`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct StreamId {
+    /// When the entry was added, in milliseconds since the UNIX epoch
+    pub ms: u64,
+    /// Tells apart the entries added in the same millisecond
+    pub seq: u64
+}
+
+impl StreamId {
+    /// The smallest possible id.
+    pub const MIN: StreamId = StreamId {
+        ms: 0,
+        seq: 0
+    };
+    /// The greatest possible id.
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX
+    };
+
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.ms.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.seq.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<StreamId, TransientError> {
+        let bytes: [u8; 16] = bytes
+            .try_into()
+            .map_err(|_| TransientError::ParsingFromByteError)?;
+
+        Ok(StreamId {
+            ms: u64::from_be_bytes(bytes[..8].try_into().expect("8 bytes")),
+            seq: u64::from_be_bytes(bytes[8..].try_into().expect("8 bytes"))
+        })
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = TransientError;
+
+    /// Parses `ms-seq`, or `ms` alone which means `ms-0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ms, seq) = s.split_once('-').unwrap_or((s, "0"));
+
+        Ok(StreamId {
+            ms: ms.parse().map_err(|_| TransientError::InvalidStreamId)?,
+            seq: seq.parse().map_err(|_| TransientError::InvalidStreamId)?
+        })
+    }
+}
+
+/// An entry of a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEntry {
+    pub id: StreamId,
+    /// The field-value pairs, in the order they were added
+    pub fields: Vec<(Vec<u8>, Vec<u8>)>
+}
+
+/// How `DB::xtrim` trims a stream, the oldest entries are removed first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTrim {
+    /// Keeps at most this many entries
+    MaxLen(u64),
+    /// Removes the entries added longer ago than this
+    MaxAge(Duration)
+}
+
+/// An entry delivered to a consumer which didn't acknowledge it yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub id: StreamId,
+    /// The consumer the entry was delivered to
+    pub consumer: String,
+    /// When the entry was delivered, in milliseconds since the UNIX epoch
+    pub delivered_at: u64
+}
+
+/// The state of a consumer group.
+#[derive(Debug, Serialize, Deserialize)]
+struct Group {
+    last_delivered: StreamId
+}
+
+/// The value of a pending entry.
+#[derive(Debug, Serialize, Deserialize)]
+struct Pending {
+    consumer: String,
+    delivered_at: u64
+}
+
+impl DB {
+    /// Appends an entry to the stream, creating it if needed, and returns the
+    /// id of the entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a stream, or
+    /// an error if the transaction fails.
+    pub fn xadd<K: AsRef<[u8]>, F: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        fields: &[(F, V)]
+    ) -> Result<StreamId, TransientError> {
+        let key = key.as_ref();
+        let fields: Vec<(Vec<u8>, Vec<u8>)> = fields
+            .iter()
+            .map(|(f, v)| (f.as_ref().to_vec(), v.as_ref().to_vec()))
+            .collect();
+        let entry = encode(&fields)?;
+
+        self.transaction(|tx| {
+            let mut stream = match tx.get_collection(&key)? {
+                Some(s) => s,
+                None => new_stream()?
+            };
+            let (len, last_id) = stream_state(&stream)?;
+
+            // The clock may go backwards, the ids never do
+            let now = self.clock.now_millis();
+            let id = if now > last_id.ms {
+                StreamId {
+                    ms: now,
+                    seq: 0
+                }
+            } else {
+                StreamId {
+                    ms: last_id.ms,
+                    seq: last_id.seq + 1
+                }
+            };
+
+            tx.insert_member(&entry_key(&stream, id), &entry)?;
+            stream.kind = CollectionKind::Stream {
+                len: len + 1,
+                last_id: id
+            };
+            tx.set_collection(&key, &stream)?;
+
+            Ok(id)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Returns the number of entries of the stream, 0 if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a stream.
+    pub fn xlen<K: AsRef<[u8]>>(&self, key: &K) -> Result<u64, TransientError> {
+        match self.get_stream(key)? {
+            Some(stream) => Ok(stream_state(&stream)?.0),
+            None => Ok(0)
+        }
+    }
+
+    /// Returns the entries whose id is between `start` and `end` included, at
+    /// most `count` of them if given.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a stream, or
+    /// an error if the entries cannot be read.
+    pub fn xrange<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>
+    ) -> Result<Vec<StreamEntry>, TransientError> {
+        match self.get_stream(key)? {
+            Some(stream) => {
+                self.stream_entries(&stream, Bound::Included(start), Bound::Included(end), count)
+            },
+            None => Ok(Vec::new())
+        }
+    }
+
+    /// Removes the oldest entries of the stream according to `trim`, and
+    /// returns how many were removed. The stream itself is kept even if it is
+    /// left empty, so its consumer groups survive.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a stream, or
+    /// an error if the transaction fails.
+    pub fn xtrim<K: AsRef<[u8]>>(&self, key: &K, trim: StreamTrim) -> Result<u64, TransientError> {
+        let key = key.as_ref();
+        let stream = match self.get_stream(&key)? {
+            Some(s) => s,
+            None => return Ok(0)
+        };
+        let (len, _) = stream_state(&stream)?;
+
+        let expired = match trim {
+            StreamTrim::MaxLen(max) => {
+                let excess = len.saturating_sub(max) as usize;
+                self.stream_entries(&stream, Bound::Unbounded, Bound::Unbounded, Some(excess))?
+            },
+            StreamTrim::MaxAge(age) => {
+                let cutoff = StreamId {
+                    ms: self
+                        .clock
+                        .now_millis()
+                        .saturating_sub(age.as_millis() as u64),
+                    seq: 0
+                };
+                self.stream_entries(&stream, Bound::Unbounded, Bound::Excluded(cutoff), None)?
+            }
+        };
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        self.transaction(|tx| {
+            // The stream may have been replaced since the scan
+            let mut current = match tx.get_collection(&key)? {
+                Some(c) if c.id == stream.id => c,
+                _ => return Ok(0)
+            };
+            let (len, last_id) = stream_state(&current)?;
+
+            let mut removed = 0;
+            for entry in &expired {
+                if tx.remove_member(&entry_key(&current, entry.id))?.is_some() {
+                    removed += 1;
+                }
+            }
+
+            current.kind = CollectionKind::Stream {
+                len: len - removed,
+                last_id
+            };
+            tx.set_collection(&key, &current)?;
+
+            Ok(removed)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Creates a consumer group which delivers the entries added after
+    /// `start`, creating the stream if needed. Returns false if the group
+    /// already exists.
+    ///
+    /// Pass `Some(StreamId::MIN)` to deliver every entry of the stream, or
+    /// `None` to only deliver the entries added from now on.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a stream, or
+    /// an error if the transaction fails.
+    pub fn xgroup_create<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str,
+        start: Option<StreamId>
+    ) -> Result<bool, TransientError> {
+        let key = key.as_ref();
+
+        self.transaction(|tx| {
+            let stream = match tx.get_collection(&key)? {
+                Some(s) => s,
+                None => {
+                    let stream = new_stream()?;
+                    tx.set_collection(&key, &stream)?;
+                    stream
+                }
+            };
+            let (_, last_id) = stream_state(&stream)?;
+
+            let group_key = group_key(&stream, group);
+            if tx.get_member(&group_key)?.is_some() {
+                return Ok(false);
+            }
+
+            tx.insert_member(
+                &group_key,
+                &encode(&Group {
+                    last_delivered: start.unwrap_or(last_id)
+                })?
+            )?;
+
+            Ok(true)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Delivers at most `count` entries the group never delivered to the
+    /// consumer, and keeps them pending until they are acknowledged with
+    /// `DB::xack`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupNotFound` if the stream or the group does not exist,
+    /// `WrongType` if the key holds something else than a stream, or an error
+    /// if the transaction fails.
+    pub fn xreadgroup<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str,
+        consumer: &str,
+        count: usize
+    ) -> Result<Vec<StreamEntry>, TransientError> {
+        let key = key.as_ref();
+
+        loop {
+            let stream = self
+                .get_stream(&key)?
+                .ok_or(TransientError::GroupNotFound)?;
+            let last_delivered = self.group(&stream, group)?.last_delivered;
+
+            let entries = self.stream_entries(
+                &stream,
+                Bound::Excluded(last_delivered),
+                Bound::Unbounded,
+                Some(count)
+            )?;
+            let last = match entries.last() {
+                Some(e) => e.id,
+                None => return Ok(entries)
+            };
+
+            let delivered = self
+                .transaction(|tx| {
+                    // Another consumer of the group may have been delivered the same entries
+                    match tx.get_collection(&key)? {
+                        Some(c) if c.id == stream.id => (),
+                        _ => return Ok(false)
+                    }
+                    let group_key = group_key(&stream, group);
+                    match tx.get_member(&group_key)? {
+                        Some(g) if decode::<Group>(&g)?.last_delivered == last_delivered => (),
+                        _ => return Ok(false)
+                    }
+
+                    let now = self.clock.now_millis();
+                    for entry in &entries {
+                        tx.insert_member(
+                            &pending_key(&stream, group, entry.id),
+                            &encode(&Pending {
+                                consumer: consumer.to_string(),
+                                delivered_at: now
+                            })?
+                        )?;
+                    }
+                    tx.insert_member(
+                        &group_key,
+                        &encode(&Group {
+                            last_delivered: last
+                        })?
+                    )?;
+
+                    Ok(true)
+                })
+                .map_err(TransactionError::into_transient)?;
+
+            if delivered {
+                return Ok(entries);
+            }
+        }
+    }
+
+    /// Acknowledges the entries, removing them from the pending entries of the
+    /// group, and returns how many were pending.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds something else than a stream, or
+    /// an error if the transaction fails.
+    pub fn xack<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str,
+        ids: &[StreamId]
+    ) -> Result<u64, TransientError> {
+        let key = key.as_ref();
+
+        self.transaction(|tx| {
+            let stream = match tx.get_collection(&key)? {
+                Some(s) => s,
+                None => return Ok(0)
+            };
+            stream_state(&stream)?;
+
+            let mut acked = 0;
+            for id in ids {
+                if tx
+                    .remove_member(&pending_key(&stream, group, *id))?
+                    .is_some()
+                {
+                    acked += 1;
+                }
+            }
+
+            Ok(acked)
+        })
+        .map_err(TransactionError::into_transient)
+    }
+
+    /// Returns the entries delivered by the group and not acknowledged yet,
+    /// ordered by id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GroupNotFound` if the stream or the group does not exist,
+    /// `WrongType` if the key holds something else than a stream, or an error
+    /// if the pending entries cannot be read.
+    pub fn xpending<K: AsRef<[u8]>>(
+        &self,
+        key: &K,
+        group: &str
+    ) -> Result<Vec<PendingEntry>, TransientError> {
+        let stream = self.get_stream(key)?.ok_or(TransientError::GroupNotFound)?;
+        self.group(&stream, group)?;
+
+        let prefix = pending_prefix(&stream, group);
+        self.prefix_range(TreeKind::Members, &prefix)
+            .map(|i| {
+                let (k, v) = i?;
+                let pending: Pending = decode(&self.values.decode_member(&k, &v)?)?;
+
+                Ok(PendingEntry {
+                    id: StreamId::from_bytes(&k[prefix.len()..])?,
+                    consumer: pending.consumer,
+                    delivered_at: pending.delivered_at
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the stream stored at the key.
+    fn get_stream<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Collection>, TransientError> {
+        match self.get_collection(key)? {
+            Some(stream) => {
+                stream_state(&stream)?;
+                Ok(Some(stream))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn group(&self, stream: &Collection, group: &str) -> Result<Group, TransientError> {
+        match self.get_member(&group_key(stream, group))? {
+            Some(g) => decode(&g),
+            None => Err(TransientError::GroupNotFound)
+        }
+    }
+
+    /// Reads the entries within the bounds, in order.
+    fn stream_entries(
+        &self,
+        stream: &Collection,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+        count: Option<usize>
+    ) -> Result<Vec<StreamEntry>, TransientError> {
+        let prefix = stream.member_key(&[ENTRY_TAG]);
+        let prefix_end = prefix_end(&prefix).ok_or(TransientError::ParsingFromByteError)?;
+
+        let start = match start {
+            Bound::Included(id) => Bound::Included(entry_key(stream, id)),
+            Bound::Excluded(id) => Bound::Excluded(entry_key(stream, id)),
+            Bound::Unbounded => Bound::Included(prefix.clone())
+        };
+        let end = match end {
+            Bound::Included(id) => Bound::Included(entry_key(stream, id)),
+            Bound::Excluded(id) => Bound::Excluded(entry_key(stream, id)),
+            Bound::Unbounded => Bound::Excluded(prefix_end)
+        };
+
+        self.storage
+            .range(
+                TreeKind::Members,
+                start.as_ref().map(Vec::as_slice),
+                end.as_ref().map(Vec::as_slice)
+            )
+            .take(count.unwrap_or(usize::MAX))
+            .map(|i| {
+                let (k, v) = i?;
+
+                Ok(StreamEntry {
+                    id: StreamId::from_bytes(&k[prefix.len()..])?,
+                    fields: decode(&self.values.decode_member(&k, &v)?)?
+                })
+            })
+            .collect()
+    }
+}
+
+fn new_stream() -> Result<Collection, TransientError> {
+    Collection::new(CollectionKind::Stream {
+        len: 0,
+        last_id: StreamId::MIN
+    })
+}
+
+/// Returns the number of entries and the id of the last entry of the stream.
+fn stream_state(stream: &Collection) -> Result<(u64, StreamId), TransientError> {
+    match stream.kind {
+        CollectionKind::Stream {
+            len,
+            last_id
+        } => Ok((len, last_id)),
+        _ => Err(TransientError::WrongType)
+    }
+}
+
+fn entry_key(stream: &Collection, id: StreamId) -> Vec<u8> {
+    stream.member_key(&[&[ENTRY_TAG][..], &id.to_bytes()].concat())
+}
+
+fn group_key(stream: &Collection, group: &str) -> Vec<u8> {
+    stream.member_key(&[&[GROUP_TAG][..], group.as_bytes()].concat())
+}
+
+/// The group name is prefixed by its length, so the pending entries of a
+/// group never share a prefix with another group's.
+fn pending_prefix(stream: &Collection, group: &str) -> Vec<u8> {
+    stream.member_key(
+        &[
+            &[PENDING_TAG][..],
+            &(group.len() as u32).to_be_bytes(),
+            group.as_bytes()
+        ]
+        .concat()
+    )
+}
+
+fn pending_key(stream: &Collection, group: &str, id: StreamId) -> Vec<u8> {
+    [pending_prefix(stream, group), id.to_bytes().to_vec()].concat()
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, TransientError> {
+    encode_to_vec(value, bincode::config::standard())
+        .map_err(|_| TransientError::ParsingToByteError)
+}
+
+fn decode<T: for<'de> Deserialize<'de>>(slice: &[u8]) -> Result<T, TransientError> {
+    Ok(decode_from_slice(slice, bincode::config::standard())
+        .map_err(|_| TransientError::ParsingFromByteError)?
+        .0)
+}
@@ -1,5 +1,6 @@
 use crate::metrics::Metrics;
 
+#[derive(Default)]
 pub struct GuardMetricChanged {
     pub keys_total_changed: i64,
     pub ttl_keys_total_changed: i64,
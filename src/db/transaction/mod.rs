@@ -1,45 +1,80 @@
-use std::error::Error;
+use std::ops::Bound;
 use std::str::from_utf8;
-use std::time::{
-    Duration,
-    SystemTime,
-    UNIX_EPOCH
-};
+use std::time::Duration;
 
-use sled::transaction::{
-    ConflictableTransactionError,
+use crate::db::bloom::BloomFilter;
+use crate::db::clock::Clock;
+use crate::db::collection::dropped_collection;
+use crate::db::encoding::ValueEncoding;
+use crate::db::errors::{
     TransactionError,
-    Transactional,
-    TransactionalTree
+    TransientError
+};
+use crate::db::expiry_from_now;
+use crate::db::index::{
+    Indexes,
+    update_entries
+};
+use crate::db::iter::prefix_end;
+use crate::db::storage::{
+    KeyValues,
+    StorageTransaction,
+    StorageTxError,
+    TreeKind
 };
-
-use crate::db::errors::TransientError;
 use crate::db::transaction::metric_handler::GuardMetricChanged;
+use crate::metrics::Metrics;
 use crate::{
     DB,
-    Metadata
+    Metadata,
+    ValueFormat
 };
 
 pub mod metric_handler;
 
 /// This struct is the Guard for the database Transaction method.
 ///
-/// This struct holds all the trees of the main database and the changed_metric,
-/// It provides us with the ability to make transactions for better data safety.
+/// This struct holds the transactional view of all the trees of the main
+/// database and the changed_metric, It provides us with the ability to make
+/// transactions for better data safety.
 ///
 /// When the transaction method concludes this struct, will check all the
 /// changed_metric, and will increment or decrement the corresponding metric in
 /// the real database, to ensure that the correct metrics will be shown.
+///
+/// The transaction only commits if nothing the guard read, the scans
+/// included, was written in the meantime, otherwise it conflicts and is run
+/// again, so the reads of a committed transaction all come from the same
+/// snapshot.
 pub struct TransactionalGuard<'a> {
-    data_tree: &'a TransactionalTree,
-    meta_tree: &'a TransactionalTree,
-    ttl_tree: &'a TransactionalTree,
-    changed_metric: &'a mut GuardMetricChanged
+    tx: &'a dyn StorageTransaction,
+    changed_metric: &'a mut GuardMetricChanged,
+    /// Keys which changed, applied to the cache and the bloom filter once the
+    /// transaction commits
+    changed_keys: &'a mut ChangedKeys,
+    bloom: Option<&'a BloomFilter>,
+    /// The secondary indexes to update along with the values
+    indexes: &'a Indexes,
+    values: &'a ValueEncoding,
+    clock: &'a dyn Clock
 }
 
-// NOTE: The reason why I didn't convert everything to Transient error is
-// because of the UnabortableTransactionError enum, where is error, they will
-// reset, If I fuck with this who knows what will be fucked up TT
+/// The keys changed by a transaction.
+#[derive(Default)]
+struct ChangedKeys {
+    /// Keys whose value changed or which were removed
+    written: Vec<Vec<u8>>,
+    /// Keys which did not exist before
+    added: Vec<Vec<u8>>,
+    /// Keys which were removed
+    removed: Vec<Vec<u8>>,
+    /// Ids of the collections which were removed or overwritten, whose members
+    /// have to be purged
+    dropped: Vec<u64>
+}
+
+// NOTE: Conflicts are returned as TransientError::TransactionConflict, they
+// have to be propagated with `?` so the transaction gets retried
 impl<'a> TransactionalGuard<'a> {
     /// Sets a key-value pair with an optional Time-To-Live (TTL).
     ///
@@ -55,39 +90,81 @@ impl<'a> TransactionalGuard<'a> {
         key: &str,
         val: &str,
         ttl: Option<Duration>
-    ) -> Result<(), Box<dyn Error>> {
-        let data_tree = &self.data_tree;
-        let freq_tree = &self.meta_tree;
-        let ttl_tree = &self.ttl_tree;
-        let byte = key.as_bytes();
-        let ttl_sec = match ttl {
-            Some(t) => {
-                let systime = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Cant get SystemTime");
-                Some((t + systime).as_secs())
-            },
-            None => None
-        };
+    ) -> Result<(), TransientError> {
+        self.set_raw(&key, &val, ttl)
+    }
 
-        match freq_tree.get(byte)? {
-            Some(m) => {
-                let mut meta = Metadata::from_u8(&m)?;
+    /// Sets a raw key-value pair with an optional Time-To-Live (TTL).
+    ///
+    /// If the key already exists, its value and TTL will be updated.
+    /// If `ttl` is `None`, the key will be persistent.
+    ///
+    /// # Errors
+    ///
+    /// This function can return an error if there's an issue with the
+    /// underlying
+    pub fn set_raw<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: &K,
+        val: &V,
+        ttl: Option<Duration>
+    ) -> Result<(), TransientError> {
+        let ttl_sec = ttl.map(|t| expiry_from_now(self.clock, t));
+
+        self.write_value(key.as_ref(), val.as_ref(), ValueFormat::default(), ttl_sec)
+    }
+
+    /// Sets the value of the key, stored in the format, with the TTL as a
+    /// timestamp in seconds. If the key already exists, its TTL is replaced.
+    pub(crate) fn write_value(
+        &mut self,
+        byte: &[u8],
+        val: &[u8],
+        format: ValueFormat,
+        ttl_sec: Option<u64>
+    ) -> Result<(), TransientError> {
+        let tx = self.tx;
+        let (val, format) = self.values.encode(byte, val, format)?;
+
+        let old_format = match self.get_metadata_raw(&byte)? {
+            Some(mut meta) => {
                 if let Some(t) = meta.ttl {
-                    let _ = ttl_tree.remove([&t.to_be_bytes()[..], byte].concat());
+                    let _ = tx.remove(TreeKind::Ttl, &[&t.to_be_bytes()[..], byte].concat());
                 }
+                let old_format = meta.format;
                 meta.ttl = ttl_sec;
-                freq_tree.insert(byte, meta.to_u8()?)?;
+                meta.format = format;
+                self.insert_metadata(byte, &meta)?;
+                Some(old_format)
             },
             None => {
-                freq_tree.insert(byte, Metadata::new(ttl_sec).to_u8()?)?;
+                let mut meta = Metadata::with_clock(self.clock, ttl_sec);
+                meta.format = format;
+                self.insert_metadata(byte, &meta)?;
+                if let Some(bloom) = self.bloom {
+                    bloom.insert(byte);
+                }
+                self.changed_keys.added.push(byte.to_vec());
+                None
             }
-        }
+        };
 
-        data_tree.insert(byte, val.as_bytes())?;
+        let old = tx.insert(TreeKind::Data, byte, &val)?;
+        update_entries(
+            tx,
+            self.indexes,
+            self.values,
+            byte,
+            old.as_deref().zip(old_format),
+            Some((&val, format))
+        )?;
+        self.changed_keys.written.push(byte.to_vec());
+        if let Some(id) = dropped_collection(old_format, Some(format)) {
+            self.changed_keys.dropped.push(id);
+        }
 
         if let Some(d) = ttl_sec {
-            ttl_tree.insert([&d.to_be_bytes()[..], byte].concat(), byte)?;
+            tx.insert(TreeKind::Ttl, &[&d.to_be_bytes()[..], byte].concat(), byte)?;
             self.changed_metric.ttl_keys_total_changed += 1;
         };
 
@@ -104,36 +181,141 @@ impl<'a> TransactionalGuard<'a> {
     ///
     /// Returns an error if the value cannot be retrieved from the database or
     /// if the value is not valid UTF-8.
-    pub fn get(&mut self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
-        let data_tree = &self.data_tree;
-        let byte = key.as_bytes();
-        let val = data_tree.get(byte)?;
+    pub fn get(&mut self, key: &str) -> Result<Option<String>, TransientError> {
+        match self.get_raw(&key)? {
+            Some(val) => {
+                Ok(Some(
+                    from_utf8(&val)
+                        .map_err(|_| TransientError::ParsingToUTF8Error)?
+                        .to_string()
+                ))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Retrieves the raw value for a given raw key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WrongType` if the key holds a collection, or an error if the
+    /// value cannot be retrieved from the database.
+    pub fn get_raw<K: AsRef<[u8]>>(&mut self, key: &K) -> Result<Option<Vec<u8>>, TransientError> {
+        match self.get_value(key.as_ref())? {
+            Some((_, format)) if format.collection.is_some() => Err(TransientError::WrongType),
+            Some((val, _)) => Ok(Some(val)),
+            None => Ok(None)
+        }
+    }
 
+    /// Retrieves the value of the key, decoded, and the format it is stored
+    /// in.
+    pub(crate) fn get_value(
+        &mut self,
+        key: &[u8]
+    ) -> Result<Option<(Vec<u8>, ValueFormat)>, TransientError> {
         self.changed_metric.get_operation_total += 1;
 
-        match val {
-            Some(val) => Ok(Some(from_utf8(&val)?.to_string())),
-            None => Ok(None)
+        let val = match self.tx.get(TreeKind::Data, key)? {
+            Some(v) => v,
+            None => return Ok(None)
+        };
+        let format = self
+            .get_metadata_raw(&key)?
+            .ok_or(TransientError::MetadataNotFound)?
+            .format;
+
+        Ok(Some((self.values.decode_owned(key, val, format)?, format)))
+    }
+
+    /// Returns the ordered raw key-value pairs whose key is within the
+    /// bounds, with the writes of the transaction applied. The collections
+    /// are skipped.
+    ///
+    /// If a key is added to or removed from the range by anything else
+    /// before the transaction commits, the transaction conflicts and is run
+    /// again, so writes depending on what was scanned are atomic with the
+    /// scan.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range cannot be read or a value cannot be
+    /// decoded.
+    pub fn range_raw<K: AsRef<[u8]>>(
+        &mut self,
+        start: Bound<K>,
+        end: Bound<K>
+    ) -> Result<KeyValues, TransientError> {
+        let pairs = self.tx.range(
+            TreeKind::Data,
+            start.as_ref().map(|k| k.as_ref()),
+            end.as_ref().map(|k| k.as_ref())
+        )?;
+
+        let mut values = Vec::with_capacity(pairs.len());
+        for (k, v) in pairs {
+            let format = self
+                .get_metadata_raw(&k)?
+                .ok_or(TransientError::MetadataNotFound)?
+                .format;
+            if format.collection.is_none() {
+                let v = self.values.decode_owned(&k, v, format)?;
+                values.push((k, v));
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Returns the ordered raw key-value pairs whose key starts with the
+    /// prefix, see `range_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range cannot be read or a value cannot be
+    /// decoded.
+    pub fn scan_prefix<P: AsRef<[u8]>>(&mut self, prefix: &P) -> Result<KeyValues, TransientError> {
+        let prefix = prefix.as_ref();
+
+        match prefix_end(prefix) {
+            Some(end) => self.range_raw(Bound::Included(prefix), Bound::Excluded(&end[..])),
+            None => self.range_raw(Bound::Included(prefix), Bound::Unbounded)
         }
     }
 
+    /// Returns true if the key exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be retrieved from the database.
+    pub fn exists<K: AsRef<[u8]>>(&self, key: &K) -> Result<bool, TransientError> {
+        Ok(self.tx.get(TreeKind::Meta, key.as_ref())?.is_some())
+    }
+
     /// Atomically increments the frequency counter for a given key.
     ///
     /// # Errors
     ///
     /// This function can return an error if the key does not exist or if there
     /// is an issue with the compare-and-swap operation.
-    pub fn increment_frequency(&mut self, key: &str) -> Result<(), Box<dyn Error>> {
-        let freq_tree = &self.meta_tree;
-        let byte = &key.as_bytes();
+    pub fn increment_frequency(&mut self, key: &str) -> Result<(), TransientError> {
+        self.increment_frequency_raw(key.as_bytes())
+    }
 
-        let metadata = freq_tree
-            .get(byte)?
+    /// Atomically increments the frequency counter for a given raw key.
+    ///
+    /// # Errors
+    ///
+    /// This function can return an error if the key does not exist.
+    pub fn increment_frequency_raw(&mut self, key: &[u8]) -> Result<(), TransientError> {
+        let meta = self
+            .get_metadata_raw(&key)?
             .ok_or(TransientError::IncretmentError)?;
-        let meta = Metadata::from_u8(&metadata)?;
 
-        freq_tree.remove(*byte)?;
-        freq_tree.insert(*byte, meta.freq_incretement().to_u8()?)?;
+        self.insert_metadata(
+            key,
+            &meta.freq_incretement().accessed(self.clock.now_secs())
+        )?;
 
         self.changed_metric.inc_freq_operation_total += 1;
 
@@ -145,24 +327,45 @@ impl<'a> TransactionalGuard<'a> {
     /// # Errors
     ///
     /// Can return an error if the transaction to remove the data fails.
-    pub fn remove(&mut self, key: &str) -> Result<(), Box<dyn Error>> {
-        let data_tree = &self.data_tree;
-        let freq_tree = &self.meta_tree;
-        let ttl_tree = &self.ttl_tree;
-        let byte = &key.as_bytes();
-        data_tree.remove(*byte)?;
-        let meta = freq_tree
-            .get(byte)?
+    pub fn remove(&mut self, key: &str) -> Result<(), TransientError> {
+        self.remove_raw(&key)
+    }
+
+    /// Removes a raw key-value pair and its associated metadata from the
+    /// database.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataNotFound` if the key does not exist.
+    pub fn remove_raw<K: AsRef<[u8]>>(&mut self, key: &K) -> Result<(), TransientError> {
+        let tx = self.tx;
+        let byte = key.as_ref();
+        let meta = self
+            .get_metadata_raw(&byte)?
             .ok_or(TransientError::MetadataNotFound)?;
-        let time = Metadata::from_u8(&meta)?.ttl;
-        freq_tree.remove(*byte)?;
+        let old = tx.remove(TreeKind::Data, byte)?;
+        update_entries(
+            tx,
+            self.indexes,
+            self.values,
+            byte,
+            old.as_deref().map(|v| (v, meta.format)),
+            None
+        )?;
+        if let Some(id) = dropped_collection(Some(meta.format), None) {
+            self.changed_keys.dropped.push(id);
+        }
+        let time = meta.ttl;
+        self.changed_keys.written.push(byte.to_vec());
+        self.changed_keys.removed.push(byte.to_vec());
+        tx.remove(TreeKind::Meta, byte)?;
 
         self.changed_metric.keys_total_changed -= 1;
 
         if let Some(t) = time {
             self.changed_metric.ttl_keys_total_changed -= 1;
 
-            let _ = ttl_tree.remove([&t.to_be_bytes()[..], &byte[..]].concat());
+            let _ = tx.remove(TreeKind::Ttl, &[&t.to_be_bytes()[..], byte].concat());
         }
 
         self.changed_metric.rm_operation_total += 1;
@@ -175,48 +378,307 @@ impl<'a> TransactionalGuard<'a> {
     /// # Errors
     ///
     /// Returns an error if the metadata cannot be retrieved or deserialized.
-    pub fn get_metadata(&self, key: &str) -> Result<Option<Metadata>, Box<dyn Error>> {
-        let freq_tree = &self.meta_tree;
-        let byte = key.as_bytes();
-        let meta = freq_tree.get(byte)?;
+    pub fn get_metadata(&self, key: &str) -> Result<Option<Metadata>, TransientError> {
+        self.get_metadata_raw(&key)
+    }
+
+    /// Retrieves the metadata for a given raw key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be retrieved or deserialized.
+    pub fn get_metadata_raw<K: AsRef<[u8]>>(
+        &self,
+        key: &K
+    ) -> Result<Option<Metadata>, TransientError> {
+        let meta = self.tx.get(TreeKind::Meta, key.as_ref())?;
         match meta {
-            Some(val) => Ok(Some(Metadata::from_u8(&val)?)),
+            Some(val) => {
+                Ok(Some(
+                    Metadata::from_u8(&val).map_err(|_| TransientError::ParsingFromByteError)?
+                ))
+            },
             None => Ok(None)
         }
     }
+
+    /// Returns the time left before the key expires.
+    ///
+    /// Returns `None` if the key does not exist or is persistent, an expired
+    /// key which the TTL thread didn't remove yet has `Duration::ZERO` left.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be retrieved or deserialized.
+    pub fn ttl<K: AsRef<[u8]>>(&self, key: &K) -> Result<Option<Duration>, TransientError> {
+        let ttl = match self.get_metadata_raw(key)?.and_then(|m| m.ttl) {
+            Some(t) => t,
+            None => return Ok(None)
+        };
+
+        Ok(Some(Duration::from_secs(
+            ttl.saturating_sub(self.clock.now_secs())
+        )))
+    }
+
+    /// Sets the Time-To-Live of an existing key, replacing its previous one.
+    ///
+    /// Returns false if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be retrieved or updated.
+    pub fn expire<K: AsRef<[u8]>>(
+        &mut self,
+        key: &K,
+        ttl: Duration
+    ) -> Result<bool, TransientError> {
+        let byte = key.as_ref();
+        let mut meta = match self.get_metadata_raw(&byte)? {
+            Some(m) => m,
+            None => return Ok(false)
+        };
+
+        match meta.ttl {
+            Some(t) => {
+                let _ = self
+                    .tx
+                    .remove(TreeKind::Ttl, &[&t.to_be_bytes()[..], byte].concat());
+            },
+            None => self.changed_metric.ttl_keys_total_changed += 1
+        }
+
+        let d = expiry_from_now(self.clock, ttl);
+        meta.ttl = Some(d);
+        self.insert_metadata(byte, &meta)?;
+        self.tx
+            .insert(TreeKind::Ttl, &[&d.to_be_bytes()[..], byte].concat(), byte)?;
+
+        Ok(true)
+    }
+
+    /// Removes the Time-To-Live of a key, making it persistent.
+    ///
+    /// Returns false if the key does not exist or was already persistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be retrieved or updated.
+    pub fn persist<K: AsRef<[u8]>>(&mut self, key: &K) -> Result<bool, TransientError> {
+        let byte = key.as_ref();
+        let mut meta = match self.get_metadata_raw(&byte)? {
+            Some(m) => m,
+            None => return Ok(false)
+        };
+
+        let t = match meta.ttl.take() {
+            Some(t) => t,
+            None => return Ok(false)
+        };
+
+        let _ = self
+            .tx
+            .remove(TreeKind::Ttl, &[&t.to_be_bytes()[..], byte].concat());
+        self.insert_metadata(byte, &meta)?;
+        self.changed_metric.ttl_keys_total_changed -= 1;
+
+        Ok(true)
+    }
+
+    /// Sets the value of an existing key, stored in the format, keeping its
+    /// TTL.
+    pub(crate) fn replace_value(
+        &mut self,
+        byte: &[u8],
+        val: &[u8],
+        format: ValueFormat
+    ) -> Result<(), TransientError> {
+        let (val, format) = self.values.encode(byte, val, format)?;
+        let mut meta = self
+            .get_metadata_raw(&byte)?
+            .ok_or(TransientError::MetadataNotFound)?;
+        let old_format = meta.format;
+        meta.format = format;
+        self.insert_metadata(byte, &meta)?;
+
+        let old = self.tx.insert(TreeKind::Data, byte, &val)?;
+        update_entries(
+            self.tx,
+            self.indexes,
+            self.values,
+            byte,
+            old.as_deref().map(|v| (v, old_format)),
+            Some((&val, format))
+        )?;
+        self.changed_keys.written.push(byte.to_vec());
+        if let Some(id) = dropped_collection(Some(old_format), Some(format)) {
+            self.changed_keys.dropped.push(id);
+        }
+
+        self.changed_metric.set_operation_total += 1;
+
+        Ok(())
+    }
+
+    /// Retrieves a member of a collection.
+    pub(crate) fn get_member(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        self.tx
+            .get(TreeKind::Members, key)?
+            .map(|v| self.values.decode_member(key, &v))
+            .transpose()
+    }
+
+    /// Inserts a member of a collection, returning the previous value.
+    pub(crate) fn insert_member(
+        &mut self,
+        key: &[u8],
+        val: &[u8]
+    ) -> Result<Option<Vec<u8>>, TransientError> {
+        let stored = self.values.encode_member(key, val)?;
+
+        self.tx
+            .insert(TreeKind::Members, key, &stored)?
+            .map(|v| self.values.decode_member(key, &v))
+            .transpose()
+    }
+
+    /// Removes a member of a collection, returning its value.
+    pub(crate) fn remove_member(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        self.tx
+            .remove(TreeKind::Members, key)?
+            .map(|v| self.values.decode_member(key, &v))
+            .transpose()
+    }
+
+    fn insert_metadata(&self, key: &[u8], meta: &Metadata) -> Result<(), TransientError> {
+        self.tx.insert(
+            TreeKind::Meta,
+            key,
+            &meta
+                .to_u8()
+                .map_err(|_| TransientError::ParsingToByteError)?
+        )?;
+
+        Ok(())
+    }
 }
 
 impl DB {
-    pub fn transaction<F>(&self, f: F) -> Result<(), TransientError>
+    /// Runs the closure atomically over all the trees of the database and
+    /// returns the value the closure returned.
+    ///
+    /// If the closure returns an error every change made inside it is rolled
+    /// back. To abort the transaction with your own error, return
+    /// `Err(TransactionError::Abort(e))`, errors from the guard can simply be
+    /// propagated with `?`.
+    ///
+    /// # Retries
+    ///
+    /// If the transaction conflicts with another one, every change is rolled
+    /// back and the closure is run again from the start, so anything the
+    /// closure does outside of the guard has to be safe to repeat. Only the
+    /// value of the run which committed is returned. By default the amount of
+    /// retries is bounded by the backend, the built-in backends retry at most
+    /// `MAX_CONFLICT_RETRIES` (100) times, `DBConfig::max_transaction_retries`
+    /// can only lower it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TransactionError::Abort` with the error the closure aborted
+    /// with, or `TransactionError::Transient` if the database or the backend
+    /// failed, `TransientError::TransactionRetryLimitReached` if the
+    /// transaction kept conflicting past `DBConfig::max_transaction_retries`,
+    /// or `TransientError::TransactionConflict` if it did past the retries of
+    /// the backend.
+    pub fn transaction<F, R, E>(&self, mut f: F) -> Result<R, TransactionError<E>>
     where
-        F: Fn(&mut TransactionalGuard) -> Result<(), Box<dyn Error>>
+        F: FnMut(&mut TransactionalGuard) -> Result<R, TransactionError<E>>
     {
-        let l: Result<GuardMetricChanged, TransactionError<()>> =
-            (&*self.data_tree, &*self.meta_tree, &*self.ttl_tree).transaction(
-                |(data_tree, meta_tree, ttl_tree)| {
-                    let mut guard_metrics = GuardMetricChanged {
-                        keys_total_changed: 0,
-                        ttl_keys_total_changed: 0,
-                        set_operation_total: 0,
-                        rm_operation_total: 0,
-                        inc_freq_operation_total: 0,
-                        get_operation_total: 0
-                    };
-                    let mut transaction_guard = TransactionalGuard {
-                        data_tree,
-                        meta_tree,
-                        ttl_tree,
-                        changed_metric: &mut guard_metrics
-                    };
-                    f(&mut transaction_guard)
-                        .map_err(|_| ConflictableTransactionError::Abort(()))?;
-
-                    Ok(guard_metrics)
+        let mut guard_metrics = GuardMetricChanged::default();
+        let mut changed_keys = ChangedKeys::default();
+        let bloom_epoch = self.bloom.as_ref().map_or(0, |b| b.epoch());
+        let mut abort_error: Option<E> = None;
+        let mut result: Option<R> = None;
+        let mut runs: u32 = 0;
+        let indexes = self.indexes.read()?;
+
+        let l = self.storage.transaction(&mut |tx| {
+            // Every run after the first one means the previous run conflicted
+            if runs > 0 {
+                Metrics::increment_transaction_conflicts();
+
+                if let Some(max) = self.max_transaction_retries
+                    && runs > max
+                {
+                    return Err(StorageTxError::Storage(
+                        TransientError::TransactionRetryLimitReached
+                    ));
                 }
-            );
 
-        l.map_err(|_| TransientError::SledTransactionError)?
-            .inc_all_metrics();
-        Ok(())
+                Metrics::increment_transaction_retries();
+            }
+            runs += 1;
+
+            guard_metrics = GuardMetricChanged::default();
+            changed_keys = ChangedKeys::default();
+            let mut transaction_guard = TransactionalGuard {
+                tx,
+                changed_metric: &mut guard_metrics,
+                changed_keys: &mut changed_keys,
+                bloom: self.bloom.as_deref(),
+                indexes: &indexes,
+                values: &self.values,
+                clock: self.clock.as_ref()
+            };
+
+            match f(&mut transaction_guard) {
+                Ok(r) => {
+                    result = Some(r);
+                    Ok(())
+                },
+                // Conflicts have to reach the backend untouched, so it can retry the closure
+                Err(TransactionError::Transient(TransientError::TransactionConflict)) => {
+                    Err(StorageTxError::Conflict)
+                },
+                Err(TransactionError::Transient(e)) => Err(StorageTxError::Storage(e)),
+                Err(TransactionError::Abort(e)) => {
+                    abort_error = Some(e);
+                    Err(StorageTxError::Abort)
+                }
+            }
+        });
+
+        match l {
+            Ok(()) => (),
+            Err(StorageTxError::Abort) => {
+                return Err(match abort_error.take() {
+                    Some(e) => TransactionError::Abort(e),
+                    None => TransactionError::Transient(TransientError::SledTransactionError)
+                });
+            },
+            Err(e) => return Err(TransactionError::Transient(e.into()))
+        }
+        drop(indexes);
+
+        guard_metrics.inc_all_metrics();
+        if let Some(cache) = &self.cache {
+            for key in &changed_keys.written {
+                cache.invalidate(key);
+            }
+        }
+        if let Some(bloom) = &self.bloom {
+            for key in &changed_keys.added {
+                bloom.confirm_insert(key, bloom_epoch);
+            }
+            for key in &changed_keys.removed {
+                bloom.remove(key, bloom_epoch);
+            }
+        }
+        self.purge_collections(&changed_keys.dropped)?;
+        self.commit_write()?;
+
+        result.ok_or(TransactionError::Transient(
+            TransientError::SledTransactionError
+        ))
     }
 }
@@ -0,0 +1,76 @@
+//! This module defines the `Workers`, the background threads of a database
+//! (the TTL, size and flush threads) shared by every handle of the `DB`.
+//!
+//! The threads are stopped by `DB::close`, or when the last handle is dropped.
+
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering
+};
+use std::sync::{
+    Arc,
+    Mutex
+};
+use std::thread::JoinHandle;
+
+use crate::db::errors::TransientError;
+
+/// A background thread of the database.
+pub(crate) type Worker = JoinHandle<Result<(), TransientError>>;
+
+/// The background threads of a database and the flag which stops them.
+#[derive(Debug)]
+pub(crate) struct Workers {
+    /// Signals all threads to gracefully shutdown
+    shutdown: Arc<AtomicBool>,
+    /// The threads which are still running, empty once stopped
+    threads: Mutex<Vec<Worker>>
+}
+
+impl Workers {
+    /// Takes ownership of the threads, which have to stop once `shutdown` is
+    /// set.
+    pub(crate) fn new(shutdown: Arc<AtomicBool>, threads: Vec<Worker>) -> Workers {
+        Workers {
+            shutdown,
+            threads: Mutex::new(threads)
+        }
+    }
+
+    /// Signals the threads to shut down and waits for them to finish, does
+    /// nothing if they were already stopped.
+    ///
+    /// Every thread is joined even if one of them panicked or failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WorkerPanicked` if a thread panicked, or else the error a
+    /// thread stopped with.
+    pub(crate) fn stop(&self) -> Result<(), TransientError> {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        let threads = match self.threads.lock() {
+            Ok(mut t) => std::mem::take(&mut *t),
+            Err(poisoned) => std::mem::take(&mut *poisoned.into_inner())
+        };
+
+        let mut res = Ok(());
+        for thread in threads {
+            match thread.join() {
+                Ok(Err(e)) if res.is_ok() => res = Err(e),
+                Ok(_) => (),
+                Err(_) => res = Err(TransientError::WorkerPanicked)
+            }
+        }
+        res
+    }
+}
+
+impl Drop for Workers {
+    /// Gracefully shuts down the threads when the last handle of the `DB` goes
+    /// out of scope.
+    fn drop(&mut self) {
+        // Nothing is left to report a panicked thread to
+        let _ = self.stop();
+    }
+}
@@ -13,15 +13,25 @@
 
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-use std::thread::JoinHandle;
 
-use db::errors::TransientError;
+use db::bloom::BloomFilter;
+use db::cache::HotCache;
+use db::cache_aside::SingleFlight;
+use db::clock::Clock;
+use db::durability::{
+    Durability,
+    GroupCommit
+};
+use db::encoding::ValueEncoding;
+use db::encryption::KeyId;
+use db::events::ExpiryNotifier;
+use db::index::IndexRegistry;
+use db::storage::StorageBackend;
+use db::workers::Workers;
 use serde::{
     Deserialize,
     Serialize
 };
-use sled::Tree;
 
 pub mod client;
 pub mod db;
@@ -32,32 +42,47 @@ pub mod server;
 
 /// This is the main struct which represents the database.
 ///
-/// This struct holds the connection to the `sled` database and provides
+/// This struct holds the storage backend (`sled` by default) and provides
 /// safe, high-level access to the various data trees. It manages a background
 /// thread for handling TTL (Time-To-Live) expirations automatically.
 ///
-/// When this struct is dropped, it will signal the background thread to shut
-/// down and wait for it to finish gracefully.
+/// A `DB` is a handle over shared internals, cloning it is cheap and every
+/// clone reads and writes the same database, so it can be passed to threads,
+/// tasks or an axum state as is. When the last handle is dropped, or `close`
+/// is called, it will signal the background threads to shut down and wait for
+/// them to finish gracefully.
 ///
-/// This struct also holds 2 Arc<sled::Tree> directly instead of a single
-/// sled::Db, since almost all of the functions uses the tree directly which
-/// requires the sled::Db to constantly open each trees.
-/// Passing trees from the struct deletes the constant need to open the trees
-#[derive(Debug)]
+/// The backend is held behind an Arc<dyn StorageBackend>, so the lifecycle
+/// logic stays the same whichever engine is storing the `data_tree`,
+/// `meta_tree` and `ttl_tree`.
+#[derive(Debug, Clone)]
 pub struct DB {
-    /// Stores the key and value
-    data_tree: Arc<Tree>,
-    /// Stores the key and the metadata
-    meta_tree: Arc<Tree>,
-    /// Stores the ttl timestamp and the key
-    ttl_tree: Arc<Tree>,
-    /// Manage the background thread which checks for expired keys
-    ttl_thread: Option<JoinHandle<Result<(), TransientError>>>,
-    /// Manage the background thread to periodically check the DB Size
-    size_thread: Option<JoinHandle<Result<(), TransientError>>>,
-    /// Signals all threads to gracefully shutdown, when the DB is dropped
-    shutdown: Arc<AtomicBool>,
-    /// Path to the database
+    /// Stores the data_tree, meta_tree and ttl_tree
+    storage: Arc<dyn StorageBackend>,
+    /// The background threads which check for expired keys, the DB size and
+    /// flush the backend if the durability policy needs it
+    workers: Arc<Workers>,
+    /// How writes are made durable
+    durability: Durability,
+    /// Writes waiting for the next group commit
+    group_commit: Arc<GroupCommit>,
+    /// How many times a conflicting transaction is retried
+    max_transaction_retries: Option<u32>,
+    /// Caches the values of the hot keys, if enabled in the config
+    cache: Option<Arc<HotCache>>,
+    /// Answers lookups of absent keys, if enabled in the config
+    bloom: Option<Arc<BloomFilter>>,
+    /// The keys being loaded by `get_or_load`
+    single_flight: Arc<SingleFlight>,
+    /// Sends the expired keys to the subscribers
+    expirations: Arc<ExpiryNotifier>,
+    /// The secondary indexes, maintained by every write
+    indexes: Arc<IndexRegistry>,
+    /// Compresses and encrypts the values, if enabled in the config
+    values: Arc<ValueEncoding>,
+    /// Where every TTL, lease, session and rate limit reads the time from
+    clock: Arc<dyn Clock>,
+    /// Path to the database, empty if the backend is not persistent
     pub path: PathBuf
 }
 
@@ -74,5 +99,25 @@ pub struct Metadata {
     pub created_at: u64,
     /// The key's time-to-live in seconds. If None, the key is persistent and
     /// never expires.
-    pub ttl: Option<u64>
+    pub ttl: Option<u64>,
+    /// Timestamp of the last access, in seconds since the UNIX epoch, the
+    /// creation until the frequency is incremented
+    pub last_accessed: u64,
+    /// How the value of the key is stored
+    pub format: ValueFormat
+}
+
+/// How the value of a key is stored, kept in its `Metadata` rather than in
+/// the value, so no value written by a user can pass for another format.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ValueFormat {
+    /// The id of the collection whose header is the value, `None` for a plain
+    /// value
+    pub collection: Option<u64>,
+    /// The id of the codec the value was compressed with, `None` if it is
+    /// stored uncompressed
+    pub codec: Option<u8>,
+    /// The id of the key the value was encrypted with, `None` if it is stored
+    /// unencrypted
+    pub key_id: Option<KeyId>
 }
@@ -2,10 +2,6 @@
 //! methods. `Metadata` is used to track information about each key-value
 //! pair, such as its creation time, access frequency, and TTL.
 
-use std::time::{
-    SystemTime,
-    UNIX_EPOCH
-};
 use std::vec;
 
 use bincode::error::{
@@ -16,22 +12,35 @@ use bincode::serde::{
     decode_from_slice,
     encode_to_vec
 };
+use serde::Deserialize;
 
-use crate::Metadata;
+use crate::db::clock::{
+    Clock,
+    SystemClock
+};
+use crate::{
+    Metadata,
+    ValueFormat
+};
 
 impl Metadata {
     /// Creates a new `Metadata` instance with an optional TTL.
     ///
     /// The `created_at` timestamp is set to the current system time.
     pub fn new(ttl: Option<u64>) -> Metadata {
-        let currtime = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Cant get the current time")
-            .as_secs();
+        Metadata::with_clock(&SystemClock, ttl)
+    }
+
+    /// Creates a new `Metadata` instance with an optional TTL, created at the
+    /// current time of the clock.
+    pub fn with_clock(clock: &dyn Clock, ttl: Option<u64>) -> Metadata {
+        let now = clock.now_secs();
         Metadata {
             freq: 0,
-            created_at: currtime,
-            ttl
+            created_at: now,
+            ttl,
+            last_accessed: now,
+            format: ValueFormat::default()
         }
     }
 
@@ -41,6 +50,12 @@ impl Metadata {
         self
     }
 
+    /// Records an access at `now`, in seconds since the UNIX epoch.
+    pub fn accessed(mut self, now: u64) -> Metadata {
+        self.last_accessed = now;
+        self
+    }
+
     /// Decrements the frequency counter.
     pub fn freq_decretement(mut self) -> Metadata {
         self.freq -= 1;
@@ -62,7 +77,34 @@ impl Metadata {
     ///
     /// Returns a `DecodeError` if deserialization fails.
     pub fn from_u8(slice: &[u8]) -> Result<Metadata, DecodeError> {
-        Ok(decode_from_slice(slice, bincode::config::standard())?.0)
+        let e = match decode_from_slice(slice, bincode::config::standard()) {
+            Ok((meta, _)) => return Ok(meta),
+            Err(e) => e
+        };
+
+        // The older layouts are shorter, so they are tried from the newest
+        if let Ok((meta, _)) =
+            decode_from_slice::<AccessedMetadata, _>(slice, bincode::config::standard())
+        {
+            return Ok(Metadata {
+                freq: meta.freq,
+                created_at: meta.created_at,
+                ttl: meta.ttl,
+                last_accessed: meta.last_accessed,
+                format: ValueFormat::default()
+            });
+        }
+
+        let legacy: LegacyMetadata = decode_from_slice(slice, bincode::config::standard())
+            .map_err(|_| e)?
+            .0;
+        Ok(Metadata {
+            freq: legacy.freq,
+            created_at: legacy.created_at,
+            ttl: legacy.ttl,
+            last_accessed: legacy.created_at,
+            format: ValueFormat::default()
+        })
     }
 
     pub fn to_response(&self) -> Vec<(String, RespValue)> {
@@ -80,6 +122,24 @@ impl Metadata {
     }
 }
 
+/// The layout of `Metadata` before `format` was added.
+#[derive(Deserialize)]
+struct AccessedMetadata {
+    freq: u64,
+    created_at: u64,
+    ttl: Option<u64>,
+    last_accessed: u64
+}
+
+/// The layout of `Metadata` before `last_accessed` was added, still read from
+/// the databases and backups written by older versions.
+#[derive(Deserialize)]
+struct LegacyMetadata {
+    freq: u64,
+    created_at: u64,
+    ttl: Option<u64>
+}
+
 pub enum RespValue {
     U64(u64),
     BulkString(Vec<u8>),
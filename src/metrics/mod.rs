@@ -47,6 +47,63 @@ impl Metrics {
         counter!("epochdb_ttl_expired_keys_total").increment(1);
     }
 
+    /// Increments the counter for transactions which conflicted.
+    pub fn increment_transaction_conflicts() {
+        counter!("epochdb_transaction_conflicts_total").increment(1);
+    }
+
+    /// Increments the counter for transaction closures which were run again
+    /// after a conflict.
+    pub fn increment_transaction_retries() {
+        counter!("epochdb_transaction_retries_total").increment(1);
+    }
+
+    /// Increments the counter for reads served by the hot-key cache.
+    pub fn increment_cache_hits() {
+        counter!("epochdb_cache_hits_total").increment(1);
+    }
+
+    /// Increments the counter for reads which missed the hot-key cache.
+    pub fn increment_cache_misses() {
+        counter!("epochdb_cache_misses_total").increment(1);
+    }
+
+    /// Sets the ratio of reads served by the hot-key cache.
+    pub fn set_cache_hit_ratio(ratio: f64) {
+        gauge!("epochdb_cache_hit_ratio").set(ratio);
+    }
+
+    /// Increments the counter for lookups of absent keys which the bloom
+    /// filter let through.
+    pub fn increment_bloom_false_positives() {
+        counter!("epochdb_bloom_false_positives_total").increment(1);
+    }
+
+    /// Sets the observed false positive rate of the bloom filter.
+    pub fn set_bloom_false_positive_rate(rate: f64) {
+        gauge!("epochdb_bloom_false_positive_rate").set(rate);
+    }
+
+    /// Adds a compressed value to the bytes compressed by the codec, before
+    /// and after compression.
+    pub fn add_compression_bytes(codec: &str, raw: usize, stored: usize) {
+        counter!("epochdb_compression_raw_bytes_total", "codec" => codec.to_string())
+            .increment(raw as u64);
+        counter!("epochdb_compression_stored_bytes_total", "codec" => codec.to_string())
+            .increment(stored as u64);
+    }
+
+    /// Increments the counter for values stored uncompressed because they
+    /// were too small or did not get smaller.
+    pub fn increment_compression_skipped(codec: &str) {
+        counter!("epochdb_compression_skipped_total", "codec" => codec.to_string()).increment(1);
+    }
+
+    /// Sets how many times smaller the compressed values got.
+    pub fn set_compression_ratio(ratio: f64) {
+        gauge!("epochdb_compression_ratio").set(ratio);
+    }
+
     /// Sets the current number of keys for a given tree.
     pub fn inc_amount_keys_total(tree: &str, value: u64) {
         gauge!("epochdb_keys_total", "tree" => tree.to_string()).set(value as f64);
@@ -7,6 +7,8 @@ pub struct ParsedResponse {
     pub key: Option<Vec<u8>>,
     pub value: Option<Vec<u8>>,
     pub ttl: Option<Duration>,
+    /// Every argument after the value
+    pub args: Vec<Vec<u8>>,
     pub len: u32
 }
 
@@ -21,6 +23,40 @@ pub enum Command {
     Ping,
     Size,
     Flush,
+    RateLimit,
+    Lock,
+    Unlock,
+    Renew,
+    Lpush,
+    Rpush,
+    Lpop,
+    Rpop,
+    Llen,
+    Lrange,
+    Ltrim,
+    Hset,
+    Hget,
+    Hdel,
+    Hgetall,
+    Hlen,
+    Hincrby,
+    Zadd,
+    Zrem,
+    Zscore,
+    Zcard,
+    Zrank,
+    Zrange,
+    Zrangebyscore,
+    Xadd,
+    Xlen,
+    Xrange,
+    Xtrim,
+    Xgroup,
+    Xreadgroup,
+    Xack,
+    Xpending,
+    Scan,
+    Stats,
     Invalid
 }
 
@@ -35,6 +71,40 @@ impl From<String> for Command {
             "ping" => Self::Ping,
             "size" => Self::Size,
             "flush" => Self::Flush,
+            "ratelimit" => Self::RateLimit,
+            "lock" => Self::Lock,
+            "unlock" => Self::Unlock,
+            "renew" => Self::Renew,
+            "lpush" => Self::Lpush,
+            "rpush" => Self::Rpush,
+            "lpop" => Self::Lpop,
+            "rpop" => Self::Rpop,
+            "llen" => Self::Llen,
+            "lrange" => Self::Lrange,
+            "ltrim" => Self::Ltrim,
+            "hset" => Self::Hset,
+            "hget" => Self::Hget,
+            "hdel" => Self::Hdel,
+            "hgetall" => Self::Hgetall,
+            "hlen" => Self::Hlen,
+            "hincrby" => Self::Hincrby,
+            "zadd" => Self::Zadd,
+            "zrem" => Self::Zrem,
+            "zscore" => Self::Zscore,
+            "zcard" => Self::Zcard,
+            "zrank" => Self::Zrank,
+            "zrange" => Self::Zrange,
+            "zrangebyscore" => Self::Zrangebyscore,
+            "xadd" => Self::Xadd,
+            "xlen" => Self::Xlen,
+            "xrange" => Self::Xrange,
+            "xtrim" => Self::Xtrim,
+            "xgroup" => Self::Xgroup,
+            "xreadgroup" => Self::Xreadgroup,
+            "xack" => Self::Xack,
+            "xpending" => Self::Xpending,
+            "scan" => Self::Scan,
+            "stats" => Self::Stats,
             _ => Self::Invalid
         }
     }
@@ -51,6 +121,40 @@ impl From<Command> for String {
             Command::Flush => "flush".to_string(),
             Command::GetMetadata => "get_metadata".to_string(),
             Command::IncrementFrequency => "increment_frequency".to_string(),
+            Command::RateLimit => "ratelimit".to_string(),
+            Command::Lock => "lock".to_string(),
+            Command::Unlock => "unlock".to_string(),
+            Command::Renew => "renew".to_string(),
+            Command::Lpush => "lpush".to_string(),
+            Command::Rpush => "rpush".to_string(),
+            Command::Lpop => "lpop".to_string(),
+            Command::Rpop => "rpop".to_string(),
+            Command::Llen => "llen".to_string(),
+            Command::Lrange => "lrange".to_string(),
+            Command::Ltrim => "ltrim".to_string(),
+            Command::Hset => "hset".to_string(),
+            Command::Hget => "hget".to_string(),
+            Command::Hdel => "hdel".to_string(),
+            Command::Hgetall => "hgetall".to_string(),
+            Command::Hlen => "hlen".to_string(),
+            Command::Hincrby => "hincrby".to_string(),
+            Command::Zadd => "zadd".to_string(),
+            Command::Zrem => "zrem".to_string(),
+            Command::Zscore => "zscore".to_string(),
+            Command::Zcard => "zcard".to_string(),
+            Command::Zrank => "zrank".to_string(),
+            Command::Zrange => "zrange".to_string(),
+            Command::Zrangebyscore => "zrangebyscore".to_string(),
+            Command::Xadd => "xadd".to_string(),
+            Command::Xlen => "xlen".to_string(),
+            Command::Xrange => "xrange".to_string(),
+            Command::Xtrim => "xtrim".to_string(),
+            Command::Xgroup => "xgroup".to_string(),
+            Command::Xreadgroup => "xreadgroup".to_string(),
+            Command::Xack => "xack".to_string(),
+            Command::Xpending => "xpending".to_string(),
+            Command::Scan => "scan".to_string(),
+            Command::Stats => "stats".to_string(),
             Command::Invalid => "Invalid".to_string()
         }
     }
@@ -67,6 +171,40 @@ impl From<Command> for &[u8] {
             Command::Flush => b"flush",
             Command::GetMetadata => b"get_metadata",
             Command::IncrementFrequency => b"increment_frequency",
+            Command::RateLimit => b"ratelimit",
+            Command::Lock => b"lock",
+            Command::Unlock => b"unlock",
+            Command::Renew => b"renew",
+            Command::Lpush => b"lpush",
+            Command::Rpush => b"rpush",
+            Command::Lpop => b"lpop",
+            Command::Rpop => b"rpop",
+            Command::Llen => b"llen",
+            Command::Lrange => b"lrange",
+            Command::Ltrim => b"ltrim",
+            Command::Hset => b"hset",
+            Command::Hget => b"hget",
+            Command::Hdel => b"hdel",
+            Command::Hgetall => b"hgetall",
+            Command::Hlen => b"hlen",
+            Command::Hincrby => b"hincrby",
+            Command::Zadd => b"zadd",
+            Command::Zrem => b"zrem",
+            Command::Zscore => b"zscore",
+            Command::Zcard => b"zcard",
+            Command::Zrank => b"zrank",
+            Command::Zrange => b"zrange",
+            Command::Zrangebyscore => b"zrangebyscore",
+            Command::Xadd => b"xadd",
+            Command::Xlen => b"xlen",
+            Command::Xrange => b"xrange",
+            Command::Xtrim => b"xtrim",
+            Command::Xgroup => b"xgroup",
+            Command::Xreadgroup => b"xreadgroup",
+            Command::Xack => b"xack",
+            Command::Xpending => b"xpending",
+            Command::Scan => b"scan",
+            Command::Stats => b"stats",
             Command::Invalid => b"Invalid"
         }
     }
@@ -90,6 +228,74 @@ impl From<&[u8]> for Command {
             Command::GetMetadata
         } else if value.eq_ignore_ascii_case(b"increment_frequency") {
             Command::IncrementFrequency
+        } else if value.eq_ignore_ascii_case(b"ratelimit") {
+            Command::RateLimit
+        } else if value.eq_ignore_ascii_case(b"lock") {
+            Command::Lock
+        } else if value.eq_ignore_ascii_case(b"unlock") {
+            Command::Unlock
+        } else if value.eq_ignore_ascii_case(b"renew") {
+            Command::Renew
+        } else if value.eq_ignore_ascii_case(b"lpush") {
+            Command::Lpush
+        } else if value.eq_ignore_ascii_case(b"rpush") {
+            Command::Rpush
+        } else if value.eq_ignore_ascii_case(b"lpop") {
+            Command::Lpop
+        } else if value.eq_ignore_ascii_case(b"rpop") {
+            Command::Rpop
+        } else if value.eq_ignore_ascii_case(b"llen") {
+            Command::Llen
+        } else if value.eq_ignore_ascii_case(b"lrange") {
+            Command::Lrange
+        } else if value.eq_ignore_ascii_case(b"ltrim") {
+            Command::Ltrim
+        } else if value.eq_ignore_ascii_case(b"hset") {
+            Command::Hset
+        } else if value.eq_ignore_ascii_case(b"hget") {
+            Command::Hget
+        } else if value.eq_ignore_ascii_case(b"hdel") {
+            Command::Hdel
+        } else if value.eq_ignore_ascii_case(b"hgetall") {
+            Command::Hgetall
+        } else if value.eq_ignore_ascii_case(b"hlen") {
+            Command::Hlen
+        } else if value.eq_ignore_ascii_case(b"hincrby") {
+            Command::Hincrby
+        } else if value.eq_ignore_ascii_case(b"zadd") {
+            Command::Zadd
+        } else if value.eq_ignore_ascii_case(b"zrem") {
+            Command::Zrem
+        } else if value.eq_ignore_ascii_case(b"zscore") {
+            Command::Zscore
+        } else if value.eq_ignore_ascii_case(b"zcard") {
+            Command::Zcard
+        } else if value.eq_ignore_ascii_case(b"zrank") {
+            Command::Zrank
+        } else if value.eq_ignore_ascii_case(b"zrange") {
+            Command::Zrange
+        } else if value.eq_ignore_ascii_case(b"zrangebyscore") {
+            Command::Zrangebyscore
+        } else if value.eq_ignore_ascii_case(b"xadd") {
+            Command::Xadd
+        } else if value.eq_ignore_ascii_case(b"xlen") {
+            Command::Xlen
+        } else if value.eq_ignore_ascii_case(b"xrange") {
+            Command::Xrange
+        } else if value.eq_ignore_ascii_case(b"xtrim") {
+            Command::Xtrim
+        } else if value.eq_ignore_ascii_case(b"xgroup") {
+            Command::Xgroup
+        } else if value.eq_ignore_ascii_case(b"xreadgroup") {
+            Command::Xreadgroup
+        } else if value.eq_ignore_ascii_case(b"xack") {
+            Command::Xack
+        } else if value.eq_ignore_ascii_case(b"xpending") {
+            Command::Xpending
+        } else if value.eq_ignore_ascii_case(b"scan") {
+            Command::Scan
+        } else if value.eq_ignore_ascii_case(b"stats") {
+            Command::Stats
         } else {
             // If the command is not recognized
             Command::Invalid
@@ -3,7 +3,6 @@ pub mod utils;
 
 use std::io::ErrorKind;
 use std::str::from_utf8;
-use std::sync::Arc;
 use std::time::Duration;
 
 use tokio::io::{
@@ -22,8 +21,10 @@ use tracing::{
     warn
 };
 
-use crate::DB;
+use crate::db::async_db::AsyncDB;
 use crate::db::errors::TransientError;
+use crate::db::rate_limit::RateLimit;
+use crate::db::stream::StreamTrim;
 use crate::metadata::RespValue;
 use crate::protocol::{
     parse_bulk_string,
@@ -33,11 +34,24 @@ use crate::server::commands::{
     Command,
     ParsedResponse
 };
-use crate::server::utils::check_argument;
+use crate::server::utils::{
+    array_reply,
+    bulk_reply,
+    check_argument,
+    check_arity,
+    decode_cursor,
+    encode_cursor,
+    entries_reply,
+    parse_f64_argument,
+    parse_i64_argument,
+    parse_stream_id_argument,
+    parse_u64_argument,
+    write_reply
+};
 
 pub static CLIENT_COMMAND_SIZE: u64 = 4096;
 
-pub async fn response_handler(mut stream: TcpStream, store: Arc<DB>) -> Result<(), TransientError> {
+pub async fn response_handler(mut stream: TcpStream, store: AsyncDB) -> Result<(), TransientError> {
     let (reader, writer) = stream.split();
     let mut bufreader = BufReader::new(reader);
     let mut bufwriter = BufWriter::new(writer);
@@ -65,6 +79,17 @@ pub async fn response_handler(mut stream: TcpStream, store: Arc<DB>) -> Result<(
                                         }
                                     })?;
                             },
+                            TransientError::ProtocolError => {
+                                warn!("Client has issued a command with a malformed argument");
+                                bufwriter
+                                    .write_all(b"-ERR Protocol error: malformed argument\r\n")
+                                    .await
+                                    .map_err(|e| {
+                                        TransientError::IOError {
+                                            error: e
+                                        }
+                                    })?;
+                            },
                             TransientError::WrongNumberOfArguments {
                                 ref command,
                                 expected,
@@ -193,7 +218,10 @@ pub async fn parse_command<T: AsyncReadExt + AsyncRead + Unpin>(
     let key = command_parts.get(1).cloned();
     let value = command_parts.get(2).cloned();
 
-    let ttl = if let Some(ttl_raw) = command_parts.get(3) {
+    // Only SET takes a ttl, the other commands parse their arguments themselves
+    let ttl = if command == Command::Set
+        && let Some(ttl_raw) = command_parts.get(3)
+    {
         Some(Duration::from_millis({
             let ttl_str = from_utf8(ttl_raw).map_err(|_| TransientError::ParsingToUTF8Error)?;
             ttl_str
@@ -209,28 +237,33 @@ pub async fn parse_command<T: AsyncReadExt + AsyncRead + Unpin>(
         key,
         value,
         ttl,
+        args: command_parts.iter().skip(3).cloned().collect(),
         len: command_parts.len() as u32
     })
 }
 
 pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
     parsed_reponse: ParsedResponse,
-    store: &Arc<DB>,
+    store: &AsyncDB,
     stream: &mut BufWriter<T>
 ) -> Result<(), TransientError> {
     let cmd = parsed_reponse.command;
     let key = parsed_reponse.key;
     let val = parsed_reponse.value;
     let ttl = parsed_reponse.ttl;
+    let args = parsed_reponse.args;
 
     match cmd {
         Command::Set => {
             check_argument(cmd.into(), 4, parsed_reponse.len, Some(3)).await?;
-            match store.set_raw(
-                &&key.ok_or(TransientError::InvalidCommand)?[..],
-                &&val.ok_or(TransientError::InvalidCommand)?[..],
-                ttl
-            ) {
+            match store
+                .set_raw(
+                    &&key.ok_or(TransientError::InvalidCommand)?[..],
+                    &&val.ok_or(TransientError::InvalidCommand)?[..],
+                    ttl
+                )
+                .await
+            {
                 Ok(_) => {
                     stream.write_all(b"+OK\r\n").await.map_err(|e| {
                         TransientError::IOError {
@@ -252,7 +285,10 @@ pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
         },
         Command::GetMetadata => {
             check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
-            match store.get_metadata_raw(&&key.ok_or(TransientError::InvalidCommand)?[..]) {
+            match store
+                .get_metadata_raw(&&key.ok_or(TransientError::InvalidCommand)?[..])
+                .await
+            {
                 Ok(v) => {
                     match v {
                         Some(val) => {
@@ -345,7 +381,10 @@ pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
         },
         Command::Rm => {
             check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
-            match store.remove_raw(&key.ok_or(TransientError::InvalidCommand)?[..]) {
+            match store
+                .remove_raw(&key.ok_or(TransientError::InvalidCommand)?[..])
+                .await
+            {
                 Ok(_) => {
                     stream.write_all(b"+OK\r\n").await.map_err(|e| {
                         TransientError::IOError {
@@ -367,7 +406,7 @@ pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
         },
         Command::Flush => {
             check_argument(cmd.into(), 1, parsed_reponse.len, None).await?;
-            match store.flush() {
+            match store.flush().await {
                 Ok(_) => {
                     stream.write_all(b"+OK\r\n").await.map_err(|e| {
                         TransientError::IOError {
@@ -389,7 +428,10 @@ pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
         },
         Command::Get => {
             check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
-            match store.get_raw(&&key.ok_or(TransientError::InvalidCommand)?[..]) {
+            match store
+                .get_raw(&&key.ok_or(TransientError::InvalidCommand)?[..])
+                .await
+            {
                 Ok(v) => {
                     match v {
                         Some(val) => {
@@ -436,7 +478,10 @@ pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
         },
         Command::IncrementFrequency => {
             check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
-            match store.increment_frequency_raw(&key.ok_or(TransientError::InvalidCommand)?[..]) {
+            match store
+                .increment_frequency_raw(&key.ok_or(TransientError::InvalidCommand)?[..])
+                .await
+            {
                 Ok(t) => {
                     match t {
                         Some(_) => {
@@ -477,7 +522,7 @@ pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
         },
         Command::Size => {
             check_argument(cmd.into(), 1, parsed_reponse.len, None).await?;
-            let size = store.get_db_size();
+            let size = store.get_db_size().await?;
             stream
                 .write_all(format!(":{}\r\n", size).as_bytes())
                 .await
@@ -487,6 +532,658 @@ pub async fn execute_commands<T: AsyncWrite + AsyncWriteExt + Unpin>(
                     }
                 })?
         },
+        Command::RateLimit => {
+            // RATELIMIT key fixed|sliding|bucket limit window_ms [cost]
+            check_arity(cmd.into(), 5, 6, parsed_reponse.len).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let limit = parse_u64_argument(&args[0])?;
+            let window = Duration::from_millis(parse_u64_argument(&args[1])?);
+            let cost = match args.get(2) {
+                Some(c) => parse_u64_argument(c)?,
+                None => 1
+            };
+
+            let algorithm = val.ok_or(TransientError::InvalidCommand)?;
+            let policy = if algorithm.eq_ignore_ascii_case(b"fixed") {
+                RateLimit::FixedWindow {
+                    limit,
+                    window
+                }
+            } else if algorithm.eq_ignore_ascii_case(b"sliding") {
+                RateLimit::SlidingWindow {
+                    limit,
+                    window
+                }
+            } else if algorithm.eq_ignore_ascii_case(b"bucket") {
+                RateLimit::TokenBucket {
+                    limit,
+                    window
+                }
+            } else {
+                return Err(TransientError::InvalidCommand);
+            };
+
+            match store.check_rate_limit(policy, &key, cost).await {
+                Ok(d) => {
+                    stream
+                        .write_all(
+                            format!(
+                                "*4\r\n:{}\r\n:{}\r\n:{}\r\n:{}\r\n",
+                                d.allowed as u8,
+                                d.remaining,
+                                d.reset_after.as_millis(),
+                                d.retry_after.as_millis()
+                            )
+                            .as_bytes()
+                        )
+                        .await
+                        .map_err(|e| {
+                            TransientError::IOError {
+                                error: e
+                            }
+                        })?
+                },
+                Err(e) => {
+                    stream
+                        .write_all(format!("-ERR {}\r\n", e).as_bytes())
+                        .await
+                        .map_err(|e| {
+                            TransientError::IOError {
+                                error: e
+                            }
+                        })?
+                },
+            };
+        },
+        Command::Lock | Command::Renew => {
+            // LOCK name owner ttl_ms, RENEW name owner ttl_ms
+            let is_lock = cmd == Command::Lock;
+            check_arity(cmd.into(), 4, 4, parsed_reponse.len).await?;
+
+            let name = key.ok_or(TransientError::InvalidCommand)?;
+            let owner = val.ok_or(TransientError::InvalidCommand)?;
+            let name = from_utf8(&name).map_err(|_| TransientError::ProtocolError)?;
+            let owner = from_utf8(&owner).map_err(|_| TransientError::ProtocolError)?;
+            let ttl = Duration::from_millis(parse_u64_argument(&args[0])?);
+
+            let res = if is_lock {
+                store.acquire_lease(name, owner, ttl).await
+            } else {
+                store.renew_lease(name, owner, ttl).await
+            };
+
+            let reply = match res {
+                Ok(Some(lease)) => format!(":{}\r\n", lease.token),
+                Ok(None) => "$-1\r\n".to_string(),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Unlock => {
+            // UNLOCK name owner
+            check_arity(cmd.into(), 3, 3, parsed_reponse.len).await?;
+
+            let name = key.ok_or(TransientError::InvalidCommand)?;
+            let owner = val.ok_or(TransientError::InvalidCommand)?;
+            let name = from_utf8(&name).map_err(|_| TransientError::ProtocolError)?;
+            let owner = from_utf8(&owner).map_err(|_| TransientError::ProtocolError)?;
+
+            let reply = match store.release_lease(name, owner).await {
+                Ok(released) => format!(":{}\r\n", released as u8),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Lpush | Command::Rpush => {
+            // LPUSH key value [value ...], RPUSH key value [value ...]
+            let is_lpush = cmd == Command::Lpush;
+            check_argument(cmd.into(), 3, parsed_reponse.len, Some(3)).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let mut values = vec![val.ok_or(TransientError::InvalidCommand)?];
+            values.extend(args);
+
+            let res = if is_lpush {
+                store.lpush(&key, &values).await
+            } else {
+                store.rpush(&key, &values).await
+            };
+
+            let reply = match res {
+                Ok(len) => format!(":{len}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Lpop | Command::Rpop => {
+            // LPOP key, RPOP key
+            let is_lpop = cmd == Command::Lpop;
+            check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let res = if is_lpop {
+                store.lpop(&key).await
+            } else {
+                store.rpop(&key).await
+            };
+
+            let reply = match res {
+                Ok(v) => bulk_reply(v.as_deref()),
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Llen => {
+            // LLEN key
+            check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
+
+            let reply = match store
+                .llen(&key.ok_or(TransientError::InvalidCommand)?)
+                .await
+            {
+                Ok(len) => format!(":{len}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Lrange | Command::Ltrim => {
+            // LRANGE key start stop, LTRIM key start stop
+            let is_lrange = cmd == Command::Lrange;
+            check_argument(cmd.into(), 4, parsed_reponse.len, None).await?;
+            check_argument(
+                String::from(if is_lrange { "lrange" } else { "ltrim" }),
+                4,
+                parsed_reponse.len,
+                Some(4)
+            )
+            .await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let start = parse_i64_argument(&val.ok_or(TransientError::InvalidCommand)?)?;
+            let stop = parse_i64_argument(&args[0])?;
+
+            let reply = if is_lrange {
+                match store.lrange(&key, start, stop).await {
+                    Ok(values) => array_reply(&values),
+                    Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+                }
+            } else {
+                match store.ltrim(&key, start, stop).await {
+                    Ok(()) => b"+OK\r\n".to_vec(),
+                    Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+                }
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Hset => {
+            // HSET key field value [field value ...]
+            check_argument(cmd.into(), 4, parsed_reponse.len, Some(4)).await?;
+            if !parsed_reponse.len.is_multiple_of(2) {
+                return Err(TransientError::WrongNumberOfArguments {
+                    command: Command::Hset.into(),
+                    expected: parsed_reponse.len + 1,
+                    received: parsed_reponse.len
+                });
+            }
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let mut fields = vec![(val.ok_or(TransientError::InvalidCommand)?, args[0].clone())];
+            fields.extend(args[1..].chunks(2).map(|c| (c[0].clone(), c[1].clone())));
+
+            let reply = match store.hset(&key, &fields).await {
+                Ok(added) => format!(":{added}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Hget => {
+            // HGET key field
+            check_arity(cmd.into(), 3, 3, parsed_reponse.len).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let field = val.ok_or(TransientError::InvalidCommand)?;
+
+            let reply = match store.hget(&key, &field).await {
+                Ok(v) => bulk_reply(v.as_deref()),
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Hdel => {
+            // HDEL key field [field ...]
+            check_argument(cmd.into(), 3, parsed_reponse.len, Some(3)).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let mut fields = vec![val.ok_or(TransientError::InvalidCommand)?];
+            fields.extend(args);
+
+            let reply = match store.hdel(&key, &fields).await {
+                Ok(removed) => format!(":{removed}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Hgetall => {
+            // HGETALL key, replies with the fields and values interleaved
+            check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
+
+            let reply = match store
+                .hgetall(&key.ok_or(TransientError::InvalidCommand)?)
+                .await
+            {
+                Ok(fields) => {
+                    let flat: Vec<Vec<u8>> = fields.into_iter().flat_map(|(f, v)| [f, v]).collect();
+                    array_reply(&flat)
+                },
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Hlen => {
+            // HLEN key
+            check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
+
+            let reply = match store
+                .hlen(&key.ok_or(TransientError::InvalidCommand)?)
+                .await
+            {
+                Ok(len) => format!(":{len}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Hincrby => {
+            // HINCRBY key field delta
+            check_arity(cmd.into(), 4, 4, parsed_reponse.len).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let field = val.ok_or(TransientError::InvalidCommand)?;
+            let delta = parse_i64_argument(&args[0])?;
+
+            let reply = match store.hincrby(&key, &field, delta).await {
+                Ok(value) => format!(":{value}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Zadd => {
+            // ZADD key score member [score member ...]
+            check_argument(cmd.into(), 4, parsed_reponse.len, Some(4)).await?;
+            if !parsed_reponse.len.is_multiple_of(2) {
+                return Err(TransientError::WrongNumberOfArguments {
+                    command: Command::Zadd.into(),
+                    expected: parsed_reponse.len + 1,
+                    received: parsed_reponse.len
+                });
+            }
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let mut members = vec![(
+                args[0].clone(),
+                parse_f64_argument(&val.ok_or(TransientError::InvalidCommand)?)?
+            )];
+            for c in args[1..].chunks(2) {
+                members.push((c[1].clone(), parse_f64_argument(&c[0])?));
+            }
+
+            let reply = match store.zadd(&key, &members).await {
+                Ok(added) => format!(":{added}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Zrem => {
+            // ZREM key member [member ...]
+            check_argument(cmd.into(), 3, parsed_reponse.len, Some(3)).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let mut members = vec![val.ok_or(TransientError::InvalidCommand)?];
+            members.extend(args);
+
+            let reply = match store.zrem(&key, &members).await {
+                Ok(removed) => format!(":{removed}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Zscore | Command::Zrank => {
+            // ZSCORE key member, ZRANK key member
+            let is_zscore = cmd == Command::Zscore;
+            check_argument(cmd.into(), 3, parsed_reponse.len, Some(3)).await?;
+            check_argument(
+                String::from(if is_zscore { "zscore" } else { "zrank" }),
+                3,
+                parsed_reponse.len,
+                None
+            )
+            .await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let member = val.ok_or(TransientError::InvalidCommand)?;
+
+            let reply = if is_zscore {
+                match store.zscore(&key, &member).await {
+                    Ok(score) => {
+                        bulk_reply(score.map(|s| s.to_string()).as_deref().map(str::as_bytes))
+                    },
+                    Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+                }
+            } else {
+                match store.zrank(&key, &member).await {
+                    Ok(Some(rank)) => format!(":{rank}\r\n").into_bytes(),
+                    Ok(None) => b"$-1\r\n".to_vec(),
+                    Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+                }
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Zcard => {
+            // ZCARD key
+            check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
+
+            let reply = match store
+                .zcard(&key.ok_or(TransientError::InvalidCommand)?)
+                .await
+            {
+                Ok(len) => format!(":{len}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Zrange | Command::Zrangebyscore => {
+            // ZRANGE key start stop [WITHSCORES], ZRANGEBYSCORE key min max [WITHSCORES]
+            let is_zrange = cmd == Command::Zrange;
+            check_argument(cmd.into(), 5, parsed_reponse.len, None).await?;
+            check_argument(
+                String::from(if is_zrange { "zrange" } else { "zrangebyscore" }),
+                5,
+                parsed_reponse.len,
+                Some(4)
+            )
+            .await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let start = val.ok_or(TransientError::InvalidCommand)?;
+            let with_scores = match args.get(1) {
+                Some(a) if a.eq_ignore_ascii_case(b"withscores") => true,
+                Some(_) => return Err(TransientError::InvalidCommand),
+                None => false
+            };
+
+            let res = if is_zrange {
+                store
+                    .zrange(
+                        &key,
+                        parse_i64_argument(&start)?,
+                        parse_i64_argument(&args[0])?
+                    )
+                    .await
+            } else {
+                store
+                    .zrange_by_score(
+                        &key,
+                        parse_f64_argument(&start)?,
+                        parse_f64_argument(&args[0])?
+                    )
+                    .await
+            };
+
+            let reply = match res {
+                Ok(members) => {
+                    let flat: Vec<Vec<u8>> = if with_scores {
+                        members
+                            .into_iter()
+                            .flat_map(|(m, s)| [m, s.to_string().into_bytes()])
+                            .collect()
+                    } else {
+                        members.into_iter().map(|(m, _)| m).collect()
+                    };
+                    array_reply(&flat)
+                },
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Xadd => {
+            // XADD key * field value [field value ...]
+            check_argument(cmd.into(), 5, parsed_reponse.len, Some(5)).await?;
+            if parsed_reponse.len.is_multiple_of(2) {
+                return Err(TransientError::WrongNumberOfArguments {
+                    command: Command::Xadd.into(),
+                    expected: parsed_reponse.len + 1,
+                    received: parsed_reponse.len
+                });
+            }
+            // The ids are always generated
+            if val.as_deref() != Some(b"*") {
+                return Err(TransientError::InvalidCommand);
+            }
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let fields: Vec<(Vec<u8>, Vec<u8>)> = args
+                .chunks(2)
+                .map(|c| (c[0].clone(), c[1].clone()))
+                .collect();
+
+            let reply = match store.xadd(&key, &fields).await {
+                Ok(id) => bulk_reply(Some(id.to_string().as_bytes())),
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Xlen => {
+            // XLEN key
+            check_argument(cmd.into(), 2, parsed_reponse.len, None).await?;
+
+            let reply = match store
+                .xlen(&key.ok_or(TransientError::InvalidCommand)?)
+                .await
+            {
+                Ok(len) => format!(":{len}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Xrange => {
+            // XRANGE key start end [COUNT count], - and + being the first and last ids
+            check_argument(cmd.into(), 6, parsed_reponse.len, None).await?;
+            check_argument(Command::Xrange.into(), 6, parsed_reponse.len, Some(4)).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let start = parse_stream_id_argument(&val.ok_or(TransientError::InvalidCommand)?)?;
+            let end = parse_stream_id_argument(&args[0])?;
+            let count = match &args[1..] {
+                [] => None,
+                [c, n] if c.eq_ignore_ascii_case(b"count") => Some(parse_u64_argument(n)? as usize),
+                _ => return Err(TransientError::InvalidCommand)
+            };
+
+            let reply = match store.xrange(&key, start, end, count).await {
+                Ok(entries) => entries_reply(&entries),
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Xtrim => {
+            // XTRIM key MAXLEN count, XTRIM key MAXAGE ms
+            check_argument(cmd.into(), 4, parsed_reponse.len, Some(4)).await?;
+            check_argument(Command::Xtrim.into(), 4, parsed_reponse.len, None).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let strategy = val.ok_or(TransientError::InvalidCommand)?;
+            let limit = parse_u64_argument(&args[0])?;
+            let trim = if strategy.eq_ignore_ascii_case(b"maxlen") {
+                StreamTrim::MaxLen(limit)
+            } else if strategy.eq_ignore_ascii_case(b"maxage") {
+                StreamTrim::MaxAge(Duration::from_millis(limit))
+            } else {
+                return Err(TransientError::InvalidCommand);
+            };
+
+            let reply = match store.xtrim(&key, trim).await {
+                Ok(removed) => format!(":{removed}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Xgroup => {
+            // XGROUP CREATE key group id, $ as the id only delivers the new entries
+            check_argument(cmd.into(), 5, parsed_reponse.len, Some(5)).await?;
+            check_argument(Command::Xgroup.into(), 5, parsed_reponse.len, None).await?;
+
+            let subcommand = key.ok_or(TransientError::InvalidCommand)?;
+            if !subcommand.eq_ignore_ascii_case(b"create") {
+                return Err(TransientError::InvalidCommand);
+            }
+            let key = val.ok_or(TransientError::InvalidCommand)?;
+            let group = from_utf8(&args[0]).map_err(|_| TransientError::ParsingToUTF8Error)?;
+            let start = match args[1].as_slice() {
+                b"$" => None,
+                id => Some(parse_stream_id_argument(id)?)
+            };
+
+            let reply = match store.xgroup_create(&key, group, start).await {
+                Ok(created) => format!(":{}\r\n", created as u8),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Xreadgroup => {
+            // XREADGROUP key group consumer [count]
+            check_argument(cmd.into(), 5, parsed_reponse.len, None).await?;
+            check_argument(Command::Xreadgroup.into(), 5, parsed_reponse.len, Some(4)).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let group = val.ok_or(TransientError::InvalidCommand)?;
+            let group = from_utf8(&group).map_err(|_| TransientError::ParsingToUTF8Error)?;
+            let consumer = from_utf8(&args[0]).map_err(|_| TransientError::ParsingToUTF8Error)?;
+            let count = match args.get(1) {
+                Some(n) => parse_u64_argument(n)? as usize,
+                None => usize::MAX
+            };
+
+            let reply = match store.xreadgroup(&key, group, consumer, count).await {
+                Ok(entries) => entries_reply(&entries),
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Xack => {
+            // XACK key group id [id ...]
+            check_argument(cmd.into(), 4, parsed_reponse.len, Some(4)).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let group = val.ok_or(TransientError::InvalidCommand)?;
+            let group = from_utf8(&group).map_err(|_| TransientError::ParsingToUTF8Error)?;
+            let ids = args
+                .iter()
+                .map(|id| parse_stream_id_argument(id))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let reply = match store.xack(&key, group, &ids).await {
+                Ok(acked) => format!(":{acked}\r\n"),
+                Err(e) => format!("-ERR {}\r\n", e)
+            };
+            write_reply(stream, reply.as_bytes()).await?;
+        },
+        Command::Xpending => {
+            // XPENDING key group, replies with the id, the consumer and the idle time in ms
+            // of each pending entry
+            check_argument(cmd.into(), 3, parsed_reponse.len, Some(3)).await?;
+            check_argument(Command::Xpending.into(), 3, parsed_reponse.len, None).await?;
+
+            let key = key.ok_or(TransientError::InvalidCommand)?;
+            let group = val.ok_or(TransientError::InvalidCommand)?;
+            let group = from_utf8(&group).map_err(|_| TransientError::ParsingToUTF8Error)?;
+
+            let reply = match store.xpending(&key, group).await {
+                Ok(pending) => {
+                    let now = store.db().clock.now_millis();
+                    let mut reply = format!("*{}\r\n", pending.len()).into_bytes();
+                    for p in pending {
+                        reply.extend(b"*3\r\n");
+                        reply.extend(bulk_reply(Some(p.id.to_string().as_bytes())));
+                        reply.extend(bulk_reply(Some(p.consumer.as_bytes())));
+                        reply.extend(
+                            format!(":{}\r\n", now.saturating_sub(p.delivered_at)).as_bytes()
+                        );
+                    }
+                    reply
+                },
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Scan => {
+            // SCAN cursor [MATCH pattern] [COUNT count], replies with the next cursor and
+            // the keys
+            check_argument(cmd.into(), 6, parsed_reponse.len, None).await?;
+            check_argument(Command::Scan.into(), 6, parsed_reponse.len, Some(2)).await?;
+
+            let cursor = decode_cursor(&key.ok_or(TransientError::InvalidCommand)?)?;
+            let mut options: Vec<Vec<u8>> = val.into_iter().collect();
+            options.extend(args);
+            if !options.len().is_multiple_of(2) {
+                return Err(TransientError::InvalidCommand);
+            }
+
+            let mut pattern = None;
+            let mut count = 10;
+            for option in options.chunks(2) {
+                if option[0].eq_ignore_ascii_case(b"match") {
+                    pattern = Some(option[1].clone());
+                } else if option[0].eq_ignore_ascii_case(b"count") {
+                    count = parse_u64_argument(&option[1])? as usize;
+                } else {
+                    return Err(TransientError::InvalidCommand);
+                }
+            }
+
+            let reply = match store.scan(&cursor, pattern.as_deref(), count).await {
+                Ok(page) => {
+                    let mut reply = b"*2\r\n".to_vec();
+                    reply.extend(bulk_reply(Some(
+                        encode_cursor(page.cursor.as_deref()).as_bytes()
+                    )));
+                    reply.extend(array_reply(&page.keys));
+                    reply
+                },
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
+        Command::Stats => {
+            // STATS, replies with the name and the value of every statistic interleaved
+            check_argument(cmd.into(), 1, parsed_reponse.len, None).await?;
+
+            let reply = match store.stats().await {
+                Ok(stats) => {
+                    let secs = |d: Option<Duration>| d.map_or(-1, |d| d.as_secs() as i64);
+                    let fields = [
+                        ("live_keys", stats.live_keys.to_string()),
+                        ("ttl_keys", stats.ttl_keys.to_string()),
+                        ("expired_keys", stats.expired_keys.to_string()),
+                        ("freq_min", stats.freq.min.to_string()),
+                        ("freq_max", stats.freq.max.to_string()),
+                        ("freq_p50", stats.freq.p50.to_string()),
+                        ("freq_p99", stats.freq.p99.to_string()),
+                        ("value_size_max", stats.value_size.max.to_string()),
+                        ("value_size_p50", stats.value_size.p50.to_string()),
+                        ("value_size_p99", stats.value_size.p99.to_string()),
+                        ("oldest_key_age", secs(stats.oldest_key_age).to_string()),
+                        ("next_expiry", secs(stats.next_expiry).to_string())
+                    ];
+                    let flat: Vec<&[u8]> = fields
+                        .iter()
+                        .flat_map(|(n, v)| [n.as_bytes(), v.as_bytes()])
+                        .collect();
+                    array_reply(&flat)
+                },
+                Err(e) => format!("-ERR {}\r\n", e).into_bytes()
+            };
+            write_reply(stream, &reply).await?;
+        },
         Command::Invalid => {
             stream
                 .write_all(format!("-ERR {}\r\n", TransientError::InvalidCommand).as_bytes())
@@ -1,9 +1,19 @@
+use std::str::from_utf8;
+
+use tokio::io::{
+    AsyncWrite,
+    AsyncWriteExt
+};
 use tracing_subscriber::{
     EnvFilter,
     fmt
 };
 
 use crate::db::errors::TransientError;
+use crate::db::stream::{
+    StreamEntry,
+    StreamId
+};
 
 pub async fn check_argument(
     command: String,
@@ -29,6 +39,138 @@ pub async fn check_argument(
     Ok(())
 }
 
+/// Checks that a command received between `min` and `max` arguments, the
+/// command itself included.
+pub async fn check_arity(
+    command: String,
+    min: u32,
+    max: u32,
+    received: u32
+) -> Result<(), TransientError> {
+    if received < min || received > max {
+        return Err(TransientError::WrongNumberOfArguments {
+            command,
+            expected: if received < min { min } else { max },
+            received
+        });
+    }
+    Ok(())
+}
+
+/// Parses a command argument as a u64.
+pub fn parse_u64_argument(arg: &[u8]) -> Result<u64, TransientError> {
+    from_utf8(arg)
+        .map_err(|_| TransientError::ProtocolError)?
+        .parse::<u64>()
+        .map_err(|_| TransientError::InvalidCommand)
+}
+
+/// Parses a command argument as an i64.
+pub fn parse_i64_argument(arg: &[u8]) -> Result<i64, TransientError> {
+    from_utf8(arg)
+        .map_err(|_| TransientError::ProtocolError)?
+        .parse::<i64>()
+        .map_err(|_| TransientError::InvalidCommand)
+}
+
+/// Parses a command argument as an f64, `-inf` and `+inf` included.
+pub fn parse_f64_argument(arg: &[u8]) -> Result<f64, TransientError> {
+    from_utf8(arg)
+        .map_err(|_| TransientError::ProtocolError)?
+        .parse::<f64>()
+        .map_err(|_| TransientError::InvalidCommand)
+}
+
+/// Formats a bulk string reply, `$-1` if there is no value.
+pub fn bulk_reply(value: Option<&[u8]>) -> Vec<u8> {
+    match value {
+        Some(v) => [format!("${}\r\n", v.len()).as_bytes(), v, b"\r\n"].concat(),
+        None => b"$-1\r\n".to_vec()
+    }
+}
+
+/// Formats an array reply of bulk strings.
+pub fn array_reply<V: AsRef<[u8]>>(values: &[V]) -> Vec<u8> {
+    let mut reply = format!("*{}\r\n", values.len()).into_bytes();
+    for v in values {
+        reply.extend(bulk_reply(Some(v.as_ref())));
+    }
+    reply
+}
+
+/// Formats an array reply of stream entries, each one an array of its id and
+/// of its fields and values interleaved.
+pub fn entries_reply(entries: &[StreamEntry]) -> Vec<u8> {
+    let mut reply = format!("*{}\r\n", entries.len()).into_bytes();
+    for e in entries {
+        let flat: Vec<&[u8]> = e
+            .fields
+            .iter()
+            .flat_map(|(f, v)| [f.as_slice(), v.as_slice()])
+            .collect();
+
+        reply.extend(b"*2\r\n");
+        reply.extend(bulk_reply(Some(e.id.to_string().as_bytes())));
+        reply.extend(array_reply(&flat));
+    }
+    reply
+}
+
+/// Parses a command argument as a stream id, `-` and `+` being the smallest
+/// and the greatest ids.
+pub fn parse_stream_id_argument(arg: &[u8]) -> Result<StreamId, TransientError> {
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        _ => {
+            from_utf8(arg)
+                .map_err(|_| TransientError::ParsingToUTF8Error)?
+                .parse()
+                .map_err(|_| TransientError::InvalidCommand)
+        },
+    }
+}
+
+/// Encodes a scan cursor for the clients, `0` being the start and the end of
+/// the scan.
+pub fn encode_cursor(cursor: Option<&[u8]>) -> String {
+    match cursor {
+        Some(c) => c.iter().map(|b| format!("{b:02x}")).collect(),
+        None => "0".to_string()
+    }
+}
+
+/// Decodes a scan cursor sent by a client.
+pub fn decode_cursor(arg: &[u8]) -> Result<Vec<u8>, TransientError> {
+    if arg == b"0" {
+        return Ok(Vec::new());
+    }
+    if !arg.len().is_multiple_of(2) {
+        return Err(TransientError::InvalidCommand);
+    }
+
+    arg.chunks(2)
+        .map(|c| {
+            from_utf8(c)
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or(TransientError::InvalidCommand)
+        })
+        .collect()
+}
+
+/// Writes a reply to the client.
+pub async fn write_reply<T: AsyncWrite + Unpin>(
+    stream: &mut T,
+    reply: &[u8]
+) -> Result<(), TransientError> {
+    stream.write_all(reply).await.map_err(|e| {
+        TransientError::IOError {
+            error: e
+        }
+    })
+}
+
 pub fn init_logger(default_val: String) {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_val));
 
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::async_db::AsyncDB;
+use epoch_db::db::errors::TransactionError;
+use epoch_db::db::storage::MemoryBackend;
+use epoch_db::db::stream::StreamId;
+use tempfile::tempdir;
+
+fn open() -> AsyncDB {
+    AsyncDB::new(DB::with_storage(MemoryBackend::new()).unwrap())
+}
+
+#[tokio::test]
+async fn test_async_set_get_remove() {
+    let db = open();
+
+    db.set("user:1", "Alice", None).await.unwrap();
+    assert_eq!(Some("Alice".to_string()), db.get("user:1").await.unwrap());
+
+    db.increment_frequency("user:1").await.unwrap();
+    assert_eq!(1, db.get_metadata("user:1").await.unwrap().unwrap().freq);
+
+    db.remove("user:1").await.unwrap();
+    assert!(db.get("user:1").await.unwrap().is_none());
+
+    // The sync API sees the same database
+    db.set_raw(&"raw", &[1u8, 2, 3], Some(Duration::from_secs(60)))
+        .await
+        .unwrap();
+    assert_eq!(Some(vec![1, 2, 3]), db.db().get_raw(&"raw").unwrap());
+    assert!(db.ttl(&"raw").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_async_collections() {
+    let db = open();
+
+    assert_eq!(2, db.rpush(&"list", &["a", "b"]).await.unwrap());
+    assert_eq!(
+        vec![b"a".to_vec(), b"b".to_vec()],
+        db.lrange(&"list", 0, -1).await.unwrap()
+    );
+
+    db.hset(&"hash", &[("field", "value")]).await.unwrap();
+    assert_eq!(
+        Some(b"value".to_vec()),
+        db.hget(&"hash", &"field").await.unwrap()
+    );
+
+    db.zadd(&"zset", &[("m", 1.5)]).await.unwrap();
+    assert_eq!(Some(1.5), db.zscore(&"zset", &"m").await.unwrap());
+
+    let id = db.xadd(&"stream", &[("f", "v")]).await.unwrap();
+    assert_eq!(1, db.xlen(&"stream").await.unwrap());
+    assert_eq!(
+        id,
+        db.xrange(&"stream", StreamId::MIN, StreamId::MAX, None)
+            .await
+            .unwrap()[0]
+            .id
+    );
+
+    assert_eq!(4, db.keys(&"*").await.unwrap().len());
+    assert_eq!(4, db.stats().await.unwrap().live_keys);
+}
+
+#[tokio::test]
+async fn test_async_transaction_and_run() {
+    let db = open();
+
+    db.transaction(|tx| {
+        tx.set("a", "1", None)?;
+        tx.set("b", "2", None)?;
+        Ok::<(), TransactionError<()>>(())
+    })
+    .await
+    .unwrap();
+
+    let size = db.run(|db| Ok(db.get_db_size())).await.unwrap();
+    assert_eq!(2, size);
+}
+
+#[tokio::test]
+async fn test_async_calls_run_concurrently() {
+    let temp_dir = tempdir().unwrap();
+    let db = AsyncDB::open(temp_dir.path()).await.unwrap();
+
+    let mut handles = Vec::new();
+    for i in 0..16 {
+        let db = db.clone();
+        handles.push(tokio::spawn(async move {
+            db.set(&format!("key:{i}"), "v", None).await.unwrap();
+        }));
+    }
+    for h in handles {
+        h.await.unwrap();
+    }
+
+    assert_eq!(16, db.get_db_size().await.unwrap());
+}
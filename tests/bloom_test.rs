@@ -0,0 +1,125 @@
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::bloom::{
+    BloomConfig,
+    BloomFilter
+};
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::errors::TransactionError;
+use epoch_db::db::storage::{
+    MemoryBackend,
+    SledBackend
+};
+use tempfile::tempdir;
+
+fn open() -> DB {
+    DB::with_config(
+        MemoryBackend::new(),
+        DBConfig::new().bloom_filter(BloomConfig::new(1000))
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_bloom_filter_never_hides_present_keys() {
+    let bloom = BloomFilter::new(BloomConfig::new(100));
+    for i in 0..100 {
+        bloom.insert(format!("key:{i}").as_bytes());
+    }
+
+    for i in 0..100 {
+        assert!(bloom.may_contain(format!("key:{i}").as_bytes()));
+    }
+
+    let epoch = bloom.epoch();
+    for i in 0..50 {
+        bloom.remove(format!("key:{i}").as_bytes(), epoch);
+    }
+    for i in 50..100 {
+        assert!(bloom.may_contain(format!("key:{i}").as_bytes()));
+    }
+}
+
+#[test]
+fn test_bloom_filter_answers_absent_keys() {
+    let db = open();
+    db.set("user:1", "alice", None).unwrap();
+
+    for i in 0..100 {
+        assert!(db.get(&format!("missing:{i}")).unwrap().is_none());
+        assert!(db.get_metadata(&format!("missing:{i}")).unwrap().is_none());
+    }
+    assert_eq!("alice", db.get("user:1").unwrap().unwrap());
+    assert!(db.get_metadata("user:1").unwrap().is_some());
+
+    assert!(db.bloom_filter().unwrap().false_positive_rate() < 0.1);
+}
+
+#[test]
+fn test_bloom_filter_follows_writes() {
+    let db = open();
+    db.set("user:1", "alice", None).unwrap();
+    db.remove("user:1").unwrap();
+    assert!(db.get("user:1").unwrap().is_none());
+
+    db.transaction(|tx| {
+        tx.set("user:2", "bob", None)?;
+        Ok::<(), TransactionError<()>>(())
+    })
+    .unwrap();
+    assert_eq!("bob", db.get("user:2").unwrap().unwrap());
+
+    db.set("session:1", "token", Some(Duration::from_secs(1)))
+        .unwrap();
+    thread::sleep(Duration::from_secs(2));
+    assert!(db.get("session:1").unwrap().is_none());
+
+    db.rebuild_bloom_filter().unwrap();
+    assert_eq!("bob", db.get("user:2").unwrap().unwrap());
+    assert!(!db.bloom_filter().unwrap().may_contain(b"user:1"));
+}
+
+#[test]
+fn test_bloom_filter_is_built_on_open() {
+    let temp_dir = tempdir().unwrap();
+
+    let db = DB::new(temp_dir.path()).unwrap();
+    db.set("user:1", "alice", None).unwrap();
+    drop(db);
+
+    let db = DB::with_config(
+        SledBackend::open(temp_dir.path()).unwrap(),
+        DBConfig::new().bloom_filter(BloomConfig::new(1000))
+    )
+    .unwrap();
+    assert_eq!("alice", db.get("user:1").unwrap().unwrap());
+    assert!(db.exists(&"user:1").unwrap());
+}
+
+#[test]
+fn test_bloom_filter_is_built_on_restore() {
+    let temp_dir = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+
+    let db = DB::new(temp_dir.path()).unwrap();
+    db.set("user:1", "alice", None).unwrap();
+    db.backup_to(backup.path()).unwrap();
+    drop(db);
+
+    let file = std::fs::read_dir(backup.path())
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let db = DB::load_from_with_config(
+        &file,
+        MemoryBackend::new(),
+        DBConfig::new().bloom_filter(BloomConfig::new(1000))
+    )
+    .unwrap();
+    assert_eq!("alice", db.get("user:1").unwrap().unwrap());
+    assert!(db.exists(&"user:1").unwrap());
+}
@@ -0,0 +1,171 @@
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering
+};
+use std::sync::{
+    Arc,
+    Barrier
+};
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::cache_aside::{
+    CacheAsideOptions,
+    LoadError
+};
+use epoch_db::db::storage::MemoryBackend;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+#[test]
+fn test_get_or_insert_with_loads_once() {
+    let db = open();
+    let calls = AtomicUsize::new(0);
+
+    for _ in 0..3 {
+        let v = db
+            .get_or_insert_with(&"user:1", Some(Duration::from_secs(60)), || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, ()>("alice")
+            })
+            .unwrap();
+        assert_eq!(b"alice".to_vec(), v);
+    }
+
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+    assert_eq!("alice", db.get("user:1").unwrap().unwrap());
+}
+
+#[test]
+fn test_get_or_insert_with_single_flight() {
+    let db = Arc::new(open());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let barrier = Arc::new(Barrier::new(8));
+    let mut handles = Vec::new();
+
+    for _ in 0..8 {
+        let db = Arc::clone(&db);
+        let calls = Arc::clone(&calls);
+        let barrier = Arc::clone(&barrier);
+        handles.push(thread::spawn(move || {
+            barrier.wait();
+            db.get_or_insert_with(&"report", None, || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(200));
+                Ok::<_, ()>("expensive")
+            })
+            .unwrap()
+        }));
+    }
+
+    for h in handles {
+        assert_eq!(b"expensive".to_vec(), h.join().unwrap());
+    }
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_get_or_insert_with_loader_error() {
+    let db = open();
+
+    let res = db.get_or_insert_with(&"user:1", None, || Err::<&str, _>("backend down"));
+    assert!(matches!(res, Err(LoadError::Loader("backend down"))));
+    assert!(db.get("user:1").unwrap().is_none());
+}
+
+#[test]
+fn test_stale_while_revalidate() {
+    let db = open();
+    let options = CacheAsideOptions::new(Some(Duration::from_millis(300)))
+        .stale_while_revalidate(Duration::from_secs(5));
+
+    db.get_or_load(&"config", &options, || Ok::<_, ()>(Some(b"v1".to_vec())))
+        .unwrap();
+    thread::sleep(Duration::from_millis(400));
+
+    // While one caller reloads, the others get the stale value right away
+    let db = Arc::new(db);
+    let reloading = {
+        let db = Arc::clone(&db);
+        thread::spawn(move || {
+            db.get_or_load(&"config", &options, || {
+                thread::sleep(Duration::from_millis(300));
+                Ok::<_, ()>(Some(b"v2".to_vec()))
+            })
+            .unwrap()
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+
+    let stale = db
+        .get_or_load(&"config", &options, || -> Result<_, ()> {
+            panic!("Only one caller reloads")
+        })
+        .unwrap();
+    assert_eq!(Some(b"v1".to_vec()), stale);
+
+    assert_eq!(Some(b"v2".to_vec()), reloading.join().unwrap());
+    assert_eq!("v2", db.get("config").unwrap().unwrap());
+}
+
+#[test]
+fn test_negative_caching() {
+    let db = open();
+    let options = CacheAsideOptions::new(Some(Duration::from_secs(60)))
+        .negative_ttl(Duration::from_millis(300));
+    let calls = AtomicUsize::new(0);
+    let loader = || {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Ok::<_, ()>(None)
+    };
+
+    assert!(
+        db.get_or_load(&"ghost", &options, loader)
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        db.get_or_load(&"ghost", &options, loader)
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+
+    thread::sleep(Duration::from_millis(400));
+    assert!(
+        db.get_or_load(&"ghost", &options, loader)
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(2, calls.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_cache_aside_without_a_practical_ttl() {
+    let db = open();
+    let options = CacheAsideOptions::new(Some(Duration::MAX))
+        .stale_while_revalidate(Duration::MAX)
+        .negative_ttl(Duration::MAX);
+
+    db.get_or_load(&"config", &options, || Ok::<_, ()>(Some(b"v1".to_vec())))
+        .unwrap();
+    let cached = db
+        .get_or_load(&"config", &options, || -> Result<_, ()> {
+            panic!("The value is still fresh")
+        })
+        .unwrap();
+    assert_eq!(Some(b"v1".to_vec()), cached);
+
+    db.get_or_load(&"ghost", &options, || Ok::<_, ()>(None))
+        .unwrap();
+    assert!(
+        db.get_or_load(&"ghost", &options, || -> Result<_, ()> {
+            panic!("The miss is still cached")
+        })
+        .unwrap()
+        .is_none()
+    );
+}
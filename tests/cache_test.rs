@@ -0,0 +1,84 @@
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::errors::TransactionError;
+use epoch_db::db::storage::MemoryBackend;
+
+fn open(capacity: usize) -> DB {
+    DB::with_config(
+        MemoryBackend::new(),
+        DBConfig::new().cache_capacity(capacity)
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_cache_serves_hot_keys() {
+    let db = open(8);
+    db.set("user:1", "alice", None).unwrap();
+
+    assert_eq!("alice", db.get("user:1").unwrap().unwrap());
+    assert_eq!("alice", db.get("user:1").unwrap().unwrap());
+
+    let cache = db.cache().unwrap();
+    assert_eq!(1, cache.len());
+    assert_eq!(0.5, cache.hit_ratio());
+}
+
+#[test]
+fn test_cache_is_invalidated_by_writes() {
+    let db = open(8);
+    db.set("user:1", "alice", None).unwrap();
+    db.get("user:1").unwrap();
+
+    db.set("user:1", "bob", None).unwrap();
+    assert_eq!("bob", db.get("user:1").unwrap().unwrap());
+
+    db.transaction(|tx| {
+        tx.set("user:1", "carol", None)?;
+        Ok::<(), TransactionError<()>>(())
+    })
+    .unwrap();
+    assert_eq!("carol", db.get("user:1").unwrap().unwrap());
+
+    db.remove("user:1").unwrap();
+    assert!(db.get("user:1").unwrap().is_none());
+    assert!(db.cache().unwrap().is_empty());
+}
+
+#[test]
+fn test_cache_is_invalidated_by_ttl_expiry() {
+    let db = open(8);
+    db.set("session:1", "token", Some(Duration::from_secs(1)))
+        .unwrap();
+    db.get("session:1").unwrap();
+    assert_eq!(1, db.cache().unwrap().len());
+
+    thread::sleep(Duration::from_secs(2));
+
+    assert!(db.get("session:1").unwrap().is_none());
+    assert!(db.cache().unwrap().is_empty());
+}
+
+#[test]
+fn test_cache_admission_prefers_frequent_keys() {
+    let db = open(1);
+    db.set("hot", "1", None).unwrap();
+    db.set("cold", "2", None).unwrap();
+
+    for _ in 0..5 {
+        db.increment_frequency("hot").unwrap();
+    }
+
+    db.get("hot").unwrap();
+    // The cold key is read but never admitted over the hot one
+    db.get("cold").unwrap();
+    db.get("cold").unwrap();
+
+    let before = db.cache().unwrap().hit_ratio();
+    db.get("hot").unwrap();
+    assert!(db.cache().unwrap().hit_ratio() > before);
+    assert_eq!(1, db.cache().unwrap().len());
+}
@@ -0,0 +1,254 @@
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::compression::{
+    Codec,
+    CompressionConfig
+};
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::errors::{
+    TransactionError,
+    TransientError
+};
+use epoch_db::db::index::json_field;
+use epoch_db::db::storage::{
+    MemoryBackend,
+    SledBackend
+};
+use tempfile::tempdir;
+
+/// A run-length encoding, good enough for the repetitive values of the tests.
+#[derive(Debug)]
+struct Rle;
+
+impl Codec for Rle {
+    fn id(&self) -> u8 {
+        200
+    }
+
+    fn name(&self) -> &str {
+        "rle"
+    }
+
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError> {
+        let mut out = Vec::new();
+        for chunk in value.chunk_by(|a, b| a == b) {
+            for part in chunk.chunks(u8::MAX as usize) {
+                out.extend_from_slice(&[part.len() as u8, part[0]]);
+            }
+        }
+        Ok(out)
+    }
+
+    fn decompress(&self, value: &[u8]) -> Result<Vec<u8>, TransientError> {
+        if !value.len().is_multiple_of(2) {
+            return Err(TransientError::DecompressionFailed);
+        }
+        Ok(value
+            .chunks(2)
+            .flat_map(|p| std::iter::repeat_n(p[1], p[0] as usize))
+            .collect())
+    }
+}
+
+/// Opens the database again, sled releases the lock of the previous handle
+/// shortly after it is dropped.
+fn reopen(path: &Path) -> SledBackend {
+    for _ in 0..50 {
+        if let Ok(backend) = SledBackend::open(path) {
+            return backend;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    SledBackend::open(path).unwrap()
+}
+
+fn open(config: DBConfig) -> DB {
+    DB::with_config(MemoryBackend::new(), config).unwrap()
+}
+
+#[test]
+fn test_values_are_compressed_from_the_min_size() {
+    let db = open(DBConfig::new().compression(CompressionConfig::new(Rle).min_size(64)));
+
+    let big = "a".repeat(1000);
+    db.set("big", &big, None).unwrap();
+    db.set("small", "aaaa", None).unwrap();
+    // Compressing doesn't make it smaller, so it is stored as it is
+    let mixed: String = (0..200)
+        .map(|i| char::from(b'a' + (i % 26) as u8))
+        .collect();
+    db.set("mixed", &mixed, None).unwrap();
+
+    assert_eq!(Some(big.clone()), db.get("big").unwrap());
+    assert_eq!(Some("aaaa".to_string()), db.get("small").unwrap());
+    assert_eq!(Some(mixed.clone()), db.get("mixed").unwrap());
+
+    assert!(db.key_info(&"big").unwrap().unwrap().value_len < 100);
+    assert_eq!(4, db.key_info(&"small").unwrap().unwrap().value_len);
+    assert_eq!(200, db.key_info(&"mixed").unwrap().unwrap().value_len);
+
+    let stats = db.compression_stats();
+    assert_eq!(1, stats.compressed);
+    assert_eq!(2, stats.skipped);
+    assert_eq!(1000, stats.raw_bytes);
+    assert!(stats.ratio() > 10.0, "{}", stats.ratio());
+}
+
+#[test]
+fn test_namespace_compression() {
+    let db = open(
+        DBConfig::new()
+            .namespace_compression("html:", CompressionConfig::new(Rle).min_size(0))
+            .namespace_compression("html:raw:", CompressionConfig::new(Rle).min_size(10_000))
+    );
+
+    let value = "x".repeat(500);
+    db.set("html:home", &value, None).unwrap();
+    db.set("html:raw:home", &value, None).unwrap();
+    db.set("json:home", &value, None).unwrap();
+
+    assert!(db.key_info(&"html:home").unwrap().unwrap().value_len < 500);
+    // The longest prefix wins
+    assert_eq!(
+        500,
+        db.key_info(&"html:raw:home").unwrap().unwrap().value_len
+    );
+    assert_eq!(500, db.key_info(&"json:home").unwrap().unwrap().value_len);
+
+    for key in ["html:home", "html:raw:home", "json:home"] {
+        assert_eq!(Some(value.clone()), db.get(key).unwrap());
+    }
+}
+
+#[test]
+fn test_compressed_values_everywhere() {
+    let mut db = open(
+        DBConfig::new()
+            .cache_capacity(8)
+            .compression(CompressionConfig::new(Rle).min_size(0))
+    );
+    db.create_index("tenant", json_field("tenant")).unwrap();
+
+    let doc = format!(r#"{{"tenant":"acme","pad":"{}"}}"#, " ".repeat(300));
+    db.set("doc:1", &doc, None).unwrap();
+    assert!(db.key_info(&"doc:1").unwrap().unwrap().value_len < 200);
+
+    // The extractors, the cache, the transactions and the iterator all see
+    // the value as it was written
+    assert_eq!(
+        vec![b"doc:1".to_vec()],
+        db.query_index("tenant", &"acme").unwrap()
+    );
+    assert_eq!(Some(doc.clone()), db.get("doc:1").unwrap());
+    assert_eq!(Some(doc.clone()), db.get("doc:1").unwrap());
+    let read = db
+        .transaction(|tx| Ok::<_, TransactionError<()>>(tx.get("doc:1")?))
+        .unwrap();
+    assert_eq!(Some(doc.clone()), read);
+    let (key, value, _) = db.iter().next().unwrap().unwrap();
+    assert_eq!(("doc:1", doc.as_str()), (key.as_str(), value.as_str()));
+
+    db.rebuild_index("tenant").unwrap();
+    assert_eq!(1, db.query_index("tenant", &"acme").unwrap().len());
+    db.remove("doc:1").unwrap();
+    assert!(db.query_index("tenant", &"acme").unwrap().is_empty());
+
+    // The collection headers are compressed like any other value
+    db.rpush(&"list", &["a".repeat(100), "b".repeat(100)])
+        .unwrap();
+    assert_eq!(2, db.llen(&"list").unwrap());
+    db.remove("list").unwrap();
+    assert_eq!(0, db.storage_stats().unwrap().members.keys);
+}
+
+#[test]
+fn test_compressed_and_uncompressed_values_coexist() {
+    let temp_dir = tempdir().unwrap();
+    let value = "v".repeat(1000);
+
+    let db = DB::new(temp_dir.path()).unwrap();
+    db.set("before", &value, None).unwrap();
+    drop(db);
+
+    let db = DB::with_config(
+        reopen(temp_dir.path()),
+        DBConfig::new().compression(CompressionConfig::new(Rle))
+    )
+    .unwrap();
+    db.set("after", &value, None).unwrap();
+
+    assert_eq!(1000, db.key_info(&"before").unwrap().unwrap().value_len);
+    assert!(db.key_info(&"after").unwrap().unwrap().value_len < 1000);
+    assert_eq!(Some(value.clone()), db.get("before").unwrap());
+    assert_eq!(Some(value), db.get("after").unwrap());
+}
+
+#[test]
+fn test_values_shaped_like_compressed_ones_round_trip() {
+    // The marker compressed values used to start with, the id of the codec
+    // and a valid run of the codec
+    let value = [&b"\0epoch:compressed\0"[..], &[200], &[4, b'a']].concat();
+
+    let plain = open(DBConfig::new());
+    let compressed = open(DBConfig::new().compression(CompressionConfig::new(Rle).min_size(64)));
+    for db in [plain, compressed] {
+        db.set_raw(&"forged", &value, None).unwrap();
+        assert_eq!(Some(value.clone()), db.get_raw(&"forged").unwrap());
+    }
+}
+
+#[test]
+fn test_custom_codec_has_to_be_configured_to_read() {
+    let temp_dir = tempdir().unwrap();
+    let value = "v".repeat(1000);
+
+    let db = DB::with_config(
+        SledBackend::open(temp_dir.path()).unwrap(),
+        DBConfig::new().namespace_compression("zip:", CompressionConfig::new(Rle))
+    )
+    .unwrap();
+    db.set("zip:1", &value, None).unwrap();
+    db.set("raw:1", &value, None).unwrap();
+    drop(db);
+
+    let db = DB::with_storage(reopen(temp_dir.path())).unwrap();
+    assert_eq!(Some(value), db.get("raw:1").unwrap());
+    assert!(matches!(
+        db.get("zip:1"),
+        Err(TransientError::UnknownCodec {
+            id: 200
+        })
+    ));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd() {
+    use epoch_db::db::compression::Zstd;
+
+    let db = open(DBConfig::new().compression(CompressionConfig::new(Zstd::default())));
+
+    let fragment = "<li class=\"item\">fragment</li>".repeat(100);
+    db.set("fragment", &fragment, None).unwrap();
+
+    assert_eq!(Some(fragment.clone()), db.get("fragment").unwrap());
+    assert!(db.key_info(&"fragment").unwrap().unwrap().value_len < 300);
+    assert!(db.compression_stats().ratio() > 5.0);
+}
+
+#[cfg(feature = "deflate")]
+#[test]
+fn test_deflate() {
+    use epoch_db::db::compression::Deflate;
+
+    let db = open(DBConfig::new().compression(CompressionConfig::new(Deflate::default())));
+
+    let json = r#"{"name":"value","list":[1,2,3]}"#.repeat(100);
+    db.set("json", &json, None).unwrap();
+
+    assert_eq!(Some(json.clone()), db.get("json").unwrap());
+    assert!(db.compression_stats().ratio() > 5.0);
+}
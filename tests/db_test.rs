@@ -1,4 +1,8 @@
 use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering
+};
 use std::thread::{
     self,
     sleep
@@ -9,7 +13,16 @@ use std::time::{
     UNIX_EPOCH
 };
 
-use epoch_db::DB;
+use epoch_db::db::clock::Clock;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::storage::MemoryBackend;
+use epoch_db::{
+    DB,
+    Metadata,
+    ValueFormat
+};
+use serde::Serialize;
 use tempfile::tempdir;
 
 #[test]
@@ -173,3 +186,134 @@ fn test_data_integrity_on_update() {
         "created_at timestamp should not change on a value update."
     );
 }
+
+#[test]
+fn test_metadata_written_before_last_accessed_is_read() {
+    // The layout of the metadata before the last access was tracked
+    #[derive(Serialize)]
+    struct LegacyMetadata {
+        freq: u64,
+        created_at: u64,
+        ttl: Option<u64>
+    }
+
+    let legacy = LegacyMetadata {
+        freq: 3,
+        created_at: 1_000,
+        ttl: Some(2_000)
+    };
+    let bytes = bincode::serde::encode_to_vec(&legacy, bincode::config::standard()).unwrap();
+
+    let meta = Metadata::from_u8(&bytes).unwrap();
+    assert_eq!(3, meta.freq);
+    assert_eq!(1_000, meta.created_at);
+    assert_eq!(Some(2_000), meta.ttl);
+    assert_eq!(1_000, meta.last_accessed);
+
+    let meta = meta.accessed(1_500);
+    assert_eq!(meta, Metadata::from_u8(&meta.to_u8().unwrap()).unwrap());
+}
+
+#[test]
+fn test_metadata_written_before_the_format_is_read() {
+    // The layout of the metadata before the format of the value was kept
+    #[derive(Serialize)]
+    struct AccessedMetadata {
+        freq: u64,
+        created_at: u64,
+        ttl: Option<u64>,
+        last_accessed: u64
+    }
+
+    let accessed = AccessedMetadata {
+        freq: 3,
+        created_at: 1_000,
+        ttl: None,
+        last_accessed: 1_500
+    };
+    let bytes = bincode::serde::encode_to_vec(&accessed, bincode::config::standard()).unwrap();
+
+    let meta = Metadata::from_u8(&bytes).unwrap();
+    assert_eq!(3, meta.freq);
+    assert_eq!(None, meta.ttl);
+    assert_eq!(1_500, meta.last_accessed);
+    assert_eq!(ValueFormat::default(), meta.format);
+}
+
+#[test]
+fn test_cloned_handles_share_the_database() {
+    let temp_dir = tempdir().unwrap();
+    let db = DB::new(temp_dir.path()).unwrap();
+
+    let mut handles = vec![];
+    for t in 0..4 {
+        let db = db.clone();
+        handles.push(thread::spawn(move || {
+            db.set(&format!("key:{t}"), "value", None).unwrap();
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(4, db.get_db_size());
+
+    // The workers keep running while a handle is alive
+    let other = db.clone();
+    drop(db);
+    other
+        .set("short", "lived", Some(Duration::from_secs(1)))
+        .unwrap();
+    sleep(Duration::from_millis(2500));
+    assert!(other.get("short").unwrap().is_none());
+}
+
+#[test]
+fn test_close_stops_the_workers_of_every_handle() {
+    let temp_dir = tempdir().unwrap();
+    let db = DB::new(temp_dir.path()).unwrap();
+    let other = db.clone();
+
+    db.close().unwrap();
+    // Closing twice is fine
+    other.close().unwrap();
+
+    // The handles stay usable, but the expired keys wait for remove_expired
+    other
+        .set("short", "lived", Some(Duration::from_secs(1)))
+        .unwrap();
+    sleep(Duration::from_millis(2500));
+    assert!(db.get("short").unwrap().is_some());
+    assert_eq!(1, db.remove_expired().unwrap());
+    assert!(db.get("short").unwrap().is_none());
+}
+
+/// A clock which panics once it is broken, to make the TTL thread panic.
+#[derive(Debug, Clone, Default)]
+struct BreakingClock {
+    broken: Arc<AtomicBool>
+}
+
+impl Clock for BreakingClock {
+    fn now(&self) -> Duration {
+        if self.broken.load(Ordering::SeqCst) {
+            panic!("clock is broken");
+        }
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+    }
+}
+
+#[test]
+fn test_close_reports_a_panicked_worker() {
+    let clock = BreakingClock::default();
+    let db = DB::with_config(MemoryBackend::new(), DBConfig::new().clock(clock.clone())).unwrap();
+
+    // The TTL thread only reads the clock once a key has a TTL
+    db.set("short", "lived", Some(Duration::from_secs(60)))
+        .unwrap();
+    clock.broken.store(true, Ordering::SeqCst);
+    sleep(Duration::from_millis(500));
+
+    assert!(matches!(db.close(), Err(TransientError::WorkerPanicked)));
+    // The worker was joined already, dropping the last handle doesn't panic
+    drop(db);
+}
@@ -0,0 +1,289 @@
+use std::ops::Bound;
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicBool,
+    AtomicUsize,
+    Ordering
+};
+use std::thread;
+use std::time::{
+    Duration,
+    Instant
+};
+
+use epoch_db::DB;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::durability::Durability;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::storage::{
+    MemoryBackend,
+    SledBackend,
+    StorageBackend,
+    StorageIter,
+    StorageTransaction,
+    StorageTxError,
+    TreeKind
+};
+use tempfile::tempdir;
+
+/// What the background threads did to a `FailingBackend`.
+#[derive(Debug, Default)]
+struct Probe {
+    /// Fails the flushes and the size walks while set
+    broken: AtomicBool,
+    flushes: AtomicUsize,
+    size_reads: AtomicUsize
+}
+
+impl Probe {
+    fn fail_if_broken(&self) -> Result<(), TransientError> {
+        match self.broken.load(Ordering::SeqCst) {
+            true => {
+                Err(TransientError::IOError {
+                    error: std::io::ErrorKind::NotFound.into()
+                })
+            },
+            false => Ok(())
+        }
+    }
+}
+
+/// A `MemoryBackend` with a path, so the database runs every background
+/// thread over it, whose flushes and size walks can fail.
+#[derive(Debug)]
+struct FailingBackend {
+    inner: MemoryBackend,
+    path: PathBuf,
+    probe: Arc<Probe>
+}
+
+impl FailingBackend {
+    fn new(path: &Path) -> (FailingBackend, Arc<Probe>) {
+        let probe = Arc::new(Probe::default());
+        let backend = FailingBackend {
+            inner: MemoryBackend::new(),
+            path: path.to_path_buf(),
+            probe: Arc::clone(&probe)
+        };
+        (backend, probe)
+    }
+}
+
+impl StorageBackend for FailingBackend {
+    fn get(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        self.inner.get(tree, key)
+    }
+
+    fn insert(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        value: &[u8]
+    ) -> Result<Option<Vec<u8>>, TransientError> {
+        self.inner.insert(tree, key, value)
+    }
+
+    fn remove(&self, tree: TreeKind, key: &[u8]) -> Result<Option<Vec<u8>>, TransientError> {
+        self.inner.remove(tree, key)
+    }
+
+    fn compare_and_swap(
+        &self,
+        tree: TreeKind,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>
+    ) -> Result<bool, TransientError> {
+        self.inner.compare_and_swap(tree, key, old, new)
+    }
+
+    fn range(&self, tree: TreeKind, start: Bound<&[u8]>, end: Bound<&[u8]>) -> StorageIter {
+        self.inner.range(tree, start, end)
+    }
+
+    fn len(&self, tree: TreeKind) -> usize {
+        self.inner.len(tree)
+    }
+
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn StorageTransaction) -> Result<(), StorageTxError>
+    ) -> Result<(), StorageTxError> {
+        self.inner.transaction(f)
+    }
+
+    fn flush(&self) -> Result<(), TransientError> {
+        self.probe.flushes.fetch_add(1, Ordering::SeqCst);
+        self.probe.fail_if_broken()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn disk_size(&self) -> Result<u64, TransientError> {
+        self.probe.size_reads.fetch_add(1, Ordering::SeqCst);
+        self.probe.fail_if_broken()?;
+        Ok(0)
+    }
+}
+
+fn open(path: &std::path::Path, durability: Durability) -> DB {
+    DB::with_config(
+        SledBackend::open(path).unwrap(),
+        DBConfig::new().durability(durability)
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_sync_every_write_survives_reopen() {
+    let temp_dir = tempdir().unwrap();
+
+    let db = open(temp_dir.path(), Durability::SyncEveryWrite);
+    db.set("billing:1", "100", None).unwrap();
+    db.set("billing:2", "200", None).unwrap();
+    db.remove("billing:2").unwrap();
+    drop(db);
+
+    let db = DB::new(temp_dir.path()).unwrap();
+    assert_eq!("100", db.get("billing:1").unwrap().unwrap());
+    assert!(db.get("billing:2").unwrap().is_none());
+}
+
+#[test]
+fn test_group_commit_waits_for_flush() {
+    let temp_dir = tempdir().unwrap();
+    let window = Duration::from_millis(200);
+
+    let db = open(temp_dir.path(), Durability::GroupCommit(window));
+
+    let start = Instant::now();
+    db.set("user:1", "Alice", None).unwrap();
+    assert!(
+        start.elapsed() < window * 3,
+        "A write should wait at most a few group commit windows."
+    );
+    assert_eq!("Alice", db.get("user:1").unwrap().unwrap());
+}
+
+#[test]
+fn test_group_commit_concurrent_writers() {
+    let temp_dir = tempdir().unwrap();
+    let db = Arc::new(open(
+        temp_dir.path(),
+        Durability::GroupCommit(Duration::from_millis(10))
+    ));
+
+    let mut handles = vec![];
+    for t in 0..8 {
+        let db_clone = Arc::clone(&db);
+        handles.push(thread::spawn(move || {
+            for i in 0..20 {
+                db_clone
+                    .set(&format!("key:{t}:{i}"), "value", None)
+                    .unwrap();
+            }
+        }));
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(160, db.get_db_size());
+}
+
+#[test]
+fn test_periodic_and_set_durable() {
+    let temp_dir = tempdir().unwrap();
+
+    let db = open(
+        temp_dir.path(),
+        Durability::Periodic(Duration::from_secs(60))
+    );
+    db.set("cache:1", "fragment", None).unwrap();
+    db.set_durable("billing:1", "100", None).unwrap();
+
+    // Dropping shouldnt wait for the whole flush interval
+    let start = Instant::now();
+    drop(db);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let db = DB::new(temp_dir.path()).unwrap();
+    assert_eq!("fragment", db.get("cache:1").unwrap().unwrap());
+    assert_eq!("100", db.get("billing:1").unwrap().unwrap());
+}
+
+#[test]
+fn test_group_commit_after_close_flushes_on_write() {
+    let temp_dir = tempdir().unwrap();
+    let db = open(
+        temp_dir.path(),
+        Durability::GroupCommit(Duration::from_secs(3600))
+    );
+
+    db.close().unwrap();
+
+    // There is no flush thread anymore, the write flushes itself instead of
+    // waiting for a group commit which never comes
+    let start = Instant::now();
+    db.set("user:1", "Alice", None).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!("Alice", db.get("user:1").unwrap().unwrap());
+}
+
+#[test]
+fn test_size_thread_survives_a_failed_walk() {
+    let temp_dir = tempdir().unwrap();
+    let (backend, probe) = FailingBackend::new(temp_dir.path());
+    probe.broken.store(true, Ordering::SeqCst);
+
+    let db = DB::with_storage(backend).unwrap();
+    thread::sleep(Duration::from_millis(350));
+    probe.broken.store(false, Ordering::SeqCst);
+    let failed = probe.size_reads.load(Ordering::SeqCst);
+    assert!(failed > 0);
+
+    // The size is read again on the next ticks
+    thread::sleep(Duration::from_millis(350));
+    assert!(probe.size_reads.load(Ordering::SeqCst) > failed);
+    db.close().unwrap();
+}
+
+#[test]
+fn test_periodic_flush_survives_a_failed_flush() {
+    let temp_dir = tempdir().unwrap();
+    let (backend, probe) = FailingBackend::new(temp_dir.path());
+    probe.broken.store(true, Ordering::SeqCst);
+
+    let db = DB::with_config(
+        backend,
+        DBConfig::new().durability(Durability::Periodic(Duration::from_millis(100)))
+    )
+    .unwrap();
+    thread::sleep(Duration::from_millis(350));
+    let failed = probe.flushes.load(Ordering::SeqCst);
+    assert!(failed > 0);
+
+    // The flushes go on, and the last one failing is reported by close
+    thread::sleep(Duration::from_millis(350));
+    assert!(probe.flushes.load(Ordering::SeqCst) > failed);
+    assert!(matches!(db.close(), Err(TransientError::IOError { .. })));
+
+    // Once the flushes work again, closing succeeds
+    let (backend, probe) = FailingBackend::new(temp_dir.path());
+    let db = DB::with_config(
+        backend,
+        DBConfig::new().durability(Durability::Periodic(Duration::from_millis(100)))
+    )
+    .unwrap();
+    probe.broken.store(true, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(250));
+    probe.broken.store(false, Ordering::SeqCst);
+    db.close().unwrap();
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{
+    AtomicU32,
+    Ordering
+};
+use std::sync::{
+    Arc,
+    Mutex
+};
+use std::thread::sleep;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::encryption::{
+    EncryptionKey,
+    KeyId,
+    KeyProvider,
+    StaticKeys
+};
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::index::json_field;
+use epoch_db::db::storage::{
+    MemoryBackend,
+    SledBackend
+};
+use tempfile::tempdir;
+
+/// A provider whose keys can be rotated and revoked while the database is
+/// open, its clones share the keys.
+#[derive(Debug, Clone, Default)]
+struct RotatingKeys {
+    current: Arc<AtomicU32>,
+    keys: Arc<Mutex<HashMap<KeyId, EncryptionKey>>>
+}
+
+impl RotatingKeys {
+    fn rotate(&self, id: KeyId, key: EncryptionKey) {
+        self.keys.lock().unwrap().insert(id, key);
+        self.current.store(id, Ordering::SeqCst);
+    }
+
+    fn revoke(&self, id: KeyId) {
+        self.keys.lock().unwrap().remove(&id);
+    }
+}
+
+impl KeyProvider for RotatingKeys {
+    fn current_key_id(&self) -> KeyId {
+        self.current.load(Ordering::SeqCst)
+    }
+
+    fn key(&self, id: KeyId) -> Result<EncryptionKey, TransientError> {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(TransientError::KeyNotFound {
+                id
+            })
+    }
+}
+
+/// Opens the database again, sled releases the lock of the previous handle
+/// shortly after it is dropped.
+fn reopen(path: &Path) -> SledBackend {
+    for _ in 0..50 {
+        if let Ok(backend) = SledBackend::open(path) {
+            return backend;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    SledBackend::open(path).unwrap()
+}
+
+/// Returns true if the bytes appear in any file of the directory.
+fn appears_in(dir: &Path, needle: &[u8]) -> bool {
+    fs::read_dir(dir).unwrap().any(|entry| {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            return appears_in(&path, needle);
+        }
+        fs::read(&path)
+            .unwrap()
+            .windows(needle.len())
+            .any(|w| w == needle)
+    })
+}
+
+#[test]
+fn test_values_are_encrypted_at_rest() {
+    let temp_dir = tempdir().unwrap();
+    let keys = StaticKeys::new(1, EncryptionKey::generate().unwrap());
+
+    let db = DB::with_config(
+        SledBackend::open(temp_dir.path()).unwrap(),
+        DBConfig::new().encryption(keys)
+    )
+    .unwrap();
+    db.set("session:1", "token-5f2a9c1e7d", None).unwrap();
+    db.set("session:2", "token-5f2a9c1e7d", None).unwrap();
+    db.flush().unwrap();
+
+    assert_eq!(
+        Some("token-5f2a9c1e7d".to_string()),
+        db.get("session:1").unwrap()
+    );
+    assert!(!appears_in(temp_dir.path(), b"token-5f2a9c1e7d"));
+    drop(db);
+
+    // Without the key the values cannot be read
+    let db = DB::with_storage(reopen(temp_dir.path())).unwrap();
+    assert!(matches!(
+        db.get("session:1"),
+        Err(TransientError::KeyNotFound {
+            id: 1
+        })
+    ));
+}
+
+#[test]
+fn test_key_rotation() {
+    let keys = RotatingKeys::default();
+    keys.rotate(1, EncryptionKey::generate().unwrap());
+
+    let db = DB::with_config(
+        MemoryBackend::new(),
+        DBConfig::new().encryption(keys.clone())
+    )
+    .unwrap();
+    db.create_index("owner", json_field("owner")).unwrap();
+
+    db.set("doc:old", r#"{"owner":"alice"}"#, None).unwrap();
+    keys.rotate(2, EncryptionKey::generate().unwrap());
+    db.set("doc:new", r#"{"owner":"alice"}"#, None).unwrap();
+
+    // Both keys decrypt, and the indexes see the plain values
+    assert!(db.get("doc:old").unwrap().is_some());
+    assert!(db.get("doc:new").unwrap().is_some());
+    assert_eq!(2, db.query_index("owner", &"alice").unwrap().len());
+
+    // Writing the value again encrypts it with the current key, after which
+    // the previous key can be retired
+    db.set("doc:old", r#"{"owner":"bob"}"#, None).unwrap();
+    keys.revoke(1);
+    assert_eq!(
+        Some(r#"{"owner":"bob"}"#.to_string()),
+        db.get("doc:old").unwrap()
+    );
+    assert_eq!(
+        vec![b"doc:new".to_vec()],
+        db.query_index("owner", &"alice").unwrap()
+    );
+
+    keys.rotate(3, EncryptionKey::generate().unwrap());
+    keys.revoke(2);
+    assert!(matches!(
+        db.get("doc:new"),
+        Err(TransientError::KeyNotFound {
+            id: 2
+        })
+    ));
+}
+
+#[test]
+fn test_undecryptable_values_can_still_be_written_and_expire() {
+    let keys = RotatingKeys::default();
+    keys.rotate(1, EncryptionKey::generate().unwrap());
+
+    let db = DB::with_config(
+        MemoryBackend::new(),
+        DBConfig::new().encryption(keys.clone())
+    )
+    .unwrap();
+    db.create_index("owner", json_field("owner")).unwrap();
+
+    db.set("doc:1", r#"{"owner":"alice"}"#, None).unwrap();
+    db.set("doc:2", r#"{"owner":"alice"}"#, None).unwrap();
+    db.set(
+        "doc:3",
+        r#"{"owner":"alice"}"#,
+        Some(Duration::from_secs(1))
+    )
+    .unwrap();
+    keys.rotate(2, EncryptionKey::generate().unwrap());
+    keys.revoke(1);
+
+    // The old values can't be decrypted anymore, which doesn't prevent
+    // replacing or removing them
+    db.set("doc:1", r#"{"owner":"bob"}"#, None).unwrap();
+    db.remove("doc:2").unwrap();
+    assert_eq!(
+        vec![b"doc:1".to_vec()],
+        db.query_index("owner", &"bob").unwrap()
+    );
+
+    // Nor expiring them, and the TTL thread keeps running
+    sleep(Duration::from_millis(2100));
+    assert!(!db.exists(&"doc:3").unwrap());
+    db.set("doc:4", "short", Some(Duration::from_secs(1)))
+        .unwrap();
+    sleep(Duration::from_millis(2100));
+    assert!(!db.exists(&"doc:4").unwrap());
+
+    // The entries left behind by the undecryptable values go on a rebuild
+    db.rebuild_index("owner").unwrap();
+    assert!(db.query_index("owner", &"alice").unwrap().is_empty());
+}
+
+#[test]
+fn test_wrong_key_fails_to_decrypt() {
+    let keys = RotatingKeys::default();
+    keys.rotate(1, EncryptionKey::generate().unwrap());
+
+    let db = DB::with_config(
+        MemoryBackend::new(),
+        DBConfig::new().encryption(keys.clone())
+    )
+    .unwrap();
+    db.set("user:1", "alice@example.com", None).unwrap();
+
+    // Another key under the same id
+    keys.rotate(1, EncryptionKey::generate().unwrap());
+    assert!(matches!(
+        db.get("user:1"),
+        Err(TransientError::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn test_collections_with_encryption() {
+    let keys = StaticKeys::new(1, EncryptionKey::generate().unwrap());
+    let db = DB::with_config(MemoryBackend::new(), DBConfig::new().encryption(keys)).unwrap();
+
+    db.rpush(&"list", &["a", "b"]).unwrap();
+    db.hset(&"hash", &[("field", "value")]).unwrap();
+
+    assert_eq!(2, db.llen(&"list").unwrap());
+    assert_eq!(Some(b"value".to_vec()), db.hget(&"hash", &"field").unwrap());
+    db.remove("list").unwrap();
+    assert_eq!(1, db.storage_stats().unwrap().members.keys);
+}
+
+#[test]
+fn test_members_and_index_terms_are_encrypted_at_rest() {
+    let temp_dir = tempdir().unwrap();
+    let keys = StaticKeys::new(1, EncryptionKey::generate().unwrap());
+
+    let db = DB::with_config(
+        SledBackend::open(temp_dir.path()).unwrap(),
+        DBConfig::new().encryption(keys)
+    )
+    .unwrap();
+    db.create_index("tenant", json_field("tenant")).unwrap();
+    db.set("doc:1", r#"{"tenant":"tenant-8c1f3e"}"#, None)
+        .unwrap();
+    db.rpush(&"list", &["member-2d7b90"]).unwrap();
+    db.hset(&"hash", &[("field", "member-e41a6c")]).unwrap();
+    db.flush().unwrap();
+
+    assert_eq!(
+        vec![b"doc:1".to_vec()],
+        db.query_index("tenant", &"tenant-8c1f3e").unwrap()
+    );
+    assert_eq!(
+        vec![b"member-2d7b90".to_vec()],
+        db.lrange(&"list", 0, -1).unwrap()
+    );
+    assert_eq!(
+        Some(b"member-e41a6c".to_vec()),
+        db.hget(&"hash", &"field").unwrap()
+    );
+    assert!(!appears_in(temp_dir.path(), b"tenant-8c1f3e"));
+    assert!(!appears_in(temp_dir.path(), b"member-2d7b90"));
+    assert!(!appears_in(temp_dir.path(), b"member-e41a6c"));
+}
+
+#[test]
+fn test_unencrypted_values_are_rejected_until_migrated() {
+    let temp_dir = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let keys = StaticKeys::new(1, EncryptionKey::generate().unwrap());
+
+    let db = DB::new(temp_dir.path()).unwrap();
+    db.set("user:1", "alice@example.com", None).unwrap();
+    db.hset(&"hash", &[("field", "value")]).unwrap();
+    db.backup_to(backup.path()).unwrap();
+    drop(db);
+
+    // Enabling the encryption doesn't make the plain values trusted
+    let db = DB::with_config(
+        reopen(temp_dir.path()),
+        DBConfig::new().encryption(keys.clone())
+    )
+    .unwrap();
+    assert!(matches!(
+        db.get("user:1"),
+        Err(TransientError::NotEncrypted)
+    ));
+    assert!(matches!(
+        db.hget(&"hash", &"field"),
+        Err(TransientError::NotEncrypted)
+    ));
+    drop(db);
+
+    // Loading the backup with the encryption configured encrypts everything
+    let file = fs::read_dir(backup.path())
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let migrated = tempdir().unwrap();
+    let db = DB::load_from_with_config(
+        &file,
+        SledBackend::open(migrated.path()).unwrap(),
+        DBConfig::new().encryption(keys)
+    )
+    .unwrap();
+    db.flush().unwrap();
+    assert_eq!(
+        Some("alice@example.com".to_string()),
+        db.get("user:1").unwrap()
+    );
+    assert_eq!(Some(b"value".to_vec()), db.hget(&"hash", &"field").unwrap());
+    assert_eq!(
+        Some(1),
+        db.get_metadata("user:1").unwrap().unwrap().format.key_id
+    );
+    assert!(!appears_in(migrated.path(), b"alice@example.com"));
+}
+
+#[test]
+fn test_values_shaped_like_encrypted_ones_round_trip() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    // What an encrypted value used to start with
+    let value = [&b"\0epoch:encrypted\0"[..], &[0, 0, 0, 1], &[7; 40]].concat();
+    db.set_raw(&"forged", &value, None).unwrap();
+
+    assert_eq!(Some(value), db.get_raw(&"forged").unwrap());
+}
+
+#[test]
+fn test_encrypted_backup() {
+    let temp_dir = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+    let keys = StaticKeys::new(7, EncryptionKey::generate().unwrap());
+
+    let db = DB::with_config(
+        SledBackend::open(temp_dir.path()).unwrap(),
+        DBConfig::new().encryption(keys.clone())
+    )
+    .unwrap();
+    db.set("user:alice", "alice@example.com", None).unwrap();
+    db.rpush(&"list", &["member"]).unwrap();
+    db.backup_to(backup.path()).unwrap();
+    drop(db);
+
+    let files: Vec<_> = fs::read_dir(backup.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(1, files.len(), "the clear archive is removed");
+    let file = &files[0];
+    assert!(!appears_in(backup.path(), b"user:alice"));
+    assert!(!appears_in(backup.path(), b"data.epoch"));
+
+    // Restoring needs the key
+    let restored = tempdir().unwrap();
+    assert!(matches!(
+        DB::load_from(file, restored.path()),
+        Err(TransientError::KeyNotFound {
+            id: 7
+        })
+    ));
+
+    let db = DB::load_from_with_config(
+        file,
+        MemoryBackend::new(),
+        DBConfig::new().encryption(keys.clone())
+    )
+    .unwrap();
+    assert_eq!(
+        Some("alice@example.com".to_string()),
+        db.get("user:alice").unwrap()
+    );
+    assert_eq!(vec![b"member".to_vec()], db.lrange(&"list", 0, -1).unwrap());
+
+    // A truncated backup is detected
+    let bytes = fs::read(file).unwrap();
+    let truncated = backup.path().join("truncated.zip");
+    fs::write(&truncated, &bytes[..bytes.len() - 10]).unwrap();
+    assert!(matches!(
+        DB::load_from_with_config(
+            &truncated,
+            MemoryBackend::new(),
+            DBConfig::new().encryption(keys)
+        ),
+        Err(TransientError::DecryptionFailed)
+    ));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_compression_with_encryption() {
+    use epoch_db::db::compression::{
+        CompressionConfig,
+        Zstd
+    };
+
+    let keys = StaticKeys::new(1, EncryptionKey::generate().unwrap());
+    let db = DB::with_config(
+        MemoryBackend::new(),
+        DBConfig::new()
+            .compression(CompressionConfig::new(Zstd::default()))
+            .encryption(keys)
+    )
+    .unwrap();
+
+    let fragment = "<div class=\"card\">profile</div>".repeat(100);
+    db.set("fragment:1", &fragment, None).unwrap();
+
+    assert_eq!(Some(fragment.clone()), db.get("fragment:1").unwrap());
+    // Compressed before being encrypted, the ciphertext would not compress
+    assert!(db.key_info(&"fragment:1").unwrap().unwrap().value_len < 300);
+}
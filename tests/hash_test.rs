@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::storage::MemoryBackend;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+#[test]
+fn test_hash_set_get_del() {
+    let db = open();
+
+    assert_eq!(
+        2,
+        db.hset(&"user:1", &[("name", "alice"), ("city", "paris")])
+            .unwrap()
+    );
+    // Overwriting a field doesn't add it again
+    assert_eq!(
+        1,
+        db.hset(&"user:1", &[("city", "lyon"), ("age", "30")])
+            .unwrap()
+    );
+
+    assert_eq!(
+        b"lyon".to_vec(),
+        db.hget(&"user:1", &"city").unwrap().unwrap()
+    );
+    assert!(db.hget(&"user:1", &"email").unwrap().is_none());
+    assert_eq!(3, db.hlen(&"user:1").unwrap());
+
+    let all = db.hgetall(&"user:1").unwrap();
+    assert_eq!(
+        vec![
+            (b"age".to_vec(), b"30".to_vec()),
+            (b"city".to_vec(), b"lyon".to_vec()),
+            (b"name".to_vec(), b"alice".to_vec())
+        ],
+        all
+    );
+
+    assert_eq!(2, db.hdel(&"user:1", &["age", "city", "email"]).unwrap());
+    assert_eq!(1, db.hlen(&"user:1").unwrap());
+
+    // The hash goes away with its last field
+    assert_eq!(1, db.hdel(&"user:1", &["name"]).unwrap());
+    assert!(!db.exists(&"user:1").unwrap());
+    assert!(db.hgetall(&"user:1").unwrap().is_empty());
+}
+
+#[test]
+fn test_hash_incrby() {
+    let db = open();
+
+    assert_eq!(5, db.hincrby(&"stats", &"visits", 5).unwrap());
+    assert_eq!(3, db.hincrby(&"stats", &"visits", -2).unwrap());
+    assert_eq!(1, db.hlen(&"stats").unwrap());
+
+    db.hset(&"stats", &[("name", "home")]).unwrap();
+    assert!(matches!(
+        db.hincrby(&"stats", &"name", 1),
+        Err(TransientError::NotAnInteger)
+    ));
+
+    db.hset(&"stats", &[("max", i64::MAX.to_string())]).unwrap();
+    assert!(matches!(
+        db.hincrby(&"stats", &"max", 1),
+        Err(TransientError::NotAnInteger)
+    ));
+}
+
+#[test]
+fn test_hash_shares_metadata() {
+    let db = open();
+    db.hset(&"session", &[("a", "1"), ("b", "2")]).unwrap();
+    assert!(db.expire(&"session", Duration::from_secs(60)).unwrap());
+
+    // Writing fields keeps the TTL of the whole hash
+    db.hset(&"session", &[("c", "3")]).unwrap();
+    db.hincrby(&"session", &"d", 1).unwrap();
+    assert!(db.ttl(&"session").unwrap().is_some());
+
+    db.increment_frequency("session").unwrap();
+    assert_eq!(1, db.get_metadata("session").unwrap().unwrap().freq);
+}
+
+#[test]
+fn test_hash_wrong_type() {
+    let db = open();
+    db.rpush(&"list", &["a"]).unwrap();
+
+    assert!(matches!(
+        db.hset(&"list", &[("f", "v")]),
+        Err(TransientError::WrongType)
+    ));
+    assert!(matches!(
+        db.hget(&"list", &"f"),
+        Err(TransientError::WrongType)
+    ));
+    assert!(matches!(db.llen(&"list"), Ok(1)));
+
+    db.hset(&"hash", &[("f", "v")]).unwrap();
+    assert!(matches!(db.lpop(&"hash"), Err(TransientError::WrongType)));
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::index::json_field;
+use epoch_db::db::storage::MemoryBackend;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+fn keys(keys: Vec<Vec<u8>>) -> Vec<String> {
+    keys.into_iter()
+        .map(|k| String::from_utf8(k).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_index_follows_writes() {
+    let db = open();
+    db.create_index("tenant", json_field("tenant_id")).unwrap();
+
+    db.set("doc:1", r#"{"tenant_id": "acme"}"#, None).unwrap();
+    db.set("doc:2", r#"{"tenant_id": "acme"}"#, None).unwrap();
+    db.set("doc:3", r#"{"tenant_id": "globex"}"#, None).unwrap();
+    db.set("plain", "not json", None).unwrap();
+    assert_eq!(
+        vec!["doc:1", "doc:2"],
+        keys(db.query_index("tenant", &"acme").unwrap())
+    );
+
+    // Moving a document to another tenant moves its entry
+    db.set("doc:2", r#"{"tenant_id": "globex"}"#, None).unwrap();
+    assert_eq!(
+        vec!["doc:1"],
+        keys(db.query_index("tenant", &"acme").unwrap())
+    );
+    assert_eq!(
+        vec!["doc:2", "doc:3"],
+        keys(db.query_index("tenant", &"globex").unwrap())
+    );
+
+    db.remove("doc:3").unwrap();
+    db.transaction(|tx| {
+        tx.remove("doc:1")?;
+        tx.set("doc:4", r#"{"tenant_id": "acme"}"#, None)?;
+        Ok::<_, epoch_db::db::errors::TransactionError<()>>(())
+    })
+    .unwrap();
+    assert_eq!(
+        vec!["doc:4"],
+        keys(db.query_index("tenant", &"acme").unwrap())
+    );
+    assert_eq!(
+        vec!["doc:2"],
+        keys(db.query_index("tenant", &"globex").unwrap())
+    );
+    assert!(db.query_index("tenant", &"initech").unwrap().is_empty());
+}
+
+#[test]
+fn test_index_built_from_existing_data() {
+    let db = open();
+    db.set("a", r#"{"owner": {"tags": ["red", 7, true]}}"#, None)
+        .unwrap();
+    db.set("b", r#"{"owner": {"tags": "red"}}"#, None).unwrap();
+    db.rpush(&"list", &[r#"{"owner": {"tags": "red"}}"#])
+        .unwrap();
+
+    db.create_index("tags", json_field("owner.tags")).unwrap();
+    assert_eq!(
+        vec!["a", "b"],
+        keys(db.query_index("tags", &"red").unwrap())
+    );
+    assert_eq!(vec!["a"], keys(db.query_index("tags", &"7").unwrap()));
+    assert_eq!(vec!["a"], keys(db.query_index("tags", &"true").unwrap()));
+
+    // Replacing the index drops the entries the new extractor doesn't return
+    db.create_index("tags", |v: &[u8]| vec![v[..1].to_vec()])
+        .unwrap();
+    assert!(db.query_index("tags", &"red").unwrap().is_empty());
+    assert_eq!(vec!["a", "b"], keys(db.query_index("tags", &"{").unwrap()));
+
+    assert!(db.drop_index("tags").unwrap());
+    assert!(!db.drop_index("tags").unwrap());
+    assert!(matches!(
+        db.query_index("tags", &"{"),
+        Err(TransientError::IndexNotFound)
+    ));
+    assert!(matches!(
+        db.rebuild_index("tags"),
+        Err(TransientError::IndexNotFound)
+    ));
+}
+
+#[test]
+fn test_index_entries_expire() {
+    let db = open();
+    db.create_index("tenant", json_field("tenant_id")).unwrap();
+
+    db.set(
+        "session",
+        r#"{"tenant_id": "acme"}"#,
+        Some(Duration::from_secs(1))
+    )
+    .unwrap();
+    assert_eq!(
+        vec!["session"],
+        keys(db.query_index("tenant", &"acme").unwrap())
+    );
+
+    thread::sleep(Duration::from_millis(2100));
+    assert!(db.query_index("tenant", &"acme").unwrap().is_empty());
+}
+
+#[test]
+fn test_index_survives_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+
+    {
+        let db = DB::new(dir.path()).unwrap();
+        db.create_index("tenant", json_field("tenant_id")).unwrap();
+        db.set("doc:1", r#"{"tenant_id": "acme"}"#, None).unwrap();
+        db.set("doc:2", r#"{"tenant_id": "acme"}"#, None).unwrap();
+    }
+
+    // Written while the index was not created
+    let db = DB::new(dir.path()).unwrap();
+    db.set("doc:2", r#"{"tenant_id": "globex"}"#, None).unwrap();
+
+    db.create_index("tenant", json_field("tenant_id")).unwrap();
+    assert_eq!(
+        vec!["doc:1"],
+        keys(db.query_index("tenant", &"acme").unwrap())
+    );
+    assert_eq!(
+        vec!["doc:2"],
+        keys(db.query_index("tenant", &"globex").unwrap())
+    );
+}
+
+#[test]
+fn test_drop_index_removes_every_entry() {
+    let db = Arc::new(open());
+    db.create_index("tenant", json_field("tenant_id")).unwrap();
+    for i in 0..50 {
+        db.set(&format!("doc:{i}"), r#"{"tenant_id": "acme"}"#, None)
+            .unwrap();
+    }
+
+    let writer = {
+        let db = Arc::clone(&db);
+        thread::spawn(move || {
+            for i in 0..200 {
+                db.set(
+                    &format!("doc:{}", i % 50),
+                    r#"{"tenant_id": "initech"}"#,
+                    None
+                )
+                .unwrap();
+            }
+        })
+    };
+    assert!(db.drop_index("tenant").unwrap());
+    writer.join().unwrap();
+
+    // No write added an entry back once the index was dropped
+    assert_eq!(0, db.storage_stats().unwrap().index.keys);
+}
@@ -0,0 +1,111 @@
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::clock::MockClock;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::storage::MemoryBackend;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+fn open_with_clock() -> (DB, MockClock) {
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let db = DB::with_config(MemoryBackend::new(), DBConfig::new().clock(clock.clone())).unwrap();
+
+    (db, clock)
+}
+
+#[test]
+fn test_lease_mutual_exclusion() {
+    let db = open();
+    let ttl = Duration::from_secs(10);
+
+    let lease = db.acquire_lease("jobs", "worker-1", ttl).unwrap().unwrap();
+    assert_eq!("worker-1", lease.owner);
+    assert!(db.acquire_lease("jobs", "worker-2", ttl).unwrap().is_none());
+
+    // Re-acquiring keeps the token
+    let again = db.acquire_lease("jobs", "worker-1", ttl).unwrap().unwrap();
+    assert_eq!(lease.token, again.token);
+
+    assert!(!db.release_lease("jobs", "worker-2").unwrap());
+    assert!(db.release_lease("jobs", "worker-1").unwrap());
+    assert!(db.get_lease("jobs").unwrap().is_none());
+
+    let next = db.acquire_lease("jobs", "worker-2", ttl).unwrap().unwrap();
+    assert!(next.token > lease.token);
+}
+
+#[test]
+fn test_lease_renew() {
+    let db = open();
+    let lease = db
+        .acquire_lease("jobs", "worker-1", Duration::from_millis(500))
+        .unwrap()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(300));
+    let renewed = db
+        .renew_lease("jobs", "worker-1", Duration::from_millis(500))
+        .unwrap()
+        .unwrap();
+    assert_eq!(lease.token, renewed.token);
+    assert!(renewed.expires_at > lease.expires_at);
+
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!("worker-1", db.get_lease("jobs").unwrap().unwrap().owner);
+    assert!(
+        db.renew_lease("jobs", "worker-2", Duration::from_secs(1))
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_crashed_holder_lease_expires() {
+    let (db, clock) = open_with_clock();
+    let lease = db
+        .acquire_lease("jobs", "worker-1", Duration::from_secs(30))
+        .unwrap()
+        .unwrap();
+
+    // worker-1 crashed and never renews
+    clock.advance(Duration::from_secs(40));
+    let next = db
+        .acquire_lease("jobs", "worker-2", Duration::from_secs(10))
+        .unwrap()
+        .unwrap();
+    assert!(next.token > lease.token);
+    assert!(
+        db.renew_lease("jobs", "worker-1", Duration::from_secs(1))
+            .unwrap()
+            .is_none()
+    );
+
+    // The expiration pass removes the lease key of an expired lease
+    db.acquire_lease("other", "worker-1", Duration::from_secs(10))
+        .unwrap();
+    clock.advance(Duration::from_secs(20));
+    db.remove_expired().unwrap();
+    assert!(!db.exists(&"lease:other").unwrap());
+}
+
+#[test]
+fn test_lease_without_a_practical_ttl() {
+    let (db, clock) = open_with_clock();
+
+    let lease = db
+        .acquire_lease("jobs", "worker-1", Duration::MAX)
+        .unwrap()
+        .unwrap();
+    assert_eq!(u64::MAX, lease.expires_at);
+
+    clock.advance(Duration::from_secs(365 * 24 * 3600));
+    assert!(
+        db.acquire_lease("jobs", "worker-2", Duration::from_secs(10))
+            .unwrap()
+            .is_none()
+    );
+}
@@ -0,0 +1,175 @@
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::storage::MemoryBackend;
+use tempfile::tempdir;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+fn range(db: &DB, key: &str, start: i64, stop: i64) -> Vec<String> {
+    db.lrange(&key, start, stop)
+        .unwrap()
+        .into_iter()
+        .map(|v| String::from_utf8(v).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_list_push_pop() {
+    let db = open();
+
+    assert_eq!(2, db.rpush(&"jobs", &["b", "c"]).unwrap());
+    assert_eq!(4, db.lpush(&"jobs", &["a", "z"]).unwrap());
+    assert_eq!(vec!["z", "a", "b", "c"], range(&db, "jobs", 0, -1));
+    assert_eq!(4, db.llen(&"jobs").unwrap());
+
+    assert_eq!(b"z".to_vec(), db.lpop(&"jobs").unwrap().unwrap());
+    assert_eq!(b"c".to_vec(), db.rpop(&"jobs").unwrap().unwrap());
+    assert_eq!(vec!["a", "b"], range(&db, "jobs", 0, -1));
+
+    // Popping the last element removes the list
+    db.lpop(&"jobs").unwrap();
+    db.lpop(&"jobs").unwrap();
+    assert!(db.lpop(&"jobs").unwrap().is_none());
+    assert_eq!(0, db.llen(&"jobs").unwrap());
+    assert!(!db.exists(&"jobs").unwrap());
+}
+
+#[test]
+fn test_list_range_and_trim() {
+    let db = open();
+    db.rpush(&"l", &["0", "1", "2", "3", "4", "5"]).unwrap();
+
+    assert_eq!(vec!["1", "2"], range(&db, "l", 1, 2));
+    assert_eq!(vec!["4", "5"], range(&db, "l", -2, -1));
+    assert_eq!(
+        vec!["0", "1", "2", "3", "4", "5"],
+        range(&db, "l", -100, 100)
+    );
+    assert!(range(&db, "l", 4, 2).is_empty());
+    assert!(range(&db, "l", 6, 10).is_empty());
+
+    db.ltrim(&"l", 1, -2).unwrap();
+    assert_eq!(vec!["1", "2", "3", "4"], range(&db, "l", 0, -1));
+
+    db.ltrim(&"l", 5, 10).unwrap();
+    assert!(!db.exists(&"l").unwrap());
+}
+
+#[test]
+fn test_list_wrong_type() {
+    let db = open();
+    db.set("plain", "value", None).unwrap();
+
+    assert!(matches!(
+        db.rpush(&"plain", &["a"]),
+        Err(TransientError::WrongType)
+    ));
+    assert!(matches!(db.llen(&"plain"), Err(TransientError::WrongType)));
+}
+
+#[test]
+fn test_list_read_as_a_plain_value() {
+    let db = open();
+    db.rpush(&"l", &["a"]).unwrap();
+
+    assert!(matches!(db.get("l"), Err(TransientError::WrongType)));
+    assert!(matches!(db.get_raw(&"l"), Err(TransientError::WrongType)));
+    let read = db.transaction(|tx| tx.get_raw(&"l").map_err(Into::into));
+    assert!(matches!(
+        read.map_err(|e| e.into_transient()),
+        Err(TransientError::WrongType)
+    ));
+}
+
+#[test]
+fn test_plain_value_never_read_as_a_list() {
+    let db = open();
+    // The bytes of the header of a list from 0 to 5, with and without the
+    // marker the headers used to start with
+    let header = [0u8, 0, 5];
+    let marked = [&b"\0epoch:collection\0"[..], &[0; 8], &header].concat();
+
+    for value in [&header[..], &marked] {
+        db.set_raw(&"forged", &value, None).unwrap();
+
+        assert!(matches!(db.llen(&"forged"), Err(TransientError::WrongType)));
+        assert!(matches!(
+            db.rpush(&"forged", &["a"]),
+            Err(TransientError::WrongType)
+        ));
+        assert_eq!(Some(value.to_vec()), db.get_raw(&"forged").unwrap());
+    }
+}
+
+#[test]
+fn test_list_removed_and_recreated() {
+    let db = open();
+    db.rpush(&"l", &["old-1", "old-2"]).unwrap();
+
+    db.remove("l").unwrap();
+    assert_eq!(0, db.llen(&"l").unwrap());
+
+    db.rpush(&"l", &["new"]).unwrap();
+    assert_eq!(vec!["new"], range(&db, "l", 0, -1));
+}
+
+#[test]
+fn test_list_expires_as_a_whole() {
+    let db = open();
+    db.rpush(&"l", &["a", "b"]).unwrap();
+    assert!(db.expire(&"l", Duration::from_secs(1)).unwrap());
+
+    // Pushing keeps the TTL of the list
+    db.rpush(&"l", &["c"]).unwrap();
+    assert!(db.ttl(&"l").unwrap().is_some());
+
+    thread::sleep(Duration::from_millis(2100));
+    assert_eq!(0, db.llen(&"l").unwrap());
+
+    db.rpush(&"l", &["fresh"]).unwrap();
+    assert_eq!(vec!["fresh"], range(&db, "l", 0, -1));
+}
+
+#[test]
+fn test_list_backup() {
+    let temp_dir = tempdir().unwrap();
+    let backup = tempdir().unwrap();
+
+    let db = DB::new(temp_dir.path()).unwrap();
+    db.rpush(&"l", &["a", "b", "c"]).unwrap();
+    db.backup_to(backup.path()).unwrap();
+    drop(db);
+
+    let file = backup.path().read_dir().unwrap().next().unwrap().unwrap();
+    let restored = tempdir().unwrap();
+    let db = DB::load_from(&file.path(), restored.path()).unwrap();
+
+    assert_eq!(vec!["a", "b", "c"], range(&db, "l", 0, -1));
+}
+
+#[test]
+fn test_list_range_while_popping() {
+    let db = open();
+    let values: Vec<String> = (0..500).map(|i| format!("{i:03}")).collect();
+    db.rpush(&"jobs", &values).unwrap();
+
+    let popper = {
+        let db = db.clone();
+        thread::spawn(move || while db.lpop(&"jobs").unwrap().is_some() {})
+    };
+
+    // Every read sees a part of the list in order, whatever was popped
+    loop {
+        let read = range(&db, "jobs", 0, -1);
+        assert!(read.windows(2).all(|w| w[0] < w[1]));
+        if read.is_empty() {
+            break;
+        }
+    }
+    popper.join().unwrap();
+}
@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::rate_limit::RateLimit;
+use epoch_db::db::storage::MemoryBackend;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+#[test]
+fn test_fixed_window() {
+    let db = open();
+    let limiter = db.rate_limiter(RateLimit::FixedWindow {
+        limit: 3,
+        window: Duration::from_secs(60)
+    });
+
+    for remaining in (0..3).rev() {
+        let d = limiter.check(&"user:1", 1).unwrap();
+        assert!(d.allowed);
+        assert_eq!(remaining, d.remaining);
+    }
+
+    let d = limiter.check(&"user:1", 1).unwrap();
+    assert!(!d.allowed);
+    assert!(d.retry_after > Duration::ZERO);
+    assert!(d.reset_after <= Duration::from_secs(60));
+
+    // Other keys have their own quota
+    assert!(limiter.check(&"user:2", 1).unwrap().allowed);
+    assert_eq!(0, limiter.peek(&"user:1").unwrap().remaining);
+}
+
+#[test]
+fn test_sliding_window_carries_previous_window() {
+    let db = open();
+    let limiter = db.rate_limiter(RateLimit::SlidingWindow {
+        limit: 4,
+        window: Duration::from_millis(500)
+    });
+
+    // Wait for the start of a window, so the quota is used within one window
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    thread::sleep(Duration::from_millis(500 - now % 500));
+
+    assert!(limiter.check(&"user:1", 4).unwrap().allowed);
+    assert!(!limiter.check(&"user:1", 1).unwrap().allowed);
+
+    // Early in the next window most of the previous one still counts
+    thread::sleep(Duration::from_millis(550));
+    let d = limiter.peek(&"user:1").unwrap();
+    assert!(d.remaining < 4);
+
+    // Once both windows slid out the whole quota is back
+    thread::sleep(Duration::from_millis(1000));
+    assert_eq!(4, limiter.peek(&"user:1").unwrap().remaining);
+}
+
+#[test]
+fn test_token_bucket_refills() {
+    let db = open();
+    let limiter = db.rate_limiter(RateLimit::TokenBucket {
+        limit: 10,
+        window: Duration::from_secs(1)
+    });
+
+    assert!(limiter.check(&"user:1", 10).unwrap().allowed);
+    let d = limiter.check(&"user:1", 5).unwrap();
+    assert!(!d.allowed);
+    assert!(d.retry_after <= Duration::from_millis(500));
+
+    thread::sleep(Duration::from_millis(600));
+    assert!(limiter.check(&"user:1", 5).unwrap().allowed);
+}
+
+#[test]
+fn test_concurrent_checks_never_exceed_limit() {
+    let db = Arc::new(open());
+    let mut handles = Vec::new();
+
+    for _ in 0..8 {
+        let db = Arc::clone(&db);
+        handles.push(thread::spawn(move || {
+            let limiter = db.rate_limiter(RateLimit::FixedWindow {
+                limit: 20,
+                window: Duration::from_secs(60)
+            });
+            (0..10)
+                .filter(|_| limiter.check(&"shared", 1).unwrap().allowed)
+                .count()
+        }));
+    }
+
+    let allowed: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    assert_eq!(20, allowed);
+}
+
+#[test]
+fn test_invalid_rate_limit() {
+    let db = open();
+    let limiter = db.rate_limiter(RateLimit::FixedWindow {
+        limit: 0,
+        window: Duration::from_secs(1)
+    });
+
+    assert!(matches!(
+        limiter.check(&"user:1", 1),
+        Err(TransientError::InvalidRateLimit)
+    ));
+}
+
+#[test]
+fn test_cost_above_limit_is_denied_without_overflow() {
+    let db = open();
+    let policies = [
+        RateLimit::FixedWindow {
+            limit: 3,
+            window: Duration::from_secs(60)
+        },
+        RateLimit::SlidingWindow {
+            limit: 3,
+            window: Duration::from_secs(60)
+        },
+        RateLimit::TokenBucket {
+            limit: 3,
+            window: Duration::from_secs(60)
+        }
+    ];
+
+    for policy in policies {
+        let limiter = db.rate_limiter(policy);
+        assert!(limiter.check(&"ip", 1).unwrap().allowed);
+
+        let d = limiter.check(&"ip", u64::MAX).unwrap();
+        assert!(!d.allowed);
+        assert_eq!(Duration::ZERO, d.retry_after);
+        assert_eq!(2, d.remaining);
+
+        // Nothing was consumed by the denied check
+        assert!(limiter.check(&"ip", 2).unwrap().allowed);
+        db.remove_raw("ratelimit:ip").unwrap();
+    }
+}
+
+#[test]
+fn test_huge_limit_and_window_do_not_overflow() {
+    let db = open();
+    let limiter = db.rate_limiter(RateLimit::SlidingWindow {
+        limit: u64::MAX,
+        window: Duration::MAX
+    });
+
+    assert!(limiter.check(&"ip", u64::MAX).unwrap().allowed);
+    let d = limiter.check(&"ip", 1).unwrap();
+    assert!(!d.allowed);
+    assert_eq!(0, d.remaining);
+}
+
+#[test]
+fn test_peek_does_not_write() {
+    let db = open();
+    let limiter = db.rate_limiter(RateLimit::FixedWindow {
+        limit: 3,
+        window: Duration::from_secs(60)
+    });
+
+    assert_eq!(3, limiter.peek(&"user:1").unwrap().remaining);
+    assert_eq!(None, db.get_raw(&"ratelimit:user:1").unwrap());
+
+    limiter.check(&"user:1", 1).unwrap();
+    let state = db.get_raw(&"ratelimit:user:1").unwrap();
+    assert_eq!(2, limiter.peek(&"user:1").unwrap().remaining);
+    assert_eq!(state, db.get_raw(&"ratelimit:user:1").unwrap());
+}
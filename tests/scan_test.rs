@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+
+use epoch_db::DB;
+use epoch_db::db::scan::glob_match;
+use epoch_db::db::storage::MemoryBackend;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+fn keys(keys: Vec<Vec<u8>>) -> Vec<String> {
+    keys.into_iter()
+        .map(|k| String::from_utf8(k).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match(b"*", b""));
+    assert!(glob_match(b"user:*", b"user:42"));
+    assert!(!glob_match(b"user:*", b"session:42"));
+    assert!(glob_match(b"*:42", b"user:42"));
+    assert!(glob_match(b"u*r*2", b"user:42"));
+    assert!(!glob_match(b"u*r*3", b"user:42"));
+    assert!(glob_match(b"h?llo", b"hallo"));
+    assert!(!glob_match(b"h?llo", b"hllo"));
+    assert!(glob_match(b"h[ae]llo", b"hello"));
+    assert!(!glob_match(b"h[ae]llo", b"hillo"));
+    assert!(glob_match(b"h[^e]llo", b"hallo"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+    assert!(!glob_match(b"h[a-c]llo", b"hdllo"));
+    assert!(glob_match(b"what\\?", b"what?"));
+    assert!(!glob_match(b"what\\?", b"whats"));
+    assert!(glob_match(b"a[b", b"a[b"));
+}
+
+#[test]
+fn test_keys_by_pattern() {
+    let db = open();
+    for key in ["user:1", "user:2", "user:10", "session:1", "users"] {
+        db.set(key, "v", None).unwrap();
+    }
+    db.rpush(&"user:list", &["a"]).unwrap();
+
+    assert_eq!(
+        vec!["user:1", "user:10", "user:2", "user:list"],
+        keys(db.keys(&"user:*").unwrap())
+    );
+    assert_eq!(vec!["user:1", "user:2"], keys(db.keys(&"user:?").unwrap()));
+    assert_eq!(vec!["session:1", "user:1"], keys(db.keys(&"*:1").unwrap()));
+    assert!(db.keys(&"nothing*").unwrap().is_empty());
+}
+
+#[test]
+fn test_scan_pages() {
+    let db = open();
+    for i in 0..25 {
+        db.set(&format!("key:{i:02}"), "v", None).unwrap();
+    }
+    db.set("other", "v", None).unwrap();
+
+    let mut seen = Vec::new();
+    let mut cursor = Vec::new();
+    let mut pages = 0;
+    loop {
+        let page = db.scan(&cursor, Some(b"key:*"), 10).unwrap();
+        assert!(page.keys.len() <= 10);
+        seen.extend(keys(page.keys));
+        pages += 1;
+
+        match page.cursor {
+            Some(c) => cursor = c,
+            None => break
+        }
+    }
+
+    assert_eq!(3, pages);
+    assert_eq!(
+        (0..25).map(|i| format!("key:{i:02}")).collect::<Vec<_>>(),
+        seen
+    );
+
+    // Without a pattern every key is returned
+    let page = db.scan(&[], None, 100).unwrap();
+    assert_eq!(26, page.keys.len());
+    assert!(page.cursor.is_none());
+}
+
+#[test]
+fn test_scan_stable_across_writes() {
+    let db = open();
+    for i in 0..20 {
+        db.set(&format!("k{i:02}"), "v", None).unwrap();
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor = Vec::new();
+    let mut round = 0;
+    loop {
+        let page = db.scan(&cursor, None, 3).unwrap();
+        seen.extend(keys(page.keys));
+
+        // Keys added and removed on both sides of the cursor
+        db.set(&format!("k{round:02}x"), "v", None).unwrap();
+        db.set(&format!("k{:02}x", 19 - round), "v", None).unwrap();
+        if round % 2 == 1 {
+            db.remove(&format!("k{round:02}x")).unwrap();
+        }
+        round += 1;
+
+        match page.cursor {
+            Some(c) => cursor = c,
+            None => break
+        }
+    }
+
+    // Every original key is returned exactly once
+    let unique: HashSet<&String> = seen.iter().collect();
+    assert_eq!(seen.len(), unique.len());
+    for i in 0..20 {
+        assert!(unique.contains(&format!("k{i:02}")));
+    }
+}
+
+#[test]
+fn test_scan_key_equal_to_prefix() {
+    let db = open();
+    db.set("key:", "v", None).unwrap();
+    db.set("key:1", "v", None).unwrap();
+    db.set("key:2", "v", None).unwrap();
+
+    let mut seen = Vec::new();
+    let mut cursor = Vec::new();
+    for _ in 0..10 {
+        let page = db.scan(&cursor, Some(b"key:*"), 1).unwrap();
+        seen.extend(keys(page.keys));
+
+        match page.cursor {
+            Some(c) => cursor = c,
+            None => break
+        }
+    }
+
+    assert_eq!(vec!["key:", "key:1", "key:2"], seen);
+}
@@ -3,6 +3,12 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use epoch_db::DB;
+use epoch_db::db::async_db::AsyncDB;
+use epoch_db::db::clock::MockClock;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::storage::MemoryBackend;
+use epoch_db::db::stream::StreamId;
 use epoch_db::server::commands::ParsedResponse;
 use epoch_db::server::{
     execute_commands,
@@ -25,7 +31,7 @@ async fn execute_test_command(input: ParsedResponse, store: Arc<DB>) -> Vec<u8>
     let buf: Vec<u8> = Vec::new();
     let mut c = Cursor::new(buf);
     let mut buf_writer = BufWriter::new(&mut c);
-    execute_commands(input, &store, &mut buf_writer)
+    execute_commands(input, &AsyncDB::from(store), &mut buf_writer)
         .await
         .unwrap();
     buf_writer.flush().await.unwrap();
@@ -233,7 +239,7 @@ async fn test_execute_rm_key_not_found() {
     let r = execute_test_command(cmd, store).await;
 
     // Assert
-    assert_eq!(r, b"-ERR Sled Transaction failed\n\r\n");
+    assert_eq!(r, b"-ERR Metadata is not found\n\r\n");
 }
 
 #[tokio::test]
@@ -398,3 +404,413 @@ async fn test_execute_set_too_many_argument() {
     // assert
     assert_eq!(r, b"-ERR Wrong number of arguments for \"set\" command; Needed at least 3 arguments, Received 5 arguments\r\n");
 }
+
+#[tokio::test]
+async fn test_execute_ratelimit_fixed() {
+    //Input
+    let input = b"*6\r\n$9\r\nRATELIMIT\r\n$3\r\nkey\r\n$5\r\nfixed\r\n$1\r\n2\r\n$5\r\n60000\r\n$1\r\n2\r\n";
+
+    // DB SETUP
+    let store = Arc::new(DB::new(tempfile::tempdir().unwrap().path()).unwrap());
+
+    // Cmd parse and execute
+    let cmd = parse_test_command(input).await;
+    let r = execute_test_command(cmd, store.clone()).await;
+
+    // Assert
+    assert!(r.starts_with(b"*4\r\n:1\r\n:0\r\n:"));
+
+    let cmd = parse_test_command(input).await;
+    let r = execute_test_command(cmd, store).await;
+    assert!(r.starts_with(b"*4\r\n:0\r\n:0\r\n:"));
+}
+
+#[tokio::test]
+async fn test_execute_ratelimit_arguments() {
+    let too_many = b"*7\r\n$9\r\nRATELIMIT\r\n$3\r\nkey\r\n$5\r\nfixed\r\n$1\r\n2\r\n$5\r\n60000\r\n$1\r\n1\r\n$1\r\n1\r\n";
+    let not_utf8 =
+        b"*5\r\n$9\r\nRATELIMIT\r\n$3\r\nkey\r\n$5\r\nfixed\r\n$1\r\n\xff\r\n$5\r\n60000\r\n";
+    let huge_cost = b"*6\r\n$9\r\nRATELIMIT\r\n$3\r\nkey\r\n$5\r\nfixed\r\n$1\r\n2\r\n$5\r\n60000\r\n$20\r\n18446744073709551615\r\n";
+
+    // DB SETUP
+    let store = AsyncDB::from(Arc::new(
+        DB::new(tempfile::tempdir().unwrap().path()).unwrap()
+    ));
+    let mut buf_writer = BufWriter::new(Cursor::new(Vec::new()));
+
+    let cmd = parse_test_command(too_many).await;
+    assert!(matches!(
+        execute_commands(cmd, &store, &mut buf_writer).await,
+        Err(TransientError::WrongNumberOfArguments {
+            expected: 6,
+            received: 7,
+            ..
+        })
+    ));
+
+    let cmd = parse_test_command(not_utf8).await;
+    assert!(matches!(
+        execute_commands(cmd, &store, &mut buf_writer).await,
+        Err(TransientError::ProtocolError)
+    ));
+
+    // A cost above the limit is denied without consuming the quota
+    let cmd = parse_test_command(huge_cost).await;
+    execute_commands(cmd, &store, &mut buf_writer)
+        .await
+        .unwrap();
+    buf_writer.flush().await.unwrap();
+    assert!(
+        buf_writer
+            .get_ref()
+            .get_ref()
+            .starts_with(b"*4\r\n:0\r\n:2\r\n:")
+    );
+}
+
+#[tokio::test]
+async fn test_execute_lock_arguments() {
+    let lock_short = b"*3\r\n$4\r\nLOCK\r\n$4\r\njobs\r\n$2\r\nw1\r\n";
+    let lock_long = b"*5\r\n$4\r\nLOCK\r\n$4\r\njobs\r\n$2\r\nw1\r\n$5\r\n10000\r\n$1\r\nx\r\n";
+    let unlock_long = b"*4\r\n$6\r\nUNLOCK\r\n$4\r\njobs\r\n$2\r\nw1\r\n$1\r\nx\r\n";
+    let lock_not_utf8 = b"*4\r\n$4\r\nLOCK\r\n$4\r\njobs\r\n$1\r\n\xff\r\n$5\r\n10000\r\n";
+    let ttl_not_utf8 = b"*4\r\n$5\r\nRENEW\r\n$4\r\njobs\r\n$2\r\nw1\r\n$1\r\n\xff\r\n";
+
+    // DB SETUP
+    let store = AsyncDB::from(Arc::new(
+        DB::new(tempfile::tempdir().unwrap().path()).unwrap()
+    ));
+    let mut buf_writer = BufWriter::new(Cursor::new(Vec::new()));
+
+    for (input, expected, received) in [
+        (&lock_short[..], 4, 3),
+        (lock_long, 4, 5),
+        (unlock_long, 3, 4)
+    ] {
+        let cmd = parse_test_command(input).await;
+        let res = execute_commands(cmd, &store, &mut buf_writer).await;
+        assert!(
+            matches!(
+                res,
+                Err(TransientError::WrongNumberOfArguments {
+                    expected: e,
+                    received: r,
+                    ..
+                }) if e == expected && r == received
+            ),
+            "{res:?}"
+        );
+    }
+
+    for input in [&lock_not_utf8[..], ttl_not_utf8] {
+        let cmd = parse_test_command(input).await;
+        assert!(matches!(
+            execute_commands(cmd, &store, &mut buf_writer).await,
+            Err(TransientError::ProtocolError)
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_execute_lock_unlock() {
+    let lock_1 = b"*4\r\n$4\r\nLOCK\r\n$4\r\njobs\r\n$2\r\nw1\r\n$5\r\n10000\r\n";
+    let lock_2 = b"*4\r\n$4\r\nLOCK\r\n$4\r\njobs\r\n$2\r\nw2\r\n$5\r\n10000\r\n";
+    let renew_1 = b"*4\r\n$5\r\nRENEW\r\n$4\r\njobs\r\n$2\r\nw1\r\n$5\r\n10000\r\n";
+    let unlock_1 = b"*3\r\n$6\r\nUNLOCK\r\n$4\r\njobs\r\n$2\r\nw1\r\n";
+
+    // DB SETUP
+    let store = Arc::new(DB::new(tempfile::tempdir().unwrap().path()).unwrap());
+
+    // Cmd parse and execute
+    let cmd = parse_test_command(lock_1).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":1\r\n");
+
+    let cmd = parse_test_command(lock_2).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b"$-1\r\n");
+
+    let cmd = parse_test_command(renew_1).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":1\r\n");
+
+    let cmd = parse_test_command(unlock_1).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":1\r\n");
+
+    let cmd = parse_test_command(lock_2).await;
+    assert_eq!(execute_test_command(cmd, store).await, b":2\r\n");
+}
+
+#[tokio::test]
+async fn test_execute_list_commands() {
+    let rpush = b"*4\r\n$5\r\nRPUSH\r\n$4\r\njobs\r\n$1\r\na\r\n$1\r\nb\r\n";
+    let lpush = b"*3\r\n$5\r\nLPUSH\r\n$4\r\njobs\r\n$1\r\nz\r\n";
+    let lrange = b"*4\r\n$6\r\nLRANGE\r\n$4\r\njobs\r\n$1\r\n0\r\n$2\r\n-1\r\n";
+    let rpop = b"*2\r\n$4\r\nRPOP\r\n$4\r\njobs\r\n";
+    let llen = b"*2\r\n$4\r\nLLEN\r\n$4\r\njobs\r\n";
+
+    // DB SETUP
+    let store = Arc::new(DB::new(tempfile::tempdir().unwrap().path()).unwrap());
+
+    // Cmd parse and execute
+    let cmd = parse_test_command(rpush).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":2\r\n");
+
+    let cmd = parse_test_command(lpush).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":3\r\n");
+
+    let cmd = parse_test_command(lrange).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"*3\r\n$1\r\nz\r\n$1\r\na\r\n$1\r\nb\r\n"
+    );
+
+    let cmd = parse_test_command(rpop).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"$1\r\nb\r\n"
+    );
+
+    let cmd = parse_test_command(llen).await;
+    assert_eq!(execute_test_command(cmd, store).await, b":2\r\n");
+}
+
+#[tokio::test]
+async fn test_execute_hash_commands() {
+    let hset =
+        b"*6\r\n$4\r\nHSET\r\n$4\r\nuser\r\n$4\r\nname\r\n$5\r\nalice\r\n$3\r\nage\r\n$2\r\n30\r\n";
+    let hget = b"*3\r\n$4\r\nHGET\r\n$4\r\nuser\r\n$4\r\nname\r\n";
+    let hincrby = b"*4\r\n$7\r\nHINCRBY\r\n$4\r\nuser\r\n$3\r\nage\r\n$1\r\n1\r\n";
+    let hgetall = b"*2\r\n$7\r\nHGETALL\r\n$4\r\nuser\r\n";
+    let hdel = b"*3\r\n$4\r\nHDEL\r\n$4\r\nuser\r\n$3\r\nage\r\n";
+
+    // DB SETUP
+    let store = Arc::new(DB::new(tempfile::tempdir().unwrap().path()).unwrap());
+
+    // Cmd parse and execute
+    let cmd = parse_test_command(hset).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":2\r\n");
+
+    let cmd = parse_test_command(hget).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"$5\r\nalice\r\n"
+    );
+
+    let cmd = parse_test_command(hincrby).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":31\r\n");
+
+    let cmd = parse_test_command(hgetall).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"*4\r\n$3\r\nage\r\n$2\r\n31\r\n$4\r\nname\r\n$5\r\nalice\r\n"
+    );
+
+    let cmd = parse_test_command(hdel).await;
+    assert_eq!(execute_test_command(cmd, store).await, b":1\r\n");
+}
+
+#[tokio::test]
+async fn test_execute_hash_arguments() {
+    let hget_long = b"*4\r\n$4\r\nHGET\r\n$4\r\nuser\r\n$4\r\nname\r\n$1\r\nx\r\n";
+    let hincrby_short = b"*3\r\n$7\r\nHINCRBY\r\n$4\r\nuser\r\n$3\r\nage\r\n";
+    let hincrby_long = b"*5\r\n$7\r\nHINCRBY\r\n$4\r\nuser\r\n$3\r\nage\r\n$1\r\n1\r\n$1\r\n1\r\n";
+
+    // DB SETUP
+    let store = AsyncDB::from(Arc::new(
+        DB::new(tempfile::tempdir().unwrap().path()).unwrap()
+    ));
+    let mut buf_writer = BufWriter::new(Cursor::new(Vec::new()));
+
+    for (input, expected, received) in [
+        (&hget_long[..], 3, 4),
+        (hincrby_short, 4, 3),
+        (hincrby_long, 4, 5)
+    ] {
+        let cmd = parse_test_command(input).await;
+        let res = execute_commands(cmd, &store, &mut buf_writer).await;
+        assert!(
+            matches!(
+                res,
+                Err(TransientError::WrongNumberOfArguments {
+                    expected: e,
+                    received: r,
+                    ..
+                }) if e == expected && r == received
+            ),
+            "{res:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_execute_sorted_set_commands() {
+    let zadd =
+        b"*6\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$2\r\n10\r\n$5\r\nalice\r\n$3\r\n2.5\r\n$3\r\nbob\r\n";
+    let zrange =
+        b"*5\r\n$6\r\nZRANGE\r\n$5\r\nboard\r\n$1\r\n0\r\n$2\r\n-1\r\n$10\r\nWITHSCORES\r\n";
+    let zrangebyscore = b"*4\r\n$13\r\nZRANGEBYSCORE\r\n$5\r\nboard\r\n$1\r\n5\r\n$4\r\n+inf\r\n";
+    let zscore = b"*3\r\n$6\r\nZSCORE\r\n$5\r\nboard\r\n$3\r\nbob\r\n";
+    let zrank = b"*3\r\n$5\r\nZRANK\r\n$5\r\nboard\r\n$5\r\nalice\r\n";
+
+    // DB SETUP
+    let store = Arc::new(DB::new(tempfile::tempdir().unwrap().path()).unwrap());
+
+    // Cmd parse and execute
+    let cmd = parse_test_command(zadd).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":2\r\n");
+
+    let cmd = parse_test_command(zrange).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"*4\r\n$3\r\nbob\r\n$3\r\n2.5\r\n$5\r\nalice\r\n$2\r\n10\r\n"
+    );
+
+    let cmd = parse_test_command(zrangebyscore).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"*1\r\n$5\r\nalice\r\n"
+    );
+
+    let cmd = parse_test_command(zscore).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"$3\r\n2.5\r\n"
+    );
+
+    let cmd = parse_test_command(zrank).await;
+    assert_eq!(execute_test_command(cmd, store).await, b":1\r\n");
+}
+
+#[tokio::test]
+async fn test_execute_stream_commands() {
+    let xgroup =
+        b"*5\r\n$6\r\nXGROUP\r\n$6\r\nCREATE\r\n$4\r\njobs\r\n$7\r\nworkers\r\n$1\r\n0\r\n";
+    let xadd = b"*5\r\n$4\r\nXADD\r\n$4\r\njobs\r\n$1\r\n*\r\n$3\r\njob\r\n$1\r\na\r\n";
+    let xlen = b"*2\r\n$4\r\nXLEN\r\n$4\r\njobs\r\n";
+    let xrange =
+        b"*6\r\n$6\r\nXRANGE\r\n$4\r\njobs\r\n$1\r\n-\r\n$1\r\n+\r\n$5\r\nCOUNT\r\n$1\r\n1\r\n";
+    let xreadgroup =
+        b"*5\r\n$10\r\nXREADGROUP\r\n$4\r\njobs\r\n$7\r\nworkers\r\n$5\r\nalice\r\n$1\r\n5\r\n";
+    let xtrim = b"*4\r\n$5\r\nXTRIM\r\n$4\r\njobs\r\n$6\r\nMAXLEN\r\n$1\r\n0\r\n";
+
+    // DB SETUP
+    let store = Arc::new(DB::new(tempfile::tempdir().unwrap().path()).unwrap());
+
+    // Cmd parse and execute
+    let cmd = parse_test_command(xgroup).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":1\r\n");
+
+    let cmd = parse_test_command(xadd).await;
+    let reply = execute_test_command(cmd, store.clone()).await;
+    let id = store
+        .xrange(&"jobs", StreamId::MIN, StreamId::MAX, None)
+        .unwrap()[0]
+        .id
+        .to_string();
+    assert_eq!(reply, format!("${}\r\n{id}\r\n", id.len()).into_bytes());
+
+    let cmd = parse_test_command(xlen).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":1\r\n");
+
+    let entry = format!(
+        "*1\r\n*2\r\n${}\r\n{id}\r\n*2\r\n$3\r\njob\r\n$1\r\na\r\n",
+        id.len()
+    );
+    let cmd = parse_test_command(xrange).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        entry.as_bytes()
+    );
+
+    let cmd = parse_test_command(xreadgroup).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        entry.as_bytes()
+    );
+
+    let ack = format!(
+        "*4\r\n$4\r\nXACK\r\n$4\r\njobs\r\n$7\r\nworkers\r\n${}\r\n{id}\r\n",
+        id.len()
+    );
+    let cmd = parse_test_command(ack.as_bytes()).await;
+    assert_eq!(execute_test_command(cmd, store.clone()).await, b":1\r\n");
+
+    let cmd = parse_test_command(xtrim).await;
+    assert_eq!(execute_test_command(cmd, store).await, b":1\r\n");
+}
+
+#[tokio::test]
+async fn test_execute_scan_command() {
+    let first =
+        b"*6\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nMATCH\r\n$6\r\nuser:*\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n";
+
+    // DB SETUP
+    let store = Arc::new(DB::new(tempfile::tempdir().unwrap().path()).unwrap());
+    for key in ["user:1", "user:2", "user:3", "zebra"] {
+        store.set(key, "v", None).unwrap();
+    }
+
+    // Cmd parse and execute, the cursor is the last key examined in hex
+    let cmd = parse_test_command(first).await;
+    assert_eq!(
+        execute_test_command(cmd, store.clone()).await,
+        b"*2\r\n$12\r\n757365723a32\r\n*2\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n"
+    );
+
+    let next = b"*6\r\n$4\r\nSCAN\r\n$12\r\n757365723a32\r\n$5\r\nMATCH\r\n$6\r\nuser:*\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n";
+    let cmd = parse_test_command(next).await;
+    assert_eq!(
+        execute_test_command(cmd, store).await,
+        b"*2\r\n$1\r\n0\r\n*1\r\n$6\r\nuser:3\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_execute_stats_command() {
+    let input = b"*1\r\n$5\r\nSTATS\r\n";
+
+    // DB SETUP
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let store = Arc::new(
+        DB::with_config(MemoryBackend::new(), DBConfig::new().clock(clock.clone())).unwrap()
+    );
+    store.set("a", "12", None).unwrap();
+    store.set("b", "1", Some(Duration::from_secs(60))).unwrap();
+    store.increment_frequency("a").unwrap();
+    clock.advance(Duration::from_secs(10));
+
+    // Cmd parse and execute
+    let cmd = parse_test_command(input).await;
+    let fields = [
+        "live_keys",
+        "2",
+        "ttl_keys",
+        "1",
+        "expired_keys",
+        "0",
+        "freq_min",
+        "0",
+        "freq_max",
+        "1",
+        "freq_p50",
+        "0",
+        "freq_p99",
+        "1",
+        "value_size_max",
+        "2",
+        "value_size_p50",
+        "1",
+        "value_size_p99",
+        "2",
+        "oldest_key_age",
+        "10",
+        "next_expiry",
+        "50"
+    ];
+    let mut expected = format!("*{}\r\n", fields.len());
+    for f in fields {
+        expected.push_str(&format!("${}\r\n{}\r\n", f.len(), f));
+    }
+    assert_eq!(
+        execute_test_command(cmd, store).await,
+        expected.into_bytes()
+    );
+}
@@ -31,6 +31,7 @@ async fn test_parse_get_simple() {
             key: Some(Vec::from(b"key")),
             value: None,
             ttl: None,
+            args: vec![],
             len: 2
         }
     );
@@ -49,6 +50,7 @@ async fn test_parse_set_with_ttl() {
             value: Some(b"value".to_vec()),
             // Changed to from_millis to be explicit, since your parser uses it
             ttl: Some(Duration::from_millis(1000)),
+            args: vec![b"1000".to_vec()],
             len: 4
         }
     );
@@ -66,6 +68,7 @@ async fn test_parse_set_empty_value() {
             key: Some(b"key".to_vec()),
             value: Some(vec![]), // More idiomatic than [].to_vec()
             ttl: None,
+            args: vec![],
             len: 3
         }
     )
@@ -83,6 +86,7 @@ async fn test_parse_set_binary_value() {
             key: Some(vec![0xDE, 0xAD, 0xBE, 0xEF]),
             value: Some(vec![0xCA, 0xFE]),
             ttl: None,
+            args: vec![],
             len: 3
         }
     )
@@ -100,6 +104,7 @@ async fn test_parse_remove_simple() {
             key: Some(b"key".to_vec()),
             value: None,
             ttl: None,
+            args: vec![],
             len: 2
         }
     )
@@ -117,6 +122,7 @@ async fn test_parse_increment_frequency_simple() {
             key: Some(b"counter1".to_vec()),
             value: None,
             ttl: None,
+            args: vec![],
             len: 2
         }
     )
@@ -134,6 +140,7 @@ async fn test_parse_get_metadata_simple() {
             key: Some(b"key".to_vec()),
             value: None,
             ttl: None,
+            args: vec![],
             len: 2
         }
     )
@@ -151,6 +158,7 @@ async fn test_parse_size() {
             key: None,
             value: None,
             ttl: None,
+            args: vec![],
             len: 1
         }
     )
@@ -168,6 +176,7 @@ async fn test_parse_ping() {
             key: None,
             value: None,
             ttl: None,
+            args: vec![],
             len: 1
         }
     )
@@ -185,6 +194,7 @@ async fn test_parse_flush() {
             key: None,
             value: None,
             ttl: None,
+            args: vec![],
             len: 1
         }
     )
@@ -202,6 +212,7 @@ async fn test_parse_command_case_sensitivity() {
             key: Some(b"key".to_vec()),
             value: None,
             ttl: None,
+            args: vec![],
             len: 2
         }
     )
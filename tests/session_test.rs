@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::clock::MockClock;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::session::{
+    Session,
+    SessionConfig
+};
+use epoch_db::db::storage::MemoryBackend;
+use serde::{
+    Deserialize,
+    Serialize
+};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Cart {
+    items: Vec<String>
+}
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+fn open_with_clock() -> (DB, MockClock) {
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let db = DB::with_config(MemoryBackend::new(), DBConfig::new().clock(clock.clone())).unwrap();
+
+    (db, clock)
+}
+
+#[test]
+fn test_session_create_get_update() {
+    let db = open();
+    let store = db.session_store(SessionConfig::new(Duration::from_secs(60)));
+
+    let cart = Cart {
+        items: vec!["apple".to_string()]
+    };
+    let id = store.create("alice", &cart).unwrap();
+    assert_eq!(32, id.len());
+
+    let session: Session<Cart> = store.get(&id).unwrap().unwrap();
+    assert_eq!("alice", session.user);
+    assert_eq!(cart, session.payload);
+
+    let cart = Cart {
+        items: vec!["apple".to_string(), "pear".to_string()]
+    };
+    assert!(store.update(&id, &cart).unwrap());
+    assert_eq!(cart, store.get::<Cart>(&id).unwrap().unwrap().payload);
+
+    assert!(store.get::<Cart>("missing").unwrap().is_none());
+}
+
+#[test]
+fn test_session_idle_timeout_is_renewed_on_access() {
+    let (db, clock) = open_with_clock();
+    let store = db.session_store(SessionConfig::new(Duration::from_secs(80)));
+    let id = store.create("alice", &()).unwrap();
+
+    for _ in 0..3 {
+        clock.advance(Duration::from_secs(40));
+        assert!(store.renew(&id).unwrap());
+    }
+
+    clock.advance(Duration::from_secs(100));
+    assert!(store.get::<()>(&id).unwrap().is_none());
+}
+
+#[test]
+fn test_session_absolute_timeout() {
+    let (db, clock) = open_with_clock();
+    let store = db.session_store(
+        SessionConfig::new(Duration::from_secs(60)).absolute_timeout(Duration::from_secs(90))
+    );
+    let id = store.create("alice", &()).unwrap();
+
+    clock.advance(Duration::from_secs(50));
+    assert!(store.renew(&id).unwrap());
+    clock.advance(Duration::from_secs(50));
+    assert!(!store.renew(&id).unwrap());
+}
+
+#[test]
+fn test_session_revoke_user() {
+    let db = open();
+    let store = db.session_store(SessionConfig::new(Duration::from_secs(60)));
+
+    let a1 = store.create("alice", &()).unwrap();
+    let a2 = store.create("alice", &()).unwrap();
+    let b1 = store.create("bob", &()).unwrap();
+    // A user whose name starts with another user's name
+    let ab = store.create("alice:b", &()).unwrap();
+
+    let mut sessions = store.user_sessions("alice").unwrap();
+    sessions.sort();
+    let mut expected = vec![a1.clone(), a2.clone()];
+    expected.sort();
+    assert_eq!(expected, sessions);
+
+    assert_eq!(2, store.revoke_user("alice").unwrap());
+    assert!(store.get::<()>(&a1).unwrap().is_none());
+    assert!(store.get::<()>(&a2).unwrap().is_none());
+    assert!(store.get::<()>(&b1).unwrap().is_some());
+    assert!(store.get::<()>(&ab).unwrap().is_some());
+
+    assert!(store.revoke(&b1).unwrap());
+    assert!(!store.revoke(&b1).unwrap());
+    assert!(store.user_sessions("bob").unwrap().is_empty());
+}
+
+#[test]
+fn test_session_expiry_events() {
+    let db = open();
+    let store = db.session_store(SessionConfig::new(Duration::from_millis(500)));
+    let events = store.expirations();
+
+    let id = store.create("alice", &()).unwrap();
+
+    // Swept by the TTL thread, whose precision is a second
+    let expired = events.recv_timeout(Duration::from_secs(4)).unwrap();
+    assert_eq!(id, expired.id);
+    assert_eq!(Some("alice".to_string()), expired.user);
+    assert!(store.user_sessions("alice").unwrap().is_empty());
+}
+
+#[test]
+fn test_session_without_a_practical_timeout() {
+    let (db, clock) = open_with_clock();
+    let store = db.session_store(SessionConfig::new(Duration::MAX).absolute_timeout(Duration::MAX));
+
+    let cart = Cart {
+        items: vec!["apple".to_string()]
+    };
+    let id = store.create("alice", &cart).unwrap();
+
+    clock.advance(Duration::from_secs(365 * 24 * 3600));
+    assert_eq!(cart, store.get::<Cart>(&id).unwrap().unwrap().payload);
+}
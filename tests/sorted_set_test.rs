@@ -0,0 +1,127 @@
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::storage::MemoryBackend;
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+fn members(scored: Vec<(Vec<u8>, f64)>) -> Vec<String> {
+    scored
+        .into_iter()
+        .map(|(m, _)| String::from_utf8(m).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_sorted_set_order() {
+    let db = open();
+
+    assert_eq!(
+        5,
+        db.zadd(
+            &"board",
+            &[
+                ("carol", 12.5),
+                ("alice", -3.0),
+                ("bob", 12.5),
+                ("dave", 0.0),
+                ("erin", 1e9)
+            ]
+        )
+        .unwrap()
+    );
+
+    // Ties are ordered by member
+    assert_eq!(
+        vec!["alice", "dave", "bob", "carol", "erin"],
+        members(db.zrange(&"board", 0, -1).unwrap())
+    );
+    assert_eq!(5, db.zcard(&"board").unwrap());
+    assert_eq!(Some(12.5), db.zscore(&"board", &"carol").unwrap());
+    assert_eq!(Some(2), db.zrank(&"board", &"bob").unwrap());
+    assert!(db.zrank(&"board", &"nobody").unwrap().is_none());
+
+    // Updating a score moves the member
+    assert_eq!(0, db.zadd(&"board", &[("alice", 100.0)]).unwrap());
+    assert_eq!(Some(3), db.zrank(&"board", &"alice").unwrap());
+    assert_eq!(
+        vec!["alice", "erin"],
+        members(db.zrange(&"board", -2, -1).unwrap())
+    );
+}
+
+#[test]
+fn test_sorted_set_range_by_score() {
+    let db = open();
+    db.zadd(
+        &"jobs",
+        &[("a", 10.0), ("b", 20.0), ("c", 30.0), ("d", -5.0)]
+    )
+    .unwrap();
+
+    assert_eq!(
+        vec![(b"b".to_vec(), 20.0), (b"c".to_vec(), 30.0)],
+        db.zrange_by_score(&"jobs", 20.0, 30.0).unwrap()
+    );
+    assert_eq!(
+        vec!["d", "a"],
+        members(
+            db.zrange_by_score(&"jobs", f64::NEG_INFINITY, 15.0)
+                .unwrap()
+        )
+    );
+    assert_eq!(
+        4,
+        db.zrange_by_score(&"jobs", f64::NEG_INFINITY, f64::INFINITY)
+            .unwrap()
+            .len()
+    );
+    assert!(db.zrange_by_score(&"jobs", 31.0, 40.0).unwrap().is_empty());
+    assert!(db.zrange_by_score(&"jobs", 30.0, 10.0).unwrap().is_empty());
+}
+
+#[test]
+fn test_sorted_set_remove() {
+    let db = open();
+    db.zadd(&"s", &[("a", 1.0), ("b", 2.0)]).unwrap();
+
+    assert_eq!(1, db.zrem(&"s", &["a", "zzz"]).unwrap());
+    assert!(db.zscore(&"s", &"a").unwrap().is_none());
+    assert_eq!(vec!["b"], members(db.zrange(&"s", 0, -1).unwrap()));
+
+    assert_eq!(1, db.zrem(&"s", &["b"]).unwrap());
+    assert!(!db.exists(&"s").unwrap());
+}
+
+#[test]
+fn test_sorted_set_invalid() {
+    let db = open();
+
+    assert!(matches!(
+        db.zadd(&"s", &[("a", f64::NAN)]),
+        Err(TransientError::InvalidScore)
+    ));
+
+    db.hset(&"h", &[("f", "v")]).unwrap();
+    assert!(matches!(
+        db.zadd(&"h", &[("a", 1.0)]),
+        Err(TransientError::WrongType)
+    ));
+}
+
+#[test]
+fn test_sorted_set_expires_as_a_whole() {
+    let db = open();
+    db.zadd(&"s", &[("a", 1.0), ("b", 2.0)]).unwrap();
+    assert!(db.expire(&"s", Duration::from_secs(1)).unwrap());
+
+    thread::sleep(Duration::from_millis(2100));
+    assert_eq!(0, db.zcard(&"s").unwrap());
+
+    db.zadd(&"s", &[("c", 3.0)]).unwrap();
+    assert_eq!(vec!["c"], members(db.zrange(&"s", 0, -1).unwrap()));
+}
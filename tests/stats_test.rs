@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::clock::MockClock;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::stats::Distribution;
+use epoch_db::db::storage::MemoryBackend;
+use tempfile::tempdir;
+
+#[test]
+fn test_storage_stats_counts_trees() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    db.set("a", "12345", None).unwrap();
+    db.set("bb", "1", None).unwrap();
+    db.rpush(&"list", &["x", "y"]).unwrap();
+
+    let stats = db.storage_stats().unwrap();
+    assert_eq!(0, stats.disk_bytes);
+    assert_eq!(3, stats.data.keys);
+    assert_eq!(3, stats.meta.keys);
+    assert_eq!(0, stats.ttl.keys);
+    assert_eq!(2, stats.members.keys);
+    assert_eq!(0, stats.index.keys);
+    assert!(stats.data.key_bytes >= 1 + 2 + 4);
+    assert!(stats.data.value_bytes > 5);
+    assert_eq!(
+        stats.data.bytes() + stats.meta.bytes() + stats.members.bytes(),
+        stats.logical_bytes()
+    );
+
+    db.remove("a").unwrap();
+    let after = db.storage_stats().unwrap();
+    assert_eq!(2, after.data.keys);
+    assert_eq!(stats.data.key_bytes - 1, after.data.key_bytes);
+}
+
+#[test]
+fn test_storage_stats_disk_size() {
+    let temp_dir = tempdir().unwrap();
+    let db = DB::new(temp_dir.path()).unwrap();
+
+    let value = "v".repeat(64 * 1024);
+    for i in 0..32 {
+        db.set(&format!("key:{i}"), &value, None).unwrap();
+    }
+    db.flush().unwrap();
+
+    let stats = db.storage_stats().unwrap();
+    assert_eq!(32, stats.data.keys);
+    assert!(stats.data.value_bytes >= 32 * 64 * 1024);
+    // The directory inode alone is a few KiB, the data is 2 MiB
+    assert!(stats.disk_bytes >= 1024 * 1024, "{}", stats.disk_bytes);
+}
+
+#[test]
+fn test_stats_counts_expired_keys_apart() {
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let db = DB::with_config(MemoryBackend::new(), DBConfig::new().clock(clock.clone())).unwrap();
+
+    let empty = db.stats().unwrap();
+    assert_eq!(0, empty.live_keys);
+    assert_eq!(Distribution::default(), empty.freq);
+    assert_eq!(None, empty.oldest_key_age);
+    assert_eq!(None, empty.next_expiry);
+
+    db.set("old", "1", None).unwrap();
+    clock.advance(Duration::from_secs(100));
+    db.set("short", "22", Some(Duration::from_secs(10)))
+        .unwrap();
+    db.set("long", "333", Some(Duration::from_secs(60)))
+        .unwrap();
+    for _ in 0..5 {
+        db.increment_frequency("long").unwrap();
+    }
+
+    let stats = db.stats().unwrap();
+    assert_eq!(3, stats.live_keys);
+    assert_eq!(2, stats.ttl_keys);
+    assert_eq!(0, stats.expired_keys);
+    assert_eq!(Some(Duration::from_secs(100)), stats.oldest_key_age);
+    assert_eq!(Some(Duration::from_secs(10)), stats.next_expiry);
+    assert_eq!(5, stats.freq.max);
+    assert_eq!(3, stats.value_size.max);
+
+    // The expired key is never live, whether the TTL thread removed it yet or
+    // not
+    clock.advance(Duration::from_secs(20));
+    let stats = db.stats().unwrap();
+    assert_eq!(2, stats.live_keys);
+    assert_eq!(1, stats.ttl_keys);
+    assert!(stats.expired_keys <= 1);
+    assert_eq!(Some(Duration::from_secs(40)), stats.next_expiry);
+
+    db.remove_expired().unwrap();
+    let stats = db.stats().unwrap();
+    assert_eq!(2, stats.live_keys);
+    assert_eq!(0, stats.expired_keys);
+}
+
+#[test]
+fn test_distribution_percentiles() {
+    let d = Distribution::from_values((1..=100).rev().collect());
+    assert_eq!(100, d.count);
+    assert_eq!(1, d.min);
+    assert_eq!(100, d.max);
+    assert_eq!(50.5, d.mean);
+    assert_eq!(50, d.p50);
+    assert_eq!(90, d.p90);
+    assert_eq!(99, d.p99);
+
+    let single = Distribution::from_values(vec![7]);
+    assert_eq!((7, 7, 7), (single.min, single.p50, single.p99));
+}
+
+#[test]
+fn test_key_info() {
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let db = DB::with_config(MemoryBackend::new(), DBConfig::new().clock(clock.clone())).unwrap();
+
+    assert!(db.key_info(&"missing").unwrap().is_none());
+
+    db.set("user:1", "Alice", Some(Duration::from_secs(60)))
+        .unwrap();
+    clock.advance(Duration::from_secs(10));
+
+    let info = db.key_info(&"user:1").unwrap().unwrap();
+    assert_eq!(5, info.value_len);
+    assert_eq!(0, info.members_len);
+    assert!(info.metadata_len > 0);
+    assert_eq!(0, info.freq);
+    assert_eq!(Some(Duration::from_secs(50)), info.ttl);
+    assert_eq!(Duration::from_secs(10), info.idle);
+
+    // Incrementing the frequency is an access, reading is not
+    db.increment_frequency("user:1").unwrap();
+    clock.advance(Duration::from_secs(3));
+    db.get("user:1").unwrap();
+    let info = db.key_info(&"user:1").unwrap().unwrap();
+    assert_eq!(1, info.freq);
+    assert_eq!(Duration::from_secs(3), info.idle);
+
+    db.rpush(&"list", &["abc", "de"]).unwrap();
+    let info = db.key_info(&"list").unwrap().unwrap();
+    assert_eq!(None, info.ttl);
+    assert!(info.members_len >= 5);
+    assert_eq!(info.value_len + info.members_len, info.size());
+}
+
+#[test]
+fn test_big_keys_by_prefix() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    db.set("session:1", &"x".repeat(100), None).unwrap();
+    db.set("session:2", &"x".repeat(300), None).unwrap();
+    db.set("session:3", &"x".repeat(200), None).unwrap();
+    db.set("user:1", &"x".repeat(50), None).unwrap();
+    db.set("plain", "x", None).unwrap();
+
+    let reports = db.big_keys(2).unwrap();
+    assert_eq!(3, reports.len());
+
+    assert_eq!(b"session:".to_vec(), reports[0].prefix);
+    assert_eq!(3, reports[0].keys);
+    assert_eq!(600, reports[0].bytes);
+    assert_eq!(
+        vec![(b"session:2".to_vec(), 300), (b"session:3".to_vec(), 200)],
+        reports[0].largest
+    );
+
+    assert_eq!(b"user:".to_vec(), reports[1].prefix);
+    assert_eq!(vec![(b"user:1".to_vec(), 50)], reports[1].largest);
+
+    assert!(reports[2].prefix.is_empty());
+    assert_eq!(1, reports[2].bytes);
+}
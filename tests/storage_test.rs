@@ -0,0 +1,219 @@
+use std::ops::Bound;
+use std::sync::Arc;
+use std::thread::{
+    self,
+    sleep
+};
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::errors::TransactionError;
+use epoch_db::db::storage::{
+    MAX_CONFLICT_RETRIES,
+    MemoryBackend,
+    SledBackend,
+    StorageBackend,
+    StorageTxError,
+    TreeKind
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_memory_set_get_remove() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    db.set("user:1", "Alice", None).unwrap();
+
+    assert_eq!("Alice", db.get("user:1").unwrap().unwrap());
+    assert_eq!(0, db.get_metadata("user:1").unwrap().unwrap().freq);
+
+    db.increment_frequency("user:1").unwrap();
+    assert_eq!(1, db.get_metadata("user:1").unwrap().unwrap().freq);
+
+    db.remove("user:1").unwrap();
+
+    assert!(db.get("user:1").unwrap().is_none());
+    assert!(db.get_metadata("user:1").unwrap().is_none());
+    assert_eq!(0, db.get_db_size());
+}
+
+#[test]
+fn test_memory_ttl() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    db.set("user:1", "Alice", Some(Duration::from_secs(1)))
+        .unwrap();
+
+    assert_eq!("Alice", db.get("user:1").unwrap().unwrap());
+
+    sleep(Duration::from_secs(2));
+
+    assert_eq!(None, db.get("user:1").unwrap());
+}
+
+#[test]
+fn test_memory_transaction_rollback() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    db.set("user:charlie", "initial_state", None).unwrap();
+
+    let result: Result<(), _> = db.transaction(|tx| {
+        tx.set("user:charlie", "rolled_back", None)?;
+        tx.set("user:dave", "rolled_back", None)?;
+
+        Err(TransactionError::Abort("Something went wrong!"))
+    });
+
+    assert!(result.is_err());
+    assert_eq!("initial_state", db.get("user:charlie").unwrap().unwrap());
+    assert!(db.get("user:dave").unwrap().is_none());
+}
+
+#[test]
+fn test_memory_transaction_isolation() {
+    let db = Arc::new(DB::with_storage(MemoryBackend::new()).unwrap());
+
+    db.set("counter", "0", None).unwrap();
+
+    let mut handles = vec![];
+    for _ in 0..10 {
+        let db_clone = Arc::clone(&db);
+        handles.push(thread::spawn(move || {
+            for _ in 0..50 {
+                db_clone
+                    .transaction(|tx| {
+                        let current: u64 = tx
+                            .get("counter")?
+                            .unwrap()
+                            .parse()
+                            .map_err(TransactionError::Abort)?;
+                        tx.set("counter", &(current + 1).to_string(), None)?;
+                        Ok(())
+                    })
+                    .unwrap();
+            }
+        }));
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!("500", db.get("counter").unwrap().unwrap());
+}
+
+#[test]
+fn test_memory_transaction_conflict_is_bounded() {
+    let storage = MemoryBackend::new();
+
+    let mut runs = 0;
+    let res = storage.transaction(&mut |tx| {
+        runs += 1;
+        tx.insert(TreeKind::Data, b"a", b"1")?;
+        Err(StorageTxError::Conflict)
+    });
+
+    assert!(matches!(res, Err(StorageTxError::Conflict)));
+    assert_eq!(MAX_CONFLICT_RETRIES + 1, runs);
+    assert!(storage.get(TreeKind::Data, b"a").unwrap().is_none());
+}
+
+/// Runs the same sequence of raw operations on both backends, so they behave
+/// the same way.
+fn check_backend(storage: &dyn StorageBackend) {
+    storage.insert(TreeKind::Data, b"b", b"2").unwrap();
+    storage.insert(TreeKind::Data, b"a", b"1").unwrap();
+    storage.insert(TreeKind::Data, b"c", b"3").unwrap();
+
+    // Trees are independent
+    assert!(storage.get(TreeKind::Meta, b"a").unwrap().is_none());
+    assert_eq!(3, storage.len(TreeKind::Data));
+
+    // Range is ordered and respects the bounds
+    let keys: Vec<Vec<u8>> = storage
+        .range(
+            TreeKind::Data,
+            Bound::Included(&b"a"[..]),
+            Bound::Excluded(&b"c"[..])
+        )
+        .map(|i| i.unwrap().0)
+        .collect();
+    assert_eq!(vec![b"a".to_vec(), b"b".to_vec()], keys);
+
+    // Compare and swap
+    assert!(
+        !storage
+            .compare_and_swap(TreeKind::Data, b"a", Some(b"0"), Some(b"9"))
+            .unwrap()
+    );
+    assert!(
+        storage
+            .compare_and_swap(TreeKind::Data, b"a", Some(b"1"), Some(b"9"))
+            .unwrap()
+    );
+    assert!(
+        storage
+            .compare_and_swap(TreeKind::Data, b"d", None, Some(b"4"))
+            .unwrap()
+    );
+    assert_eq!(
+        b"9".to_vec(),
+        storage.get(TreeKind::Data, b"a").unwrap().unwrap()
+    );
+
+    // Aborted transactions leave nothing behind
+    let res = storage.transaction(&mut |tx| {
+        tx.insert(TreeKind::Meta, b"x", b"x")?;
+        tx.remove(TreeKind::Data, b"a")?;
+        assert!(tx.get(TreeKind::Data, b"a")?.is_none());
+        Err(StorageTxError::Abort)
+    });
+    assert!(matches!(res, Err(StorageTxError::Abort)));
+    assert!(storage.get(TreeKind::Meta, b"x").unwrap().is_none());
+    assert!(storage.get(TreeKind::Data, b"a").unwrap().is_some());
+
+    // Committed ones are applied to every tree
+    storage
+        .transaction(&mut |tx| {
+            tx.insert(TreeKind::Meta, b"x", b"x")?;
+            tx.remove(TreeKind::Data, b"a")?;
+            Ok(())
+        })
+        .unwrap();
+    assert!(storage.get(TreeKind::Meta, b"x").unwrap().is_some());
+    assert!(storage.get(TreeKind::Data, b"a").unwrap().is_none());
+
+    // Ranges inside a transaction see its own writes
+    storage
+        .transaction(&mut |tx| {
+            tx.insert(TreeKind::Data, b"bb", b"5")?;
+            tx.remove(TreeKind::Data, b"c")?;
+            let keys: Vec<Vec<u8>> = tx
+                .range(TreeKind::Data, Bound::Included(&b"b"[..]), Bound::Unbounded)?
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect();
+            assert_eq!(vec![b"b".to_vec(), b"bb".to_vec(), b"d".to_vec()], keys);
+            assert!(
+                tx.range(
+                    TreeKind::Data,
+                    Bound::Included(&b"d"[..]),
+                    Bound::Excluded(&b"a"[..])
+                )?
+                .is_empty()
+            );
+            Err(StorageTxError::Abort)
+        })
+        .unwrap_err();
+}
+
+#[test]
+fn test_memory_backend_operations() {
+    check_backend(&MemoryBackend::new());
+}
+
+#[test]
+fn test_sled_backend_operations() {
+    let temp_dir = tempdir().unwrap();
+    check_backend(&SledBackend::open(temp_dir.path()).unwrap());
+}
@@ -0,0 +1,176 @@
+use std::thread;
+use std::time::Duration;
+
+use epoch_db::DB;
+use epoch_db::db::errors::TransientError;
+use epoch_db::db::storage::MemoryBackend;
+use epoch_db::db::stream::{
+    StreamId,
+    StreamTrim
+};
+
+fn open() -> DB {
+    DB::with_storage(MemoryBackend::new()).unwrap()
+}
+
+#[test]
+fn test_stream_add_and_range() {
+    let db = open();
+
+    let ids: Vec<StreamId> = (0..5)
+        .map(|i| db.xadd(&"events", &[("n", i.to_string())]).unwrap())
+        .collect();
+
+    // Ids always increase, even within the same millisecond
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(5, db.xlen(&"events").unwrap());
+
+    let all = db
+        .xrange(&"events", StreamId::MIN, StreamId::MAX, None)
+        .unwrap();
+    assert_eq!(ids, all.iter().map(|e| e.id).collect::<Vec<_>>());
+    assert_eq!(vec![(b"n".to_vec(), b"3".to_vec())], all[3].fields);
+
+    let some = db.xrange(&"events", ids[1], ids[3], None).unwrap();
+    assert_eq!(&ids[1..=3], some.iter().map(|e| e.id).collect::<Vec<_>>());
+    let first = db
+        .xrange(&"events", ids[1], StreamId::MAX, Some(2))
+        .unwrap();
+    assert_eq!(&ids[1..3], first.iter().map(|e| e.id).collect::<Vec<_>>());
+
+    assert_eq!(ids[2], ids[2].to_string().parse().unwrap());
+    assert!(matches!(
+        "1-x".parse::<StreamId>(),
+        Err(TransientError::InvalidStreamId)
+    ));
+}
+
+#[test]
+fn test_stream_trim() {
+    let db = open();
+
+    for i in 0..5 {
+        db.xadd(&"events", &[("n", i.to_string())]).unwrap();
+    }
+    assert_eq!(2, db.xtrim(&"events", StreamTrim::MaxLen(3)).unwrap());
+    assert_eq!(3, db.xlen(&"events").unwrap());
+    let left = db
+        .xrange(&"events", StreamId::MIN, StreamId::MAX, None)
+        .unwrap();
+    assert_eq!(b"2".to_vec(), left[0].fields[0].1);
+
+    thread::sleep(Duration::from_millis(50));
+    let recent = db.xadd(&"events", &[("n", "5")]).unwrap();
+    assert_eq!(
+        3,
+        db.xtrim(&"events", StreamTrim::MaxAge(Duration::from_millis(25)))
+            .unwrap()
+    );
+    let left = db
+        .xrange(&"events", StreamId::MIN, StreamId::MAX, None)
+        .unwrap();
+    assert_eq!(vec![recent], left.iter().map(|e| e.id).collect::<Vec<_>>());
+
+    // The stream is kept once empty
+    assert_eq!(1, db.xtrim(&"events", StreamTrim::MaxLen(0)).unwrap());
+    assert_eq!(0, db.xlen(&"events").unwrap());
+    assert!(db.exists(&"events").unwrap());
+}
+
+#[test]
+fn test_stream_consumer_groups() {
+    let db = open();
+
+    let first = db.xadd(&"jobs", &[("job", "a")]).unwrap();
+    assert!(
+        db.xgroup_create(&"jobs", "workers", Some(StreamId::MIN))
+            .unwrap()
+    );
+    assert!(!db.xgroup_create(&"jobs", "workers", None).unwrap());
+    let second = db.xadd(&"jobs", &[("job", "b")]).unwrap();
+    let third = db.xadd(&"jobs", &[("job", "c")]).unwrap();
+
+    // Every entry is delivered once to the group
+    let alice = db.xreadgroup(&"jobs", "workers", "alice", 2).unwrap();
+    assert_eq!(
+        vec![first, second],
+        alice.iter().map(|e| e.id).collect::<Vec<_>>()
+    );
+    let bob = db.xreadgroup(&"jobs", "workers", "bob", 10).unwrap();
+    assert_eq!(vec![third], bob.iter().map(|e| e.id).collect::<Vec<_>>());
+    assert!(
+        db.xreadgroup(&"jobs", "workers", "bob", 10)
+            .unwrap()
+            .is_empty()
+    );
+
+    let pending = db.xpending(&"jobs", "workers").unwrap();
+    assert_eq!(
+        vec![(first, "alice"), (second, "alice"), (third, "bob")],
+        pending
+            .iter()
+            .map(|p| (p.id, p.consumer.as_str()))
+            .collect::<Vec<_>>()
+    );
+
+    assert_eq!(
+        2,
+        db.xack(&"jobs", "workers", &[first, third, third]).unwrap()
+    );
+    let pending = db.xpending(&"jobs", "workers").unwrap();
+    assert_eq!(
+        vec![second],
+        pending.iter().map(|p| p.id).collect::<Vec<_>>()
+    );
+
+    // A group created with `None` only sees the new entries
+    assert!(db.xgroup_create(&"jobs", "audit", None).unwrap());
+    assert!(
+        db.xreadgroup(&"jobs", "audit", "carol", 10)
+            .unwrap()
+            .is_empty()
+    );
+    let fourth = db.xadd(&"jobs", &[("job", "d")]).unwrap();
+    let carol = db.xreadgroup(&"jobs", "audit", "carol", 10).unwrap();
+    assert_eq!(vec![fourth], carol.iter().map(|e| e.id).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_stream_errors() {
+    let db = open();
+
+    assert!(matches!(
+        db.xreadgroup(&"missing", "workers", "alice", 1),
+        Err(TransientError::GroupNotFound)
+    ));
+    db.xadd(&"jobs", &[("job", "a")]).unwrap();
+    assert!(matches!(
+        db.xpending(&"jobs", "workers"),
+        Err(TransientError::GroupNotFound)
+    ));
+
+    db.set("plain", "value", None).unwrap();
+    assert!(matches!(
+        db.xadd(&"plain", &[("f", "v")]),
+        Err(TransientError::WrongType)
+    ));
+    db.rpush(&"list", &["a"]).unwrap();
+    assert!(matches!(db.xlen(&"list"), Err(TransientError::WrongType)));
+}
+
+#[test]
+fn test_stream_removed_with_its_groups() {
+    let db = open();
+
+    db.xadd(&"jobs", &[("job", "a")]).unwrap();
+    db.xgroup_create(&"jobs", "workers", Some(StreamId::MIN))
+        .unwrap();
+    db.xreadgroup(&"jobs", "workers", "alice", 1).unwrap();
+
+    db.remove("jobs").unwrap();
+    assert_eq!(0, db.xlen(&"jobs").unwrap());
+    assert!(matches!(
+        db.xpending(&"jobs", "workers"),
+        Err(TransientError::GroupNotFound)
+    ));
+}
@@ -1,7 +1,21 @@
+use std::ops::Bound;
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 use epoch_db::DB;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::errors::{
+    TransactionError,
+    TransientError
+};
+use epoch_db::db::storage::{
+    KeyValues,
+    MAX_CONFLICT_RETRIES,
+    MemoryBackend,
+    SledBackend,
+    StorageBackend
+};
 use tempfile::tempdir;
 /// Tests the "happy path" for a transaction.
 /// It verifies that if the closure succeeds, all the changes within it
@@ -16,7 +30,7 @@ fn test_transaction_commit_on_success() {
     db.set("user:bob", "inactive", None).unwrap();
 
     // Run the transaction
-    let result = db.transaction(|tx| {
+    let result: Result<(), TransactionError<()>> = db.transaction(|tx| {
         // Perform multiple operations
         tx.remove("user:alice")?;
         tx.set("user:bob", "active_transferred", None)?;
@@ -50,12 +64,12 @@ fn test_transaction_rollback_on_failure() {
     db.set("user:charlie", "initial_state", None).unwrap();
 
     // Run a transaction that will fail
-    let result = db.transaction(|tx| {
+    let result: Result<(), _> = db.transaction(|tx| {
         // Make a change
         tx.set("user:charlie", "new_state_that_should_be_rolled_back", None)?;
 
         // Force the transaction to fail
-        Err(Box::from("Something went wrong!"))
+        Err(TransactionError::Abort("Something went wrong!"))
     });
 
     // The transaction itself should report the failure
@@ -95,7 +109,8 @@ fn test_transaction_isolation() {
                 db_clone
                     .transaction(|tx| {
                         let current_val_str = tx.get("counter")?.unwrap();
-                        let current_val: u64 = current_val_str.parse()?;
+                        let current_val: u64 =
+                            current_val_str.parse().map_err(TransactionError::Abort)?;
                         let new_val = current_val + 1;
                         tx.set("counter", &new_val.to_string(), None)?;
                         Ok(())
@@ -121,3 +136,322 @@ fn test_transaction_isolation() {
         "The final counter should be correct, proving no lost updates occurred."
     );
 }
+
+#[derive(Debug, PartialEq)]
+enum TransferError {
+    InsufficientFunds { balance: u64 }
+}
+
+/// Tests that the error the closure aborted with reaches the caller untouched,
+/// and that it can be told apart from the errors of the database itself.
+#[test]
+fn test_transaction_typed_errors() {
+    let temp_dir = tempdir().unwrap();
+    let db = DB::new(temp_dir.path()).unwrap();
+
+    db.set("user:erin", "10", None).unwrap();
+
+    // Business rule failure
+    let result = db.transaction(|tx| {
+        let balance: u64 = tx.get("user:erin")?.unwrap().parse().unwrap();
+        if balance < 20 {
+            return Err(TransactionError::Abort(TransferError::InsufficientFunds {
+                balance
+            }));
+        }
+        tx.set("user:erin", &(balance - 20).to_string(), None)?;
+        Ok(())
+    });
+
+    match result {
+        Err(TransactionError::Abort(e)) => {
+            assert_eq!(
+                e,
+                TransferError::InsufficientFunds {
+                    balance: 10
+                }
+            )
+        },
+        _ => panic!("The business rule error should be preserved.")
+    }
+
+    // Missing key
+    let result: Result<(), TransactionError<TransferError>> = db.transaction(|tx| {
+        tx.remove("user:missing")?;
+        Ok(())
+    });
+
+    assert!(matches!(
+        result,
+        Err(TransactionError::Transient(
+            TransientError::MetadataNotFound
+        ))
+    ));
+    assert_eq!("10", db.get("user:erin").unwrap().unwrap());
+
+    // The writes outside of a transaction keep the cause as well
+    assert!(matches!(
+        db.remove("user:missing"),
+        Err(TransientError::MetadataNotFound)
+    ));
+}
+
+/// Tests that the value returned by the closure is returned by the
+/// transaction, without having to smuggle it out.
+#[test]
+fn test_transaction_returns_value() {
+    let temp_dir = tempdir().unwrap();
+    let db = DB::new(temp_dir.path()).unwrap();
+
+    db.set("session:old", "payload", None).unwrap();
+
+    let moved: Result<Option<String>, TransactionError<()>> = db.transaction(|tx| {
+        let payload = tx.get("session:old")?;
+        if let Some(p) = &payload {
+            tx.remove("session:old")?;
+            tx.set("session:new", p, None)?;
+        }
+        Ok(payload)
+    });
+
+    assert_eq!(Some("payload".to_string()), moved.unwrap());
+    assert!(db.get("session:old").unwrap().is_none());
+    assert_eq!("payload", db.get("session:new").unwrap().unwrap());
+}
+
+/// Tests that a transaction which keeps conflicting gives up once it reaches
+/// the configured retry limit, and that the FnMut closure keeps its state
+/// between runs.
+#[test]
+fn test_transaction_retry_limit() {
+    let db = DB::with_config(
+        MemoryBackend::new(),
+        DBConfig::new().max_transaction_retries(3)
+    )
+    .unwrap();
+
+    let mut runs = 0;
+    let result: Result<(), TransactionError<()>> = db.transaction(|tx| {
+        runs += 1;
+        tx.set("key", "value", None)?;
+        Err(TransactionError::Transient(
+            TransientError::TransactionConflict
+        ))
+    });
+
+    assert_eq!(4, runs, "The first run and 3 retries should have happened.");
+    assert!(matches!(
+        result,
+        Err(TransactionError::Transient(
+            TransientError::TransactionRetryLimitReached
+        ))
+    ));
+    assert!(db.get("key").unwrap().is_none());
+
+    // A transaction which conflicts once commits on its retry
+    let mut runs = 0;
+    let result: Result<u32, TransactionError<()>> = db.transaction(|tx| {
+        runs += 1;
+        tx.set("key", "value", None)?;
+        if runs == 1 {
+            return Err(TransactionError::Transient(
+                TransientError::TransactionConflict
+            ));
+        }
+        Ok(runs)
+    });
+
+    assert_eq!(2, result.unwrap());
+    assert_eq!("value", db.get("key").unwrap().unwrap());
+}
+
+/// Tests that without a configured limit, the backend gives up on a
+/// transaction which keeps conflicting.
+#[test]
+fn test_transaction_retries_are_bounded_by_default() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    let mut runs = 0;
+    let result: Result<(), TransactionError<()>> = db.transaction(|_| {
+        runs += 1;
+        Err(TransactionError::Transient(
+            TransientError::TransactionConflict
+        ))
+    });
+
+    assert_eq!(MAX_CONFLICT_RETRIES + 1, runs);
+    assert!(matches!(
+        result,
+        Err(TransactionError::Transient(
+            TransientError::TransactionConflict
+        ))
+    ));
+}
+
+/// Tests the raw, exists and TTL operations of the guard with a swap and a
+/// conditional TTL refresh written entirely inside transactions.
+#[test]
+fn test_transaction_raw_and_ttl_operations() {
+    let temp_dir = tempdir().unwrap();
+    let db = DB::new(temp_dir.path()).unwrap();
+
+    db.set_raw(b"a", b"\x00\x01", None).unwrap();
+    db.set_raw(b"b", b"\x02\x03", Some(Duration::from_secs(60)))
+        .unwrap();
+
+    // Swap the values, keeping the TTL of each key
+    let result: Result<(), TransactionError<()>> = db.transaction(|tx| {
+        let a = tx.get_raw(b"a")?.unwrap();
+        let b = tx.get_raw(b"b")?.unwrap();
+        let b_ttl = tx.ttl(b"b")?;
+        tx.set_raw(b"a", &b, None)?;
+        tx.set_raw(b"b", &a, b_ttl)?;
+        Ok(())
+    });
+    result.unwrap();
+
+    assert_eq!(b"\x02\x03".to_vec(), db.get_raw(b"a").unwrap().unwrap());
+    assert_eq!(b"\x00\x01".to_vec(), db.get_raw(b"b").unwrap().unwrap());
+    assert!(db.ttl(b"a").unwrap().is_none());
+    assert!(db.ttl(b"b").unwrap().is_some());
+
+    // Refresh the TTL only if the key exists and is about to expire
+    let refreshed: Result<bool, TransactionError<()>> = db.transaction(|tx| {
+        if !tx.exists(b"b")? {
+            return Ok(false);
+        }
+        match tx.ttl(b"b")? {
+            Some(left) if left < Duration::from_secs(120) => {
+                Ok(tx.expire(b"b", Duration::from_secs(3600))?)
+            },
+            _ => Ok(false)
+        }
+    });
+    assert!(refreshed.unwrap());
+    assert!(db.ttl(b"b").unwrap().unwrap() > Duration::from_secs(3000));
+
+    let result: Result<(bool, bool, bool), TransactionError<()>> = db.transaction(|tx| {
+        Ok((
+            tx.persist(b"b")?,
+            tx.persist(b"a")?,
+            tx.expire(b"missing", Duration::from_secs(1))?
+        ))
+    });
+    assert_eq!((true, false, false), result.unwrap());
+    assert!(db.ttl(b"b").unwrap().is_none());
+    assert!(db.get_metadata_raw(b"b").unwrap().unwrap().ttl.is_none());
+}
+
+/// Tests that the scans of a transaction see its own writes, and that the
+/// writes depending on a scan are atomic with it.
+#[test]
+fn test_transaction_scans() {
+    let db = DB::with_storage(MemoryBackend::new()).unwrap();
+
+    db.set("session:a", "alice", None).unwrap();
+    db.set("session:b", "bob", None).unwrap();
+    db.set("user:a", "alice", None).unwrap();
+
+    // Archive every session in one go
+    let archived: Result<usize, TransactionError<()>> = db.transaction(|tx| {
+        tx.set("session:c", "carol", None)?;
+        tx.remove("session:b")?;
+
+        let sessions = tx.scan_prefix(b"session:")?;
+        for (key, value) in &sessions {
+            tx.remove_raw(key)?;
+            tx.set_raw(&[b"archive:", &key[8..]].concat(), value, None)?;
+        }
+        Ok(sessions.len())
+    });
+
+    assert_eq!(2, archived.unwrap());
+    assert!(db.keys(b"session:*").unwrap().is_empty());
+    assert_eq!(
+        vec![b"archive:a".to_vec(), b"archive:c".to_vec()],
+        db.keys(b"archive:*").unwrap()
+    );
+
+    let result: Result<KeyValues, TransactionError<()>> = db
+        .transaction(|tx| Ok(tx.range_raw(Bound::Excluded(&b"archive:a"[..]), Bound::Unbounded)?));
+    assert_eq!(
+        vec![
+            (b"archive:c".to_vec(), b"carol".to_vec()),
+            (b"user:a".to_vec(), b"alice".to_vec())
+        ],
+        result.unwrap()
+    );
+}
+
+/// Appends items named after how many there are from many threads, two
+/// transactions reading the same count would overwrite the same item.
+fn append_concurrently<S: StorageBackend + 'static>(storage: S) {
+    let db = DB::with_storage(storage).unwrap();
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let db = db.clone();
+            thread::spawn(move || {
+                for _ in 0..25 {
+                    let result: Result<(), TransactionError<()>> = db.transaction(|tx| {
+                        let count = tx.scan_prefix(b"item:")?.len();
+                        tx.set(&format!("item:{count:04}"), "x", None)?;
+                        Ok(())
+                    });
+                    result.unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(200, db.keys(b"item:*").unwrap().len());
+}
+
+#[test]
+fn test_transaction_scans_are_isolated() {
+    let temp_dir = tempdir().unwrap();
+
+    append_concurrently(MemoryBackend::new());
+    append_concurrently(SledBackend::open(temp_dir.path()).unwrap());
+}
+
+/// Writes to the database from inside a transaction closure, the write is
+/// not part of the transaction and makes it conflict with what it read.
+fn write_inside_transaction<S: StorageBackend + 'static>(storage: S) {
+    let db = DB::with_storage(storage).unwrap();
+    db.set("counter", "1", None).unwrap();
+
+    let mut runs = 0;
+    let result: Result<(), TransactionError<()>> = db.transaction(|tx| {
+        runs += 1;
+        let count: u32 = tx.get("counter")?.unwrap().parse().unwrap();
+        tx.scan_prefix(b"item:")?;
+        if runs == 1 {
+            db.set("counter", "5", None)?;
+        }
+        if runs == 2 {
+            db.set("item:1", "x", None)?;
+        }
+        tx.set("counter", &(count + 1).to_string(), None)?;
+        Ok(())
+    });
+
+    result.unwrap();
+    assert_eq!(
+        3, runs,
+        "A changed key and a changed range should conflict."
+    );
+    assert_eq!("6", db.get("counter").unwrap().unwrap());
+}
+
+#[test]
+fn test_writes_inside_a_transaction_conflict_with_it() {
+    let temp_dir = tempdir().unwrap();
+
+    write_inside_transaction(MemoryBackend::new());
+    write_inside_transaction(SledBackend::open(temp_dir.path()).unwrap());
+}
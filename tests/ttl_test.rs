@@ -2,7 +2,33 @@ use std::thread::sleep;
 use std::time::Duration;
 
 use epoch_db::DB;
-use tempfile::tempdir;
+use epoch_db::db::clock::MockClock;
+use epoch_db::db::config::DBConfig;
+use epoch_db::db::storage::SledBackend;
+use tempfile::{
+    TempDir,
+    tempdir
+};
+
+/// Opens a database whose time only moves when the returned clock is
+/// advanced.
+fn open() -> (DB, MockClock, TempDir) {
+    let temp_dir = tempdir().unwrap();
+    let clock = MockClock::new(Duration::from_secs(1_000_000));
+    let db = DB::with_config(
+        SledBackend::open(temp_dir.path()).unwrap(),
+        DBConfig::new().clock(clock.clone())
+    )
+    .unwrap();
+
+    (db, clock, temp_dir)
+}
+
+/// Moves the time forward and runs the expiration pass.
+fn advance(db: &DB, clock: &MockClock, by: Duration) {
+    clock.advance(by);
+    db.remove_expired().unwrap();
+}
 
 #[test]
 fn test_ttl() {
@@ -22,8 +48,7 @@ fn test_ttl() {
 
 #[test]
 fn test_ttl_update() {
-    let temp_dir = tempdir().unwrap();
-    let db = DB::new(temp_dir.path()).unwrap();
+    let (db, clock, _dir) = open();
 
     db.set("user:update", "Alice", Some(Duration::from_secs(2)))
         .unwrap();
@@ -31,7 +56,7 @@ fn test_ttl_update() {
     db.set("user:update", "Alice V2", Some(Duration::from_secs(10)))
         .unwrap();
 
-    sleep(Duration::from_secs(3));
+    advance(&db, &clock, Duration::from_secs(3));
 
     assert_eq!(
         "Alice V2",
@@ -42,15 +67,14 @@ fn test_ttl_update() {
 
 #[test]
 fn test_ttl_removal_to_permanent() {
-    let temp_dir = tempdir().unwrap();
-    let db = DB::new(temp_dir.path()).unwrap();
+    let (db, clock, _dir) = open();
 
     db.set("user:permanent", "Bob", Some(Duration::from_secs(2)))
         .unwrap();
 
     db.set("user:permanent", "Bob The Permanent", None).unwrap();
 
-    sleep(Duration::from_secs(3));
+    advance(&db, &clock, Duration::from_secs(3));
 
     assert_eq!(
         "Bob The Permanent",
@@ -61,12 +85,11 @@ fn test_ttl_removal_to_permanent() {
 
 #[test]
 fn test_no_ttl_is_permanent() {
-    let temp_dir = tempdir().unwrap();
-    let db = DB::new(temp_dir.path()).unwrap();
+    let (db, clock, _dir) = open();
 
     db.set("user:no_ttl", "Charlie", None).unwrap();
 
-    sleep(Duration::from_secs(3));
+    advance(&db, &clock, Duration::from_secs(3));
 
     assert!(
         db.get("user:no_ttl").unwrap().is_some(),
@@ -76,8 +99,7 @@ fn test_no_ttl_is_permanent() {
 
 #[test]
 fn test_manual_removal_of_ttl_key() {
-    let temp_dir = tempdir().unwrap();
-    let db = DB::new(temp_dir.path()).unwrap();
+    let (db, _clock, _dir) = open();
 
     db.set(
         "user:manual_delete",
@@ -100,3 +122,46 @@ fn test_manual_removal_of_ttl_key() {
         "Metadata should be gone after manual remove."
     );
 }
+
+#[test]
+fn test_expire_and_persist() {
+    let (db, clock, _dir) = open();
+
+    db.set("user:expire", "Erin", None).unwrap();
+    db.set("user:persist", "Frank", Some(Duration::from_secs(2)))
+        .unwrap();
+
+    assert!(db.exists(&"user:expire").unwrap());
+    assert!(db.expire(&"user:expire", Duration::from_secs(2)).unwrap());
+    assert!(db.persist(&"user:persist").unwrap());
+    assert!(!db.expire(&"user:missing", Duration::from_secs(2)).unwrap());
+
+    advance(&db, &clock, Duration::from_secs(3));
+
+    assert!(
+        !db.exists(&"user:expire").unwrap(),
+        "Key should expire after its TTL was set with expire."
+    );
+    assert_eq!(
+        "Frank",
+        db.get("user:persist").unwrap().unwrap(),
+        "Key should persist after its TTL was removed with persist."
+    );
+}
+
+#[test]
+fn test_ttl_with_mock_clock() {
+    let (db, clock, _dir) = open();
+
+    db.set("user:mock", "Grace", Some(Duration::from_secs(60)))
+        .unwrap();
+    assert_eq!(Some(Duration::from_secs(60)), db.ttl(&"user:mock").unwrap());
+
+    advance(&db, &clock, Duration::from_secs(59));
+    assert_eq!(Some(Duration::from_secs(1)), db.ttl(&"user:mock").unwrap());
+    assert!(db.get("user:mock").unwrap().is_some());
+
+    advance(&db, &clock, Duration::from_secs(1));
+    assert!(db.get("user:mock").unwrap().is_none());
+    assert!(db.get_metadata("user:mock").unwrap().is_none());
+}